@@ -0,0 +1,21 @@
+-- Enrichment checkpoint per chain, advanced independently of ingestion.
+-- The worker consumes committed transfers in id order after last_enriched_transfer_id.
+-- Additive stages also record the last transfer id they applied, so a batch retried
+-- after a later stage failed is not added to the graph edges or rollups a second time.
+CREATE TABLE IF NOT EXISTS enrichment_state (
+    chain_id                   BIGINT       PRIMARY KEY,
+    last_enriched_transfer_id  BIGINT       NOT NULL DEFAULT 0,
+    last_enriched_block        BIGINT,
+    edges_transfer_id          BIGINT       NOT NULL DEFAULT 0,
+    rollups_transfer_id        BIGINT       NOT NULL DEFAULT 0,
+    paused                     BOOLEAN      NOT NULL DEFAULT FALSE,
+    updated_at                 TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_transfers_chain_id ON transfers (chain_id, id);
+
+-- Transfers stored before this migration were enriched inline during ingestion.
+INSERT INTO enrichment_state (chain_id, last_enriched_transfer_id, last_enriched_block,
+                              edges_transfer_id, rollups_transfer_id)
+SELECT chain_id, MAX(id), MAX(block_number), MAX(id), MAX(id) FROM transfers GROUP BY chain_id
+ON CONFLICT (chain_id) DO NOTHING;
@@ -0,0 +1,300 @@
+-- Partition transfers and the tables hanging off them by chain_id, then by calendar month (UTC).
+-- Each table becomes <table> -> <table>_c<chain_id> -> <table>_c<chain_id>_<YYYYMM>.
+-- Child tables carry the transfer's chain_id and block_timestamp so their foreign keys
+-- reference the full transfers key and their partitions line up with the transfer partitions.
+
+CREATE SCHEMA IF NOT EXISTS archive;
+
+-- Create the chain and month partitions of every partitioned table for one chain and month.
+-- Called by the indexer before each insert and by the partition maintenance task.
+CREATE OR REPLACE FUNCTION ensure_monthly_partitions(p_chain_id BIGINT, p_month DATE)
+RETURNS VOID AS $$
+DECLARE
+    parent      TEXT;
+    chain_part  TEXT;
+    month_part  TEXT;
+    month_start DATE := date_trunc('month', p_month)::DATE;
+BEGIN
+    FOREACH parent IN ARRAY ARRAY['transfers', 'defi_events', 'anomalies',
+                                  'transfer_entity_flags', 'onramp_transfers'] LOOP
+        chain_part := format('%s_c%s', parent, p_chain_id);
+        month_part := format('%s_%s', chain_part, to_char(month_start, 'YYYYMM'));
+
+        IF to_regclass(chain_part) IS NULL THEN
+            EXECUTE format(
+                'CREATE TABLE IF NOT EXISTS %I PARTITION OF %I FOR VALUES IN (%s) PARTITION BY RANGE (block_timestamp)',
+                chain_part, parent, p_chain_id);
+        END IF;
+
+        -- A month retired in detach mode keeps its name, so it is not recreated
+        IF to_regclass(month_part) IS NULL THEN
+            EXECUTE format(
+                'CREATE TABLE IF NOT EXISTS %I PARTITION OF %I FOR VALUES FROM (%L) TO (%L)',
+                month_part, chain_part,
+                month_start::TIMESTAMP AT TIME ZONE 'UTC',
+                (month_start + INTERVAL '1 month')::TIMESTAMP AT TIME ZONE 'UTC');
+        END IF;
+    END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Detach one chain/month from every partitioned table, optionally moving it to the archive schema.
+-- Referencing partitions are detached first and their foreign keys are re-pointed at the
+-- detached transfers partition, so the retired month stays internally consistent.
+-- Returns the number of partitions detached; months already retired are skipped.
+CREATE OR REPLACE FUNCTION retire_monthly_partitions(p_chain_id BIGINT, p_month DATE, p_archive BOOLEAN)
+RETURNS INTEGER AS $$
+DECLARE
+    suffix         TEXT := format('_c%s_%s', p_chain_id, to_char(p_month, 'YYYYMM'));
+    transfers_part TEXT := 'transfers' || suffix;
+    child          TEXT;
+    part           TEXT;
+    fk             RECORD;
+    detached       TEXT[] := '{}';
+    repoint        TEXT[] := '{}';
+BEGIN
+    FOREACH child IN ARRAY ARRAY['anomalies', 'transfer_entity_flags', 'onramp_transfers'] LOOP
+        part := child || suffix;
+        CONTINUE WHEN NOT EXISTS (SELECT 1 FROM pg_inherits WHERE inhrelid = to_regclass(part));
+
+        EXECUTE format('ALTER TABLE %I DETACH PARTITION %I', format('%s_c%s', child, p_chain_id), part);
+        detached := detached || part;
+
+        -- Dropping the root constraint also drops the per-partition clones Postgres created
+        FOR fk IN
+            SELECT conname FROM pg_constraint
+            WHERE conrelid = to_regclass(part) AND contype = 'f' AND conparentid = 0
+              AND confrelid IN (SELECT relid FROM pg_partition_tree('transfers'))
+        LOOP
+            EXECUTE format('ALTER TABLE %I DROP CONSTRAINT %I', part, fk.conname);
+        END LOOP;
+        repoint := repoint || part;
+    END LOOP;
+
+    FOREACH child IN ARRAY ARRAY['transfers', 'defi_events'] LOOP
+        part := child || suffix;
+        CONTINUE WHEN NOT EXISTS (SELECT 1 FROM pg_inherits WHERE inhrelid = to_regclass(part));
+
+        EXECUTE format('ALTER TABLE %I DETACH PARTITION %I', format('%s_c%s', child, p_chain_id), part);
+        detached := detached || part;
+    END LOOP;
+
+    IF to_regclass(transfers_part) IS NOT NULL THEN
+        FOREACH part IN ARRAY repoint LOOP
+            EXECUTE format(
+                'ALTER TABLE %I ADD FOREIGN KEY (transfer_id, chain_id, block_timestamp) REFERENCES %I (id, chain_id, block_timestamp) ON DELETE CASCADE',
+                part, transfers_part);
+        END LOOP;
+    END IF;
+
+    IF p_archive THEN
+        FOREACH part IN ARRAY detached LOOP
+            EXECUTE format('ALTER TABLE %I SET SCHEMA archive', part);
+        END LOOP;
+    END IF;
+
+    RETURN coalesce(array_length(detached, 1), 0);
+END;
+$$ LANGUAGE plpgsql;
+
+-- Move the existing tables aside; their constraint names would collide with the new ones
+ALTER TABLE onramp_transfers RENAME TO onramp_transfers_unpartitioned;
+ALTER TABLE onramp_transfers_unpartitioned RENAME CONSTRAINT onramp_transfers_pkey TO onramp_transfers_unpartitioned_pkey;
+DROP INDEX IF EXISTS idx_onramp_transfers_provider;
+
+ALTER TABLE transfer_entity_flags RENAME TO transfer_entity_flags_unpartitioned;
+ALTER TABLE transfer_entity_flags_unpartitioned RENAME CONSTRAINT transfer_entity_flags_pkey TO transfer_entity_flags_unpartitioned_pkey;
+ALTER TABLE transfer_entity_flags_unpartitioned RENAME CONSTRAINT transfer_entity_flags_transfer_id_entity_label_id_side_key TO transfer_entity_flags_unpartitioned_key;
+DROP INDEX IF EXISTS idx_transfer_entity_flags_transfer;
+DROP INDEX IF EXISTS idx_transfer_entity_flags_entity;
+
+ALTER TABLE anomalies RENAME TO anomalies_unpartitioned;
+ALTER TABLE anomalies_unpartitioned RENAME CONSTRAINT anomalies_pkey TO anomalies_unpartitioned_pkey;
+ALTER TABLE anomalies_unpartitioned RENAME CONSTRAINT anomalies_transfer_id_anomaly_type_key TO anomalies_unpartitioned_key;
+DROP INDEX IF EXISTS idx_anomalies_type;
+DROP INDEX IF EXISTS idx_anomalies_risk;
+DROP INDEX IF EXISTS idx_anomalies_chain;
+DROP INDEX IF EXISTS idx_anomalies_address;
+DROP INDEX IF EXISTS idx_anomalies_unresolved;
+
+ALTER TABLE defi_events RENAME TO defi_events_unpartitioned;
+ALTER TABLE defi_events_unpartitioned RENAME CONSTRAINT defi_events_pkey TO defi_events_unpartitioned_pkey;
+ALTER TABLE defi_events_unpartitioned RENAME CONSTRAINT defi_events_chain_id_tx_hash_log_index_key TO defi_events_unpartitioned_key;
+DROP INDEX IF EXISTS idx_defi_events_chain_block;
+DROP INDEX IF EXISTS idx_defi_events_account;
+DROP INDEX IF EXISTS idx_defi_events_protocol;
+DROP INDEX IF EXISTS idx_defi_events_event_type;
+DROP INDEX IF EXISTS idx_defi_events_tx_hash;
+DROP INDEX IF EXISTS idx_defi_events_timestamp;
+
+ALTER TABLE transfers RENAME TO transfers_unpartitioned;
+ALTER TABLE transfers_unpartitioned RENAME CONSTRAINT transfers_pkey TO transfers_unpartitioned_pkey;
+ALTER TABLE transfers_unpartitioned RENAME CONSTRAINT transfers_chain_id_tx_hash_log_index_key TO transfers_unpartitioned_key;
+DROP INDEX IF EXISTS idx_transfers_chain_block;
+DROP INDEX IF EXISTS idx_transfers_from;
+DROP INDEX IF EXISTS idx_transfers_to;
+DROP INDEX IF EXISTS idx_transfers_token;
+DROP INDEX IF EXISTS idx_transfers_timestamp;
+DROP INDEX IF EXISTS idx_transfers_tx_hash;
+DROP INDEX IF EXISTS idx_transfers_chain_id;
+
+-- Partitioned tables. Unique constraints must include the partition key columns.
+CREATE TABLE transfers (
+    id              BIGINT       NOT NULL DEFAULT nextval('transfers_id_seq'),
+    chain_id        BIGINT       NOT NULL,
+    block_number    BIGINT       NOT NULL,
+    block_hash      BYTEA        NOT NULL,
+    tx_hash         BYTEA        NOT NULL,
+    log_index       INTEGER      NOT NULL,
+    token_address   BYTEA        NOT NULL,
+    from_address    BYTEA        NOT NULL,
+    to_address      BYTEA        NOT NULL,
+    amount          NUMERIC      NOT NULL,
+    token_symbol    VARCHAR(16)  NOT NULL,
+    token_decimals  SMALLINT     NOT NULL,
+    block_timestamp TIMESTAMPTZ  NOT NULL,
+    PRIMARY KEY (id, chain_id, block_timestamp),
+    UNIQUE (chain_id, tx_hash, log_index, block_timestamp)
+) PARTITION BY LIST (chain_id);
+
+CREATE TABLE defi_events (
+    id               BIGINT       NOT NULL DEFAULT nextval('defi_events_id_seq'),
+    chain_id         BIGINT       NOT NULL,
+    block_number     BIGINT       NOT NULL,
+    tx_hash          BYTEA        NOT NULL,
+    log_index        INT          NOT NULL,
+    protocol         TEXT         NOT NULL,
+    event_type       TEXT         NOT NULL,
+    contract_address BYTEA        NOT NULL,
+    account          BYTEA,
+    token_in         BYTEA,
+    token_out        BYTEA,
+    amount_in        NUMERIC,
+    amount_out       NUMERIC,
+    block_timestamp  TIMESTAMPTZ  NOT NULL,
+    raw_data         JSONB,
+    PRIMARY KEY (id, chain_id, block_timestamp),
+    UNIQUE (chain_id, tx_hash, log_index, block_timestamp)
+) PARTITION BY LIST (chain_id);
+
+-- block_timestamp is the timestamp of the anomaly's transfer
+CREATE TABLE anomalies (
+    id              BIGINT       NOT NULL DEFAULT nextval('anomalies_id_seq'),
+    transfer_id     BIGINT,
+    chain_id        BIGINT       NOT NULL,
+    block_timestamp TIMESTAMPTZ  NOT NULL,
+    anomaly_type    VARCHAR(64)  NOT NULL,
+    risk_score      REAL         NOT NULL,
+    flags           TEXT[]       NOT NULL DEFAULT '{}',
+    details         JSONB,
+    address         BYTEA,
+    detected_at     TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    resolved        BOOLEAN      NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id, chain_id, block_timestamp),
+    UNIQUE (transfer_id, anomaly_type, chain_id, block_timestamp),
+    FOREIGN KEY (transfer_id, chain_id, block_timestamp)
+        REFERENCES transfers (id, chain_id, block_timestamp) ON DELETE CASCADE
+) PARTITION BY LIST (chain_id);
+
+CREATE TABLE transfer_entity_flags (
+    id              BIGINT       NOT NULL DEFAULT nextval('transfer_entity_flags_id_seq'),
+    transfer_id     BIGINT       NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    block_timestamp TIMESTAMPTZ  NOT NULL,
+    entity_label_id INTEGER      NOT NULL REFERENCES entity_labels(id),
+    side            VARCHAR(4)   NOT NULL,
+    PRIMARY KEY (id, chain_id, block_timestamp),
+    UNIQUE (transfer_id, entity_label_id, side, chain_id, block_timestamp),
+    FOREIGN KEY (transfer_id, chain_id, block_timestamp)
+        REFERENCES transfers (id, chain_id, block_timestamp) ON DELETE CASCADE
+) PARTITION BY LIST (chain_id);
+
+CREATE TABLE onramp_transfers (
+    transfer_id     BIGINT       NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    block_timestamp TIMESTAMPTZ  NOT NULL,
+    provider_id     INTEGER      NOT NULL REFERENCES onramp_providers(id),
+    direction       VARCHAR(8)   NOT NULL, -- 'deposit' (user -> exchange) or 'withdrawal' (exchange -> user)
+    PRIMARY KEY (transfer_id, chain_id, block_timestamp),
+    FOREIGN KEY (transfer_id, chain_id, block_timestamp)
+        REFERENCES transfers (id, chain_id, block_timestamp) ON DELETE CASCADE
+) PARTITION BY LIST (chain_id);
+
+ALTER SEQUENCE transfers_id_seq OWNED BY transfers.id;
+ALTER SEQUENCE defi_events_id_seq OWNED BY defi_events.id;
+ALTER SEQUENCE anomalies_id_seq OWNED BY anomalies.id;
+ALTER SEQUENCE transfer_entity_flags_id_seq OWNED BY transfer_entity_flags.id;
+
+-- Indexes on the partitioned parents cascade to every partition
+CREATE INDEX IF NOT EXISTS idx_transfers_chain_block ON transfers (chain_id, block_number);
+CREATE INDEX IF NOT EXISTS idx_transfers_chain_id ON transfers (chain_id, id);
+CREATE INDEX IF NOT EXISTS idx_transfers_from ON transfers (from_address);
+CREATE INDEX IF NOT EXISTS idx_transfers_to ON transfers (to_address);
+CREATE INDEX IF NOT EXISTS idx_transfers_token ON transfers (token_address);
+CREATE INDEX IF NOT EXISTS idx_transfers_timestamp ON transfers (block_timestamp);
+CREATE INDEX IF NOT EXISTS idx_transfers_tx_hash ON transfers (tx_hash);
+
+CREATE INDEX IF NOT EXISTS idx_defi_events_chain_block ON defi_events (chain_id, block_number);
+CREATE INDEX IF NOT EXISTS idx_defi_events_account ON defi_events (account);
+CREATE INDEX IF NOT EXISTS idx_defi_events_protocol ON defi_events (protocol);
+CREATE INDEX IF NOT EXISTS idx_defi_events_event_type ON defi_events (event_type);
+CREATE INDEX IF NOT EXISTS idx_defi_events_tx_hash ON defi_events (tx_hash);
+CREATE INDEX IF NOT EXISTS idx_defi_events_timestamp ON defi_events (block_timestamp);
+
+CREATE INDEX IF NOT EXISTS idx_anomalies_id ON anomalies (id);
+CREATE INDEX IF NOT EXISTS idx_anomalies_type ON anomalies (anomaly_type);
+CREATE INDEX IF NOT EXISTS idx_anomalies_risk ON anomalies (risk_score DESC);
+CREATE INDEX IF NOT EXISTS idx_anomalies_chain ON anomalies (chain_id, detected_at);
+CREATE INDEX IF NOT EXISTS idx_anomalies_address ON anomalies (address);
+CREATE INDEX IF NOT EXISTS idx_anomalies_unresolved ON anomalies (resolved) WHERE resolved = FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_transfer_entity_flags_transfer ON transfer_entity_flags (transfer_id);
+CREATE INDEX IF NOT EXISTS idx_transfer_entity_flags_entity ON transfer_entity_flags (entity_label_id);
+
+CREATE INDEX IF NOT EXISTS idx_onramp_transfers_provider ON onramp_transfers (provider_id);
+
+-- Partitions for the months already holding data
+SELECT ensure_monthly_partitions(chain_id, month)
+FROM (
+    SELECT DISTINCT chain_id, date_trunc('month', block_timestamp AT TIME ZONE 'UTC')::DATE AS month
+    FROM transfers_unpartitioned
+    UNION
+    SELECT DISTINCT chain_id, date_trunc('month', block_timestamp AT TIME ZONE 'UTC')::DATE
+    FROM defi_events_unpartitioned
+    UNION
+    SELECT DISTINCT chain_id, date_trunc('month', detected_at AT TIME ZONE 'UTC')::DATE
+    FROM anomalies_unpartitioned
+    WHERE transfer_id IS NULL
+) months;
+
+INSERT INTO transfers
+SELECT id, chain_id, block_number, block_hash, tx_hash, log_index, token_address, from_address,
+       to_address, amount, token_symbol, token_decimals, block_timestamp
+FROM transfers_unpartitioned;
+
+INSERT INTO defi_events
+SELECT id, chain_id, block_number, tx_hash, log_index, protocol, event_type, contract_address,
+       account, token_in, token_out, amount_in, amount_out, block_timestamp, raw_data
+FROM defi_events_unpartitioned;
+
+INSERT INTO anomalies
+SELECT a.id, a.transfer_id, a.chain_id, COALESCE(t.block_timestamp, a.detected_at), a.anomaly_type,
+       a.risk_score, a.flags, a.details, a.address, a.detected_at, a.resolved
+FROM anomalies_unpartitioned a
+LEFT JOIN transfers_unpartitioned t ON t.id = a.transfer_id;
+
+INSERT INTO transfer_entity_flags
+SELECT f.id, f.transfer_id, t.chain_id, t.block_timestamp, f.entity_label_id, f.side
+FROM transfer_entity_flags_unpartitioned f
+JOIN transfers_unpartitioned t ON t.id = f.transfer_id;
+
+INSERT INTO onramp_transfers
+SELECT o.transfer_id, t.chain_id, t.block_timestamp, o.provider_id, o.direction
+FROM onramp_transfers_unpartitioned o
+JOIN transfers_unpartitioned t ON t.id = o.transfer_id;
+
+DROP TABLE onramp_transfers_unpartitioned;
+DROP TABLE transfer_entity_flags_unpartitioned;
+DROP TABLE anomalies_unpartitioned;
+DROP TABLE defi_events_unpartitioned;
+DROP TABLE transfers_unpartitioned;
@@ -0,0 +1,92 @@
+-- Rollups maintained incrementally by the enrichment pipeline.
+-- Additive metrics are kept in hourly UTC buckets so the API can serve hour, day and week
+-- series; volumes are in token units (amount / 10^decimals).
+
+-- Volume per token, chain and hour
+CREATE TABLE IF NOT EXISTS rollup_token_volume (
+    bucket          TIMESTAMPTZ  NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    token_address   BYTEA        NOT NULL,
+    token_symbol    VARCHAR(16)  NOT NULL,
+    transfer_count  BIGINT       NOT NULL DEFAULT 0,
+    volume          NUMERIC      NOT NULL DEFAULT 0,
+    PRIMARY KEY (bucket, chain_id, token_address)
+);
+
+-- Volume per entity label, chain and hour. volume_in is received by the entity.
+CREATE TABLE IF NOT EXISTS rollup_entity_volume (
+    bucket          TIMESTAMPTZ  NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    entity_label_id INTEGER      NOT NULL REFERENCES entity_labels(id) ON DELETE CASCADE,
+    transfer_count  BIGINT       NOT NULL DEFAULT 0,
+    volume_in       NUMERIC      NOT NULL DEFAULT 0,
+    volume_out      NUMERIC      NOT NULL DEFAULT 0,
+    PRIMARY KEY (bucket, chain_id, entity_label_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rollup_entity_volume_label ON rollup_entity_volume (entity_label_id, bucket);
+
+-- Anomalies per type, chain and hour of the anomalous transfer
+CREATE TABLE IF NOT EXISTS rollup_anomalies (
+    bucket          TIMESTAMPTZ  NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    anomaly_type    VARCHAR(64)  NOT NULL,
+    anomaly_count   BIGINT       NOT NULL DEFAULT 0,
+    PRIMARY KEY (bucket, chain_id, anomaly_type)
+);
+
+-- Unique address counts are not additive, so daily membership is kept to count each
+-- address once per day and to compute weekly uniques.
+CREATE TABLE IF NOT EXISTS rollup_daily_addresses (
+    day             DATE         NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    role            VARCHAR(8)   NOT NULL, -- 'sender' or 'receiver'
+    address         BYTEA        NOT NULL,
+    PRIMARY KEY (day, chain_id, role, address)
+);
+
+CREATE TABLE IF NOT EXISTS rollup_daily_unique_addresses (
+    day             DATE         NOT NULL,
+    chain_id        BIGINT       NOT NULL,
+    senders         BIGINT       NOT NULL DEFAULT 0,
+    receivers       BIGINT       NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, chain_id)
+);
+
+-- Backfill from transfers the enrichment worker has already processed;
+-- later transfers are added by the worker as it reaches them.
+INSERT INTO rollup_token_volume (bucket, chain_id, token_address, token_symbol, transfer_count, volume)
+SELECT date_trunc('hour', t.block_timestamp, 'UTC'), t.chain_id, t.token_address, MAX(t.token_symbol),
+       COUNT(*), SUM(t.amount / power(10::NUMERIC, t.token_decimals))
+FROM transfers t
+JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.last_enriched_transfer_id
+GROUP BY 1, 2, 3;
+
+INSERT INTO rollup_entity_volume (bucket, chain_id, entity_label_id, transfer_count, volume_in, volume_out)
+SELECT date_trunc('hour', t.block_timestamp, 'UTC'), t.chain_id, f.entity_label_id, COUNT(*),
+       COALESCE(SUM(t.amount / power(10::NUMERIC, t.token_decimals)) FILTER (WHERE f.side = 'to'), 0),
+       COALESCE(SUM(t.amount / power(10::NUMERIC, t.token_decimals)) FILTER (WHERE f.side = 'from'), 0)
+FROM transfer_entity_flags f
+JOIN transfers t
+  ON t.id = f.transfer_id AND t.chain_id = f.chain_id AND t.block_timestamp = f.block_timestamp
+JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.last_enriched_transfer_id
+GROUP BY 1, 2, 3;
+
+INSERT INTO rollup_anomalies (bucket, chain_id, anomaly_type, anomaly_count)
+SELECT date_trunc('hour', block_timestamp, 'UTC'), chain_id, anomaly_type, COUNT(*)
+FROM anomalies
+GROUP BY 1, 2, 3;
+
+INSERT INTO rollup_daily_addresses (day, chain_id, role, address)
+SELECT (t.block_timestamp AT TIME ZONE 'UTC')::DATE, t.chain_id, 'sender', t.from_address
+FROM transfers t
+JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.last_enriched_transfer_id
+UNION
+SELECT (t.block_timestamp AT TIME ZONE 'UTC')::DATE, t.chain_id, 'receiver', t.to_address
+FROM transfers t
+JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.last_enriched_transfer_id;
+
+INSERT INTO rollup_daily_unique_addresses (day, chain_id, senders, receivers)
+SELECT day, chain_id, COUNT(*) FILTER (WHERE role = 'sender'), COUNT(*) FILTER (WHERE role = 'receiver')
+FROM rollup_daily_addresses
+GROUP BY 1, 2;
@@ -0,0 +1,9 @@
+-- Parquet export high-water marks: the last row id written per dataset and chain.
+CREATE TABLE IF NOT EXISTS export_state (
+    dataset           VARCHAR(32)  NOT NULL, -- 'transfers', 'defi_events', 'anomalies', 'entity_flags'
+    chain_id          BIGINT       NOT NULL,
+    last_exported_id  BIGINT       NOT NULL DEFAULT 0,
+    rows_exported     BIGINT       NOT NULL DEFAULT 0,
+    updated_at        TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (dataset, chain_id)
+);
@@ -0,0 +1,10 @@
+-- Custom anomaly rule definitions, one row per rule version. A version's definition
+-- never changes once recorded; anomalies reference it via details->>'rule_version'.
+CREATE TABLE IF NOT EXISTS anomaly_rules (
+    name             VARCHAR(64)  NOT NULL,
+    version          INTEGER      NOT NULL,
+    definition       JSONB        NOT NULL,
+    description      TEXT         NOT NULL DEFAULT '',
+    first_loaded_at  TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (name, version)
+);
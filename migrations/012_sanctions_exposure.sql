@@ -0,0 +1,15 @@
+-- Indirect exposure to sanctioned addresses over the wallet graph, recomputed per chain.
+-- Inbound: share of funds received that trace back to a sanctioned address within K hops.
+-- Outbound: share of funds sent that reach a sanctioned address within K hops.
+CREATE TABLE IF NOT EXISTS wallet_sanctions_exposure (
+    address            BYTEA             NOT NULL,
+    chain_id           BIGINT            NOT NULL,
+    inbound_exposure   DOUBLE PRECISION  NOT NULL DEFAULT 0,
+    inbound_hops       SMALLINT,          -- nearest sanctioned source, NULL when none
+    outbound_exposure  DOUBLE PRECISION  NOT NULL DEFAULT 0,
+    outbound_hops      SMALLINT,
+    computed_at        TIMESTAMPTZ       NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (address, chain_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sanctions_exposure_address ON wallet_sanctions_exposure (address);
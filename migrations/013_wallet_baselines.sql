@@ -0,0 +1,16 @@
+-- Rolling per-wallet behaviour, updated incrementally by the enrichment pipeline.
+-- Amount statistics are exponentially weighted over log10 of the token amount.
+CREATE TABLE IF NOT EXISTS wallet_baselines (
+    address            BYTEA             NOT NULL,
+    chain_id           BIGINT            NOT NULL,
+    transfer_count     BIGINT            NOT NULL DEFAULT 0,
+    log_amount_mean    DOUBLE PRECISION  NOT NULL DEFAULT 0,
+    log_amount_var     DOUBLE PRECISION  NOT NULL DEFAULT 0,
+    daily_count_mean   DOUBLE PRECISION  NOT NULL DEFAULT 0,
+    current_day        DATE              NOT NULL,
+    current_day_count  INTEGER           NOT NULL DEFAULT 0,
+    counterparties     JSONB             NOT NULL DEFAULT '{}', -- hex address -> transfer count
+    last_transfer_id   BIGINT            NOT NULL DEFAULT 0,    -- newest transfer folded in
+    updated_at         TIMESTAMPTZ       NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (address, chain_id)
+);
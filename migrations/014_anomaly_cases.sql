@@ -0,0 +1,54 @@
+-- Review workflow for anomalies: lifecycle status, assignee and grouping into cases.
+-- `resolved` is kept in step with the status (false_positive and closed are resolved)
+-- so existing filters and exports keep working.
+ALTER TABLE anomalies ADD COLUMN IF NOT EXISTS status VARCHAR(16) NOT NULL DEFAULT 'open';
+ALTER TABLE anomalies ADD COLUMN IF NOT EXISTS assignee VARCHAR(128);
+ALTER TABLE anomalies ADD COLUMN IF NOT EXISTS case_id BIGINT;
+
+UPDATE anomalies SET status = 'closed' WHERE resolved AND status = 'open';
+
+CREATE INDEX IF NOT EXISTS idx_anomalies_status ON anomalies (status);
+CREATE INDEX IF NOT EXISTS idx_anomalies_case ON anomalies (case_id) WHERE case_id IS NOT NULL;
+
+-- A case groups related anomalies under one investigation
+CREATE TABLE IF NOT EXISTS anomaly_cases (
+    id           BIGSERIAL     PRIMARY KEY,
+    title        VARCHAR(256)  NOT NULL,
+    description  TEXT          NOT NULL DEFAULT '',
+    status       VARCHAR(16)   NOT NULL DEFAULT 'open',
+    assignee     VARCHAR(128),
+    created_by   VARCHAR(128)  NOT NULL,
+    created_at   TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    updated_at   TIMESTAMPTZ   NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_anomaly_cases_status ON anomaly_cases (status);
+
+-- Analyst notes on either an anomaly or a case
+CREATE TABLE IF NOT EXISTS analyst_notes (
+    id          BIGSERIAL     PRIMARY KEY,
+    anomaly_id  BIGINT,
+    case_id     BIGINT        REFERENCES anomaly_cases(id) ON DELETE CASCADE,
+    author      VARCHAR(128)  NOT NULL,
+    body        TEXT          NOT NULL,
+    created_at  TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    CHECK ((anomaly_id IS NULL) <> (case_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_analyst_notes_anomaly ON analyst_notes (anomaly_id) WHERE anomaly_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_analyst_notes_case ON analyst_notes (case_id) WHERE case_id IS NOT NULL;
+
+-- Every workflow change, oldest first per subject
+CREATE TABLE IF NOT EXISTS review_audit_log (
+    id          BIGSERIAL     PRIMARY KEY,
+    anomaly_id  BIGINT,
+    case_id     BIGINT,
+    actor       VARCHAR(128)  NOT NULL,
+    action      VARCHAR(32)   NOT NULL, -- 'status', 'assignee', 'case', 'note', 'created', 'title'
+    old_value   TEXT,
+    new_value   TEXT,
+    created_at  TIMESTAMPTZ   NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_review_audit_anomaly ON review_audit_log (anomaly_id, id) WHERE anomaly_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_review_audit_case ON review_audit_log (case_id, id) WHERE case_id IS NOT NULL;
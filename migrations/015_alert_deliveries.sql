@@ -0,0 +1,25 @@
+-- Outbound alert delivery log. Each row is one payload for one sink: a single anomaly
+-- ('alert') or a day's held alerts ('digest'). Rows are kept after delivery as the audit log.
+CREATE TABLE IF NOT EXISTS alert_deliveries (
+    id                BIGSERIAL     PRIMARY KEY,
+    sink              VARCHAR(64)   NOT NULL,
+    kind              VARCHAR(8)    NOT NULL DEFAULT 'alert',    -- 'alert' or 'digest'
+    anomaly_id        BIGINT,                                    -- alerts only
+    risk_score        REAL,
+    payload           JSONB         NOT NULL,
+    status            VARCHAR(16)   NOT NULL DEFAULT 'pending',  -- 'pending', 'delivered', 'failed', 'held', 'digested'
+    attempts          INTEGER       NOT NULL DEFAULT 0,
+    next_attempt_at   TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    last_status_code  INTEGER,
+    last_error        TEXT,
+    digest_id         BIGINT,                                    -- digest that carried a held alert
+    created_at        TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    delivered_at      TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_alert_deliveries_anomaly
+    ON alert_deliveries (sink, anomaly_id) WHERE kind = 'alert';
+CREATE INDEX IF NOT EXISTS idx_alert_deliveries_due
+    ON alert_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_alert_deliveries_held
+    ON alert_deliveries (sink, created_at) WHERE status = 'held';
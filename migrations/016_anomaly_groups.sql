@@ -0,0 +1,30 @@
+-- Repeated anomalies of one type on one address, grouped by time window. A group carries
+-- a running count and the latest evidence so analysts and alert sinks see one entry
+-- instead of one per transfer.
+CREATE TABLE IF NOT EXISTS anomaly_groups (
+    id                 BIGSERIAL     PRIMARY KEY,
+    chain_id           BIGINT        NOT NULL,
+    address            BYTEA         NOT NULL,
+    anomaly_type       VARCHAR(64)   NOT NULL,
+    first_seen         TIMESTAMPTZ   NOT NULL,
+    last_seen          TIMESTAMPTZ   NOT NULL,
+    anomaly_count      INTEGER       NOT NULL DEFAULT 0,
+    max_risk_score     REAL          NOT NULL DEFAULT 0,
+    latest_anomaly_id  BIGINT,
+    latest_risk_score  REAL,
+    latest_flags       TEXT[]        NOT NULL DEFAULT '{}',
+    latest_details     JSONB         NOT NULL DEFAULT '{}',
+    updated_at         TIMESTAMPTZ   NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_anomaly_groups_key
+    ON anomaly_groups (chain_id, address, anomaly_type, last_seen DESC);
+CREATE INDEX IF NOT EXISTS idx_anomaly_groups_last_seen ON anomaly_groups (last_seen DESC);
+
+ALTER TABLE anomalies ADD COLUMN IF NOT EXISTS group_id BIGINT;
+CREATE INDEX IF NOT EXISTS idx_anomalies_group ON anomalies (group_id) WHERE group_id IS NOT NULL;
+
+-- Alerts for a group share its id so cooldowns can be checked per sink
+ALTER TABLE alert_deliveries ADD COLUMN IF NOT EXISTS group_id BIGINT;
+CREATE INDEX IF NOT EXISTS idx_alert_deliveries_group
+    ON alert_deliveries (sink, group_id, created_at) WHERE kind = 'alert' AND group_id IS NOT NULL;
@@ -0,0 +1,19 @@
+-- Per-address risk score on one chain, combined from decayed anomalies, sanctions exposure,
+-- entity labels, counterparty labels and wallet age. Each factor is between 0 and 1;
+-- `breakdown` keeps the inputs behind them. Scores are as of `computed_at`.
+CREATE TABLE IF NOT EXISTS wallet_risk_scores (
+    address              BYTEA         NOT NULL,
+    chain_id             BIGINT        NOT NULL,
+    score                REAL          NOT NULL,   -- 0 to 100
+    anomaly_factor       REAL          NOT NULL DEFAULT 0,
+    exposure_factor      REAL          NOT NULL DEFAULT 0,
+    label_factor         REAL          NOT NULL DEFAULT 0,
+    counterparty_factor  REAL          NOT NULL DEFAULT 0,
+    age_factor           REAL          NOT NULL DEFAULT 0,
+    breakdown            JSONB         NOT NULL DEFAULT '{}',
+    computed_at          TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (address, chain_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_wallet_risk_scores_score ON wallet_risk_scores (chain_id, score DESC);
+CREATE INDEX IF NOT EXISTS idx_wallet_risk_scores_computed ON wallet_risk_scores (computed_at);
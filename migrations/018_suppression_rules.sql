@@ -0,0 +1,45 @@
+-- Suppression rules for known benign activity. A rule matches anomalies whose address,
+-- entity or entity type matches, optionally narrowed to a chain and anomaly types.
+-- Unset scope columns match anything. Rules from config carry a unique name and are
+-- re-synced at startup; rules from the API are removed by disabling them.
+CREATE TABLE IF NOT EXISTS suppression_rules (
+    id             BIGSERIAL     PRIMARY KEY,
+    name           VARCHAR(128),                              -- config rules only
+    source         VARCHAR(16)   NOT NULL DEFAULT 'api',      -- 'api' or 'config'
+    address        BYTEA,
+    entity_name    VARCHAR(256),
+    entity_type    VARCHAR(64),
+    chain_id       BIGINT,
+    anomaly_types  TEXT[]        NOT NULL DEFAULT '{}',       -- empty matches every type
+    reason         TEXT          NOT NULL,
+    expires_at     TIMESTAMPTZ,
+    created_by     VARCHAR(128)  NOT NULL,
+    created_at     TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    updated_at     TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    disabled_at    TIMESTAMPTZ,
+    disabled_by    VARCHAR(128)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_suppression_rules_config_name
+    ON suppression_rules (name) WHERE source = 'config';
+CREATE INDEX IF NOT EXISTS idx_suppression_rules_active
+    ON suppression_rules (expires_at) WHERE disabled_at IS NULL;
+
+-- Anomalies a rule held back, kept whole so suppression can be audited and reversed
+CREATE TABLE IF NOT EXISTS suppressed_anomalies (
+    id               BIGSERIAL     PRIMARY KEY,
+    rule_id          BIGINT        NOT NULL REFERENCES suppression_rules (id),
+    transfer_id      BIGINT,
+    chain_id         BIGINT        NOT NULL,
+    block_timestamp  TIMESTAMPTZ   NOT NULL,
+    anomaly_type     VARCHAR(64)   NOT NULL,
+    risk_score       REAL          NOT NULL,
+    address          BYTEA,
+    flags            TEXT[]        NOT NULL DEFAULT '{}',
+    details          JSONB         NOT NULL DEFAULT '{}',
+    suppressed_at    TIMESTAMPTZ   NOT NULL DEFAULT NOW(),
+    UNIQUE (transfer_id, anomaly_type, chain_id, block_timestamp)
+);
+
+CREATE INDEX IF NOT EXISTS idx_suppressed_anomalies_rule
+    ON suppressed_anomalies (rule_id, suppressed_at DESC);
@@ -0,0 +1,26 @@
+-- USD value of each transfer, set by the pricing stage of enrichment. NULL until priced,
+-- or when neither a DEX price nor an FX rate for the token's currency is known.
+ALTER TABLE transfers ADD COLUMN IF NOT EXISTS amount_usd NUMERIC;
+
+-- USD per unit of a registry currency, from `valid_from` until the next rate.
+-- Loaded from the configured rates file or written here directly.
+CREATE TABLE IF NOT EXISTS fx_rates (
+    currency    VARCHAR(6)    NOT NULL REFERENCES fiat_currencies (code),
+    valid_from  TIMESTAMPTZ   NOT NULL,
+    usd_rate    NUMERIC       NOT NULL,
+    source      VARCHAR(32)   NOT NULL DEFAULT 'file',
+    PRIMARY KEY (currency, valid_from)
+);
+
+-- Hourly token prices observed on DEX swaps against a reference token. The price is
+-- usd_volume / token_volume. Buckets are recomputed from `defi_events` swaps, so
+-- rewriting one is idempotent.
+CREATE TABLE IF NOT EXISTS dex_token_prices (
+    chain_id       BIGINT       NOT NULL,
+    token_address  BYTEA        NOT NULL,
+    bucket         TIMESTAMPTZ  NOT NULL,
+    token_volume   NUMERIC      NOT NULL DEFAULT 0,
+    usd_volume     NUMERIC      NOT NULL DEFAULT 0,
+    swap_count     INT          NOT NULL DEFAULT 0,
+    PRIMARY KEY (chain_id, token_address, bucket)
+);
@@ -0,0 +1,23 @@
+-- Implied USD price of each pegged token per window of `window_blocks` blocks, from swaps
+-- against other pegged tokens in DEX pairs and Curve pools. The price is
+-- usd_volume / token_volume. Windows are recomputed from `defi_events` swaps, so
+-- rewriting one is idempotent.
+CREATE TABLE IF NOT EXISTS stablecoin_prices (
+    chain_id       BIGINT        NOT NULL,
+    token_address  BYTEA         NOT NULL,
+    token_symbol   VARCHAR(16)   NOT NULL,
+    window_start   BIGINT        NOT NULL,
+    window_blocks  INT           NOT NULL,
+    started_at     TIMESTAMPTZ   NOT NULL,
+    ended_at       TIMESTAMPTZ   NOT NULL,
+    price_usd      NUMERIC       NOT NULL,
+    -- FX rate of the token's currency at `ended_at`, NULL when no rate is known
+    peg_usd        NUMERIC,
+    token_volume   NUMERIC       NOT NULL,
+    usd_volume     NUMERIC       NOT NULL,
+    swap_count     INT           NOT NULL,
+    PRIMARY KEY (chain_id, token_address, window_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stablecoin_prices_started_at
+    ON stablecoin_prices (chain_id, token_symbol, started_at);
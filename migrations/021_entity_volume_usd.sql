@@ -0,0 +1,14 @@
+-- Entity volumes move to USD, as one entity receives and sends several tokens. Unpriced
+-- transfers add to the transfer count but not to the volume; historical rows can be valued
+-- afterwards with `reenrich --stages prices`.
+DELETE FROM rollup_entity_volume;
+
+INSERT INTO rollup_entity_volume (bucket, chain_id, entity_label_id, transfer_count, volume_in, volume_out)
+SELECT date_trunc('hour', t.block_timestamp, 'UTC'), t.chain_id, f.entity_label_id, COUNT(*),
+       COALESCE(SUM(t.amount_usd) FILTER (WHERE f.side = 'to'), 0),
+       COALESCE(SUM(t.amount_usd) FILTER (WHERE f.side = 'from'), 0)
+FROM transfer_entity_flags f
+JOIN transfers t
+  ON t.id = f.transfer_id AND t.chain_id = f.chain_id AND t.block_timestamp = f.block_timestamp
+JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.rollups_transfer_id
+GROUP BY 1, 2, 3;
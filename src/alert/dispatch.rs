@@ -0,0 +1,421 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::anomaly::rules::{raw_to_human, usd_amount};
+use crate::anomaly::types::AnomalyRecord;
+use crate::config::{AlertMode, AlertingConfig};
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+use super::routing::AlertRouter;
+use super::webhook::{self, WebhookSink};
+
+/// Deliveries sent per poll.
+const DELIVERY_BATCH: i64 = 100;
+
+/// Queue just-persisted anomalies for their routed sinks. Immediate routes are queued
+/// for delivery; digest routes are held for the sink's next digest. An anomaly is queued
+/// at most once per sink, so replayed batches do not alert twice.
+///
+/// Grouped anomalies alert once per group: the latest member of a batch is queued with
+/// the group's running count, an alert still waiting to go out is refreshed with the
+/// newer evidence instead, and nothing is queued while the sink's cooldown for the group
+/// is running. Returns the number of deliveries queued.
+pub async fn enqueue(
+    pool: &PgPool,
+    router: &AlertRouter,
+    anomalies: &[AnomalyRecord],
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+) -> eyre::Result<u64> {
+    if router.is_empty() || anomalies.is_empty() {
+        return Ok(0);
+    }
+
+    let by_id: HashMap<i64, &StablecoinTransfer> = transfers
+        .iter()
+        .filter_map(|t| t.id.map(|id| (id, t)))
+        .collect();
+
+    let mut sinks = Vec::new();
+    let mut statuses = Vec::new();
+    let mut cooldowns = Vec::new();
+    let mut transfer_ids = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut timestamps = Vec::new();
+    let mut anomaly_types = Vec::new();
+    let mut payloads = Vec::new();
+    for anomaly in anomalies {
+        let Some(transfer_id) = anomaly.transfer_id else {
+            continue;
+        };
+        let transfer = by_id.get(&transfer_id).copied();
+        let routed = router.route(anomaly, transfer, label_store);
+        if routed.is_empty() {
+            continue;
+        }
+
+        let payload = alert_payload(anomaly, transfer, label_store);
+        for (sink, mode, cooldown_secs) in routed {
+            sinks.push(sink);
+            statuses.push(match mode {
+                AlertMode::Immediate => "pending",
+                AlertMode::Digest => "held",
+            });
+            cooldowns.push(cooldown_secs as i64);
+            transfer_ids.push(transfer_id);
+            chain_ids.push(anomaly.chain_id);
+            timestamps.push(anomaly.block_timestamp);
+            anomaly_types.push(anomaly.anomaly_type.as_str());
+            payloads.push(payload.clone());
+        }
+    }
+    if sinks.is_empty() {
+        return Ok(0);
+    }
+
+    // Data-modifying CTEs share one snapshot, so the insert checks `refreshed` itself
+    // rather than seeing the refreshed rows in alert_deliveries.
+    let result = sqlx::query(
+        "WITH latest AS (
+             SELECT DISTINCT ON (i.sink, COALESCE(a.group_id, -a.id))
+                    i.sink, i.status, i.cooldown_secs, a.id AS anomaly_id, a.group_id,
+                    a.risk_score,
+                    i.payload || jsonb_build_object('anomaly_id', a.id)
+                      || CASE WHEN g.id IS NULL THEN '{}'::JSONB
+                         ELSE jsonb_build_object('group', jsonb_build_object(
+                             'id', g.id, 'count', g.anomaly_count, 'first_seen', g.first_seen,
+                             'last_seen', g.last_seen, 'max_risk_score', g.max_risk_score))
+                         END AS payload
+             FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::BIGINT[], $5::BIGINT[],
+                         $6::TIMESTAMPTZ[], $7::TEXT[], $8::JSONB[])
+                  AS i(sink, status, cooldown_secs, transfer_id, chain_id, block_timestamp,
+                       anomaly_type, payload)
+             JOIN anomalies a
+               ON a.transfer_id = i.transfer_id AND a.chain_id = i.chain_id
+              AND a.block_timestamp = i.block_timestamp AND a.anomaly_type = i.anomaly_type
+             LEFT JOIN anomaly_groups g ON g.id = a.group_id
+             ORDER BY i.sink, COALESCE(a.group_id, -a.id), a.block_timestamp DESC, a.id DESC
+         ), refreshed AS (
+             UPDATE alert_deliveries d
+             SET anomaly_id = l.anomaly_id, payload = l.payload,
+                 risk_score = GREATEST(d.risk_score, l.risk_score)
+             FROM latest l
+             WHERE l.group_id IS NOT NULL AND d.kind = 'alert' AND d.sink = l.sink
+               AND d.group_id = l.group_id AND d.status IN ('pending', 'held')
+               AND d.attempts = 0 AND d.anomaly_id < l.anomaly_id
+             RETURNING d.sink, d.group_id
+         )
+         INSERT INTO alert_deliveries (sink, kind, anomaly_id, group_id, risk_score, payload, status)
+         SELECT l.sink, 'alert', l.anomaly_id, l.group_id, l.risk_score, l.payload, l.status
+         FROM latest l
+         WHERE NOT EXISTS (
+                 SELECT 1 FROM refreshed r WHERE r.sink = l.sink AND r.group_id = l.group_id)
+           AND NOT EXISTS (
+                 SELECT 1 FROM alert_deliveries d
+                 WHERE d.kind = 'alert' AND d.sink = l.sink AND d.group_id = l.group_id
+                   AND (d.anomaly_id >= l.anomaly_id
+                        OR d.created_at > NOW() - make_interval(secs => l.cooldown_secs)
+                        OR (d.status IN ('pending', 'held') AND d.attempts = 0)))
+         ON CONFLICT (sink, anomaly_id) WHERE kind = 'alert' DO NOTHING",
+    )
+    .bind(&sinks)
+    .bind(&statuses)
+    .bind(&cooldowns)
+    .bind(&transfer_ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&anomaly_types)
+    .bind(&payloads)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// JSON body for one anomaly. `anomaly_id` is added when the delivery is queued.
+fn alert_payload(
+    anomaly: &AnomalyRecord,
+    transfer: Option<&StablecoinTransfer>,
+    label_store: &EntityLabelStore,
+) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "event": "anomaly",
+        "anomaly_type": anomaly.anomaly_type.as_str(),
+        "chain_id": anomaly.chain_id,
+        "risk_score": anomaly.risk_score,
+        "flags": anomaly.flags,
+        "details": anomaly.details,
+        "address": anomaly.address.as_ref().map(|a| format!("0x{}", hex::encode(a))),
+        "transfer_id": anomaly.transfer_id,
+        "block_timestamp": anomaly.block_timestamp,
+    });
+
+    if let Some(t) = transfer {
+        let entities: Vec<serde_json::Value> = [("from", &t.from_address), ("to", &t.to_address)]
+            .into_iter()
+            .flat_map(|(side, address)| {
+                label_store.lookup(address).unwrap_or_default().iter().map(move |label| {
+                    serde_json::json!({
+                        "side": side,
+                        "name": label.entity_name,
+                        "type": label.entity_type,
+                    })
+                })
+            })
+            .collect();
+        payload["transfer"] = serde_json::json!({
+            "tx_hash": format!("0x{}", hex::encode(&t.tx_hash)),
+            "log_index": t.log_index,
+            "block_number": t.block_number,
+            "from": format!("0x{}", hex::encode(&t.from_address)),
+            "to": format!("0x{}", hex::encode(&t.to_address)),
+            "token": t.token_symbol,
+            "amount": raw_to_human(&t.amount, t.token_decimals),
+            "amount_usd": t.amount_usd.as_ref().map(|_| usd_amount(t)),
+            "entities": entities,
+        });
+    }
+
+    payload
+}
+
+/// Delay before retrying a delivery that has failed `attempts` times.
+pub fn backoff(config: &AlertingConfig, attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    let secs = config
+        .initial_backoff_secs
+        .saturating_mul(1u64 << doublings)
+        .min(config.max_backoff_secs);
+    Duration::from_secs(secs)
+}
+
+/// Start of the digest period that has most recently closed: today at `digest_hour_utc`
+/// once that has passed, otherwise yesterday at that hour.
+pub fn digest_cutoff(now: DateTime<Utc>, digest_hour_utc: u32) -> DateTime<Utc> {
+    let today = now
+        .date_naive()
+        .and_hms_opt(digest_hour_utc, 0, 0)
+        .expect("digest hour is validated")
+        .and_utc();
+    if now >= today {
+        today
+    } else {
+        today - ChronoDuration::days(1)
+    }
+}
+
+/// Sends queued deliveries to their webhooks and rolls held alerts into daily digests.
+pub struct AlertDispatcher {
+    config: AlertingConfig,
+    client: reqwest::Client,
+    sinks: HashMap<String, WebhookSink>,
+}
+
+impl AlertDispatcher {
+    pub fn new(config: AlertingConfig) -> eyre::Result<Self> {
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|sink| Ok((sink.name.clone(), WebhookSink::from_config(sink)?)))
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self {
+            client: webhook::client(config.request_timeout_secs)?,
+            config,
+            sinks,
+        })
+    }
+
+    /// Turn each sink's alerts held before the last digest cutoff into one digest delivery.
+    /// Returns the number of digests queued.
+    pub async fn queue_digests(&self, pool: &PgPool, now: DateTime<Utc>) -> eyre::Result<u64> {
+        let cutoff = digest_cutoff(now, self.config.digest_hour_utc);
+        let sinks: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT sink FROM alert_deliveries WHERE status = 'held' AND created_at < $1",
+        )
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await?;
+
+        let mut queued = 0;
+        for sink in sinks {
+            let mut tx = pool.begin().await?;
+            let held: Vec<(i64, Option<f32>, serde_json::Value)> = sqlx::query_as(
+                "SELECT id, risk_score, payload FROM alert_deliveries
+                 WHERE sink = $1 AND status = 'held' AND created_at < $2
+                 ORDER BY id
+                 FOR UPDATE",
+            )
+            .bind(&sink)
+            .bind(cutoff)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let ids: Vec<i64> = held.iter().map(|(id, _, _)| *id).collect();
+            let max_risk = held.iter().filter_map(|(_, risk, _)| *risk).fold(0.0f32, f32::max);
+            let payload = serde_json::json!({
+                "event": "digest",
+                "sink": sink,
+                "period_end": cutoff,
+                "count": held.len(),
+                "max_risk_score": max_risk,
+                "alerts": held.into_iter().map(|(_, _, payload)| payload).collect::<Vec<_>>(),
+            });
+
+            let (digest_id,): (i64,) = sqlx::query_as(
+                "INSERT INTO alert_deliveries (sink, kind, risk_score, payload)
+                 VALUES ($1, 'digest', $2, $3) RETURNING id",
+            )
+            .bind(&sink)
+            .bind(max_risk)
+            .bind(&payload)
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE alert_deliveries SET status = 'digested', digest_id = $2
+                 WHERE id = ANY($1)",
+            )
+            .bind(&ids)
+            .bind(digest_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            tracing::info!(sink = %sink, alerts = ids.len(), digest_id, "Alert digest queued");
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// Attempt every delivery that is due, in batches. Failures are rescheduled with
+    /// exponential backoff until `max_attempts`, then marked failed.
+    /// Returns the number delivered.
+    pub async fn deliver_due(&self, pool: &PgPool) -> eyre::Result<u64> {
+        let mut delivered = 0;
+        loop {
+            let due: Vec<(i64, String, serde_json::Value, i32)> = sqlx::query_as(
+                "SELECT id, sink, payload, attempts FROM alert_deliveries
+                 WHERE status = 'pending' AND next_attempt_at <= NOW()
+                 ORDER BY next_attempt_at, id
+                 LIMIT $1",
+            )
+            .bind(DELIVERY_BATCH)
+            .fetch_all(pool)
+            .await?;
+            if due.is_empty() {
+                break;
+            }
+
+            for (id, sink_name, payload, attempts) in due {
+                let attempts = attempts + 1;
+                let outcome = match self.sinks.get(&sink_name) {
+                    Some(sink) => {
+                        webhook::post(&self.client, sink, id, serde_json::to_vec(&payload)?).await
+                    }
+                    None => Err(eyre::eyre!("sink '{}' is not configured", sink_name)),
+                };
+
+                match outcome {
+                    Ok(code) if (200..300).contains(&code) => {
+                        sqlx::query(
+                            "UPDATE alert_deliveries
+                             SET status = 'delivered', attempts = $2, last_status_code = $3,
+                                 last_error = NULL, delivered_at = NOW()
+                             WHERE id = $1",
+                        )
+                        .bind(id)
+                        .bind(attempts)
+                        .bind(code as i32)
+                        .execute(pool)
+                        .await?;
+                        delivered += 1;
+                    }
+                    outcome => {
+                        let (code, error) = match outcome {
+                            Ok(code) => (Some(code as i32), format!("HTTP {}", code)),
+                            Err(e) => (None, e.to_string()),
+                        };
+                        let exhausted = attempts >= self.config.max_attempts;
+                        let retry_in = backoff(&self.config, attempts).as_secs() as i64;
+                        sqlx::query(
+                            "UPDATE alert_deliveries
+                             SET status = $2, attempts = $3, last_status_code = $4, last_error = $5,
+                                 next_attempt_at = NOW() + make_interval(secs => $6)
+                             WHERE id = $1",
+                        )
+                        .bind(id)
+                        .bind(if exhausted { "failed" } else { "pending" })
+                        .bind(attempts)
+                        .bind(code)
+                        .bind(&error)
+                        .bind(retry_in as f64)
+                        .execute(pool)
+                        .await?;
+                        tracing::warn!(
+                            delivery_id = id,
+                            sink = %sink_name,
+                            attempts,
+                            exhausted,
+                            error = %error,
+                            "Alert delivery failed"
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+}
+
+/// Background task: queue digests when due and deliver pending alerts every `poll_interval_secs`.
+pub async fn run_alert_dispatcher(
+    dispatcher: AlertDispatcher,
+    pool: PgPool,
+    shutdown: CancellationToken,
+) -> eyre::Result<()> {
+    let interval = Duration::from_secs(dispatcher.config.poll_interval_secs);
+    tracing::info!(sinks = dispatcher.sinks.len(), "Starting alert dispatcher");
+
+    while !shutdown.is_cancelled() {
+        if let Err(e) = dispatcher.queue_digests(&pool, Utc::now()).await {
+            tracing::error!(error = %e, "Alert digest failed");
+        }
+        match dispatcher.deliver_due(&pool).await {
+            Ok(0) => {}
+            Ok(delivered) => tracing::info!(delivered, "Alerts delivered"),
+            Err(e) => tracing::error!(error = %e, "Alert delivery failed"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    tracing::info!("Alert dispatcher stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_backoff_and_digest_cutoff() {
+        let config = AlertingConfig::default();
+        assert_eq!(backoff(&config, 1).as_secs(), 10);
+        assert_eq!(backoff(&config, 4).as_secs(), 80);
+        assert_eq!(backoff(&config, 40).as_secs(), 3600);
+
+        let before = Utc.with_ymd_and_hms(2024, 3, 2, 7, 59, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2024, 3, 2, 8, 0, 0).unwrap();
+        assert_eq!(digest_cutoff(before, 8), Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap());
+        assert_eq!(digest_cutoff(after, 8), after);
+    }
+}
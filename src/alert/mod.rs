@@ -0,0 +1,3 @@
+pub mod dispatch;
+pub mod routing;
+pub mod webhook;
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::anomaly::types::AnomalyRecord;
+use crate::config::{AlertMode, AlertingConfig, ChainConfig};
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+/// A route with chain names resolved to ids and entity names lowercased.
+#[derive(Debug)]
+struct Route {
+    sink: String,
+    mode: AlertMode,
+    anomaly_types: HashSet<String>,
+    min_risk: f32,
+    chain_ids: HashSet<i64>,
+    entities: HashSet<String>,
+    cooldown_secs: u64,
+}
+
+/// Decides which sinks receive an anomaly, and how.
+#[derive(Debug)]
+pub struct AlertRouter {
+    routes: Vec<Route>,
+}
+
+impl AlertRouter {
+    /// Resolve the configured routes. A router built from a disabled config routes nothing.
+    pub fn new(config: &AlertingConfig, chains: &[ChainConfig]) -> Self {
+        if !config.enabled {
+            return Self { routes: Vec::new() };
+        }
+        let routes = config
+            .routes
+            .iter()
+            .map(|route| Route {
+                sink: route.sink.clone(),
+                mode: route.mode,
+                anomaly_types: route.anomaly_types.iter().cloned().collect(),
+                min_risk: route.min_risk,
+                chain_ids: chains
+                    .iter()
+                    .filter(|c| route.chains.contains(&c.name))
+                    .map(|c| c.chain_id as i64)
+                    .collect(),
+                entities: route.entities.iter().map(|e| e.to_lowercase()).collect(),
+                cooldown_secs: route.cooldown_secs.unwrap_or(config.cooldown_secs),
+            })
+            .collect();
+        Self { routes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Sinks the anomaly goes to, each once, with the cooldown between notifications for
+    /// the same anomaly group. When several routes reach the same sink, immediate delivery
+    /// wins over the digest and the shortest cooldown applies.
+    pub fn route(
+        &self,
+        anomaly: &AnomalyRecord,
+        transfer: Option<&StablecoinTransfer>,
+        label_store: &EntityLabelStore,
+    ) -> Vec<(&str, AlertMode, u64)> {
+        let mut sinks: HashMap<&str, (AlertMode, u64)> = HashMap::new();
+        for route in &self.routes {
+            if !route.matches(anomaly, transfer, label_store) {
+                continue;
+            }
+            let (mode, cooldown) = sinks
+                .entry(route.sink.as_str())
+                .or_insert((route.mode, route.cooldown_secs));
+            if route.mode == AlertMode::Immediate {
+                *mode = AlertMode::Immediate;
+            }
+            *cooldown = (*cooldown).min(route.cooldown_secs);
+        }
+
+        let mut sinks: Vec<(&str, AlertMode, u64)> = sinks
+            .into_iter()
+            .map(|(sink, (mode, cooldown))| (sink, mode, cooldown))
+            .collect();
+        sinks.sort_by_key(|(sink, _, _)| *sink);
+        sinks
+    }
+}
+
+impl Route {
+    fn matches(
+        &self,
+        anomaly: &AnomalyRecord,
+        transfer: Option<&StablecoinTransfer>,
+        label_store: &EntityLabelStore,
+    ) -> bool {
+        if anomaly.risk_score < self.min_risk {
+            return false;
+        }
+        if !self.anomaly_types.is_empty() && !self.anomaly_types.contains(anomaly.anomaly_type.as_str()) {
+            return false;
+        }
+        if !self.chain_ids.is_empty() && !self.chain_ids.contains(&anomaly.chain_id) {
+            return false;
+        }
+        if self.entities.is_empty() {
+            return true;
+        }
+
+        let Some(transfer) = transfer else {
+            return false;
+        };
+        [&transfer.from_address, &transfer.to_address]
+            .into_iter()
+            .filter_map(|address| label_store.lookup(address))
+            .flatten()
+            .any(|label| {
+                self.entities.contains(&label.entity_name.to_lowercase())
+                    || self.entities.contains(&label.entity_type.to_lowercase())
+            })
+    }
+}
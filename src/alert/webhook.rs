@@ -0,0 +1,102 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+use crate::config::WebhookSinkConfig;
+
+/// Header carrying `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`.
+pub const SIGNATURE_HEADER: &str = "X-Chainwatch-Signature";
+/// Header carrying the delivery id, stable across retries so receivers can deduplicate.
+pub const DELIVERY_HEADER: &str = "X-Chainwatch-Delivery";
+
+/// A configured webhook with its secret resolved from the environment.
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    pub name: String,
+    pub url: String,
+    secret: String,
+}
+
+impl WebhookSink {
+    pub fn from_config(config: &WebhookSinkConfig) -> eyre::Result<Self> {
+        let secret = std::env::var(&config.secret_env).map_err(|_| {
+            eyre::eyre!(
+                "alerting sink '{}': environment variable {} is not set",
+                config.name,
+                config.secret_env
+            )
+        })?;
+        if secret.is_empty() {
+            return Err(eyre::eyre!(
+                "alerting sink '{}': environment variable {} is empty",
+                config.name,
+                config.secret_env
+            ));
+        }
+        Ok(Self {
+            name: config.name.clone(),
+            url: config.url.clone(),
+            secret,
+        })
+    }
+
+    /// Signature header value for a body sent at `timestamp`.
+    pub fn signature(&self, timestamp: i64, body: &[u8]) -> String {
+        format!("t={},v1={}", timestamp, sign(self.secret.as_bytes(), timestamp, body))
+    }
+}
+
+/// Hex HMAC-SHA256 of `"<timestamp>.<body>"`. Binding the timestamp lets receivers
+/// reject replays of old deliveries.
+pub fn sign(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn client(timeout_secs: u64) -> eyre::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()?)
+}
+
+/// POST a signed body. Returns the HTTP status; transport errors are returned as errors.
+pub async fn post(
+    client: &reqwest::Client,
+    sink: &WebhookSink,
+    delivery_id: i64,
+    body: Vec<u8>,
+) -> eyre::Result<u16> {
+    let timestamp = chrono::Utc::now().timestamp();
+    let response = client
+        .post(&sink.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sink.signature(timestamp, &body))
+        .header(DELIVERY_HEADER, delivery_id.to_string())
+        .body(body)
+        .send()
+        .await?;
+    Ok(response.status().as_u16())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let body = br#"{"anomaly_id":1}"#;
+        let signature = sign(b"secret", 1700000000, body);
+
+        // HMAC-SHA256 of `1700000000.{"anomaly_id":1}` under "secret"
+        assert_eq!(
+            signature,
+            "c5594e9e8553eaf70117e5e976255b1113b44b173c3bd7562ba080aee45227ac"
+        );
+
+        assert_ne!(signature, sign(b"secret", 1700000001, body));
+        assert_ne!(signature, sign(b"other", 1700000000, body));
+    }
+}
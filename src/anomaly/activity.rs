@@ -0,0 +1,384 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+use crate::config::AnomalyDetectionConfig;
+use crate::indexer::types::StablecoinTransfer;
+
+/// Orders a wallet's transfers by time; block and log index keep each one distinct so
+/// recording a transfer twice counts it once.
+type ActivityKey = (DateTime<Utc>, i64, i32);
+
+#[derive(Debug, Default)]
+struct AddressActivity {
+    sent: BTreeSet<ActivityKey>,
+    received: BTreeSet<ActivityKey>,
+}
+
+/// Time spans `(start, end]`, sorted and disjoint.
+type Spans = Vec<(DateTime<Utc>, DateTime<Utc>)>;
+
+/// Recent transfers per (address, chain), kept in memory so the velocity and cross-chain
+/// rules need no query per transfer.
+///
+/// Each batch is covered with one query over every chain's stored transfers in the parts of
+/// its window not loaded yet, so a chain whose worker lags behind still counts towards
+/// cross-chain activity. Entries older than the longest rule window behind each chain's
+/// latest batch are dropped.
+#[derive(Debug)]
+pub struct ActivityWindows {
+    retention: Duration,
+    wallets: HashMap<(Vec<u8>, i64), AddressActivity>,
+    /// Chains with any entry, for the cross-chain count.
+    chains: BTreeSet<i64>,
+    /// Latest timestamp of each chain's most recent batch, pruning starts from here.
+    latest: HashMap<i64, DateTime<Utc>>,
+    swept_at: HashMap<i64, DateTime<Utc>>,
+    /// Spans for which every chain's stored transfers are in memory. A sweep drops a
+    /// chain's entries up to its own horizon, so spans end up starting past the latest
+    /// horizon of any chain and a lagging chain's window is loaded again.
+    loaded: Spans,
+}
+
+impl ActivityWindows {
+    pub fn new(config: &AnomalyDetectionConfig) -> Self {
+        let retention_secs = config
+            .velocity
+            .window_secs
+            .max(config.cross_chain.window_secs);
+        Self {
+            retention: Duration::seconds(retention_secs as i64),
+            wallets: HashMap::new(),
+            chains: BTreeSet::new(),
+            latest: HashMap::new(),
+            swept_at: HashMap::new(),
+            loaded: Vec::new(),
+        }
+    }
+
+    /// Warm the windows with each chain's transfers leading up to its enrichment checkpoint.
+    /// The window ends at the latest transfer still stored at or below the checkpoint, as a
+    /// reorg or replay may have removed the checkpoint's own transfer.
+    pub async fn load_from_db(
+        pool: &PgPool,
+        config: &AnomalyDetectionConfig,
+    ) -> eyre::Result<Self> {
+        let mut windows = Self::new(config);
+        let rows: Vec<TransferActivity> = sqlx::query_as(
+            "SELECT t.chain_id, t.from_address, t.to_address, t.block_timestamp,
+                    t.block_number, t.log_index
+             FROM enrichment_state e
+             CROSS JOIN LATERAL (
+                 SELECT block_timestamp FROM transfers
+                 WHERE chain_id = e.chain_id AND id <= e.last_enriched_transfer_id
+                 ORDER BY id DESC
+                 LIMIT 1
+             ) c
+             JOIN transfers t ON t.chain_id = e.chain_id
+                AND t.id <= e.last_enriched_transfer_id
+                AND t.block_timestamp > c.block_timestamp - make_interval(secs => $1)
+                AND t.block_timestamp <= c.block_timestamp",
+        )
+        .bind(windows.retention.num_seconds() as f64)
+        .fetch_all(pool)
+        .await?;
+
+        for (chain_id, .., timestamp, _, _) in &rows {
+            let latest = windows.latest.entry(*chain_id).or_insert(*timestamp);
+            *latest = (*latest).max(*timestamp);
+        }
+        windows.insert_rows(rows);
+        tracing::info!(wallets = windows.wallets.len(), "Loaded activity windows");
+        Ok(windows)
+    }
+
+    /// Load every stored transfer on any chain in the window leading up to `transfers`.
+    /// Only the parts of the window not loaded yet are read, so moving forward through
+    /// time reads each transfer once, while a chain behind the others or a replay from an
+    /// earlier block reads what the others' sweeps dropped.
+    pub async fn cover(
+        &mut self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<()> {
+        let Some(first) = transfers.iter().map(|t| t.block_timestamp).min() else {
+            return Ok(());
+        };
+        let last = transfers
+            .iter()
+            .map(|t| t.block_timestamp)
+            .max()
+            .unwrap_or(first);
+
+        // Sweep first, so nothing the batch loads is dropped before it is analyzed
+        self.advance(transfers);
+        let gaps = gaps(&self.loaded, first - self.retention, last);
+        if gaps.is_empty() {
+            return Ok(());
+        }
+        let starts: Vec<DateTime<Utc>> = gaps.iter().map(|(start, _)| *start).collect();
+        let ends: Vec<DateTime<Utc>> = gaps.iter().map(|(_, end)| *end).collect();
+        let rows: Vec<TransferActivity> = sqlx::query_as(
+            "SELECT t.chain_id, t.from_address, t.to_address, t.block_timestamp,
+                    t.block_number, t.log_index
+             FROM UNNEST($1::TIMESTAMPTZ[], $2::TIMESTAMPTZ[]) AS g(since, until)
+             JOIN transfers t ON t.block_timestamp > g.since AND t.block_timestamp <= g.until",
+        )
+        .bind(&starts)
+        .bind(&ends)
+        .fetch_all(pool)
+        .await?;
+
+        self.insert_rows(rows);
+        for (start, end) in gaps {
+            mark_loaded(&mut self.loaded, start, end);
+        }
+        Ok(())
+    }
+
+    /// Record a batch of transfers before analyzing it, so each transfer counts itself as
+    /// it would once stored.
+    pub fn record(&mut self, transfers: &[StablecoinTransfer]) {
+        for t in transfers {
+            self.insert(
+                t.chain_id,
+                &t.from_address,
+                &t.to_address,
+                (t.block_timestamp, t.block_number, t.log_index),
+            );
+        }
+        self.advance(transfers);
+    }
+
+    /// Move each chain of the batch to its latest transfer and sweep if due. A replay from
+    /// an earlier block moves the chain's window back with it.
+    fn advance(&mut self, transfers: &[StablecoinTransfer]) {
+        let mut batch_latest: HashMap<i64, DateTime<Utc>> = HashMap::new();
+        for t in transfers {
+            let latest = batch_latest.entry(t.chain_id).or_insert(t.block_timestamp);
+            *latest = (*latest).max(t.block_timestamp);
+        }
+        self.latest.extend(batch_latest);
+        self.sweep();
+    }
+
+    /// Transfers sent by `address` on `chain_id` in `(at - window_secs, at]`.
+    pub fn sent_count(
+        &self,
+        address: &[u8],
+        chain_id: i64,
+        at: DateTime<Utc>,
+        window_secs: u64,
+    ) -> usize {
+        self.wallets
+            .get(&(address.to_vec(), chain_id))
+            .map_or(0, |activity| {
+                in_window(&activity.sent, at, window_secs).count()
+            })
+    }
+
+    /// Chains on which `address` sent or received a transfer in `(at - window_secs, at]`.
+    pub fn active_chains(&self, address: &[u8], at: DateTime<Utc>, window_secs: u64) -> usize {
+        self.chains
+            .iter()
+            .filter(|chain_id| {
+                self.wallets
+                    .get(&(address.to_vec(), **chain_id))
+                    .is_some_and(|activity| {
+                        in_window(&activity.sent, at, window_secs).next().is_some()
+                            || in_window(&activity.received, at, window_secs)
+                                .next()
+                                .is_some()
+                    })
+            })
+            .count()
+    }
+
+    fn insert_rows(&mut self, rows: Vec<TransferActivity>) {
+        for (chain_id, from, to, timestamp, block, log_index) in rows {
+            self.insert(chain_id, &from, &to, (timestamp, block, log_index));
+        }
+    }
+
+    fn insert(&mut self, chain_id: i64, from: &[u8], to: &[u8], key: ActivityKey) {
+        self.chains.insert(chain_id);
+        self.wallets
+            .entry((from.to_vec(), chain_id))
+            .or_default()
+            .sent
+            .insert(key);
+        self.wallets
+            .entry((to.to_vec(), chain_id))
+            .or_default()
+            .received
+            .insert(key);
+    }
+
+    /// Drop entries that no window of their chain can reach once a chain has moved a full
+    /// retention period since its last sweep, or has moved back. Chains without batches of
+    /// their own keep entries down to the earliest horizon of the others.
+    fn sweep(&mut self) {
+        let due = self.latest.iter().any(|(chain_id, latest)| {
+            self.swept_at
+                .get(chain_id)
+                .is_none_or(|swept| *latest >= *swept + self.retention || latest < swept)
+        });
+        if !due {
+            return;
+        }
+
+        let horizons: HashMap<i64, DateTime<Utc>> = self
+            .latest
+            .iter()
+            .map(|(chain_id, latest)| (*chain_id, *latest - self.retention))
+            .collect();
+        let (Some(earliest), Some(furthest)) = (
+            horizons.values().min().copied(),
+            horizons.values().max().copied(),
+        ) else {
+            return;
+        };
+        self.wallets.retain(|(_, chain_id), activity| {
+            let horizon = horizons.get(chain_id).unwrap_or(&earliest);
+            activity.sent.retain(|(ts, ..)| ts > horizon);
+            activity.received.retain(|(ts, ..)| ts > horizon);
+            !activity.sent.is_empty() || !activity.received.is_empty()
+        });
+        self.chains = self.wallets.keys().map(|(_, chain_id)| *chain_id).collect();
+        trim_loaded(&mut self.loaded, furthest);
+        self.swept_at = self.latest.clone();
+    }
+}
+
+type TransferActivity = (i64, Vec<u8>, Vec<u8>, DateTime<Utc>, i64, i32);
+
+/// The parts of `(since, until]` not covered by `spans`.
+fn gaps(spans: &Spans, since: DateTime<Utc>, until: DateTime<Utc>) -> Spans {
+    let mut gaps = Vec::new();
+    let mut from = since;
+    for &(start, end) in spans {
+        if end <= from {
+            continue;
+        }
+        if start >= until {
+            break;
+        }
+        if start > from {
+            gaps.push((from, start));
+        }
+        from = end;
+    }
+    if from < until {
+        gaps.push((from, until));
+    }
+    gaps
+}
+
+/// Add `(start, end]` to `spans`, merging it with the spans it overlaps or touches.
+fn mark_loaded(spans: &mut Spans, start: DateTime<Utc>, end: DateTime<Utc>) {
+    let (mut start, mut end) = (start, end);
+    spans.retain(|&(s, e)| {
+        if e < start || s > end {
+            return true;
+        }
+        start = start.min(s);
+        end = end.max(e);
+        false
+    });
+    let at = spans.partition_point(|&(s, _)| s < start);
+    spans.insert(at, (start, end));
+}
+
+/// Forget everything at or before `horizon`.
+fn trim_loaded(spans: &mut Spans, horizon: DateTime<Utc>) {
+    spans.retain(|&(_, end)| end > horizon);
+    if let Some(first) = spans.first_mut() {
+        first.0 = first.0.max(horizon);
+    }
+}
+
+fn in_window(
+    keys: &BTreeSet<ActivityKey>,
+    at: DateTime<Utc>,
+    window_secs: u64,
+) -> impl Iterator<Item = &ActivityKey> {
+    let from = at - Duration::seconds(window_secs as i64);
+    keys.range((
+        Bound::Excluded((from, i64::MAX, i32::MAX)),
+        Bound::Included((at, i64::MAX, i32::MAX)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use chrono::TimeZone;
+
+    fn transfer(chain_id: i64, from: u8, to: u8, block: i64, minute: u32) -> StablecoinTransfer {
+        StablecoinTransfer {
+            id: Some(block),
+            chain_id,
+            block_timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 12, minute, 0).unwrap(),
+            ..test_transfer(block, from, to, 1)
+        }
+    }
+
+    #[test]
+    fn test_windows_count_once_and_drop_old_entries() {
+        let mut config = AnomalyDetectionConfig::default();
+        config.velocity.window_secs = 600;
+        config.cross_chain.window_secs = 600;
+        let mut windows = ActivityWindows::new(&config);
+
+        let batch = vec![
+            transfer(1, 0xaa, 0xbb, 1, 0),
+            transfer(1, 0xaa, 0xcc, 2, 5),
+            transfer(10, 0xdd, 0xaa, 3, 6),
+        ];
+        windows.record(&batch);
+        // Replaying the same batch does not double count
+        windows.record(&batch);
+
+        let at = batch[1].block_timestamp;
+        assert_eq!(windows.sent_count(&[0xaa; 20], 1, at, 600), 2);
+        assert_eq!(windows.sent_count(&[0xaa; 20], 1, at, 120), 1);
+        assert_eq!(windows.active_chains(&[0xaa; 20], at, 600), 1);
+        assert_eq!(
+            windows.active_chains(&[0xaa; 20], batch[2].block_timestamp, 600),
+            2
+        );
+
+        // Once chain 1 moves on, its entries fall out of reach and are dropped
+        windows.record(&[transfer(1, 0xee, 0xff, 4, 30)]);
+        assert_eq!(
+            windows.sent_count(&[0xaa; 20], 1, batch[1].block_timestamp, 600),
+            0
+        );
+        assert_eq!(
+            windows.active_chains(&[0xaa; 20], batch[2].block_timestamp, 600),
+            1
+        );
+    }
+
+    #[test]
+    fn test_loaded_spans_leave_gaps_for_swept_and_new_ranges() {
+        let at = |minute: u32| Utc.with_ymd_and_hms(2024, 3, 1, 12, minute, 0).unwrap();
+        let mut loaded = Vec::new();
+        assert_eq!(gaps(&loaded, at(0), at(10)), vec![(at(0), at(10))]);
+
+        mark_loaded(&mut loaded, at(0), at(10));
+        assert_eq!(gaps(&loaded, at(5), at(15)), vec![(at(10), at(15))]);
+        mark_loaded(&mut loaded, at(10), at(15));
+        mark_loaded(&mut loaded, at(20), at(30));
+        assert_eq!(loaded, vec![(at(0), at(15)), (at(20), at(30))]);
+        assert_eq!(gaps(&loaded, at(12), at(25)), vec![(at(15), at(20))]);
+        assert!(gaps(&loaded, at(21), at(30)).is_empty());
+
+        // A chain far ahead sweeps past minute 22, so a lagging chain's window is read again
+        trim_loaded(&mut loaded, at(22));
+        assert_eq!(loaded, vec![(at(22), at(30))]);
+        assert_eq!(gaps(&loaded, at(5), at(25)), vec![(at(5), at(22))]);
+    }
+}
@@ -0,0 +1,333 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::anomaly::activity::ActivityWindows;
+use crate::anomaly::engine::AnomalyEngine;
+use crate::anomaly::types::AnomalyType;
+use crate::db::repository::{self, TransferRange};
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+use crate::wallet::first_seen;
+
+/// Identifies a transfer across chains: (chain id, tx hash, log index).
+type TransferRef = (i64, Vec<u8>, i32);
+
+/// Which stored transfers to run the candidate rules over.
+#[derive(Debug, Clone)]
+pub struct BacktestOptions {
+    /// (chain id, chain name) of every chain to replay.
+    pub chains: Vec<(i64, String)>,
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub batch_size: i64,
+}
+
+/// Alerts the candidate rules would have raised, and how they score against ground truth.
+#[derive(Debug, Default, Serialize)]
+pub struct BacktestReport {
+    pub transfers_scanned: u64,
+    /// Alerts per anomaly type and chain name, after suppression rules.
+    pub alerts: BTreeMap<String, BTreeMap<String, u64>>,
+    pub alerts_total: u64,
+    /// Detected anomalies held back by an active suppression rule, per anomaly type.
+    pub suppressed: BTreeMap<String, u64>,
+    /// Types that depend on state a replay cannot rebuild, so are left out.
+    pub skipped_types: Vec<String>,
+    pub ground_truth: Option<GroundTruthReport>,
+}
+
+/// Precision and recall against the labelled transfers inside the backtested range.
+#[derive(Debug, Default, Serialize)]
+pub struct GroundTruthReport {
+    /// Labelled transfers found in the range.
+    pub labelled: u64,
+    /// Labelled transfers not found in the range, so not scored.
+    pub out_of_range: u64,
+    pub overall: RuleScore,
+    pub rules: BTreeMap<String, RuleScore>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RuleScore {
+    /// Distinct transfers the rule alerted on.
+    pub alerted: u64,
+    /// Alerted transfers the rule is expected to catch.
+    pub true_positives: u64,
+    pub false_positives: u64,
+    /// Labelled transfers this rule is expected to catch.
+    pub expected: u64,
+    /// Expected transfers the rule did not alert on.
+    pub missed: u64,
+    /// `true_positives / alerted`, absent without alerts.
+    pub precision: Option<f64>,
+    /// Share of `expected` alerted on, absent when nothing is expected.
+    pub recall: Option<f64>,
+}
+
+/// Labelled transfers, each with the anomaly types expected to catch it. An empty set
+/// means any rule alerting on it is correct and every rule is expected to.
+#[derive(Debug, Default)]
+pub struct GroundTruth {
+    labels: HashMap<TransferRef, BTreeSet<String>>,
+}
+
+impl GroundTruth {
+    /// Parse a CSV with a header row and columns `chain_id, tx_hash, log_index` and an
+    /// optional `anomaly_type`. A transfer may appear on several rows, one per type.
+    /// Transfers not listed count as benign.
+    pub fn load(path: &str) -> eyre::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| eyre::eyre!("Failed to open ground truth CSV '{}': {}", path, e))?;
+
+        let mut truth = Self::default();
+        for (line, result) in reader.records().enumerate() {
+            let record = result?;
+            let field = |i: usize| record.get(i).unwrap_or("");
+            let row = line + 2;
+            let chain_id: i64 = field(0)
+                .parse()
+                .map_err(|e| eyre::eyre!("Ground truth row {}: bad chain_id: {}", row, e))?;
+            let tx_hash = hex::decode(field(1).trim_start_matches("0x"))
+                .map_err(|e| eyre::eyre!("Ground truth row {}: bad tx_hash: {}", row, e))?;
+            let log_index: i32 = field(2)
+                .parse()
+                .map_err(|e| eyre::eyre!("Ground truth row {}: bad log_index: {}", row, e))?;
+
+            let types = truth
+                .labels
+                .entry((chain_id, tx_hash, log_index))
+                .or_default();
+            if !field(3).is_empty() {
+                types.insert(field(3).to_string());
+            }
+        }
+        Ok(truth)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    fn expects(&self, key: &TransferRef, anomaly_type: &str) -> bool {
+        self.labels
+            .get(key)
+            .is_some_and(|types| types.is_empty() || types.contains(anomaly_type))
+    }
+}
+
+/// Run `engine` over stored transfers of each chain in the range without writing anything,
+/// and count the alerts it raises. Suppression rules are applied but their hits are not
+/// recorded. With `truth`, score each rule against the labelled transfers in the range.
+pub async fn backtest(
+    pool: &PgPool,
+    engine: &AnomalyEngine,
+    label_store: &EntityLabelStore,
+    options: &BacktestOptions,
+    truth: Option<&GroundTruth>,
+) -> eyre::Result<BacktestReport> {
+    let mut report = BacktestReport {
+        skipped_types: AnomalyType::LIVE_ONLY.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    };
+    let mut alerted: BTreeMap<String, HashSet<TransferRef>> = BTreeMap::new();
+    let mut labelled_in_range: HashSet<TransferRef> = HashSet::new();
+
+    let range = TransferRange {
+        from_block: options.from_block,
+        to_block: options.to_block,
+        since: options.since,
+        until: options.until,
+    };
+    for (chain_id, chain_name) in &options.chains {
+        tracing::info!(chain = %chain_name, "Starting backtest");
+        // Each chain moves through time on its own, so each gets fresh windows
+        let mut windows = ActivityWindows::new(engine.config());
+        let mut after_id = 0i64;
+        let mut scanned = 0u64;
+
+        loop {
+            let batch = repository::get_transfers_in_range(
+                pool,
+                *chain_id,
+                after_id,
+                &range,
+                options.batch_size,
+            )
+            .await?;
+            let Some((last_id, _)) = batch.last() else {
+                break;
+            };
+            after_id = *last_id;
+
+            let transfers: Vec<StablecoinTransfer> = batch.into_iter().map(|(_, t)| t).collect();
+            if let Some(truth) = truth {
+                labelled_in_range.extend(
+                    transfers
+                        .iter()
+                        .map(|t| (t.chain_id, t.tx_hash.clone(), t.log_index))
+                        .filter(|key| truth.labels.contains_key(key)),
+                );
+            }
+
+            let tx_hashes: Vec<Vec<u8>> = transfers.iter().map(|t| t.tx_hash.clone()).collect();
+            let new_wallets = first_seen::load_first_seen_events(pool, *chain_id, &tx_hashes).await?;
+            windows.cover(pool, &transfers).await?;
+            let detected = engine
+                .analyze_batch(pool, &transfers, label_store, &new_wallets, &windows)
+                .await?;
+            let (kept, suppressed) = engine
+                .suppress(pool, detected, &transfers, label_store, false)
+                .await?;
+
+            for (_, anomaly) in &suppressed {
+                *report
+                    .suppressed
+                    .entry(anomaly.anomaly_type.as_str().to_string())
+                    .or_default() += 1;
+            }
+            for anomaly in kept {
+                let anomaly_type = anomaly.anomaly_type.as_str();
+                if AnomalyType::LIVE_ONLY.contains(&anomaly_type) {
+                    continue;
+                }
+                *report
+                    .alerts
+                    .entry(anomaly_type.to_string())
+                    .or_default()
+                    .entry(chain_name.clone())
+                    .or_default() += 1;
+                report.alerts_total += 1;
+                alerted
+                    .entry(anomaly_type.to_string())
+                    .or_default()
+                    .insert((anomaly.chain_id, anomaly.tx_hash, anomaly.log_index));
+            }
+
+            scanned += transfers.len() as u64;
+        }
+
+        tracing::info!(chain = %chain_name, scanned, "Backtest of chain complete");
+        report.transfers_scanned += scanned;
+    }
+
+    report.ground_truth = truth.map(|truth| score(truth, &alerted, &labelled_in_range));
+    Ok(report)
+}
+
+/// Score each rule's alerted transfers against the labelled ones found in the range.
+fn score(
+    truth: &GroundTruth,
+    alerted: &BTreeMap<String, HashSet<TransferRef>>,
+    labelled: &HashSet<TransferRef>,
+) -> GroundTruthReport {
+    // Rules named by a label are scored even when they raised nothing
+    let named: BTreeSet<&String> = alerted
+        .keys()
+        .chain(labelled.iter().flat_map(|key| &truth.labels[key]))
+        .collect();
+    let none = HashSet::new();
+    let rules = named
+        .into_iter()
+        .map(|anomaly_type| {
+            let keys = alerted.get(anomaly_type).unwrap_or(&none);
+            let expected: HashSet<&TransferRef> = labelled
+                .iter()
+                .filter(|key| truth.expects(key, anomaly_type))
+                .collect();
+            (anomaly_type.clone(), rule_score(keys, &expected))
+        })
+        .collect();
+
+    let any: HashSet<TransferRef> = alerted.values().flatten().cloned().collect();
+    let expected: HashSet<&TransferRef> = labelled.iter().collect();
+    GroundTruthReport {
+        labelled: labelled.len() as u64,
+        out_of_range: (truth.len() - labelled.len()) as u64,
+        overall: rule_score(&any, &expected),
+        rules,
+    }
+}
+
+fn rule_score(alerted: &HashSet<TransferRef>, expected: &HashSet<&TransferRef>) -> RuleScore {
+    let true_positives = expected.iter().filter(|key| alerted.contains(**key)).count() as u64;
+    let ratio = |n: u64, d: u64| (d > 0).then(|| n as f64 / d as f64);
+    RuleScore {
+        alerted: alerted.len() as u64,
+        true_positives,
+        false_positives: alerted.len() as u64 - true_positives,
+        expected: expected.len() as u64,
+        missed: expected.len() as u64 - true_positives,
+        precision: ratio(true_positives, alerted.len() as u64),
+        recall: ratio(true_positives, expected.len() as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u8) -> TransferRef {
+        (1, vec![n; 32], 0)
+    }
+
+    #[test]
+    fn test_score_counts_typed_and_untyped_labels() {
+        let mut truth = GroundTruth::default();
+        truth.labels.insert(key(1), BTreeSet::new());
+        truth
+            .labels
+            .insert(key(2), BTreeSet::from(["structuring".to_string()]));
+        truth.labels.insert(key(9), BTreeSet::new());
+
+        truth
+            .labels
+            .insert(key(5), BTreeSet::from(["peel_chain".to_string()]));
+        let labelled: HashSet<TransferRef> = [key(1), key(2), key(5)].into();
+        let alerted = BTreeMap::from([
+            ("large_transfer".to_string(), HashSet::from([key(1), key(3)])),
+            ("structuring".to_string(), HashSet::from([key(4)])),
+        ]);
+        let report = score(&truth, &alerted, &labelled);
+
+        assert_eq!(report.labelled, 3);
+        assert_eq!(report.out_of_range, 1);
+
+        let large = &report.rules["large_transfer"];
+        assert_eq!((large.true_positives, large.false_positives), (1, 1));
+        // Only the untyped label is expected of large_transfer
+        assert_eq!((large.expected, large.missed), (1, 0));
+        assert_eq!(large.precision, Some(0.5));
+        assert_eq!(large.recall, Some(1.0));
+
+        let structuring = &report.rules["structuring"];
+        assert_eq!(structuring.precision, Some(0.0));
+        assert_eq!(structuring.false_positives, 1);
+        assert_eq!((structuring.expected, structuring.missed), (2, 2));
+        assert_eq!(structuring.recall, Some(0.0));
+
+        // Named by a label but never raised
+        let peel = &report.rules["peel_chain"];
+        assert_eq!((peel.alerted, peel.precision), (0, None));
+        assert_eq!((peel.expected, peel.recall), (2, Some(0.0)));
+
+        // An alert on a transfer labelled for another rule is still a false positive
+        let alerted = BTreeMap::from([("peel_chain".to_string(), HashSet::from([key(2)]))]);
+        let peel = &score(&truth, &alerted, &labelled).rules["peel_chain"];
+        assert_eq!((peel.true_positives, peel.false_positives), (0, 1));
+
+        assert_eq!(report.overall.alerted, 3);
+        assert_eq!(report.overall.recall, Some(1.0 / 3.0));
+    }
+}
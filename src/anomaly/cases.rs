@@ -0,0 +1,396 @@
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// Review lifecycle shared by anomalies and cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Open,
+    Triaged,
+    Escalated,
+    FalsePositive,
+    Closed,
+}
+
+impl ReviewStatus {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "open" => Some(Self::Open),
+            "triaged" => Some(Self::Triaged),
+            "escalated" => Some(Self::Escalated),
+            "false_positive" => Some(Self::FalsePositive),
+            "closed" => Some(Self::Closed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Triaged => "triaged",
+            Self::Escalated => "escalated",
+            Self::FalsePositive => "false_positive",
+            Self::Closed => "closed",
+        }
+    }
+
+    /// False positives and closed items count as resolved.
+    pub fn is_resolved(&self) -> bool {
+        matches!(self, Self::FalsePositive | Self::Closed)
+    }
+
+    /// Whether moving from this status to `next` is allowed. Work moves forward from open
+    /// through triage and escalation to a resolution; an escalation can be sent back to
+    /// triage, and a resolved item can only be reopened.
+    pub fn can_transition_to(&self, next: ReviewStatus) -> bool {
+        use ReviewStatus::*;
+        matches!(
+            (self, next),
+            (Open, Triaged | Escalated | FalsePositive | Closed)
+                | (Triaged, Escalated | FalsePositive | Closed)
+                | (Escalated, Triaged | FalsePositive | Closed)
+                | (FalsePositive | Closed, Open)
+        )
+    }
+}
+
+/// Changes to apply to an anomaly or case. `None` leaves a field as is;
+/// `assignee: Some(None)` clears the assignee.
+#[derive(Debug, Default)]
+pub struct ReviewUpdate {
+    pub status: Option<ReviewStatus>,
+    pub assignee: Option<Option<String>>,
+    /// Cases only.
+    pub title: Option<String>,
+}
+
+/// Result of a workflow write.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewOutcome {
+    Applied,
+    NotFound,
+    InvalidTransition(ReviewStatus, ReviewStatus),
+}
+
+/// Record one change in the audit log.
+async fn audit(
+    tx: &mut Transaction<'_, Postgres>,
+    anomaly_id: Option<i64>,
+    case_id: Option<i64>,
+    actor: &str,
+    action: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> eyre::Result<()> {
+    sqlx::query(
+        "INSERT INTO review_audit_log (anomaly_id, case_id, actor, action, old_value, new_value)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(anomaly_id)
+    .bind(case_id)
+    .bind(actor)
+    .bind(action)
+    .bind(old_value)
+    .bind(new_value)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Apply a status and/or assignee change to an anomaly, auditing each field that changes.
+pub async fn update_anomaly(
+    pool: &PgPool,
+    anomaly_id: i64,
+    actor: &str,
+    update: &ReviewUpdate,
+) -> eyre::Result<ReviewOutcome> {
+    let mut tx = pool.begin().await?;
+    let row: Option<(String, Option<String>)> =
+        sqlx::query_as("SELECT status, assignee FROM anomalies WHERE id = $1 FOR UPDATE")
+            .bind(anomaly_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some((status, assignee)) = row else {
+        return Ok(ReviewOutcome::NotFound);
+    };
+    let current = ReviewStatus::parse(&status).unwrap_or(ReviewStatus::Open);
+
+    if let Some(next) = update.status.filter(|next| *next != current) {
+        if !current.can_transition_to(next) {
+            return Ok(ReviewOutcome::InvalidTransition(current, next));
+        }
+        sqlx::query("UPDATE anomalies SET status = $2, resolved = $3 WHERE id = $1")
+            .bind(anomaly_id)
+            .bind(next.as_str())
+            .bind(next.is_resolved())
+            .execute(&mut *tx)
+            .await?;
+        audit(&mut tx, Some(anomaly_id), None, actor, "status", Some(current.as_str()), Some(next.as_str()))
+            .await?;
+    }
+
+    if let Some(next) = update.assignee.as_ref().filter(|next| **next != assignee) {
+        sqlx::query("UPDATE anomalies SET assignee = $2 WHERE id = $1")
+            .bind(anomaly_id)
+            .bind(next)
+            .execute(&mut *tx)
+            .await?;
+        audit(&mut tx, Some(anomaly_id), None, actor, "assignee", assignee.as_deref(), next.as_deref())
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(ReviewOutcome::Applied)
+}
+
+/// Attach a note to an anomaly. Returns the note id, or None if the anomaly does not exist.
+pub async fn add_anomaly_note(
+    pool: &PgPool,
+    anomaly_id: i64,
+    author: &str,
+    body: &str,
+) -> eyre::Result<Option<i64>> {
+    let (exists,): (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM anomalies WHERE id = $1)")
+        .bind(anomaly_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Ok(None);
+    }
+    add_note(pool, Some(anomaly_id), None, author, body).await.map(Some)
+}
+
+/// Attach a note to a case. Returns the note id, or None if the case does not exist.
+pub async fn add_case_note(
+    pool: &PgPool,
+    case_id: i64,
+    author: &str,
+    body: &str,
+) -> eyre::Result<Option<i64>> {
+    let (exists,): (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM anomaly_cases WHERE id = $1)")
+        .bind(case_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Ok(None);
+    }
+    add_note(pool, None, Some(case_id), author, body).await.map(Some)
+}
+
+async fn add_note(
+    pool: &PgPool,
+    anomaly_id: Option<i64>,
+    case_id: Option<i64>,
+    author: &str,
+    body: &str,
+) -> eyre::Result<i64> {
+    let mut tx = pool.begin().await?;
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO analyst_notes (anomaly_id, case_id, author, body)
+         VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(anomaly_id)
+    .bind(case_id)
+    .bind(author)
+    .bind(body)
+    .fetch_one(&mut *tx)
+    .await?;
+    audit(&mut tx, anomaly_id, case_id, author, "note", None, Some(&id.to_string())).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Open a case and link the given anomalies to it. Returns the case id, or None
+/// (creating nothing) if any of the anomalies does not exist.
+pub async fn create_case(
+    pool: &PgPool,
+    actor: &str,
+    title: &str,
+    description: &str,
+    assignee: Option<&str>,
+    anomaly_ids: &[i64],
+) -> eyre::Result<Option<i64>> {
+    let mut tx = pool.begin().await?;
+    let (case_id,): (i64,) = sqlx::query_as(
+        "INSERT INTO anomaly_cases (title, description, assignee, created_by)
+         VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(title)
+    .bind(description)
+    .bind(assignee)
+    .bind(actor)
+    .fetch_one(&mut *tx)
+    .await?;
+    audit(&mut tx, None, Some(case_id), actor, "created", None, Some(title)).await?;
+
+    if !link_in(&mut tx, case_id, actor, anomaly_ids).await? {
+        return Ok(None);
+    }
+
+    tx.commit().await?;
+    Ok(Some(case_id))
+}
+
+/// Apply a status, assignee and/or title change to a case, auditing each field that changes.
+pub async fn update_case(
+    pool: &PgPool,
+    case_id: i64,
+    actor: &str,
+    update: &ReviewUpdate,
+) -> eyre::Result<ReviewOutcome> {
+    let mut tx = pool.begin().await?;
+    let row: Option<(String, Option<String>, String)> = sqlx::query_as(
+        "SELECT status, assignee, title FROM anomaly_cases WHERE id = $1 FOR UPDATE",
+    )
+    .bind(case_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((status, assignee, title)) = row else {
+        return Ok(ReviewOutcome::NotFound);
+    };
+    let current = ReviewStatus::parse(&status).unwrap_or(ReviewStatus::Open);
+
+    if let Some(next) = update.status.filter(|next| *next != current) {
+        if !current.can_transition_to(next) {
+            return Ok(ReviewOutcome::InvalidTransition(current, next));
+        }
+        sqlx::query("UPDATE anomaly_cases SET status = $2, updated_at = NOW() WHERE id = $1")
+            .bind(case_id)
+            .bind(next.as_str())
+            .execute(&mut *tx)
+            .await?;
+        audit(&mut tx, None, Some(case_id), actor, "status", Some(current.as_str()), Some(next.as_str()))
+            .await?;
+    }
+
+    if let Some(next) = update.assignee.as_ref().filter(|next| **next != assignee) {
+        sqlx::query("UPDATE anomaly_cases SET assignee = $2, updated_at = NOW() WHERE id = $1")
+            .bind(case_id)
+            .bind(next)
+            .execute(&mut *tx)
+            .await?;
+        audit(&mut tx, None, Some(case_id), actor, "assignee", assignee.as_deref(), next.as_deref())
+            .await?;
+    }
+
+    if let Some(next) = update.title.as_ref().filter(|next| **next != title) {
+        sqlx::query("UPDATE anomaly_cases SET title = $2, updated_at = NOW() WHERE id = $1")
+            .bind(case_id)
+            .bind(next)
+            .execute(&mut *tx)
+            .await?;
+        audit(&mut tx, None, Some(case_id), actor, "title", Some(&title), Some(next)).await?;
+    }
+
+    tx.commit().await?;
+    Ok(ReviewOutcome::Applied)
+}
+
+/// Link anomalies to a case, moving any that belong to another case.
+/// Nothing is linked if the case or any of the anomalies does not exist.
+pub async fn link_anomalies(
+    pool: &PgPool,
+    case_id: i64,
+    actor: &str,
+    anomaly_ids: &[i64],
+) -> eyre::Result<ReviewOutcome> {
+    let mut tx = pool.begin().await?;
+    let (exists,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM anomaly_cases WHERE id = $1)")
+            .bind(case_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if !exists || !link_in(&mut tx, case_id, actor, anomaly_ids).await? {
+        return Ok(ReviewOutcome::NotFound);
+    }
+    sqlx::query("UPDATE anomaly_cases SET updated_at = NOW() WHERE id = $1")
+        .bind(case_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(ReviewOutcome::Applied)
+}
+
+/// Link anomalies inside a transaction. Returns false if any anomaly does not exist.
+/// A move between cases is audited against the new case; the old case id is the old value.
+async fn link_in(
+    tx: &mut Transaction<'_, Postgres>,
+    case_id: i64,
+    actor: &str,
+    anomaly_ids: &[i64],
+) -> eyre::Result<bool> {
+    let rows: Vec<(i64, Option<i64>)> = sqlx::query_as(
+        "SELECT id, case_id FROM anomalies WHERE id = ANY($1) FOR UPDATE",
+    )
+    .bind(anomaly_ids)
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut wanted = anomaly_ids.to_vec();
+    wanted.sort_unstable();
+    wanted.dedup();
+    if rows.len() != wanted.len() {
+        return Ok(false);
+    }
+
+    for (anomaly_id, previous) in rows {
+        if previous == Some(case_id) {
+            continue;
+        }
+        sqlx::query("UPDATE anomalies SET case_id = $2 WHERE id = $1")
+            .bind(anomaly_id)
+            .bind(case_id)
+            .execute(&mut **tx)
+            .await?;
+        let previous = previous.map(|id| id.to_string());
+        audit(tx, Some(anomaly_id), Some(case_id), actor, "case", previous.as_deref(), Some(&case_id.to_string()))
+            .await?;
+    }
+    Ok(true)
+}
+
+/// Remove an anomaly from a case. NotFound if the anomaly is not linked to it.
+pub async fn unlink_anomaly(
+    pool: &PgPool,
+    case_id: i64,
+    anomaly_id: i64,
+    actor: &str,
+) -> eyre::Result<ReviewOutcome> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("UPDATE anomalies SET case_id = NULL WHERE id = $1 AND case_id = $2")
+        .bind(anomaly_id)
+        .bind(case_id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(ReviewOutcome::NotFound);
+    }
+    audit(&mut tx, Some(anomaly_id), Some(case_id), actor, "case", Some(&case_id.to_string()), None)
+        .await?;
+    sqlx::query("UPDATE anomaly_cases SET updated_at = NOW() WHERE id = $1")
+        .bind(case_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(ReviewOutcome::Applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_transitions() {
+        use ReviewStatus::*;
+        assert!(Open.can_transition_to(Triaged));
+        assert!(Triaged.can_transition_to(Escalated));
+        assert!(Escalated.can_transition_to(Triaged));
+        assert!(Escalated.can_transition_to(FalsePositive));
+        assert!(Closed.can_transition_to(Open));
+        assert!(!Triaged.can_transition_to(Open));
+        assert!(!Closed.can_transition_to(Escalated));
+        assert!(!FalsePositive.can_transition_to(Closed));
+
+        for status in [Open, Triaged, Escalated, FalsePositive, Closed] {
+            assert_eq!(ReviewStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(ReviewStatus::parse("resolved"), None);
+    }
+}
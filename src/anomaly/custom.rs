@@ -0,0 +1,578 @@
+use chrono::{DateTime, Timelike, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::{AggregateKind, CustomRuleConfig, CustomRulesFile, RuleSide};
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+use crate::wallet::first_seen::NewWalletEvent;
+
+use super::dsl::{Expr, Type, Value};
+use super::rules::{raw_to_human, usd_amount};
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// A custom rule that passed validation, ready to run.
+#[derive(Debug, Clone)]
+pub struct CustomRule {
+    pub config: CustomRuleConfig,
+    when: Expr,
+    score: Expr,
+    flags: Vec<Vec<TemplatePart>>,
+    inputs: Vec<String>,
+    wallet_age_sides: Vec<RuleSide>,
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Text(String),
+    Ident(String),
+}
+
+/// Identifiers every rule can use, with their types. Per-side identifiers exist for
+/// both `from.` and `to.`.
+fn base_schema() -> HashMap<String, Type> {
+    let mut schema = HashMap::from([
+        ("amount".to_string(), Type::Num),
+        ("amount_usd".to_string(), Type::Num),
+        ("token".to_string(), Type::Str),
+        ("chain_id".to_string(), Type::Num),
+        ("block_number".to_string(), Type::Num),
+        ("hour_of_day".to_string(), Type::Num),
+    ]);
+    for side in ["from", "to"] {
+        for (field, ty) in [
+            ("address", Type::Str),
+            ("labeled", Type::Bool),
+            ("sanctioned", Type::Bool),
+            ("entity_types", Type::StrList),
+            ("entity_names", Type::StrList),
+            ("label_sources", Type::StrList),
+            ("is_new", Type::Bool),
+            ("wallet_age_secs", Type::Num),
+        ] {
+            schema.insert(format!("{}.{}", side, field), ty);
+        }
+    }
+    schema
+}
+
+/// Load and validate the rules file. Disabled rules are validated but not returned.
+pub fn load_rules(path: &str) -> eyre::Result<Vec<CustomRule>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("Failed to read custom rules file '{}': {}", path, e))?;
+    let file: CustomRulesFile = toml::from_str(&content)
+        .map_err(|e| eyre::eyre!("Failed to parse custom rules file '{}': {}", path, e))?;
+
+    let mut rules = Vec::new();
+    for config in file.rules {
+        if rules
+            .iter()
+            .any(|r: &CustomRule| r.config.name == config.name)
+        {
+            return Err(eyre::eyre!("Custom rule '{}' is defined twice", config.name));
+        }
+        let name = config.name.clone();
+        let rule = compile_rule(config)
+            .map_err(|e| eyre::eyre!("Invalid custom rule '{}' in '{}': {}", name, path, e))?;
+        rules.push(rule);
+    }
+
+    Ok(rules.into_iter().filter(|r| r.config.enabled).collect())
+}
+
+/// Validate a rule definition: names, expression types, aggregates and flag templates.
+pub fn compile_rule(config: CustomRuleConfig) -> eyre::Result<CustomRule> {
+    if !is_identifier(&config.name) || config.name.len() > 64 {
+        return Err(eyre::eyre!(
+            "name must be lowercase letters, digits and underscores (at most 64)"
+        ));
+    }
+    if AnomalyType::BUILTIN.contains(&config.name.as_str()) {
+        return Err(eyre::eyre!("name collides with a built-in anomaly type"));
+    }
+    if config.version == 0 {
+        return Err(eyre::eyre!("version must be at least 1"));
+    }
+
+    let mut schema = base_schema();
+    for aggregate in &config.aggregates {
+        if !is_identifier(&aggregate.name) || schema.contains_key(&aggregate.name) {
+            return Err(eyre::eyre!(
+                "aggregate name '{}' is invalid or already in use",
+                aggregate.name
+            ));
+        }
+        if aggregate.window_secs == 0 {
+            return Err(eyre::eyre!("aggregate '{}' needs a window_secs above 0", aggregate.name));
+        }
+        schema.insert(aggregate.name.clone(), Type::Num);
+    }
+
+    let when = Expr::parse(&config.when).map_err(|e| eyre::eyre!("when: {}", e))?;
+    let when_type = when.check(&schema).map_err(|e| eyre::eyre!("when: {}", e))?;
+    if when_type != Type::Bool {
+        return Err(eyre::eyre!("when must be a condition, found {}", when_type));
+    }
+
+    let score = Expr::parse(&config.score).map_err(|e| eyre::eyre!("score: {}", e))?;
+    let score_type = score.check(&schema).map_err(|e| eyre::eyre!("score: {}", e))?;
+    if score_type != Type::Num {
+        return Err(eyre::eyre!("score must be a number, found {}", score_type));
+    }
+
+    let flags = config
+        .flags
+        .iter()
+        .map(|template| parse_template(template, &schema))
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let mut inputs: Vec<String> = Vec::new();
+    let template_idents = flags.iter().flatten().filter_map(|part| match part {
+        TemplatePart::Ident(name) => Some(name.as_str()),
+        TemplatePart::Text(_) => None,
+    });
+    for ident in when
+        .identifiers()
+        .into_iter()
+        .chain(score.identifiers())
+        .chain(template_idents)
+    {
+        if !inputs.iter().any(|i| i == ident) {
+            inputs.push(ident.to_string());
+        }
+    }
+
+    for aggregate in &config.aggregates {
+        if !inputs.contains(&aggregate.name) {
+            return Err(eyre::eyre!("aggregate '{}' is never used", aggregate.name));
+        }
+    }
+
+    let wallet_age_sides = [RuleSide::From, RuleSide::To]
+        .into_iter()
+        .filter(|side| inputs.contains(&format!("{}.wallet_age_secs", side.as_str())))
+        .collect();
+
+    Ok(CustomRule {
+        config,
+        when,
+        score,
+        flags,
+        inputs,
+        wallet_age_sides,
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some('a'..='z' | '_'))
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn parse_template(template: &str, schema: &HashMap<String, Type>) -> eyre::Result<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|i| open + i)
+            .ok_or_else(|| eyre::eyre!("flag '{}' has an unclosed '{{'", template))?;
+        if open > 0 {
+            parts.push(TemplatePart::Text(rest[..open].to_string()));
+        }
+        let ident = rest[open + 1..close].trim();
+        if !schema.contains_key(ident) {
+            return Err(eyre::eyre!("flag '{}' references unknown identifier '{}'", template, ident));
+        }
+        parts.push(TemplatePart::Ident(ident.to_string()));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Run every custom rule against a batch of transfers.
+///
+/// Rules are first evaluated with only the in-memory inputs. Wallet ages and aggregates
+/// are then fetched in one query each, for the (transfer, rule) pairs this pass could not
+/// already rule out.
+pub async fn evaluate_rules(
+    pool: &PgPool,
+    rules: &[CustomRule],
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    new_wallets: &[NewWalletEvent],
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for transfer in transfers {
+        let base = base_env(transfer, label_store, new_wallets);
+        for rule in rules {
+            if rule.when.eval(&base) != Value::Bool(false) {
+                candidates.push((transfer, rule, base.clone()));
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let first_seen = first_seen_times(pool, &candidates).await?;
+    let mut aggregates = aggregate_values(pool, &candidates).await?.into_iter();
+    let mut anomalies = Vec::new();
+
+    for (transfer, rule, mut env) in candidates {
+        for &side in &rule.wallet_age_sides {
+            let (address, key) = match side {
+                RuleSide::From => (&transfer.from_address, "from.wallet_age_secs"),
+                RuleSide::To => (&transfer.to_address, "to.wallet_age_secs"),
+            };
+            let age = first_seen
+                .get(&(transfer.chain_id, address.as_slice()))
+                .map_or(Value::Null, |first_seen| {
+                    let age = (transfer.block_timestamp - *first_seen).num_seconds().max(0);
+                    Value::Num(age as f64)
+                });
+            env.insert(key, age);
+        }
+        for aggregate in &rule.config.aggregates {
+            env.insert(aggregate.name.as_str(), aggregates.next().unwrap_or(Value::Null));
+        }
+
+        if rule.when.eval(&env) != Value::Bool(true) {
+            continue;
+        }
+
+        let risk_score = match rule.score.eval(&env) {
+            Value::Num(score) => score.clamp(0.0, 100.0) as f32,
+            _ => {
+                tracing::warn!(
+                    rule = %rule.config.name,
+                    tx_hash = %hex::encode(&transfer.tx_hash),
+                    "Custom rule matched but its score evaluated to null, skipping"
+                );
+                continue;
+            }
+        };
+
+        anomalies.push(rule.record(transfer, &env, risk_score));
+    }
+
+    Ok(anomalies)
+}
+
+/// A transfer and a rule its in-memory inputs did not rule out, with those inputs.
+type Candidate<'a> = (&'a StablecoinTransfer, &'a CustomRule, HashMap<&'a str, Value>);
+
+impl CustomRule {
+    fn record(
+        &self,
+        transfer: &StablecoinTransfer,
+        env: &HashMap<&str, Value>,
+        risk_score: f32,
+    ) -> AnomalyRecord {
+        let mut flags: Vec<String> = self
+            .flags
+            .iter()
+            .map(|parts| {
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        TemplatePart::Text(text) => text.clone(),
+                        TemplatePart::Ident(name) => env
+                            .get(name.as_str())
+                            .map(|v| v.to_string())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .collect();
+        if flags.is_empty() {
+            flags.push(format!("{}_v{}", self.config.name, self.config.version));
+        }
+
+        let inputs: serde_json::Map<String, serde_json::Value> = self
+            .inputs
+            .iter()
+            .map(|name| {
+                let value = env.get(name.as_str()).map(Value::to_json).unwrap_or_default();
+                (name.clone(), value)
+            })
+            .collect();
+
+        let address = self.config.address.map(|side| match side {
+            RuleSide::From => transfer.from_address.clone(),
+            RuleSide::To => transfer.to_address.clone(),
+        });
+
+        AnomalyRecord {
+            chain_id: transfer.chain_id,
+            anomaly_type: AnomalyType::Custom(self.config.name.clone()),
+            risk_score,
+            flags,
+            details: serde_json::json!({
+                "rule": self.config.name,
+                "rule_version": self.config.version,
+                "inputs": inputs,
+            }),
+            address,
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
+            tx_hash: transfer.tx_hash.clone(),
+            log_index: transfer.log_index,
+        }
+    }
+}
+
+/// Inputs available without touching the database.
+fn base_env<'a>(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    new_wallets: &[NewWalletEvent],
+) -> HashMap<&'a str, Value> {
+    let mut env = HashMap::from([
+        ("amount", Value::Num(raw_to_human(&transfer.amount, transfer.token_decimals))),
+        ("amount_usd", usd_amount(transfer).map_or(Value::Null, Value::Num)),
+        ("token", Value::Str(transfer.token_symbol.clone())),
+        ("chain_id", Value::Num(transfer.chain_id as f64)),
+        ("block_number", Value::Num(transfer.block_number as f64)),
+        ("hour_of_day", Value::Num(transfer.block_timestamp.hour() as f64)),
+    ]);
+
+    for (side, address) in [
+        (RuleSide::From, &transfer.from_address),
+        (RuleSide::To, &transfer.to_address),
+    ] {
+        let labels: Vec<_> = label_store
+            .lookup(address)
+            .unwrap_or_default()
+            .iter()
+            .filter(|l| l.chain_id.is_none() || l.chain_id == Some(transfer.chain_id))
+            .collect();
+        let strings = |f: fn(&crate::entity::label_store::EntityLabel) -> &String| {
+            Value::List(labels.iter().map(|l| Value::Str(f(l).clone())).collect())
+        };
+        let is_new = new_wallets.iter().any(|w| {
+            &w.address == address && w.chain_id == transfer.chain_id && w.direction == side.as_str()
+        });
+
+        let fields = match side {
+            RuleSide::From => [
+                "from.address",
+                "from.labeled",
+                "from.sanctioned",
+                "from.entity_types",
+                "from.entity_names",
+                "from.label_sources",
+                "from.is_new",
+            ],
+            RuleSide::To => [
+                "to.address",
+                "to.labeled",
+                "to.sanctioned",
+                "to.entity_types",
+                "to.entity_names",
+                "to.label_sources",
+                "to.is_new",
+            ],
+        };
+        let values = [
+            Value::Str(format!("0x{}", hex::encode(address))),
+            Value::Bool(!labels.is_empty()),
+            Value::Bool(label_store.is_sanctioned(address)),
+            strings(|l| &l.entity_type),
+            strings(|l| &l.entity_name),
+            strings(|l| &l.label_source),
+            Value::Bool(is_new),
+        ];
+        env.extend(fields.into_iter().zip(values));
+    }
+
+    env
+}
+
+/// First-seen time of each wallet whose age a candidate rule uses, by (chain, address), in
+/// one query. Wallets never recorded are missing, so their age is null.
+async fn first_seen_times<'a>(
+    pool: &PgPool,
+    candidates: &[Candidate<'a>],
+) -> eyre::Result<HashMap<(i64, &'a [u8]), DateTime<Utc>>> {
+    let mut wallets: Vec<(i64, &[u8])> = Vec::new();
+    for (transfer, rule, _) in candidates {
+        for side in &rule.wallet_age_sides {
+            let address = match side {
+                RuleSide::From => &transfer.from_address,
+                RuleSide::To => &transfer.to_address,
+            };
+            wallets.push((transfer.chain_id, address));
+        }
+    }
+    wallets.sort_unstable();
+    wallets.dedup();
+    if wallets.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let chain_ids: Vec<i64> = wallets.iter().map(|(chain_id, _)| *chain_id).collect();
+    let addresses: Vec<&[u8]> = wallets.iter().map(|(_, address)| *address).collect();
+    let rows: Vec<(i64, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT w.n - 1, f.first_seen_at
+         FROM UNNEST($1::BIGINT[], $2::BYTEA[]) WITH ORDINALITY AS w(chain_id, address, n)
+         JOIN wallet_first_seen f ON f.address = w.address AND f.chain_id = w.chain_id",
+    )
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(index, first_seen)| (wallets[index as usize], first_seen))
+        .collect())
+}
+
+/// Every candidate's aggregates, in candidate then aggregate order, in one query. Each is
+/// a window over one side's transfers ending at the candidate's transfer.
+async fn aggregate_values(pool: &PgPool, candidates: &[Candidate<'_>]) -> eyre::Result<Vec<Value>> {
+    let mut kinds = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut addresses = Vec::new();
+    let mut directions = Vec::new();
+    let mut timestamps = Vec::new();
+    let mut windows = Vec::new();
+    let mut tokens = Vec::new();
+    for (transfer, rule, _) in candidates {
+        for aggregate in &rule.config.aggregates {
+            kinds.push(aggregate.kind);
+            chain_ids.push(transfer.chain_id);
+            addresses.push(match aggregate.side {
+                RuleSide::From => transfer.from_address.as_slice(),
+                RuleSide::To => transfer.to_address.as_slice(),
+            });
+            directions.push(aggregate.direction.as_str());
+            timestamps.push(transfer.block_timestamp);
+            windows.push(aggregate.window_secs as f64);
+            tokens.push(aggregate.same_token.then_some(transfer.token_symbol.as_str()));
+        }
+    }
+    if kinds.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows: Vec<(i64, i64, f64, f64, i64)> = sqlx::query_as(
+        "SELECT a.n - 1, COUNT(t.id),
+                COALESCE(SUM(t.amount / power(10::NUMERIC, t.token_decimals)), 0)::FLOAT8,
+                COALESCE(SUM(t.amount_usd), 0)::FLOAT8,
+                COUNT(DISTINCT CASE WHEN t.from_address = a.address THEN t.to_address
+                                    ELSE t.from_address END)
+         FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::TEXT[], $4::TIMESTAMPTZ[], $5::FLOAT8[],
+                     $6::TEXT[])
+              WITH ORDINALITY AS a(chain_id, address, direction, at, window_secs, token, n)
+         LEFT JOIN transfers t
+           ON t.chain_id = a.chain_id
+          AND ((a.direction <> 'in' AND t.from_address = a.address)
+               OR (a.direction <> 'out' AND t.to_address = a.address))
+          AND t.block_timestamp > a.at - make_interval(secs => a.window_secs)
+          AND t.block_timestamp <= a.at
+          AND (a.token IS NULL OR t.token_symbol = a.token)
+         GROUP BY a.n",
+    )
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .bind(&directions)
+    .bind(&timestamps)
+    .bind(&windows)
+    .bind(&tokens)
+    .fetch_all(pool)
+    .await?;
+
+    let mut values = vec![Value::Null; kinds.len()];
+    for (index, count, sum, sum_usd, counterparties) in rows {
+        let index = index as usize;
+        values[index] = Value::Num(match kinds[index] {
+            AggregateKind::Count => count as f64,
+            AggregateKind::Sum => sum,
+            AggregateKind::SumUsd => sum_usd,
+            AggregateKind::Counterparties => counterparties as f64,
+        });
+    }
+    Ok(values)
+}
+
+/// Record each rule version's definition. A version is immutable once recorded:
+/// loading a changed definition under an existing version fails, so anomalies can
+/// always be traced back to the exact rule that raised them.
+pub async fn register_rules(pool: &PgPool, rules: &[CustomRule]) -> eyre::Result<()> {
+    for rule in rules {
+        let definition = serde_json::to_value(&rule.config)?;
+        sqlx::query(
+            "INSERT INTO anomaly_rules (name, version, definition, description)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (name, version) DO NOTHING",
+        )
+        .bind(&rule.config.name)
+        .bind(rule.config.version as i32)
+        .bind(&definition)
+        .bind(&rule.config.description)
+        .execute(pool)
+        .await?;
+
+        let (stored,): (serde_json::Value,) = sqlx::query_as(
+            "SELECT definition FROM anomaly_rules WHERE name = $1 AND version = $2",
+        )
+        .bind(&rule.config.name)
+        .bind(rule.config.version as i32)
+        .fetch_one(pool)
+        .await?;
+
+        if stored != definition {
+            return Err(eyre::eyre!(
+                "Custom rule '{}' version {} differs from the definition recorded earlier; bump its version",
+                rule.config.name,
+                rule.config.version
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_rules_file_is_valid() {
+        let content = std::fs::read_to_string("data/anomaly_rules.toml").unwrap();
+        let file: CustomRulesFile = toml::from_str(&content).unwrap();
+        assert!(!file.rules.is_empty());
+        for rule in file.rules {
+            compile_rule(rule).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let rule = |when: &str, flags: &[&str]| CustomRuleConfig {
+            name: "test_rule".to_string(),
+            version: 1,
+            enabled: true,
+            description: String::new(),
+            when: when.to_string(),
+            score: "50".to_string(),
+            flags: flags.iter().map(|f| f.to_string()).collect(),
+            address: None,
+            aggregates: Vec::new(),
+        };
+
+        assert!(compile_rule(rule("amount > 10", &["big_{amount}"])).is_ok());
+        assert!(compile_rule(rule("amount + 10", &[])).is_err());
+        assert!(compile_rule(rule("amount > 10", &["big_{amnt}"])).is_err());
+        assert!(compile_rule(rule("volume_24h > 10", &[])).is_err());
+
+        let mut builtin = rule("amount > 10", &[]);
+        builtin.name = "velocity".to_string();
+        assert!(compile_rule(builtin).is_err());
+    }
+}
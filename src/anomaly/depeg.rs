@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config::DepegConfig;
+use crate::indexer::types::StablecoinTransfer;
+
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// A stored stablecoin price window trading outside the band.
+#[derive(Debug, sqlx::FromRow)]
+struct DepegWindow {
+    token_address: Vec<u8>,
+    token_symbol: String,
+    window_start: i64,
+    window_blocks: i64,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    price_usd: f64,
+    peg_usd: f64,
+    usd_volume: f64,
+    swap_count: i32,
+    /// The window's first stored transfer of the token, which the anomaly is attached to.
+    transfer_id: i64,
+    block_timestamp: DateTime<Utc>,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+}
+
+/// Flag price windows touched by the batch where a token's swap-implied price strays more
+/// than `band` from its peg, on enough volume to trust.
+///
+/// Windows are the ones the pricing stage stored for this batch. Each is reported on the
+/// first stored transfer of its token inside the window, which later batches extending the
+/// window do not change, so a window recomputed across batches is stored once. The token is
+/// the address, so consecutive depegged windows fold into a single group.
+pub async fn check_depeg(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    config: &DepegConfig,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let mut chains: BTreeMap<i64, (BTreeSet<Vec<u8>>, i64, i64)> = BTreeMap::new();
+    for t in transfers.iter().filter(|t| t.id.is_some()) {
+        let span = chains
+            .entry(t.chain_id)
+            .or_insert_with(|| (BTreeSet::new(), t.block_number, t.block_number));
+        span.0.insert(t.token_address.clone());
+        span.1 = span.1.min(t.block_number);
+        span.2 = span.2.max(t.block_number);
+    }
+
+    let mut anomalies = Vec::new();
+    for (chain_id, (tokens, first, last)) in chains {
+        let tokens: Vec<Vec<u8>> = tokens.into_iter().collect();
+        let windows = load_depeg_windows(pool, chain_id, &tokens, first, last, config).await?;
+
+        for window in windows {
+            let deviation = deviation(window.price_usd, window.peg_usd);
+            if deviation.abs() <= config.band {
+                continue;
+            }
+            let side = if deviation < 0.0 { "below" } else { "above" };
+            anomalies.push(AnomalyRecord {
+                chain_id,
+                anomaly_type: AnomalyType::StablecoinDepeg,
+                risk_score: depeg_risk(deviation, config.band),
+                flags: vec![format!(
+                    "{}_{}_peg_{:.2}pct",
+                    window.token_symbol.to_lowercase(),
+                    side,
+                    deviation.abs() * 100.0
+                )],
+                details: serde_json::json!({
+                    "token_symbol": window.token_symbol,
+                    "token_address": hex::encode(&window.token_address),
+                    "window_start_block": window.window_start,
+                    "window_blocks": window.window_blocks,
+                    "window_started_at": window.started_at,
+                    "window_ended_at": window.ended_at,
+                    "price_usd": window.price_usd,
+                    "peg_usd": window.peg_usd,
+                    "deviation": deviation,
+                    "band": config.band,
+                    "usd_volume": window.usd_volume,
+                    "swap_count": window.swap_count,
+                }),
+                address: Some(window.token_address),
+                transfer_id: Some(window.transfer_id),
+                block_timestamp: window.block_timestamp,
+                tx_hash: window.tx_hash,
+                log_index: window.log_index,
+            });
+        }
+    }
+
+    Ok(anomalies)
+}
+
+/// Pegged price windows of `tokens` overlapping blocks `[first, last]` with enough volume
+/// and swaps to judge, each with its first stored transfer of the token.
+async fn load_depeg_windows(
+    pool: &PgPool,
+    chain_id: i64,
+    tokens: &[Vec<u8>],
+    first: i64,
+    last: i64,
+    config: &DepegConfig,
+) -> eyre::Result<Vec<DepegWindow>> {
+    let windows: Vec<DepegWindow> = sqlx::query_as(
+        "SELECT p.token_address, p.token_symbol, p.window_start,
+                p.window_blocks::BIGINT AS window_blocks, p.started_at, p.ended_at,
+                p.price_usd::FLOAT8 AS price_usd, p.peg_usd::FLOAT8 AS peg_usd,
+                p.usd_volume::FLOAT8 AS usd_volume, p.swap_count,
+                t.id AS transfer_id, t.block_timestamp, t.tx_hash, t.log_index
+         FROM stablecoin_prices p
+         JOIN LATERAL (
+             SELECT id, block_timestamp, tx_hash, log_index FROM transfers
+             WHERE chain_id = p.chain_id AND token_address = p.token_address
+               AND block_number >= p.window_start
+               AND block_number < p.window_start + p.window_blocks
+             ORDER BY block_number, log_index
+             LIMIT 1
+         ) t ON TRUE
+         WHERE p.chain_id = $1 AND p.token_address = ANY($2)
+           AND p.window_start <= $4 AND p.window_start + p.window_blocks > $3
+           AND p.peg_usd > 0
+           AND p.usd_volume >= $5::FLOAT8::NUMERIC AND p.swap_count >= $6",
+    )
+    .bind(chain_id)
+    .bind(tokens)
+    .bind(first)
+    .bind(last)
+    .bind(config.min_usd_volume)
+    .bind(config.min_swaps as i32)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(windows)
+}
+
+/// Signed distance of `price` from `peg`, as a fraction of the peg.
+fn deviation(price: f64, peg: f64) -> f64 {
+    price / peg - 1.0
+}
+
+/// 60 at the edge of the band, rising 10 per further band width up to 95.
+fn depeg_risk(deviation: f64, band: f64) -> f32 {
+    (50.0 + 10.0 * deviation.abs() / band).min(95.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depeg_risk_scales_with_band_widths() {
+        assert!((deviation(0.97, 1.0) + 0.03).abs() < 1e-9);
+        assert!((deviation(1.1, 1.1)).abs() < 1e-9);
+        assert_eq!(depeg_risk(-0.01, 0.01), 60.0);
+        assert_eq!(depeg_risk(0.02, 0.01), 70.0);
+        assert_eq!(depeg_risk(-0.5, 0.01), 95.0);
+    }
+}
@@ -0,0 +1,646 @@
+//! Expression language for custom anomaly rules.
+//!
+//! ```text
+//! amount >= 50000 and "exchange" in from.entity_types and to.wallet_age_secs < 86400
+//! min(40 + amount / 10000, 90)
+//! ```
+//!
+//! Operators, loosest first: `or`, `and`, `not`, comparisons (`== != < <= > >= in`),
+//! `+ -`, `* /`, unary `-`. Functions: `min`, `max`, `abs`, `log10`.
+//! Expressions are type-checked against the rule's identifiers when rules are loaded.
+//! A missing value (e.g. the age of a wallet that was never recorded) is null: arithmetic
+//! on null yields null and comparisons against null are false.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Static type of an expression or identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Num,
+    Str,
+    Bool,
+    StrList,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Num => "number",
+            Type::Str => "string",
+            Type::Bool => "bool",
+            Type::StrList => "list of strings",
+        })
+    }
+}
+
+/// Runtime value. `Unknown` stands in for values not fetched yet; see [`Expr::eval`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Unknown,
+    Num(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null | Value::Unknown => serde_json::Value::Null,
+            Value::Num(n) => serde_json::json!(n),
+            Value::Str(s) => serde_json::json!(s),
+            Value::Bool(b) => serde_json::json!(b),
+            Value::List(items) => items.iter().map(Value::to_json).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null | Value::Unknown => f.write_str("null"),
+            Value::Num(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{:.0}", n),
+            Value::Num(n) => write!(f, "{:.2}", n),
+            Value::Str(s) => f.write_str(s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                let parts: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                f.write_str(&parts.join(","))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Min,
+    Max,
+    Abs,
+    Log10,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<Expr>),
+    Ident(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parse an expression.
+    pub fn parse(source: &str) -> eyre::Result<Expr> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or_expr()?;
+        match parser.peek() {
+            Token::End => Ok(expr),
+            other => Err(eyre::eyre!("Unexpected {} after end of expression", other)),
+        }
+    }
+
+    /// Every identifier referenced by the expression, in order of first appearance.
+    pub fn identifiers(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        self.collect_identifiers(&mut out);
+        out
+    }
+
+    fn collect_identifiers<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Ident(name) => {
+                if !out.contains(&name.as_str()) {
+                    out.push(name);
+                }
+            }
+            Expr::List(items) | Expr::Call(_, items) => {
+                items.iter().for_each(|e| e.collect_identifiers(out))
+            }
+            Expr::Not(e) | Expr::Neg(e) => e.collect_identifiers(out),
+            Expr::Binary(_, l, r) => {
+                l.collect_identifiers(out);
+                r.collect_identifiers(out);
+            }
+            Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) => {}
+        }
+    }
+
+    /// Infer the expression's type, rejecting unknown identifiers and mismatched operands.
+    pub fn check(&self, schema: &HashMap<String, Type>) -> eyre::Result<Type> {
+        match self {
+            Expr::Num(_) => Ok(Type::Num),
+            Expr::Str(_) => Ok(Type::Str),
+            Expr::Bool(_) => Ok(Type::Bool),
+            Expr::List(items) => {
+                for item in items {
+                    expect(item, Type::Str, schema, "list item")?;
+                }
+                Ok(Type::StrList)
+            }
+            Expr::Ident(name) => schema
+                .get(name)
+                .copied()
+                .ok_or_else(|| eyre::eyre!("Unknown identifier '{}'", name)),
+            Expr::Not(e) => expect(e, Type::Bool, schema, "operand of 'not'").map(|_| Type::Bool),
+            Expr::Neg(e) => expect(e, Type::Num, schema, "operand of '-'").map(|_| Type::Num),
+            Expr::Binary(op, l, r) => match op {
+                BinOp::Or | BinOp::And => {
+                    expect(l, Type::Bool, schema, "operand of 'and'/'or'")?;
+                    expect(r, Type::Bool, schema, "operand of 'and'/'or'")?;
+                    Ok(Type::Bool)
+                }
+                BinOp::Eq | BinOp::Ne => {
+                    let lt = l.check(schema)?;
+                    let rt = r.check(schema)?;
+                    if lt != rt || lt == Type::StrList {
+                        return Err(eyre::eyre!("Cannot compare {} with {}", lt, rt));
+                    }
+                    Ok(Type::Bool)
+                }
+                BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                    expect(l, Type::Num, schema, "operand of an ordering comparison")?;
+                    expect(r, Type::Num, schema, "operand of an ordering comparison")?;
+                    Ok(Type::Bool)
+                }
+                BinOp::In => {
+                    expect(l, Type::Str, schema, "left side of 'in'")?;
+                    expect(r, Type::StrList, schema, "right side of 'in'")?;
+                    Ok(Type::Bool)
+                }
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => {
+                    expect(l, Type::Num, schema, "arithmetic operand")?;
+                    expect(r, Type::Num, schema, "arithmetic operand")?;
+                    Ok(Type::Num)
+                }
+            },
+            Expr::Call(func, args) => {
+                let arity_ok = match func {
+                    Func::Min | Func::Max => args.len() >= 2,
+                    Func::Abs | Func::Log10 => args.len() == 1,
+                };
+                if !arity_ok {
+                    return Err(eyre::eyre!(
+                        "Wrong number of arguments to {:?} ({})",
+                        func,
+                        args.len()
+                    ));
+                }
+                for arg in args {
+                    expect(arg, Type::Num, schema, "function argument")?;
+                }
+                Ok(Type::Num)
+            }
+        }
+    }
+
+    /// Evaluate against an environment. Identifiers missing from `env` evaluate to
+    /// `Value::Unknown`, which uses three-valued logic: `false and unknown` is false,
+    /// `true or unknown` is true, and anything else touching unknown stays unknown.
+    /// That lets a rule be pre-screened before its database-backed inputs are fetched.
+    pub fn eval(&self, env: &HashMap<&str, Value>) -> Value {
+        match self {
+            Expr::Num(n) => Value::Num(*n),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::List(items) => Value::List(items.iter().map(|e| e.eval(env)).collect()),
+            Expr::Ident(name) => env.get(name.as_str()).cloned().unwrap_or(Value::Unknown),
+            Expr::Not(e) => match e.eval(env) {
+                Value::Bool(b) => Value::Bool(!b),
+                Value::Unknown => Value::Unknown,
+                _ => Value::Bool(false),
+            },
+            Expr::Neg(e) => match e.eval(env) {
+                Value::Num(n) => Value::Num(-n),
+                other => null_or_unknown(&other),
+            },
+            Expr::Binary(BinOp::And, l, r) => match l.eval(env) {
+                Value::Bool(true) => truthy(r.eval(env)),
+                Value::Unknown => match r.eval(env) {
+                    Value::Bool(false) | Value::Null => Value::Bool(false),
+                    _ => Value::Unknown,
+                },
+                _ => Value::Bool(false),
+            },
+            Expr::Binary(BinOp::Or, l, r) => match l.eval(env) {
+                Value::Bool(true) => Value::Bool(true),
+                Value::Unknown => match r.eval(env) {
+                    Value::Bool(true) => Value::Bool(true),
+                    _ => Value::Unknown,
+                },
+                _ => truthy(r.eval(env)),
+            },
+            Expr::Binary(op, l, r) => binary(*op, l.eval(env), r.eval(env)),
+            Expr::Call(func, args) => {
+                let mut nums = Vec::with_capacity(args.len());
+                for arg in args {
+                    match arg.eval(env) {
+                        Value::Num(n) => nums.push(n),
+                        other => return null_or_unknown(&other),
+                    }
+                }
+                let result = match func {
+                    Func::Min => nums.iter().copied().fold(f64::INFINITY, f64::min),
+                    Func::Max => nums.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    Func::Abs => nums[0].abs(),
+                    Func::Log10 => nums[0].log10(),
+                };
+                if result.is_finite() {
+                    Value::Num(result)
+                } else {
+                    Value::Null
+                }
+            }
+        }
+    }
+}
+
+fn expect(expr: &Expr, ty: Type, schema: &HashMap<String, Type>, what: &str) -> eyre::Result<()> {
+    let actual = expr.check(schema)?;
+    if actual != ty {
+        return Err(eyre::eyre!("Expected {} as {}, found {}", ty, what, actual));
+    }
+    Ok(())
+}
+
+fn truthy(value: Value) -> Value {
+    match value {
+        Value::Bool(b) => Value::Bool(b),
+        Value::Unknown => Value::Unknown,
+        _ => Value::Bool(false),
+    }
+}
+
+fn null_or_unknown(value: &Value) -> Value {
+    if *value == Value::Unknown {
+        Value::Unknown
+    } else {
+        Value::Null
+    }
+}
+
+fn binary(op: BinOp, l: Value, r: Value) -> Value {
+    if l == Value::Unknown || r == Value::Unknown {
+        return Value::Unknown;
+    }
+
+    match (op, l, r) {
+        (BinOp::Add, Value::Num(a), Value::Num(b)) => Value::Num(a + b),
+        (BinOp::Sub, Value::Num(a), Value::Num(b)) => Value::Num(a - b),
+        (BinOp::Mul, Value::Num(a), Value::Num(b)) => Value::Num(a * b),
+        (BinOp::Div, Value::Num(a), Value::Num(b)) if b != 0.0 => Value::Num(a / b),
+        (BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div, _, _) => Value::Null,
+        (_, Value::Null, _) | (_, _, Value::Null) => Value::Bool(false),
+        (BinOp::Eq, a, b) => Value::Bool(a == b),
+        (BinOp::Ne, a, b) => Value::Bool(a != b),
+        (BinOp::Lt, Value::Num(a), Value::Num(b)) => Value::Bool(a < b),
+        (BinOp::Le, Value::Num(a), Value::Num(b)) => Value::Bool(a <= b),
+        (BinOp::Gt, Value::Num(a), Value::Num(b)) => Value::Bool(a > b),
+        (BinOp::Ge, Value::Num(a), Value::Num(b)) => Value::Bool(a >= b),
+        (BinOp::In, needle, Value::List(items)) => Value::Bool(items.contains(&needle)),
+        _ => Value::Bool(false),
+    }
+}
+
+// ============================================================
+// Tokenizer and parser
+// ============================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "number {}", n),
+            Token::Str(s) => write!(f, "string \"{}\"", s),
+            Token::Ident(s) => write!(f, "'{}'", s),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::End => f.write_str("end of expression"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 15] = [
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "(", ")", "[", "]", ",",
+];
+
+fn tokenize(source: &str) -> eyre::Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let n = text
+                .parse::<f64>()
+                .map_err(|_| eyre::eyre!("Invalid number '{}' at offset {}", text, start))?;
+            tokens.push(Token::Num(n));
+        } else if c == '"' {
+            let start = i;
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(eyre::eyre!("Unterminated string at offset {}", start)),
+                    Some('"') => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&ch) => {
+                        text.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push(Token::Str(text));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| eyre::eyre!("Unexpected character '{}' at offset {}", c, i))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+
+    tokens.push(Token::End);
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Token::Ident(s) if s == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Token::Op(o) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> eyre::Result<()> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(eyre::eyre!("Expected '{}', found {}", op, self.peek()))
+        }
+    }
+
+    fn or_expr(&mut self) -> eyre::Result<Expr> {
+        let mut left = self.and_expr()?;
+        while self.eat_keyword("or") {
+            left = Expr::Binary(BinOp::Or, Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> eyre::Result<Expr> {
+        let mut left = self.not_expr()?;
+        while self.eat_keyword("and") {
+            left = Expr::Binary(BinOp::And, Box::new(left), Box::new(self.not_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> eyre::Result<Expr> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> eyre::Result<Expr> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Token::Op("==") => BinOp::Eq,
+            Token::Op("!=") => BinOp::Ne,
+            Token::Op("<") => BinOp::Lt,
+            Token::Op("<=") => BinOp::Le,
+            Token::Op(">") => BinOp::Gt,
+            Token::Op(">=") => BinOp::Ge,
+            Token::Ident(s) if s == "in" => BinOp::In,
+            _ => return Ok(left),
+        };
+        self.next();
+        let right = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> eyre::Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.eat_op("+") {
+                BinOp::Add
+            } else if self.eat_op("-") {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> eyre::Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat_op("*") {
+                BinOp::Mul
+            } else if self.eat_op("/") {
+                BinOp::Div
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> eyre::Result<Expr> {
+        if self.eat_op("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> eyre::Result<Expr> {
+        match self.next() {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Op("(") => {
+                let inner = self.or_expr()?;
+                self.expect_op(")")?;
+                Ok(inner)
+            }
+            Token::Op("[") => {
+                let mut items = Vec::new();
+                if !self.eat_op("]") {
+                    loop {
+                        items.push(self.or_expr()?);
+                        if self.eat_op("]") {
+                            break;
+                        }
+                        self.expect_op(",")?;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "and" | "or" | "not" | "in" => {
+                    Err(eyre::eyre!("Unexpected keyword '{}'", name))
+                }
+                _ if self.eat_op("(") => {
+                    let func = match name.as_str() {
+                        "min" => Func::Min,
+                        "max" => Func::Max,
+                        "abs" => Func::Abs,
+                        "log10" => Func::Log10,
+                        _ => return Err(eyre::eyre!("Unknown function '{}'", name)),
+                    };
+                    let mut args = Vec::new();
+                    if !self.eat_op(")") {
+                        loop {
+                            args.push(self.or_expr()?);
+                            if self.eat_op(")") {
+                                break;
+                            }
+                            self.expect_op(",")?;
+                        }
+                    }
+                    Ok(Expr::Call(func, args))
+                }
+                _ => Ok(Expr::Ident(name)),
+            },
+            other => Err(eyre::eyre!("Unexpected {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> HashMap<String, Type> {
+        HashMap::from([
+            ("amount".to_string(), Type::Num),
+            ("token".to_string(), Type::Str),
+            ("from.entity_types".to_string(), Type::StrList),
+            ("to.wallet_age_secs".to_string(), Type::Num),
+        ])
+    }
+
+    #[test]
+    fn test_precedence_and_evaluation() {
+        let expr = Expr::parse("1 + 2 * 3 >= 7 and not token == \"DAI\"").unwrap();
+        assert_eq!(expr.check(&schema()).unwrap(), Type::Bool);
+
+        let env = HashMap::from([("token", Value::Str("USDC".into()))]);
+        assert_eq!(expr.eval(&env), Value::Bool(true));
+
+        let score = Expr::parse("min(40 + amount / 10_000, 90)").unwrap();
+        let env = HashMap::from([("amount", Value::Num(250_000.0))]);
+        assert_eq!(score.eval(&env), Value::Num(65.0));
+    }
+
+    #[test]
+    fn test_type_errors_are_rejected() {
+        let check = |src: &str| Expr::parse(src).unwrap().check(&schema());
+        assert!(check("amount + token").is_err());
+        assert!(check("token in from.entity_types").is_ok());
+        assert!(check("amount in from.entity_types").is_err());
+        assert!(check("sender_volume > 5").is_err());
+        assert!(Expr::parse("amount >").is_err());
+        assert!(Expr::parse("median(amount, 1)").is_err());
+    }
+
+    #[test]
+    fn test_unknown_and_null_values() {
+        let expr = Expr::parse("amount > 1000 and to.wallet_age_secs < 3600").unwrap();
+
+        // Small transfer: rejected without knowing the wallet age
+        let env = HashMap::from([("amount", Value::Num(10.0))]);
+        assert_eq!(expr.eval(&env), Value::Bool(false));
+
+        // Large transfer: undecided until the wallet age is fetched
+        let env = HashMap::from([("amount", Value::Num(5000.0))]);
+        assert_eq!(expr.eval(&env), Value::Unknown);
+
+        // Wallet never recorded: comparisons against null are false
+        let env = HashMap::from([("amount", Value::Num(5000.0)), ("to.wallet_age_secs", Value::Null)]);
+        assert_eq!(expr.eval(&env), Value::Bool(false));
+    }
+}
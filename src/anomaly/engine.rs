@@ -1,24 +1,100 @@
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
 
-use crate::config::AnomalyDetectionConfig;
+use crate::config::{AggregationConfig, AnomalyDetectionConfig, ChainConfig};
 use crate::entity::label_store::EntityLabelStore;
+use crate::graph::exposure;
 use crate::indexer::types::StablecoinTransfer;
+use crate::wallet::baseline;
 use crate::wallet::first_seen::NewWalletEvent;
 
+use super::activity::ActivityWindows;
+use super::custom::{self, CustomRule};
+use super::depeg;
+use super::fan::{self, FanDirection};
+use super::flash_loan;
+use super::groups;
+use super::issuance;
+use super::pass_through::{self, PassThroughWindow};
+use super::peel_chain;
 use super::rules;
+use super::suppression;
 use super::types::AnomalyRecord;
 
 /// The anomaly detection engine. Runs all configured rules against a batch of transfers.
 pub struct AnomalyEngine {
     config: AnomalyDetectionConfig,
+    custom_rules: Vec<CustomRule>,
+    pass_through_windows: HashMap<i64, PassThroughWindow>,
 }
 
 impl AnomalyEngine {
-    pub fn new(config: AnomalyDetectionConfig) -> Self {
-        Self { config }
+    /// Create the engine, loading and validating custom rules if a rules file is configured.
+    /// `chains` resolves per-chain rule settings that are keyed by chain name.
+    pub fn new(config: AnomalyDetectionConfig, chains: &[ChainConfig]) -> eyre::Result<Self> {
+        let custom_rules = match &config.custom_rules_path {
+            Some(path) => custom::load_rules(path)?,
+            None => Vec::new(),
+        };
+        let pass_through_windows = pass_through::resolve_windows(&config.pass_through, chains);
+        Ok(Self {
+            config,
+            custom_rules,
+            pass_through_windows,
+        })
     }
 
-    /// Analyze a batch of transfers for anomalies.
+    /// Enabled custom rules, in file order.
+    pub fn custom_rules(&self) -> &[CustomRule] {
+        &self.custom_rules
+    }
+
+    pub fn config(&self) -> &AnomalyDetectionConfig {
+        &self.config
+    }
+
+    /// Settings for grouping repeated anomalies, passed to `persist_anomalies`.
+    pub fn aggregation(&self) -> &AggregationConfig {
+        &self.config.aggregation
+    }
+
+    /// Fold a processed batch into the stored wallet baselines. Call once the batch's
+    /// anomalies are persisted. Returns the number of baselines written.
+    pub async fn record_baselines(
+        &self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<u64> {
+        if !self.config.enabled || !self.config.baseline.enabled {
+            return Ok(0);
+        }
+        baseline::update_baselines(pool, transfers, &self.config.baseline).await
+    }
+
+    /// Hold back anomalies covered by an active suppression rule, recording each hit
+    /// unless `record` is false. Returns the anomalies to persist and the suppressed ones
+    /// with the id of the rule that matched.
+    pub async fn suppress(
+        &self,
+        pool: &PgPool,
+        anomalies: Vec<AnomalyRecord>,
+        transfers: &[StablecoinTransfer],
+        label_store: &EntityLabelStore,
+        record: bool,
+    ) -> eyre::Result<(Vec<AnomalyRecord>, Vec<(i64, AnomalyRecord)>)> {
+        if anomalies.is_empty() {
+            return Ok((anomalies, Vec::new()));
+        }
+        let rules = suppression::load_active(pool).await?;
+        let (kept, suppressed) = suppression::partition(&rules, anomalies, transfers, label_store);
+        if record {
+            suppression::record_hits(pool, &suppressed).await?;
+        }
+        Ok((kept, suppressed))
+    }
+
+    /// Analyze a batch of transfers for anomalies. `windows` must already hold the batch.
     /// Returns all detected anomaly records.
     pub async fn analyze_batch(
         &self,
@@ -26,6 +102,7 @@ impl AnomalyEngine {
         transfers: &[StablecoinTransfer],
         label_store: &EntityLabelStore,
         new_wallets: &[NewWalletEvent],
+        windows: &ActivityWindows,
     ) -> eyre::Result<Vec<AnomalyRecord>> {
         if !self.config.enabled {
             return Ok(Vec::new());
@@ -33,6 +110,24 @@ impl AnomalyEngine {
 
         let mut anomalies = Vec::new();
 
+        // Stored sanctions exposure for every address in the batch, in one query
+        let exposures = if self.config.sanctions_exposure.enabled {
+            let addresses: Vec<&[u8]> = transfers
+                .iter()
+                .flat_map(|t| [t.from_address.as_slice(), t.to_address.as_slice()])
+                .collect();
+            exposure::load_exposures(pool, &addresses).await?
+        } else {
+            HashMap::new()
+        };
+
+        // Stored baselines for both sides of every transfer, folded forward as the loop runs
+        let mut baselines = if self.config.baseline.enabled {
+            baseline::load_baselines(pool, transfers).await?
+        } else {
+            HashMap::new()
+        };
+
         for transfer in transfers {
             // Rule 1: Large transfer
             if !self.config.large_transfer_thresholds.is_empty() {
@@ -48,6 +143,38 @@ impl AnomalyEngine {
                 anomalies.push(anomaly);
             }
 
+            // Rule 2b: Indirect sanctions exposure (precomputed by the exposure stage)
+            if self.config.sanctions_exposure.enabled {
+                if let Some(anomaly) = rules::check_sanctions_exposure(
+                    transfer,
+                    label_store,
+                    &exposures,
+                    &self.config.sanctions_exposure,
+                ) {
+                    anomalies.push(anomaly);
+                }
+            }
+
+            // Rule 2c: Deviation from the wallets' own baselines. Transfers already folded
+            // into a stored baseline were scored when they were first processed.
+            if self.config.baseline.enabled {
+                let folded = transfer.id.is_some_and(|id| {
+                    baseline::sides(transfer).iter().any(|(wallet, _)| {
+                        baselines
+                            .get(&(transfer.chain_id, wallet.to_vec()))
+                            .is_some_and(|b| id <= b.last_transfer_id)
+                    })
+                });
+                if !folded {
+                    if let Some(anomaly) =
+                        rules::check_baseline_deviation(transfer, &baselines, &self.config.baseline)
+                    {
+                        anomalies.push(anomaly);
+                    }
+                }
+                baseline::fold_transfer(&mut baselines, transfer, &self.config.baseline);
+            }
+
             // Rule 3: Round number
             if let Some(anomaly) =
                 rules::check_round_number(transfer, self.config.round_number.tolerance)
@@ -64,78 +191,325 @@ impl AnomalyEngine {
                 anomalies.push(anomaly);
             }
 
-            // Rule 5: Velocity (requires DB query — only run if batch is small enough)
-            if transfers.len() <= 100 {
-                if let Some(anomaly) = rules::check_velocity(
-                    pool,
+            // Rule 5: Velocity (in-memory window)
+            if let Some(anomaly) = rules::check_velocity(
+                transfer,
+                windows,
+                self.config.velocity.window_secs,
+                self.config.velocity.max_transfers,
+            ) {
+                anomalies.push(anomaly);
+            }
+
+            // Rule 6: Cross-chain activity (in-memory window)
+            if let Some(anomaly) =
+                rules::check_cross_chain_activity(transfer, windows, self.config.cross_chain.window_secs)
+            {
+                anomalies.push(anomaly);
+            }
+
+            // Rule 12: Large mints and issuance to unknown wallets
+            if self.config.issuance.enabled {
+                let issuance = &self.config.issuance;
+                anomalies.extend(issuance::check_large_mint(transfer, label_store, issuance));
+                anomalies.extend(issuance::check_issuance_recipient(
                     transfer,
-                    self.config.velocity.window_secs,
-                    self.config.velocity.max_transfers,
-                )
-                .await?
-                {
-                    anomalies.push(anomaly);
-                }
+                    label_store,
+                    issuance,
+                ));
             }
+        }
+
+        // Custom rules from the rules file, with their wallet ages and aggregates fetched
+        // for the whole batch
+        if !self.custom_rules.is_empty() {
+            anomalies.extend(
+                custom::evaluate_rules(pool, &self.custom_rules, transfers, label_store, new_wallets)
+                    .await?,
+            );
+        }
 
-            // Rule 6: Cross-chain activity (requires DB query — only run if batch is small)
-            if transfers.len() <= 50 {
-                if let Some(anomaly) = rules::check_cross_chain_activity(
+        // Rule 1b: Structuring just under the large-transfer threshold, one query for the
+        // batch's transfers that fall in the band
+        if !self.config.large_transfer_thresholds.is_empty() && self.config.structuring.enabled {
+            anomalies.extend(
+                rules::check_structuring(
                     pool,
-                    transfer,
-                    self.config.cross_chain.window_secs,
+                    transfers,
+                    &self.config.large_transfer_thresholds,
+                    &self.config.structuring,
                 )
-                .await?
-                {
-                    anomalies.push(anomaly);
-                }
+                .await?,
+            );
+        }
+
+        // Rule 7: Peel chains (graph-edge prefilter, then recursive walks over transfers)
+        if self.config.peel_chain.enabled {
+            anomalies.extend(
+                peel_chain::check_peel_chains(pool, transfers, label_store, &self.config.peel_chain)
+                    .await?,
+            );
+        }
+
+        // Rule 8: Rapid pass-through and layering, with per-chain windows
+        if self.config.pass_through.enabled {
+            anomalies.extend(
+                pass_through::check_pass_through(
+                    pool,
+                    transfers,
+                    &self.config.pass_through,
+                    &self.pass_through_windows,
+                )
+                .await?,
+            );
+        }
+
+        // Rule 9: Fan-in to a collector and fan-out from a distributor
+        for (config, direction) in [
+            (&self.config.fan_in, FanDirection::In),
+            (&self.config.fan_out, FanDirection::Out),
+        ] {
+            if config.enabled {
+                anomalies.extend(
+                    fan::check_fan(pool, transfers, label_store, config, direction).await?,
+                );
             }
         }
 
+        // Rule 12b: Burns of funds from risky sources
+        if self.config.issuance.enabled {
+            anomalies.extend(
+                issuance::check_burn_after_risky_inflow(
+                    pool,
+                    transfers,
+                    label_store,
+                    &self.config.issuance,
+                )
+                .await?,
+            );
+        }
+
+        // Rule 10: Flash loan arbitrage paying out to a fresh wallet, once per transaction
+        if self.config.flash_loan.enabled {
+            anomalies.extend(
+                flash_loan::check_flash_loans(pool, transfers, label_store, &self.config.flash_loan)
+                    .await?,
+            );
+        }
+
+        // Rule 11: Stablecoin trading off its peg in a price window the batch touches
+        if self.config.depeg.enabled {
+            anomalies.extend(depeg::check_depeg(pool, transfers, &self.config.depeg).await?);
+        }
+
         Ok(anomalies)
     }
 }
 
-/// Insert detected anomalies into the database.
+/// Insert detected anomalies into the database with a single UNNEST statement.
+/// Anomalies already recorded for the same transfer and type are skipped.
+/// New anomalies are then folded into their address's anomaly groups.
+/// Returns the ids of the newly stored anomalies and the anomalies they hold.
 pub async fn persist_anomalies(
+    pool: &PgPool,
+    mut anomalies: Vec<AnomalyRecord>,
+    aggregation: &AggregationConfig,
+) -> eyre::Result<(Vec<i64>, Vec<AnomalyRecord>)> {
+    let written = write_anomalies(pool, &anomalies, "DO NOTHING").await?;
+    let ids: Vec<i64> = written.iter().map(|(id, _)| *id).collect();
+    groups::assign_groups(pool, &ids, aggregation).await?;
+
+    // Anomalies without a transfer never conflict, so they are always among the new rows
+    let stored: HashSet<(Option<i64>, String)> = written.into_iter().map(|(_, key)| key).collect();
+    anomalies.retain(|a| stored.contains(&(a.transfer_id, a.anomaly_type.as_str().to_string())));
+    Ok((ids, anomalies))
+}
+
+/// A stored anomaly attached to a transfer, as loaded by `load_transfer_anomalies`.
+#[derive(Debug, Clone)]
+pub struct TransferAnomaly {
+    pub transfer_id: i64,
+    pub anomaly_type: String,
+    pub risk_score: f32,
+    /// An analyst moved it out of `open`, put it in a case or wrote a note on it.
+    pub triaged: bool,
+}
+
+/// SQL condition on `anomalies a` for rows an analyst has worked on. Notes and audit entries
+/// have no foreign key, so deleting such a row would orphan them.
+const TRIAGED_SQL: &str = "(a.status <> 'open' OR a.case_id IS NOT NULL
+     OR EXISTS (SELECT 1 FROM analyst_notes n WHERE n.anomaly_id = a.id))";
+
+/// Load stored anomalies attached to a set of transfers.
+pub async fn load_transfer_anomalies(
+    pool: &PgPool,
+    transfer_ids: &[i64],
+) -> eyre::Result<Vec<TransferAnomaly>> {
+    let rows: Vec<(i64, String, f32, bool)> = sqlx::query_as(&format!(
+        "SELECT a.transfer_id, a.anomaly_type, a.risk_score, {}
+         FROM anomalies a
+         WHERE a.transfer_id = ANY($1)",
+        TRIAGED_SQL
+    ))
+    .bind(transfer_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(transfer_id, anomaly_type, risk_score, triaged)| TransferAnomaly {
+            transfer_id,
+            anomaly_type,
+            risk_score,
+            triaged,
+        })
+        .collect())
+}
+
+/// Insert or refresh anomalies in bulk. Each (transfer, type) pair must appear at most once.
+/// Existing rows keep their id, resolution state and group; score, flags and details are
+/// replaced. New anomalies are grouped as in `persist_anomalies`.
+pub async fn upsert_anomalies(
     pool: &PgPool,
     anomalies: &[AnomalyRecord],
+    aggregation: &AggregationConfig,
 ) -> eyre::Result<u64> {
-    let mut count = 0u64;
-
-    for anomaly in anomalies {
-        // Look up the transfer ID
-        let transfer_id: Option<(i64,)> = sqlx::query_as(
-            "SELECT id FROM transfers
-             WHERE chain_id = $1 AND tx_hash = $2 AND log_index = $3",
-        )
-        .bind(anomaly.chain_id)
-        .bind(&anomaly.tx_hash)
-        .bind(anomaly.log_index)
-        .fetch_optional(pool)
-        .await?;
+    let ids: Vec<i64> = write_anomalies(
+        pool,
+        anomalies,
+        "DO UPDATE SET risk_score = EXCLUDED.risk_score, flags = EXCLUDED.flags,
+                       details = EXCLUDED.details, address = EXCLUDED.address",
+    )
+    .await?
+    .into_iter()
+    .map(|(id, _)| id)
+    .collect();
+    groups::assign_groups(pool, &ids, aggregation).await?;
+    Ok(ids.len() as u64)
+}
 
-        let transfer_id = transfer_id.map(|(id,)| id);
+/// Delete anomalies by (transfer_id, anomaly_type), keeping group counts in step.
+/// Triaged anomalies are kept, so analyst work is never lost to a re-run.
+pub async fn delete_transfer_anomalies(
+    pool: &PgPool,
+    keys: &[(i64, String)],
+) -> eyre::Result<u64> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+
+    let transfer_ids: Vec<i64> = keys.iter().map(|(id, _)| *id).collect();
+    let anomaly_types: Vec<&str> = keys.iter().map(|(_, t)| t.as_str()).collect();
+
+    // Deleted members no longer count towards their group
+    let deleted: i64 = sqlx::query_scalar(&format!(
+        "WITH deleted AS (
+             DELETE FROM anomalies a
+             USING UNNEST($1::BIGINT[], $2::TEXT[]) AS k(transfer_id, anomaly_type)
+             WHERE a.transfer_id = k.transfer_id AND a.anomaly_type = k.anomaly_type
+               AND NOT {}
+             RETURNING a.group_id
+         ), regrouped AS (
+             UPDATE anomaly_groups g
+             SET anomaly_count = GREATEST(g.anomaly_count - d.n, 0), updated_at = NOW()
+             FROM (SELECT group_id, COUNT(*)::INT AS n FROM deleted
+                   WHERE group_id IS NOT NULL GROUP BY group_id) d
+             WHERE g.id = d.group_id
+         )
+         SELECT COUNT(*) FROM deleted",
+        TRIAGED_SQL
+    ))
+    .bind(&transfer_ids)
+    .bind(&anomaly_types)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(deleted as u64)
+}
+
+/// Detach triaged anomalies from the transfers a reorg rolls back, so deleting those
+/// transfers keeps them instead of cascading. Each is flagged `reorged` and keeps its old
+/// transfer id in the details; untriaged anomalies go with their transfers.
+pub async fn detach_triaged_anomalies(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+    from_block: i64,
+) -> eyre::Result<u64> {
+    let detached = sqlx::query(&format!(
+        "UPDATE anomalies a
+         SET transfer_id = NULL,
+             flags = array_append(a.flags, 'reorged'),
+             details = COALESCE(a.details, '{{}}'::JSONB)
+                 || jsonb_build_object('reorged_transfer_id', a.transfer_id,
+                                       'reorged_block', t.block_number)
+         FROM transfers t
+         WHERE a.chain_id = $1 AND a.transfer_id = t.id AND a.block_timestamp = t.block_timestamp
+           AND t.chain_id = $1 AND t.block_number >= $2
+           AND {}",
+        TRIAGED_SQL
+    ))
+    .bind(chain_id)
+    .bind(from_block)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    Ok(detached)
+}
+
+/// Shared UNNEST insert for anomalies with the given ON CONFLICT action.
+/// Flags travel as JSONB arrays because Postgres arrays of arrays must be rectangular.
+/// Returns the id and (transfer_id, anomaly_type) of each row written.
+async fn write_anomalies(
+    pool: &PgPool,
+    anomalies: &[AnomalyRecord],
+    on_conflict: &str,
+) -> eyre::Result<Vec<(i64, (Option<i64>, String))>> {
+    let mut ids = Vec::new();
 
-        let flags: Vec<&str> = anomaly.flags.iter().map(|s| s.as_str()).collect();
+    for chunk in anomalies.chunks(5000) {
+        let transfer_ids: Vec<Option<i64>> = chunk.iter().map(|a| a.transfer_id).collect();
+        let chain_ids: Vec<i64> = chunk.iter().map(|a| a.chain_id).collect();
+        let timestamps: Vec<DateTime<Utc>> = chunk.iter().map(|a| a.block_timestamp).collect();
+        let anomaly_types: Vec<&str> = chunk.iter().map(|a| a.anomaly_type.as_str()).collect();
+        let risk_scores: Vec<f32> = chunk.iter().map(|a| a.risk_score).collect();
+        let flags: Vec<serde_json::Value> = chunk
+            .iter()
+            .map(|a| serde_json::json!(a.flags))
+            .collect();
+        let details: Vec<&serde_json::Value> = chunk.iter().map(|a| &a.details).collect();
+        let addresses: Vec<Option<&[u8]>> = chunk.iter().map(|a| a.address.as_deref()).collect();
 
-        let result = sqlx::query(
-            "INSERT INTO anomalies (transfer_id, chain_id, anomaly_type, risk_score, flags, details, address)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)
-             ON CONFLICT (transfer_id, anomaly_type) DO NOTHING",
-        )
-        .bind(transfer_id)
-        .bind(anomaly.chain_id)
-        .bind(anomaly.anomaly_type.as_str())
-        .bind(anomaly.risk_score)
+        let written: Vec<(i64, Option<i64>, String)> = sqlx::query_as(&format!(
+            "INSERT INTO anomalies (transfer_id, chain_id, block_timestamp, anomaly_type, risk_score,
+                                    flags, details, address)
+             SELECT i.transfer_id, i.chain_id, i.block_timestamp, i.anomaly_type, i.risk_score,
+                    ARRAY(SELECT jsonb_array_elements_text(i.flags)), i.details, i.address
+             FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::TEXT[], $5::REAL[],
+                         $6::JSONB[], $7::JSONB[], $8::BYTEA[])
+                  AS i(transfer_id, chain_id, block_timestamp, anomaly_type, risk_score, flags,
+                       details, address)
+             ON CONFLICT (transfer_id, anomaly_type, chain_id, block_timestamp) {}
+             RETURNING id, transfer_id, anomaly_type",
+            on_conflict
+        ))
+        .bind(&transfer_ids)
+        .bind(&chain_ids)
+        .bind(&timestamps)
+        .bind(&anomaly_types)
+        .bind(&risk_scores)
         .bind(&flags)
-        .bind(&anomaly.details)
-        .bind(&anomaly.address)
-        .execute(pool)
+        .bind(&details)
+        .bind(&addresses)
+        .fetch_all(pool)
         .await?;
 
-        count += result.rows_affected();
+        ids.extend(
+            written
+                .into_iter()
+                .map(|(id, transfer_id, anomaly_type)| (id, (transfer_id, anomaly_type))),
+        );
     }
 
-    Ok(count)
+    Ok(ids)
 }
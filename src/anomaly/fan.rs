@@ -0,0 +1,512 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::FanPatternConfig;
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// Counterparties listed in an anomaly's details.
+const TOP_COUNTERPARTIES: usize = 10;
+
+/// Which side of the transfer is the hub.
+#[derive(Debug, Clone, Copy)]
+pub enum FanDirection {
+    /// The receiver collects from many senders.
+    In,
+    /// The sender distributes to many receivers.
+    Out,
+}
+
+impl FanDirection {
+    fn hub_column(self) -> &'static str {
+        match self {
+            Self::In => "to_address",
+            Self::Out => "from_address",
+        }
+    }
+
+    /// The transfer's (hub, counterparty) addresses.
+    fn sides(self, transfer: &StablecoinTransfer) -> (&[u8], &[u8]) {
+        match self {
+            Self::In => (&transfer.to_address, &transfer.from_address),
+            Self::Out => (&transfer.from_address, &transfer.to_address),
+        }
+    }
+
+    fn counterparty_column(self) -> &'static str {
+        match self {
+            Self::In => "from_address",
+            Self::Out => "to_address",
+        }
+    }
+}
+
+/// A hub in the batch and its transfers there, by id.
+struct Hub<'a> {
+    chain_id: i64,
+    address: &'a [u8],
+    batch: HashMap<i64, &'a StablecoinTransfer>,
+}
+
+/// A stored transfer of one of the batch's hubs, with its counterparty and USD value.
+#[derive(sqlx::FromRow)]
+struct HubTransfer {
+    hub: i64,
+    counterparty: Vec<u8>,
+    id: i64,
+    block_timestamp: DateTime<Utc>,
+    usd: f64,
+}
+
+/// A hub's counterparties in the window, largest amount first, as (address, transfers, usd),
+/// with the totals the rule gates on.
+#[derive(Debug)]
+struct FanWindow {
+    counterparties: Vec<(Vec<u8>, i64, f64)>,
+    total: f64,
+    top_share: f64,
+}
+
+/// Check whether the batch's transfers bring their hub to at least the distinct-counterparty
+/// threshold within the window.
+///
+/// Raised once per episode: on the first transfer whose new counterparty leaves the window at
+/// or above the threshold with enough combined value and no counterparty dominating the
+/// amounts (`max_top_share`). A hub that passes again only fires once a full window has gone
+/// by without passing, folding into its anomaly group. Both only look back two windows, so
+/// the result does not depend on how transfers are batched. Hubs with an entity label
+/// (exchanges, payment processors) use `labelled_min_counterparties`. Each hub's transfers
+/// over the windows of all its batch transfers are read in one query, and the window is
+/// slid over them in memory.
+pub async fn check_fan(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    config: &FanPatternConfig,
+    direction: FanDirection,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let mut hubs: Vec<Hub> = Vec::new();
+    let mut hub_index: HashMap<(i64, &[u8]), usize> = HashMap::new();
+    for transfer in transfers {
+        let Some(id) = transfer.id else {
+            continue;
+        };
+        let (hub, counterparty) = direction.sides(transfer);
+        if hub == counterparty {
+            continue;
+        }
+        let key = (transfer.chain_id, hub);
+        let index = *hub_index.entry(key).or_insert_with(|| {
+            hubs.push(Hub {
+                chain_id: transfer.chain_id,
+                address: hub,
+                batch: HashMap::new(),
+            });
+            hubs.len() - 1
+        });
+        hubs[index].batch.insert(id, transfer);
+    }
+    if hubs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let window = Duration::seconds(config.window_secs as i64);
+    let chain_ids: Vec<i64> = hubs.iter().map(|h| h.chain_id).collect();
+    let addresses: Vec<&[u8]> = hubs.iter().map(|h| h.address).collect();
+    // Two windows back, as whether an earlier transfer passed depends on its own window
+    let sinces: Vec<DateTime<Utc>> = hubs
+        .iter()
+        .map(|h| h.batch.values().map(|t| t.block_timestamp).min().unwrap() - window * 2)
+        .collect();
+    let untils: Vec<DateTime<Utc>> = hubs
+        .iter()
+        .map(|h| h.batch.values().map(|t| t.block_timestamp).max().unwrap())
+        .collect();
+
+    let rows: Vec<HubTransfer> = sqlx::query_as(&format!(
+        "SELECT h.n - 1 AS hub, t.{cp} AS counterparty, t.id, t.block_timestamp,
+                COALESCE(t.amount_usd, 0)::FLOAT8 AS usd
+         FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::TIMESTAMPTZ[], $4::TIMESTAMPTZ[])
+              WITH ORDINALITY AS h(chain_id, hub, since, until, n)
+         JOIN transfers t
+           ON t.chain_id = h.chain_id AND t.{hub} = h.hub AND t.{cp} <> h.hub
+          AND t.block_timestamp > h.since AND t.block_timestamp <= h.until
+         ORDER BY h.n, t.block_timestamp, t.id",
+        cp = direction.counterparty_column(),
+        hub = direction.hub_column(),
+    ))
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .bind(&sinces)
+    .bind(&untils)
+    .fetch_all(pool)
+    .await?;
+
+    let mut fired: Vec<(&StablecoinTransfer, usize, FanWindow)> = Vec::new();
+    for hub_rows in rows.chunk_by(|a, b| a.hub == b.hub) {
+        let hub = hub_rows[0].hub as usize;
+        let Hub { address, batch, .. } = &hubs[hub];
+        let threshold = if label_store.lookup(address).unwrap_or_default().is_empty() {
+            config.min_counterparties
+        } else {
+            config.labelled_min_counterparties
+        } as usize;
+
+        for (id, fan) in crossings(hub_rows, window, threshold, config) {
+            if let Some(transfer) = batch.get(&id) {
+                fired.push((transfer, threshold, fan));
+            }
+        }
+    }
+
+    let fresh = match direction {
+        FanDirection::In => vec![None; fired.len()],
+        FanDirection::Out => fresh_counts(pool, &fired, config.window_secs)
+            .await?
+            .into_iter()
+            .map(Some)
+            .collect(),
+    };
+
+    Ok(fired
+        .into_iter()
+        .zip(fresh)
+        .map(|((transfer, threshold, window), fresh)| {
+            fan_anomaly(
+                transfer,
+                label_store,
+                config,
+                direction,
+                threshold,
+                window,
+                fresh,
+            )
+        })
+        .collect())
+}
+
+/// Slide the window over a hub's stored transfers, oldest first, and return the ids that
+/// pass `fan_window` with no other passing transfer in the window before them, with the
+/// window at that point. Each transfer's result only depends on the rows up to two windows
+/// before it, never on the batch.
+fn crossings(
+    hub_rows: &[HubTransfer],
+    window: Duration,
+    threshold: usize,
+    config: &FanPatternConfig,
+) -> Vec<(i64, FanWindow)> {
+    let mut crossed = Vec::new();
+    // (transfers, usd) per counterparty over the rows from `start` to the current one
+    let mut counts: HashMap<&[u8], (i64, f64)> = HashMap::new();
+    let mut start = 0;
+    let mut last_passed: Option<DateTime<Utc>> = None;
+    for row in hub_rows {
+        let entry = counts.entry(&row.counterparty).or_default();
+        entry.0 += 1;
+        entry.1 += row.usd;
+        while hub_rows[start].block_timestamp <= row.block_timestamp - window {
+            let old = &hub_rows[start];
+            let entry = counts.get_mut(old.counterparty.as_slice()).unwrap();
+            entry.0 -= 1;
+            entry.1 -= old.usd;
+            if entry.0 == 0 {
+                counts.remove(old.counterparty.as_slice());
+            }
+            start += 1;
+        }
+
+        if let Some(fan) = fan_window(&counts, &row.counterparty, threshold, config) {
+            if last_passed.is_none_or(|at| at <= row.block_timestamp - window) {
+                crossed.push((row.id, fan));
+            }
+            last_passed = Some(row.block_timestamp);
+        }
+    }
+    crossed
+}
+
+/// Gate a hub's window on the transfer that adds `counterparty`: this one new to the window
+/// and the window at `threshold` counterparties or more, with enough combined value and no
+/// single counterparty above `max_top_share`.
+fn fan_window(
+    counts: &HashMap<&[u8], (i64, f64)>,
+    counterparty: &[u8],
+    threshold: usize,
+    config: &FanPatternConfig,
+) -> Option<FanWindow> {
+    if counts.len() < threshold || counts.get(counterparty).map(|(n, _)| *n) != Some(1) {
+        return None;
+    }
+
+    let total: f64 = counts.values().map(|(_, amount)| amount).sum();
+    if total < config.min_total_amount || total <= 0.0 {
+        return None;
+    }
+
+    let mut counterparties: Vec<(Vec<u8>, i64, f64)> = counts
+        .iter()
+        .map(|(address, (count, amount))| (address.to_vec(), *count, *amount))
+        .collect();
+    counterparties.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    let top_share = counterparties[0].2 / total;
+    if top_share > config.max_top_share {
+        return None;
+    }
+
+    Some(FanWindow {
+        counterparties,
+        total,
+        top_share,
+    })
+}
+
+/// Recipients of each fan-out first seen within the window, in one query.
+async fn fresh_counts(
+    pool: &PgPool,
+    fired: &[(&StablecoinTransfer, usize, FanWindow)],
+    window_secs: u64,
+) -> eyre::Result<Vec<usize>> {
+    let mut indexes = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut addresses = Vec::new();
+    let mut timestamps = Vec::new();
+    for (i, (transfer, _, window)) in fired.iter().enumerate() {
+        for (address, ..) in &window.counterparties {
+            indexes.push(i as i64);
+            chain_ids.push(transfer.chain_id);
+            addresses.push(address.as_slice());
+            timestamps.push(transfer.block_timestamp);
+        }
+    }
+
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT r.fan, COUNT(*)
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BYTEA[], $4::TIMESTAMPTZ[])
+              AS r(fan, chain_id, address, at)
+         JOIN wallet_first_seen w ON w.chain_id = r.chain_id AND w.address = r.address
+         WHERE w.first_seen_at > r.at - make_interval(secs => $5)
+         GROUP BY r.fan",
+    )
+    .bind(&indexes)
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .bind(&timestamps)
+    .bind(window_secs as f64)
+    .fetch_all(pool)
+    .await?;
+
+    let mut counts = vec![0; fired.len()];
+    for (fan, fresh) in rows {
+        counts[fan as usize] = fresh as usize;
+    }
+    Ok(counts)
+}
+
+/// Build the anomaly for a hub whose window passed `fan_window`.
+fn fan_anomaly(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    config: &FanPatternConfig,
+    direction: FanDirection,
+    threshold: usize,
+    window: FanWindow,
+    fresh_counterparties: Option<usize>,
+) -> AnomalyRecord {
+    let (hub, _) = direction.sides(transfer);
+    let hub_labels = label_store.lookup(hub).unwrap_or_default();
+    let FanWindow {
+        counterparties: rows,
+        total,
+        top_share,
+    } = window;
+    // Herfindahl index of the amount shares: 1/n when perfectly spread, 1 for a single party
+    let concentration: f64 = rows.iter().map(|(_, _, a)| (a / total).powi(2)).sum();
+
+    let top: Vec<serde_json::Value> = rows
+        .iter()
+        .take(TOP_COUNTERPARTIES)
+        .map(|(address, count, amount)| {
+            let entities: Vec<&str> = label_store
+                .lookup(address)
+                .unwrap_or_default()
+                .iter()
+                .map(|l| l.entity_name.as_str())
+                .collect();
+            serde_json::json!({
+                "address": hex::encode(address),
+                "transfer_count": count,
+                "amount": amount,
+                "share": amount / total,
+                "entities": entities,
+            })
+        })
+        .collect();
+
+    let labelled = !hub_labels.is_empty();
+    let (anomaly_type, name) = match direction {
+        FanDirection::In => (AnomalyType::FanIn, "fan_in"),
+        FanDirection::Out => (AnomalyType::FanOut, "fan_out"),
+    };
+
+    let mut risk: f32 = if labelled { 35.0 } else { 55.0 };
+    // Evenly spread amounts look scripted rather than organic
+    if concentration <= 2.0 / rows.len() as f64 {
+        risk += 10.0;
+    }
+    let mut flags = vec![format!(
+        "{}_{}_counterparties_{:.0}_in_{}s",
+        name,
+        rows.len(),
+        total,
+        config.window_secs
+    )];
+    if let Some(fresh) = fresh_counterparties {
+        let fresh_share = fresh as f64 / rows.len() as f64;
+        risk += (25.0 * fresh_share) as f32;
+        if fresh * 2 > rows.len() {
+            flags.push(format!("{}_{}_fresh_recipients", name, fresh));
+        }
+    }
+
+    AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type,
+        risk_score: risk,
+        flags,
+        details: serde_json::json!({
+            "hub": hex::encode(hub),
+            "hub_entities": hub_labels.iter().map(|l| &l.entity_name).collect::<Vec<_>>(),
+            "labelled_hub": labelled,
+            "threshold": threshold,
+            "window_secs": config.window_secs,
+            "distinct_counterparties": rows.len(),
+            "fresh_counterparties": fresh_counterparties,
+            "total_amount": total,
+            "top_share": top_share,
+            "concentration": concentration,
+            "top_counterparties": top,
+        }),
+        address: Some(hub.to_vec()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_fan_fires_once_when_the_window_reaches_the_threshold() {
+        let config = FanPatternConfig {
+            min_total_amount: 0.0,
+            ..FanPatternConfig::default()
+        };
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let rows: Vec<HubTransfer> = (0..5u8)
+            .map(|i| HubTransfer {
+                hub: 0,
+                counterparty: vec![i; 20],
+                id: i as i64 + 1,
+                block_timestamp: start + Duration::seconds(i as i64),
+                usd: 100.0,
+            })
+            .collect();
+
+        // Only the third counterparty crosses, however the five transfers were batched
+        let crossed = crossings(&rows, Duration::hours(1), 3, &config);
+        let ids: Vec<i64> = crossed.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![3]);
+        assert_eq!(crossed[0].1.counterparties.len(), 3);
+
+        // Once the window empties out, a new burst fires again
+        let mut later: Vec<HubTransfer> = rows
+            .iter()
+            .map(|r| HubTransfer {
+                counterparty: r.counterparty.clone(),
+                block_timestamp: r.block_timestamp + Duration::hours(2),
+                id: r.id + 10,
+                ..*r
+            })
+            .collect();
+        let mut all = rows;
+        all.append(&mut later);
+        let ids: Vec<i64> = crossings(&all, Duration::hours(1), 3, &config)
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, vec![3, 13]);
+    }
+
+    #[test]
+    fn test_fan_fires_when_the_total_catches_up_after_the_count() {
+        let config = FanPatternConfig {
+            min_total_amount: 450.0,
+            ..FanPatternConfig::default()
+        };
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let rows: Vec<HubTransfer> = (0..7u8)
+            .map(|i| HubTransfer {
+                hub: 0,
+                counterparty: vec![i; 20],
+                id: i as i64 + 1,
+                block_timestamp: start + Duration::minutes(i as i64),
+                usd: 100.0,
+            })
+            .collect();
+
+        // Three counterparties are reached at the third transfer, $450 only at the fifth
+        let crossed = crossings(&rows, Duration::hours(1), 3, &config);
+        let ids: Vec<i64> = crossed.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![5]);
+        assert_eq!(crossed[0].1.total, 500.0);
+        // The sixth and seventh pass too, but the hub already fired within the window
+    }
+
+    #[test]
+    fn test_fan_window_threshold_and_top_share() {
+        let config = FanPatternConfig {
+            min_total_amount: 1_000.0,
+            ..FanPatternConfig::default()
+        };
+        let (a, b, c) = ([1u8; 20], [2u8; 20], [3u8; 20]);
+        let counts = HashMap::from([
+            (&a[..], (1, 400.0)),
+            (&b[..], (2, 400.0)),
+            (&c[..], (1, 300.0)),
+        ]);
+
+        // Fires when a counterparty new to the window brings it to the threshold
+        let window = fan_window(&counts, &a, 3, &config).unwrap();
+        assert_eq!(window.total, 1_100.0);
+        assert_eq!(window.counterparties[0].0, a.to_vec());
+        assert_eq!(window.counterparties[2].0, c.to_vec());
+        assert!(fan_window(&counts, &a, 4, &config).is_none());
+        assert!(fan_window(&counts, &a, 2, &config).is_some());
+        // A counterparty seen before in the window does not re-trigger the hub
+        assert!(fan_window(&counts, &b, 3, &config).is_none());
+
+        // One counterparty dominating the amounts is not a fan
+        let dominated = HashMap::from([
+            (&a[..], (1, 5_000.0)),
+            (&b[..], (1, 400.0)),
+            (&c[..], (1, 300.0)),
+        ]);
+        assert!(fan_window(&dominated, &c, 3, &config).is_none());
+        let relaxed = FanPatternConfig {
+            max_top_share: 0.9,
+            ..config.clone()
+        };
+        assert!(fan_window(&dominated, &c, 3, &relaxed).is_some());
+
+        // Too little value in total
+        let small = HashMap::from([(&a[..], (1, 300.0)), (&b[..], (1, 300.0))]);
+        assert!(fan_window(&small, &a, 2, &config).is_none());
+    }
+}
@@ -0,0 +1,401 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::config::FlashLoanConfig;
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+use super::rules::ZERO_ADDRESS;
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// A flash loan decoded from the transaction's logs.
+#[derive(Debug, Clone)]
+struct Loan {
+    protocol: String,
+    lender: Vec<u8>,
+    receiver: Vec<u8>,
+    asset: Vec<u8>,
+    amount: BigDecimal,
+    repaid: bool,
+}
+
+/// A flash loan or swap stored for a batch transaction.
+#[derive(Debug, sqlx::FromRow)]
+struct EventRow {
+    tx_hash: Vec<u8>,
+    protocol: String,
+    event_type: String,
+    contract_address: Vec<u8>,
+    account: Option<Vec<u8>>,
+    token_out: Option<Vec<u8>>,
+    amount_out: Option<BigDecimal>,
+    repaid: bool,
+}
+
+/// A watched transfer in a flash loan transaction.
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct TxTransfer {
+    tx_hash: Vec<u8>,
+    id: i64,
+    token_address: Vec<u8>,
+    from_address: Vec<u8>,
+    to_address: Vec<u8>,
+    amount: BigDecimal,
+    amount_usd: f64,
+}
+
+/// The loans, swap pools and watched transfers of one transaction.
+#[derive(Debug, Default)]
+struct FlashLoanTx {
+    loans: Vec<Loan>,
+    pools: BTreeSet<Vec<u8>>,
+    transfers: Vec<TxTransfer>,
+}
+
+/// Transaction hashes and block timestamps of a chain's batch transfers.
+type BatchTxs = (BTreeSet<Vec<u8>>, BTreeSet<DateTime<Utc>>);
+
+/// Who kept the proceeds of a flash loan transaction.
+#[derive(Debug, PartialEq)]
+struct Profit {
+    recipient: Vec<u8>,
+    amount_usd: f64,
+    /// Largest transfer paying the recipient, which the anomaly is attached to.
+    transfer_id: i64,
+}
+
+/// Find transactions in the batch that borrow with a flash loan, swap through at least
+/// `min_pools` pools and repay within the transaction, and flag those leaving at least
+/// `min_profit_usd` with a fresh wallet.
+///
+/// Profit is the largest net inflow of watched tokens to an address other than the lenders
+/// and the pools, so legs in unwatched tokens are not counted. Each transaction is
+/// reported once, on its largest transfer to the recipient, by the batch holding that
+/// transfer.
+pub async fn check_flash_loans(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    config: &FlashLoanConfig,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let batch: HashMap<i64, &StablecoinTransfer> =
+        transfers.iter().filter_map(|t| Some((t.id?, t))).collect();
+    let mut chains: BTreeMap<i64, BatchTxs> = BTreeMap::new();
+    for transfer in batch.values() {
+        let (tx_hashes, timestamps) = chains.entry(transfer.chain_id).or_default();
+        tx_hashes.insert(transfer.tx_hash.clone());
+        timestamps.insert(transfer.block_timestamp);
+    }
+
+    let mut anomalies = Vec::new();
+    for (chain_id, (tx_hashes, timestamps)) in chains {
+        let txs = load_flash_loan_txs(pool, chain_id, &tx_hashes, &timestamps).await?;
+
+        let mut profits = Vec::new();
+        for (tx_hash, tx) in &txs {
+            let Some(profit) = find_profit(tx, config.min_pools as usize) else {
+                continue;
+            };
+            if profit.amount_usd >= config.min_profit_usd && batch.contains_key(&profit.transfer_id)
+            {
+                profits.push((tx_hash, tx, profit));
+            }
+        }
+        if profits.is_empty() {
+            continue;
+        }
+
+        let recipients: Vec<&[u8]> = profits
+            .iter()
+            .map(|(_, _, p)| p.recipient.as_slice())
+            .collect();
+        let first_seen: HashMap<Vec<u8>, DateTime<Utc>> = sqlx::query_as(
+            "SELECT address, first_seen_at FROM wallet_first_seen
+             WHERE chain_id = $1 AND address = ANY($2)",
+        )
+        .bind(chain_id)
+        .bind(&recipients)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        for (tx_hash, tx, profit) in profits {
+            let transfer = batch[&profit.transfer_id];
+            let fresh_window = Duration::seconds(config.fresh_wallet_secs as i64);
+            let recipient_first_seen = first_seen.get(&profit.recipient).copied();
+            let fresh = recipient_first_seen
+                .is_some_and(|seen| seen >= transfer.block_timestamp - fresh_window);
+            if !fresh {
+                continue;
+            }
+
+            let protocols: BTreeSet<&str> = tx.loans.iter().map(|l| l.protocol.as_str()).collect();
+            let loans: Vec<serde_json::Value> = tx
+                .loans
+                .iter()
+                .map(|loan| {
+                    serde_json::json!({
+                        "protocol": loan.protocol,
+                        "lender": hex::encode(&loan.lender),
+                        "receiver": hex::encode(&loan.receiver),
+                        "asset": hex::encode(&loan.asset),
+                        "amount": loan.amount.to_string(),
+                    })
+                })
+                .collect();
+            let entities: Vec<&str> = label_store
+                .lookup(&profit.recipient)
+                .unwrap_or_default()
+                .iter()
+                .map(|l| l.entity_name.as_str())
+                .collect();
+
+            let mut flags = vec![format!(
+                "flash_loan_profit_{:.0}_usd_via_{}_pools",
+                profit.amount_usd,
+                tx.pools.len()
+            )];
+            flags.extend(protocols.iter().map(|p| format!("flash_loan_{}", p)));
+
+            anomalies.push(AnomalyRecord {
+                chain_id,
+                anomaly_type: AnomalyType::FlashLoanProfit,
+                risk_score: if profit.amount_usd >= config.min_profit_usd * 10.0 {
+                    90.0
+                } else {
+                    80.0
+                },
+                flags,
+                details: serde_json::json!({
+                    "tx_hash": hex::encode(tx_hash),
+                    "loans": loans,
+                    "pools": tx.pools.iter().map(hex::encode).collect::<Vec<_>>(),
+                    "recipient": hex::encode(&profit.recipient),
+                    "recipient_entities": entities,
+                    "recipient_first_seen": recipient_first_seen,
+                    "fresh_wallet_secs": config.fresh_wallet_secs,
+                    "profit_usd": profit.amount_usd,
+                    "watched_transfers": tx.transfers.len(),
+                }),
+                address: Some(profit.recipient),
+                transfer_id: Some(profit.transfer_id),
+                block_timestamp: transfer.block_timestamp,
+                tx_hash: transfer.tx_hash.clone(),
+                log_index: transfer.log_index,
+            });
+        }
+    }
+
+    Ok(anomalies)
+}
+
+/// Load the flash loans, swaps and watched transfers of those of `tx_hashes` that took a
+/// flash loan. Transfers are read in log order.
+async fn load_flash_loan_txs(
+    pool: &PgPool,
+    chain_id: i64,
+    tx_hashes: &BTreeSet<Vec<u8>>,
+    timestamps: &BTreeSet<DateTime<Utc>>,
+) -> eyre::Result<BTreeMap<Vec<u8>, FlashLoanTx>> {
+    let tx_hashes: Vec<&[u8]> = tx_hashes.iter().map(Vec::as_slice).collect();
+    let events: Vec<EventRow> = sqlx::query_as(
+        "SELECT tx_hash, protocol, event_type, contract_address, account, token_out, amount_out,
+                    amount_in IS NOT NULL AS repaid
+             FROM defi_events
+             WHERE chain_id = $1 AND tx_hash = ANY($2) AND event_type IN ('flash_loan', 'swap')
+             ORDER BY tx_hash, log_index",
+    )
+    .bind(chain_id)
+    .bind(&tx_hashes)
+    .fetch_all(pool)
+    .await?;
+
+    let mut txs: BTreeMap<Vec<u8>, FlashLoanTx> = BTreeMap::new();
+    for event in events {
+        let tx = txs.entry(event.tx_hash).or_default();
+        if event.event_type == "swap" {
+            tx.pools.insert(event.contract_address);
+            continue;
+        }
+        let (Some(receiver), Some(asset), Some(amount)) =
+            (event.account, event.token_out, event.amount_out)
+        else {
+            continue;
+        };
+        tx.loans.push(Loan {
+            protocol: event.protocol,
+            lender: event.contract_address,
+            receiver,
+            asset,
+            amount,
+            repaid: event.repaid,
+        });
+    }
+    txs.retain(|_, tx| !tx.loans.is_empty());
+    if txs.is_empty() {
+        return Ok(txs);
+    }
+
+    let loan_txs: Vec<&[u8]> = txs.keys().map(Vec::as_slice).collect();
+    let timestamps: Vec<DateTime<Utc>> = timestamps.iter().copied().collect();
+    // Unpriced transfers add nothing to the profit
+    let rows: Vec<TxTransfer> = sqlx::query_as(
+        "SELECT id, tx_hash, token_address, from_address, to_address, amount,
+                COALESCE(amount_usd, 0)::FLOAT8 AS amount_usd
+         FROM transfers
+         WHERE chain_id = $1 AND tx_hash = ANY($2) AND block_timestamp = ANY($3)
+         ORDER BY log_index",
+    )
+    .bind(chain_id)
+    .bind(&loan_txs)
+    .bind(&timestamps)
+    .fetch_all(pool)
+    .await?;
+
+    for row in rows {
+        if let Some(tx) = txs.get_mut(&row.tx_hash) {
+            tx.transfers.push(row);
+        }
+    }
+
+    Ok(txs)
+}
+
+/// The address keeping the most value from an atomic flash loan arbitrage: every loan
+/// repaid and at least `min_pools` pools swapped through. Lenders, the addresses paying
+/// out their loans (such as Aave's aTokens), the pools and the zero address are not
+/// candidates.
+fn find_profit(tx: &FlashLoanTx, min_pools: usize) -> Option<Profit> {
+    if tx.loans.is_empty() || !tx.loans.iter().all(|l| l.repaid) || tx.pools.len() < min_pools {
+        return None;
+    }
+
+    let mut excluded: BTreeSet<&[u8]> = tx.pools.iter().map(Vec::as_slice).collect();
+    excluded.insert(&ZERO_ADDRESS);
+    for loan in &tx.loans {
+        excluded.insert(&loan.lender);
+        excluded.extend(
+            tx.transfers
+                .iter()
+                .filter(|t| {
+                    t.token_address == loan.asset
+                        && t.to_address == loan.receiver
+                        && t.amount == loan.amount
+                })
+                .map(|t| t.from_address.as_slice()),
+        );
+    }
+
+    let mut net: BTreeMap<&[u8], f64> = BTreeMap::new();
+    for t in &tx.transfers {
+        *net.entry(&t.to_address).or_default() += t.amount_usd;
+        *net.entry(&t.from_address).or_default() -= t.amount_usd;
+    }
+    let (recipient, amount_usd) = net
+        .into_iter()
+        .filter(|(address, amount)| *amount > 0.0 && !excluded.contains(address))
+        .fold(
+            None,
+            |best: Option<(&[u8], f64)>, (address, amount)| match best {
+                Some((_, top)) if top >= amount => best,
+                _ => Some((address, amount)),
+            },
+        )?;
+
+    let payout = tx
+        .transfers
+        .iter()
+        .filter(|t| t.to_address == recipient)
+        .fold(None, |best: Option<&TxTransfer>, t| match best {
+            Some(top) if top.amount_usd >= t.amount_usd => best,
+            _ => Some(t),
+        })?;
+
+    Some(Profit {
+        recipient: recipient.to_vec(),
+        amount_usd,
+        transfer_id: payout.id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: u8 = 0xc0;
+
+    fn addr(n: u8) -> Vec<u8> {
+        vec![n; 20]
+    }
+
+    fn transfer(id: i64, from: u8, to: u8, amount: i64) -> TxTransfer {
+        TxTransfer {
+            tx_hash: Vec::new(),
+            id,
+            token_address: addr(USDC),
+            from_address: addr(from),
+            to_address: addr(to),
+            amount: BigDecimal::from(amount * 1_000_000),
+            amount_usd: amount as f64,
+        }
+    }
+
+    fn loan(lender: u8, receiver: u8, amount: i64, repaid: bool) -> Loan {
+        Loan {
+            protocol: "aave_v3".to_string(),
+            lender: addr(lender),
+            receiver: addr(receiver),
+            asset: addr(USDC),
+            amount: BigDecimal::from(amount * 1_000_000),
+            repaid,
+        }
+    }
+
+    #[test]
+    fn test_find_profit_follows_net_inflow_past_lenders_and_pools() {
+        // Aave pool 0xa1 lends through aToken 0xa2 to contract 0x0c, which swaps through
+        // pools 0x01 and 0x02, repays with a 900 premium and pays 80k to wallet 0xee
+        let mut tx = FlashLoanTx {
+            loans: vec![loan(0xa1, 0x0c, 1_000_000, true)],
+            pools: [addr(0x01), addr(0x02)].into(),
+            transfers: vec![
+                transfer(1, 0xa2, 0x0c, 1_000_000),
+                transfer(2, 0x0c, 0x01, 1_000_000),
+                transfer(3, 0x02, 0x0c, 1_080_900),
+                transfer(4, 0x0c, 0xa2, 1_000_900),
+                transfer(5, 0x0c, 0xee, 30_000),
+                transfer(6, 0x0c, 0xee, 50_000),
+            ],
+        };
+        assert_eq!(
+            find_profit(&tx, 2),
+            Some(Profit {
+                recipient: addr(0xee),
+                amount_usd: 80_000.0,
+                transfer_id: 6,
+            })
+        );
+        assert_eq!(find_profit(&tx, 3), None);
+
+        // The aToken's premium is not profit
+        tx.transfers.truncate(4);
+        tx.transfers[2] = transfer(3, 0x02, 0x0c, 1_000_900);
+        assert_eq!(find_profit(&tx, 2), None);
+    }
+
+    #[test]
+    fn test_find_profit_needs_every_loan_repaid() {
+        let tx = FlashLoanTx {
+            loans: vec![
+                loan(0xa1, 0x0c, 1_000, true),
+                loan(0xa1, 0x0c, 1_000, false),
+            ],
+            pools: [addr(0x01), addr(0x02)].into(),
+            transfers: vec![transfer(1, 0x02, 0xee, 5_000)],
+        };
+        assert_eq!(find_profit(&tx, 2), None);
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::AggregationConfig;
+
+type GroupKey = (i64, Vec<u8>, String);
+
+/// Group key, id, first seen and last seen of a stored group.
+type OpenGroupRow = (i64, Vec<u8>, String, i64, DateTime<Utc>, DateTime<Utc>);
+
+/// A stored anomaly that has not been assigned to a group yet.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UngroupedAnomaly {
+    pub id: i64,
+    pub chain_id: i64,
+    pub address: Vec<u8>,
+    pub anomaly_type: String,
+    pub block_timestamp: DateTime<Utc>,
+    pub risk_score: f32,
+    pub flags: Vec<String>,
+    pub details: serde_json::Value,
+}
+
+/// The stored group an anomaly may extend: the latest one for its key.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenGroup {
+    pub id: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// What one batch adds to a group. `group_id` is `None` for a group the batch starts.
+#[derive(Debug, Clone)]
+pub struct GroupDelta {
+    pub group_id: Option<i64>,
+    pub key: GroupKey,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub max_risk_score: f32,
+    /// Index into the planned anomalies of the latest member.
+    pub latest: usize,
+    pub members: Vec<i64>,
+}
+
+/// Assign anomalies (sorted by block timestamp) to groups. An anomaly joins its key's
+/// current group when it lands within the window of the group's span, otherwise it
+/// starts a new one.
+pub fn plan_groups(
+    anomalies: &[UngroupedAnomaly],
+    open: &HashMap<GroupKey, OpenGroup>,
+    config: &AggregationConfig,
+) -> Vec<GroupDelta> {
+    let mut deltas: Vec<GroupDelta> = Vec::new();
+    let mut current: HashMap<GroupKey, usize> = HashMap::new();
+
+    for (index, anomaly) in anomalies.iter().enumerate() {
+        let key = (anomaly.chain_id, anomaly.address.clone(), anomaly.anomaly_type.clone());
+        let window = Duration::seconds(config.window_for(&anomaly.anomaly_type) as i64);
+        let ts = anomaly.block_timestamp;
+        let within = |first: DateTime<Utc>, last: DateTime<Utc>| {
+            ts >= first - window && ts <= last + window
+        };
+
+        if let Some(&i) = current.get(&key) {
+            let delta = &mut deltas[i];
+            if within(delta.first_seen, delta.last_seen) {
+                delta.first_seen = delta.first_seen.min(ts);
+                if ts >= delta.last_seen {
+                    delta.last_seen = ts;
+                    delta.latest = index;
+                }
+                delta.max_risk_score = delta.max_risk_score.max(anomaly.risk_score);
+                delta.members.push(anomaly.id);
+                continue;
+            }
+        }
+
+        let group_id = match (current.contains_key(&key), open.get(&key)) {
+            (false, Some(group)) if within(group.first_seen, group.last_seen) => Some(group.id),
+            _ => None,
+        };
+        current.insert(key.clone(), deltas.len());
+        deltas.push(GroupDelta {
+            group_id,
+            key,
+            first_seen: ts,
+            last_seen: ts,
+            max_risk_score: anomaly.risk_score,
+            latest: index,
+            members: vec![anomaly.id],
+        });
+    }
+
+    deltas
+}
+
+/// Group newly stored anomalies by (chain, address, type) and time window, updating each
+/// group's running count, span, peak risk and latest evidence. Anomalies without an
+/// address, or already grouped, are left as they are. Returns the number grouped.
+pub async fn assign_groups(
+    pool: &PgPool,
+    anomaly_ids: &[i64],
+    config: &AggregationConfig,
+) -> eyre::Result<u64> {
+    if !config.enabled || anomaly_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let anomalies: Vec<UngroupedAnomaly> = sqlx::query_as(
+        "SELECT id, chain_id, address, anomaly_type, block_timestamp, risk_score, flags, details
+         FROM anomalies
+         WHERE id = ANY($1) AND group_id IS NULL AND address IS NOT NULL
+         ORDER BY block_timestamp, id",
+    )
+    .bind(anomaly_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+    if anomalies.is_empty() {
+        return Ok(0);
+    }
+
+    let mut key_chains = Vec::new();
+    let mut key_addresses = Vec::new();
+    let mut key_types = Vec::new();
+    for anomaly in &anomalies {
+        key_chains.push(anomaly.chain_id);
+        key_addresses.push(anomaly.address.as_slice());
+        key_types.push(anomaly.anomaly_type.as_str());
+    }
+    let open: Vec<OpenGroupRow> = sqlx::query_as(
+        "SELECT DISTINCT ON (g.chain_id, g.address, g.anomaly_type)
+                g.chain_id, g.address, g.anomaly_type, g.id, g.first_seen, g.last_seen
+         FROM anomaly_groups g
+         JOIN (SELECT DISTINCT * FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::TEXT[])) AS k(chain_id, address, anomaly_type)
+           ON g.chain_id = k.chain_id AND g.address = k.address AND g.anomaly_type = k.anomaly_type
+         ORDER BY g.chain_id, g.address, g.anomaly_type, g.last_seen DESC",
+    )
+    .bind(&key_chains)
+    .bind(&key_addresses)
+    .bind(&key_types)
+    .fetch_all(&mut *tx)
+    .await?;
+    let open: HashMap<GroupKey, OpenGroup> = open
+        .into_iter()
+        .map(|(chain_id, address, anomaly_type, id, first_seen, last_seen)| {
+            (
+                (chain_id, address, anomaly_type),
+                OpenGroup {
+                    id,
+                    first_seen,
+                    last_seen,
+                },
+            )
+        })
+        .collect();
+
+    let deltas = plan_groups(&anomalies, &open, config);
+
+    // Reserve ids for new groups so every delta is written by one upsert.
+    let new_groups = deltas.iter().filter(|d| d.group_id.is_none()).count() as i64;
+    let reserved: Vec<i64> = sqlx::query_scalar(
+        "SELECT nextval(pg_get_serial_sequence('anomaly_groups', 'id')) FROM generate_series(1, $1)",
+    )
+    .bind(new_groups)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut reserved = reserved.into_iter();
+
+    let mut ids = Vec::with_capacity(deltas.len());
+    let mut chain_ids = Vec::with_capacity(deltas.len());
+    let mut addresses = Vec::with_capacity(deltas.len());
+    let mut anomaly_types = Vec::with_capacity(deltas.len());
+    let mut first_seen = Vec::with_capacity(deltas.len());
+    let mut last_seen = Vec::with_capacity(deltas.len());
+    let mut counts = Vec::with_capacity(deltas.len());
+    let mut max_risks = Vec::with_capacity(deltas.len());
+    let mut latest_ids = Vec::with_capacity(deltas.len());
+    let mut latest_risks = Vec::with_capacity(deltas.len());
+    let mut latest_flags = Vec::with_capacity(deltas.len());
+    let mut latest_details = Vec::with_capacity(deltas.len());
+    let mut member_ids = Vec::new();
+    let mut member_groups = Vec::new();
+    for delta in &deltas {
+        let id = match delta.group_id {
+            Some(id) => id,
+            None => reserved
+                .next()
+                .ok_or_else(|| eyre::eyre!("Ran out of reserved anomaly group ids"))?,
+        };
+        let latest = &anomalies[delta.latest];
+        ids.push(id);
+        chain_ids.push(delta.key.0);
+        addresses.push(delta.key.1.as_slice());
+        anomaly_types.push(delta.key.2.as_str());
+        first_seen.push(delta.first_seen);
+        last_seen.push(delta.last_seen);
+        counts.push(delta.members.len() as i32);
+        max_risks.push(delta.max_risk_score);
+        latest_ids.push(latest.id);
+        latest_risks.push(latest.risk_score);
+        latest_flags.push(serde_json::json!(latest.flags));
+        latest_details.push(&latest.details);
+        for member in &delta.members {
+            member_ids.push(*member);
+            member_groups.push(id);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO anomaly_groups (id, chain_id, address, anomaly_type, first_seen, last_seen,
+                                     anomaly_count, max_risk_score, latest_anomaly_id,
+                                     latest_risk_score, latest_flags, latest_details)
+         SELECT i.id, i.chain_id, i.address, i.anomaly_type, i.first_seen, i.last_seen, i.count,
+                i.max_risk, i.latest_id, i.latest_risk,
+                ARRAY(SELECT jsonb_array_elements_text(i.latest_flags)), i.latest_details
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BYTEA[], $4::TEXT[], $5::TIMESTAMPTZ[],
+                     $6::TIMESTAMPTZ[], $7::INT[], $8::REAL[], $9::BIGINT[], $10::REAL[],
+                     $11::JSONB[], $12::JSONB[])
+              AS i(id, chain_id, address, anomaly_type, first_seen, last_seen, count, max_risk,
+                   latest_id, latest_risk, latest_flags, latest_details)
+         ON CONFLICT (id) DO UPDATE SET
+            first_seen = LEAST(anomaly_groups.first_seen, EXCLUDED.first_seen),
+            last_seen = GREATEST(anomaly_groups.last_seen, EXCLUDED.last_seen),
+            anomaly_count = anomaly_groups.anomaly_count + EXCLUDED.anomaly_count,
+            max_risk_score = GREATEST(anomaly_groups.max_risk_score, EXCLUDED.max_risk_score),
+            latest_anomaly_id = CASE WHEN EXCLUDED.last_seen >= anomaly_groups.last_seen
+                THEN EXCLUDED.latest_anomaly_id ELSE anomaly_groups.latest_anomaly_id END,
+            latest_risk_score = CASE WHEN EXCLUDED.last_seen >= anomaly_groups.last_seen
+                THEN EXCLUDED.latest_risk_score ELSE anomaly_groups.latest_risk_score END,
+            latest_flags = CASE WHEN EXCLUDED.last_seen >= anomaly_groups.last_seen
+                THEN EXCLUDED.latest_flags ELSE anomaly_groups.latest_flags END,
+            latest_details = CASE WHEN EXCLUDED.last_seen >= anomaly_groups.last_seen
+                THEN EXCLUDED.latest_details ELSE anomaly_groups.latest_details END,
+            updated_at = NOW()",
+    )
+    .bind(&ids)
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .bind(&anomaly_types)
+    .bind(&first_seen)
+    .bind(&last_seen)
+    .bind(&counts)
+    .bind(&max_risks)
+    .bind(&latest_ids)
+    .bind(&latest_risks)
+    .bind(&latest_flags)
+    .bind(&latest_details)
+    .execute(&mut *tx)
+    .await?;
+
+    let result = sqlx::query(
+        "UPDATE anomalies SET group_id = u.group_id
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS u(id, group_id)
+         WHERE anomalies.id = u.id",
+    )
+    .bind(&member_ids)
+    .bind(&member_groups)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anomaly(id: i64, address: u8, minutes: i64) -> UngroupedAnomaly {
+        UngroupedAnomaly {
+            id,
+            chain_id: 1,
+            address: vec![address],
+            anomaly_type: "velocity".into(),
+            block_timestamp: DateTime::from_timestamp(1_700_000_000 + minutes * 60, 0).unwrap(),
+            risk_score: id as f32,
+            flags: Vec::new(),
+            details: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_groups_split_on_window_and_extend_stored_group() {
+        let config = AggregationConfig {
+            enabled: true,
+            window_secs: 600,
+            windows: HashMap::new(),
+        };
+        let anomalies = vec![
+            anomaly(1, 0xaa, 0),
+            anomaly(2, 0xbb, 1),
+            anomaly(3, 0xaa, 5),
+            anomaly(4, 0xaa, 30),
+        ];
+        let stored = OpenGroup {
+            id: 77,
+            first_seen: anomalies[1].block_timestamp - Duration::minutes(20),
+            last_seen: anomalies[1].block_timestamp - Duration::minutes(8),
+        };
+        let open = HashMap::from([((1, vec![0xbb], "velocity".to_string()), stored)]);
+
+        let deltas = plan_groups(&anomalies, &open, &config);
+        assert_eq!(deltas.len(), 3);
+
+        // 0xaa at 0 and 5 minutes share a group; the 30 minute one starts another.
+        assert_eq!(deltas[0].group_id, None);
+        assert_eq!(deltas[0].members, vec![1, 3]);
+        assert_eq!(deltas[0].latest, 2);
+        assert_eq!(deltas[0].max_risk_score, 3.0);
+        assert_eq!(deltas[2].members, vec![4]);
+
+        // 0xbb lands 8 minutes after its stored group and extends it.
+        assert_eq!(deltas[1].group_id, Some(77));
+        assert_eq!(deltas[1].members, vec![2]);
+
+        let per_type = AggregationConfig {
+            windows: HashMap::from([("velocity".to_string(), 60)]),
+            ..config
+        };
+        let deltas = plan_groups(&anomalies, &open, &per_type);
+        assert_eq!(deltas.len(), 4);
+        assert!(deltas.iter().all(|d| d.group_id.is_none()));
+    }
+}
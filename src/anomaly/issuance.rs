@@ -0,0 +1,518 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::IssuanceConfig;
+use crate::entity::label_store::{EntityLabel, EntityLabelStore};
+use crate::indexer::types::StablecoinTransfer;
+
+use super::rules::{raw_to_human, usd_amount, ZERO_ADDRESS};
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// Risky sources listed in a burn anomaly's details.
+const TOP_SOURCES: usize = 10;
+
+/// Labels marking an address as an issuer treasury (`treasury_entity_types`).
+fn treasury_labels<'a>(
+    label_store: &'a EntityLabelStore,
+    address: &[u8],
+    config: &IssuanceConfig,
+) -> Vec<&'a EntityLabel> {
+    label_store
+        .lookup(address)
+        .unwrap_or_default()
+        .iter()
+        .filter(|l| config.treasury_entity_types.contains(&l.entity_type))
+        .collect()
+}
+
+/// How a transfer puts new supply into circulation: `"mint"` from the zero address, or
+/// `"treasury"` for a payout from an issuer treasury. USDT issues into its treasury with an
+/// `Issue` event rather than a `Transfer`, so the payout is the first transfer of new funds.
+fn issuance_source(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> Option<&'static str> {
+    if transfer.from_address == ZERO_ADDRESS {
+        Some("mint")
+    } else if transfer.to_address != transfer.from_address
+        && !treasury_labels(label_store, &transfer.from_address, config).is_empty()
+    {
+        Some("treasury")
+    } else {
+        None
+    }
+}
+
+/// How a transfer takes supply out of circulation: `"burn"` to the zero address, or
+/// `"treasury"` for a return to an issuer treasury, which USDT redeems with a `Redeem` event.
+fn redemption(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> Option<&'static str> {
+    if transfer.to_address == ZERO_ADDRESS {
+        Some("burn")
+    } else if transfer.to_address != transfer.from_address
+        && !treasury_labels(label_store, &transfer.to_address, config).is_empty()
+    {
+        Some("treasury")
+    } else {
+        None
+    }
+}
+
+/// Check if a transfer mints at least `min_mint_usd`, or pays as much out of an issuer
+/// treasury.
+pub fn check_large_mint(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> Option<AnomalyRecord> {
+    let usd = usd_amount(transfer)?;
+    if usd < config.min_mint_usd {
+        return None;
+    }
+    let source = issuance_source(transfer, label_store, config)?;
+
+    let risk = if usd >= config.min_mint_usd * 10.0 {
+        80.0
+    } else if usd >= config.min_mint_usd * 5.0 {
+        60.0
+    } else {
+        40.0
+    };
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::LargeMint,
+        risk_score: risk,
+        flags: vec![format!("mint_usd_{:.0}_{}", usd, transfer.token_symbol)],
+        details: serde_json::json!({
+            "amount": raw_to_human(&transfer.amount, transfer.token_decimals),
+            "amount_usd": usd,
+            "token": transfer.token_symbol,
+            "threshold": config.min_mint_usd,
+            "source": source,
+            "recipient": hex::encode(&transfer.to_address),
+            "recipient_entities": entity_names(label_store.lookup(&transfer.to_address)),
+        }),
+        address: Some(transfer.to_address.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
+/// Check if newly issued funds go to a wallet that is not a known recipient: a mint, or a
+/// payout from an issuer treasury, of at least `min_distribution_usd` to a wallet without
+/// a `known_recipient_types` label.
+pub fn check_issuance_recipient(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> Option<AnomalyRecord> {
+    if transfer.to_address == ZERO_ADDRESS {
+        return None;
+    }
+    let source = issuance_source(transfer, label_store, config)?;
+    let treasury = treasury_labels(label_store, &transfer.from_address, config);
+
+    let usd = usd_amount(transfer)?;
+    let recipient_labels = label_store.lookup(&transfer.to_address).unwrap_or_default();
+    if usd < config.min_distribution_usd
+        || recipient_labels
+            .iter()
+            .any(|l| config.known_recipient_types.contains(&l.entity_type))
+    {
+        return None;
+    }
+
+    let (recipient, risk) = if recipient_labels.is_empty() {
+        ("unlabelled", 75.0)
+    } else {
+        ("unknown", 60.0)
+    };
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::IssuanceToUnknownWallet,
+        risk_score: risk,
+        flags: vec![format!("{}_to_{}_recipient", source, recipient)],
+        details: serde_json::json!({
+            "source": source,
+            "treasury": (source == "treasury").then(|| hex::encode(&transfer.from_address)),
+            "treasury_entities": treasury.iter().map(|l| &l.entity_name).collect::<Vec<_>>(),
+            "recipient": hex::encode(&transfer.to_address),
+            "recipient_entities": entity_names(Some(recipient_labels)),
+            "amount": raw_to_human(&transfer.amount, transfer.token_decimals),
+            "amount_usd": usd,
+            "token": transfer.token_symbol,
+            "threshold": config.min_distribution_usd,
+        }),
+        address: Some(transfer.to_address.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
+/// One sender of recent inflows to a burning wallet.
+#[derive(Debug, sqlx::FromRow)]
+struct Source {
+    burn: i64,
+    address: Vec<u8>,
+    transfer_count: i64,
+    amount: f64,
+    last_at: DateTime<Utc>,
+}
+
+/// Check if burns or treasury redemptions of at least `min_burn_usd` follow inflows to the burning wallet of at
+/// least `min_risky_inflow_usd` from risky sources within `burn_lookback_secs`, which can
+/// mean tainted funds being redeemed with the issuer.
+///
+/// Sources are risky when labelled with one of `risky_entity_types`, sanctioned, or stored
+/// with a risk score of at least `min_source_risk_score`. Scores come from earlier batches,
+/// as the risk stage runs after detection. The batch's burns share one query for their
+/// inflows and one for the scores.
+pub async fn check_burn_after_risky_inflow(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let burns: Vec<&StablecoinTransfer> = transfers
+        .iter()
+        .filter(|t| {
+            t.id.is_some()
+                && usd_amount(t).is_some_and(|usd| usd >= config.min_burn_usd)
+                && redemption(t, label_store, config).is_some()
+        })
+        .collect();
+    if burns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: Vec<i64> = burns.iter().filter_map(|t| t.id).collect();
+    let chain_ids: Vec<i64> = burns.iter().map(|t| t.chain_id).collect();
+    let burners: Vec<&[u8]> = burns.iter().map(|t| t.from_address.as_slice()).collect();
+    let timestamps: Vec<DateTime<Utc>> = burns.iter().map(|t| t.block_timestamp).collect();
+
+    let rows: Vec<Source> = sqlx::query_as(
+        "SELECT b.n - 1 AS burn, t.from_address AS address, COUNT(*) AS transfer_count,
+                COALESCE(SUM(t.amount_usd), 0)::FLOAT8 AS amount, MAX(t.block_timestamp) AS last_at
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BYTEA[], $4::TIMESTAMPTZ[])
+              WITH ORDINALITY AS b(id, chain_id, burner, at, n)
+         JOIN transfers t
+           ON t.chain_id = b.chain_id AND t.to_address = b.burner
+          AND t.from_address <> b.burner AND t.from_address <> $6
+          AND t.block_timestamp > b.at - make_interval(secs => $5)
+          AND (t.block_timestamp, t.id) <= (b.at, b.id)
+         GROUP BY b.n, t.from_address",
+    )
+    .bind(&ids)
+    .bind(&chain_ids)
+    .bind(&burners)
+    .bind(&timestamps)
+    .bind(config.burn_lookback_secs as f64)
+    .bind(&ZERO_ADDRESS[..])
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let source_chains: Vec<i64> = rows.iter().map(|s| chain_ids[s.burn as usize]).collect();
+    let addresses: Vec<&[u8]> = rows.iter().map(|s| s.address.as_slice()).collect();
+    let scores: HashMap<(i64, Vec<u8>), f32> = sqlx::query_as::<_, (i64, Vec<u8>, f32)>(
+        "SELECT s.chain_id, s.address, s.score
+         FROM wallet_risk_scores s
+         JOIN (SELECT DISTINCT * FROM UNNEST($1::BIGINT[], $2::BYTEA[])) AS w(chain_id, address)
+           ON s.chain_id = w.chain_id AND s.address = w.address
+         WHERE s.score >= $3",
+    )
+    .bind(&source_chains)
+    .bind(&addresses)
+    .bind(config.min_source_risk_score)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(chain_id, address, score)| ((chain_id, address), score))
+    .collect();
+
+    let mut sources: Vec<Vec<Source>> = burns.iter().map(|_| Vec::new()).collect();
+    for row in rows {
+        sources[row.burn as usize].push(row);
+    }
+    Ok(burns
+        .into_iter()
+        .zip(sources)
+        .filter_map(|(transfer, sources)| {
+            burn_anomaly(transfer, sources, &scores, label_store, config)
+        })
+        .collect())
+}
+
+/// Build the anomaly for a burn from its wallet's recent inflows per source, if the risky
+/// ones add up to `min_risky_inflow_usd`. `scores` holds the stored risk scores at or above
+/// `min_source_risk_score`, by (chain, address).
+fn burn_anomaly(
+    transfer: &StablecoinTransfer,
+    sources: Vec<Source>,
+    scores: &HashMap<(i64, Vec<u8>), f32>,
+    label_store: &EntityLabelStore,
+    config: &IssuanceConfig,
+) -> Option<AnomalyRecord> {
+    let burner = &transfer.from_address;
+    let score = |address: &Vec<u8>| scores.get(&(transfer.chain_id, address.clone())).copied();
+    let mut risky: Vec<(Source, Vec<&EntityLabel>, bool)> = sources
+        .into_iter()
+        .filter_map(|source| {
+            let labels: Vec<&EntityLabel> = label_store
+                .lookup(&source.address)
+                .unwrap_or_default()
+                .iter()
+                .filter(|l| config.risky_entity_types.contains(&l.entity_type))
+                .collect();
+            let sanctioned = label_store.is_sanctioned(&source.address);
+            if labels.is_empty() && !sanctioned && score(&source.address).is_none() {
+                return None;
+            }
+            Some((source, labels, sanctioned))
+        })
+        .collect();
+    let risky_usd: f64 = risky.iter().map(|(s, ..)| s.amount).sum();
+    if risky.is_empty() || risky_usd < config.min_risky_inflow_usd {
+        return None;
+    }
+    risky.sort_by(|a, b| b.0.amount.total_cmp(&a.0.amount));
+
+    let any_sanctioned = risky.iter().any(|(_, _, sanctioned)| *sanctioned);
+    let mut flags = vec![format!(
+        "burn_after_{:.0}_usd_from_{}_risky_sources",
+        risky_usd,
+        risky.len()
+    )];
+    if any_sanctioned {
+        flags.push("burn_after_sanctioned_inflow".to_string());
+    }
+    let sources: Vec<serde_json::Value> = risky
+        .iter()
+        .take(TOP_SOURCES)
+        .map(|(source, labels, sanctioned)| {
+            serde_json::json!({
+                "address": hex::encode(&source.address),
+                "transfer_count": source.transfer_count,
+                "amount": source.amount,
+                "last_at": source.last_at,
+                "entities": labels.iter().map(|l| &l.entity_name).collect::<Vec<_>>(),
+                "sanctioned": sanctioned,
+                "risk_score": score(&source.address),
+            })
+        })
+        .collect();
+
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::BurnAfterRiskyInflow,
+        risk_score: if any_sanctioned { 90.0 } else { 80.0 },
+        flags,
+        details: serde_json::json!({
+            "burner": hex::encode(burner),
+            "redemption": redemption(transfer, label_store, config),
+            "amount": raw_to_human(&transfer.amount, transfer.token_decimals),
+            "amount_usd": usd_amount(transfer),
+            "token": transfer.token_symbol,
+            "lookback_secs": config.burn_lookback_secs,
+            "risky_inflow_usd": risky_usd,
+            "risky_source_count": risky.len(),
+            "sources": sources,
+        }),
+        address: Some(burner.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
+fn entity_names(labels: Option<&[EntityLabel]>) -> Vec<&str> {
+    labels
+        .unwrap_or_default()
+        .iter()
+        .map(|l| l.entity_name.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use bigdecimal::BigDecimal;
+
+    fn transfer(from: [u8; 20], to: [u8; 20], usd: i64) -> StablecoinTransfer {
+        StablecoinTransfer {
+            id: Some(1),
+            from_address: from.to_vec(),
+            to_address: to.to_vec(),
+            token_symbol: "USDT".to_string(),
+            block_timestamp: Utc::now(),
+            amount_usd: Some(BigDecimal::from(usd)),
+            ..test_transfer(100, 0, 0, usd * 1_000_000)
+        }
+    }
+
+    fn label(address: [u8; 20], entity_type: &str) -> EntityLabel {
+        EntityLabel {
+            id: 1,
+            address: address.to_vec(),
+            chain_id: None,
+            entity_name: format!("{} wallet", entity_type),
+            entity_type: entity_type.to_string(),
+            label_source: "config".to_string(),
+            confidence: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_mints_and_treasury_payouts_to_unknown_wallets() {
+        let config = IssuanceConfig::default();
+        let (treasury, exchange, wallet) = ([7u8; 20], [8u8; 20], [9u8; 20]);
+        let mut labels = EntityLabelStore::default();
+        labels.insert_memory(label(treasury, "issuer"));
+        labels.insert_memory(label(exchange, "exchange"));
+
+        // Minting to the labelled treasury is large but expected
+        let mint = transfer(ZERO_ADDRESS, treasury, 50_000_000);
+        assert_eq!(
+            check_large_mint(&mint, &labels, &config)
+                .unwrap()
+                .risk_score,
+            60.0
+        );
+        assert!(check_issuance_recipient(&mint, &labels, &config).is_none());
+        assert!(check_large_mint(
+            &transfer(ZERO_ADDRESS, treasury, 5_000_000),
+            &labels,
+            &config
+        )
+        .is_none());
+
+        let payout =
+            check_issuance_recipient(&transfer(treasury, wallet, 2_000_000), &labels, &config)
+                .unwrap();
+        assert_eq!(payout.flags, ["treasury_to_unlabelled_recipient"]);
+        assert!(check_issuance_recipient(
+            &transfer(treasury, exchange, 2_000_000),
+            &labels,
+            &config
+        )
+        .is_none());
+
+        let direct =
+            check_issuance_recipient(&transfer(ZERO_ADDRESS, wallet, 2_000_000), &labels, &config)
+                .unwrap();
+        assert_eq!(direct.flags, ["mint_to_unlabelled_recipient"]);
+        // Ordinary transfers between wallets are not issuance
+        assert!(check_issuance_recipient(
+            &transfer(wallet, [3u8; 20], 2_000_000),
+            &labels,
+            &config
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_burn_after_inflows_from_risky_sources() {
+        let config = IssuanceConfig::default();
+        let (burner, mixer, scored, wallet) = ([4u8; 20], [5u8; 20], [6u8; 20], [7u8; 20]);
+        let mut labels = EntityLabelStore::default();
+        labels.insert_memory(label(mixer, "mixer"));
+        let scores = HashMap::from([((1, scored.to_vec()), 80.0)]);
+
+        let source = |address: [u8; 20], amount: f64| Source {
+            burn: 0,
+            address: address.to_vec(),
+            transfer_count: 1,
+            amount,
+            last_at: Utc::now(),
+        };
+        let burn = transfer(burner, ZERO_ADDRESS, 500_000);
+
+        let anomaly = burn_anomaly(
+            &burn,
+            vec![
+                source(mixer, 60_000.0),
+                source(scored, 50_000.0),
+                source(wallet, 900_000.0),
+            ],
+            &scores,
+            &labels,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            anomaly.flags,
+            ["burn_after_110000_usd_from_2_risky_sources"]
+        );
+        assert_eq!(anomaly.risk_score, 80.0);
+        assert_eq!(anomaly.details["sources"][0]["entities"][0], "mixer wallet");
+        assert_eq!(anomaly.details["sources"][1]["risk_score"], 80.0);
+
+        // Inflows from unlabelled, unscored wallets do not count
+        assert!(burn_anomaly(
+            &burn,
+            vec![source(mixer, 60_000.0), source(wallet, 900_000.0)],
+            &scores,
+            &labels,
+            &config,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_treasury_payouts_and_returns_count_as_issuance() {
+        let config = IssuanceConfig::default();
+        let (treasury, mixer, wallet) = ([7u8; 20], [5u8; 20], [9u8; 20]);
+        let mut labels = EntityLabelStore::default();
+        labels.insert_memory(label(treasury, "issuer"));
+        labels.insert_memory(label(mixer, "mixer"));
+
+        // USDT issues into its treasury without a Transfer, so the payout is the mint
+        let payout = transfer(treasury, wallet, 20_000_000);
+        let mint = check_large_mint(&payout, &labels, &config).unwrap();
+        assert_eq!(mint.details["source"], "treasury");
+        assert_eq!(mint.risk_score, 40.0);
+        assert_eq!(mint.address, Some(wallet.to_vec()));
+        assert!(check_large_mint(&transfer(wallet, mixer, 20_000_000), &labels, &config).is_none());
+
+        // Labelled recipients that are not exchanges or issuers are still unknown
+        let to_mixer =
+            check_issuance_recipient(&transfer(treasury, mixer, 2_000_000), &labels, &config)
+                .unwrap();
+        assert_eq!(to_mixer.flags, ["treasury_to_unknown_recipient"]);
+        assert_eq!(to_mixer.risk_score, 60.0);
+
+        // Returning funds to the treasury redeems them, like a burn
+        let returned = transfer(wallet, treasury, 500_000);
+        assert_eq!(redemption(&returned, &labels, &config), Some("treasury"));
+        assert_eq!(
+            redemption(&transfer(wallet, ZERO_ADDRESS, 500_000), &labels, &config),
+            Some("burn")
+        );
+        assert!(redemption(&transfer(wallet, mixer, 500_000), &labels, &config).is_none());
+
+        let source = Source {
+            burn: 0,
+            address: mixer.to_vec(),
+            transfer_count: 2,
+            amount: 150_000.0,
+            last_at: Utc::now(),
+        };
+        let anomaly =
+            burn_anomaly(&returned, vec![source], &HashMap::new(), &labels, &config).unwrap();
+        assert_eq!(anomaly.details["redemption"], "treasury");
+        assert_eq!(anomaly.address, Some(wallet.to_vec()));
+    }
+}
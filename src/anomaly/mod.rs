@@ -1,3 +1,16 @@
+pub mod activity;
+pub mod backtest;
+pub mod cases;
+pub mod custom;
+pub mod depeg;
+pub mod dsl;
 pub mod engine;
+pub mod fan;
+pub mod flash_loan;
+pub mod groups;
+pub mod issuance;
+pub mod pass_through;
+pub mod peel_chain;
 pub mod rules;
+pub mod suppression;
 pub mod types;
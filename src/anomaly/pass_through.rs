@@ -0,0 +1,438 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::{ChainConfig, PassThroughConfig};
+use crate::graph::tracker::{self, EdgeProbe};
+use crate::indexer::types::StablecoinTransfer;
+
+use super::rules::{raw_to_human, usd_amount};
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// How soon a wallet must forward what it received, resolved for one chain.
+#[derive(Debug, Clone, Copy)]
+pub struct PassThroughWindow {
+    pub window_secs: u64,
+    pub window_blocks: Option<u64>,
+}
+
+/// Resolve per-chain window overrides to chain ids. Chains without an override
+/// use the global window and are left out of the map.
+pub fn resolve_windows(
+    config: &PassThroughConfig,
+    chains: &[ChainConfig],
+) -> HashMap<i64, PassThroughWindow> {
+    chains
+        .iter()
+        .filter_map(|chain| {
+            let window = config.chains.get(&chain.name)?;
+            Some((
+                chain.chain_id as i64,
+                PassThroughWindow {
+                    window_secs: window.window_secs.unwrap_or(config.window_secs),
+                    window_blocks: window.window_blocks.or(config.window_blocks),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// One transfer in a pass-through run.
+struct Hop {
+    id: i64,
+    from_address: Vec<u8>,
+    to_address: Vec<u8>,
+    amount: f64,
+    block_number: i64,
+    block_timestamp: DateTime<Utc>,
+}
+
+/// A stored transfer reached walking a run back from the candidate at `candidate`.
+#[derive(sqlx::FromRow)]
+struct HopRow {
+    candidate: i64,
+    id: i64,
+    from_address: Vec<u8>,
+    to_address: Vec<u8>,
+    amount: BigDecimal,
+    block_number: i64,
+    block_timestamp: DateTime<Utc>,
+}
+
+/// Check whether the batch's transfers forward nearly all of an amount their sender
+/// received within the window, and whether they extend a run of such hops into layering.
+///
+/// The runs are walked backwards through the stored transfers, which include the current
+/// batch, in one query for all candidates. A `rapid_pass_through` anomaly is raised on
+/// every pass-through hop; a `layering` anomaly is raised once per run, on the hop that
+/// makes it `layering_min_hops` long. Fresh wallets come from the first-seen records
+/// written by `WalletTracker`. Chains without an entry in `windows` use the global window.
+pub async fn check_pass_through(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    config: &PassThroughConfig,
+    windows: &HashMap<i64, PassThroughWindow>,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let window_for = |chain_id: i64| {
+        windows
+            .get(&chain_id)
+            .copied()
+            .unwrap_or(PassThroughWindow {
+                window_secs: config.window_secs,
+                window_blocks: config.window_blocks,
+            })
+    };
+    let candidates: Vec<(&StablecoinTransfer, PassThroughWindow)> = transfers
+        .iter()
+        .filter(|t| t.id.is_some() && usd_amount(t).is_some_and(|usd| usd >= config.min_amount))
+        .map(|t| (t, window_for(t.chain_id)))
+        .collect();
+
+    let probes: Vec<EdgeProbe> = candidates
+        .iter()
+        .map(|(t, window)| EdgeProbe {
+            chain_id: t.chain_id,
+            address: &t.from_address,
+            at: t.block_timestamp,
+            window_secs: window.window_secs,
+        })
+        .collect();
+    let fed = tracker::recent_incoming_edges(pool, &probes).await?;
+    let candidates: Vec<(&StablecoinTransfer, PassThroughWindow)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| fed.contains(i))
+        .map(|(_, candidate)| candidate)
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: Vec<i64> = candidates.iter().filter_map(|(t, _)| t.id).collect();
+    let chain_ids: Vec<i64> = candidates.iter().map(|(t, _)| t.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> =
+        candidates.iter().map(|(t, _)| t.block_timestamp).collect();
+    let window_secs: Vec<f64> = candidates
+        .iter()
+        .map(|(_, w)| w.window_secs as f64)
+        .collect();
+    let window_blocks: Vec<Option<i64>> = candidates
+        .iter()
+        .map(|(_, w)| w.window_blocks.map(|b| b as i64))
+        .collect();
+
+    let rows: Vec<HopRow> = sqlx::query_as(
+        "WITH RECURSIVE hops AS (
+             SELECT c.n AS candidate, 0 AS depth, t.chain_id, t.token_address,
+                    c.window_secs, c.window_blocks, t.id, t.from_address, t.to_address,
+                    t.amount, t.block_number, t.block_timestamp,
+                    ARRAY[t.to_address, t.from_address] AS seen
+             FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::FLOAT8[],
+                         $5::BIGINT[])
+                  WITH ORDINALITY AS c(id, chain_id, at, window_secs, window_blocks, n)
+             JOIN transfers t
+               ON t.id = c.id AND t.chain_id = c.chain_id AND t.block_timestamp = c.at
+             UNION ALL
+             SELECT h.candidate, h.depth + 1, h.chain_id, h.token_address, h.window_secs,
+                    h.window_blocks, p.id, p.from_address, p.to_address, p.amount,
+                    p.block_number, p.block_timestamp, array_append(h.seen, p.from_address)
+             FROM hops h
+             CROSS JOIN LATERAL (
+                 SELECT id, from_address, to_address, amount, block_number, block_timestamp
+                 FROM transfers
+                 WHERE chain_id = h.chain_id AND token_address = h.token_address
+                   AND to_address = h.from_address
+                   AND (block_timestamp, id) < (h.block_timestamp, h.id)
+                   AND block_timestamp >= h.block_timestamp - make_interval(secs => h.window_secs)
+                   AND (h.window_blocks IS NULL OR block_number >= h.block_number - h.window_blocks)
+                   AND amount >= h.amount AND h.amount >= amount * $6
+                 ORDER BY block_timestamp DESC, id DESC
+                 LIMIT 1
+             ) p
+             WHERE h.depth < $7 AND NOT (p.from_address = ANY(h.seen))
+         )
+         SELECT candidate - 1 AS candidate, id, from_address, to_address, amount, block_number,
+                block_timestamp
+         FROM hops ORDER BY candidate, depth DESC",
+    )
+    .bind(&ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&window_secs)
+    .bind(&window_blocks)
+    .bind(BigDecimal::try_from(config.min_forward_ratio)?)
+    // One past the layering length, to tell a run that just reached it from a longer one
+    .bind(config.layering_min_hops as i32 + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let mut runs: Vec<Vec<Hop>> = candidates.iter().map(|_| Vec::new()).collect();
+    for row in rows {
+        let decimals = candidates[row.candidate as usize].0.token_decimals;
+        runs[row.candidate as usize].push(Hop {
+            id: row.id,
+            from_address: row.from_address,
+            to_address: row.to_address,
+            amount: raw_to_human(&row.amount, decimals),
+            block_number: row.block_number,
+            block_timestamp: row.block_timestamp,
+        });
+    }
+
+    // Wallets that passed the funds on: the receiver of every hop but the last
+    let mut wallet_chains = Vec::new();
+    let mut wallets = Vec::new();
+    for ((transfer, _), hops) in candidates.iter().zip(&runs) {
+        for hop in hops.iter().rev().skip(1) {
+            wallet_chains.push(transfer.chain_id);
+            wallets.push(hop.to_address.as_slice());
+        }
+    }
+    let first_seen: HashMap<(i64, Vec<u8>), DateTime<Utc>> = if wallets.is_empty() {
+        HashMap::new()
+    } else {
+        sqlx::query_as::<_, (i64, Vec<u8>, DateTime<Utc>)>(
+            "SELECT f.chain_id, f.address, f.first_seen_at
+             FROM wallet_first_seen f
+             JOIN (SELECT DISTINCT * FROM UNNEST($1::BIGINT[], $2::BYTEA[])) AS w(chain_id, address)
+               ON f.chain_id = w.chain_id AND f.address = w.address",
+        )
+        .bind(&wallet_chains)
+        .bind(&wallets)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(chain_id, address, seen)| ((chain_id, address), seen))
+        .collect()
+    };
+
+    let fresh_window = Duration::seconds(config.fresh_wallet_secs as i64);
+    Ok(candidates
+        .iter()
+        .zip(&runs)
+        .filter(|(_, hops)| hops.len() >= 2)
+        .flat_map(|(&(transfer, window), hops)| {
+            let steps = pass_steps(hops, transfer.chain_id, &first_seen, fresh_window);
+            pass_through_anomalies(transfer, hops, &steps, config, window)
+        })
+        .collect())
+}
+
+/// Pair up consecutive hops of a run, oldest first, marking wallets first seen within
+/// `fresh_window` before they received the funds.
+fn pass_steps<'a>(
+    hops: &'a [Hop],
+    chain_id: i64,
+    first_seen: &HashMap<(i64, Vec<u8>), DateTime<Utc>>,
+    fresh_window: Duration,
+) -> Vec<PassStep<'a>> {
+    hops.windows(2)
+        .map(|pair| {
+            let (incoming, outgoing) = (&pair[0], &pair[1]);
+            let wallet_first_seen = first_seen
+                .get(&(chain_id, incoming.to_address.clone()))
+                .copied();
+            PassStep {
+                fresh: wallet_first_seen
+                    .is_some_and(|seen| seen >= incoming.block_timestamp - fresh_window),
+                wallet_first_seen,
+                elapsed_secs: (outgoing.block_timestamp - incoming.block_timestamp).num_seconds(),
+                elapsed_blocks: outgoing.block_number - incoming.block_number,
+                incoming,
+                outgoing,
+            }
+        })
+        .collect()
+}
+
+/// The pass-through anomaly for the run's last step, plus layering once the run reaches
+/// `layering_min_hops`.
+fn pass_through_anomalies(
+    transfer: &StablecoinTransfer,
+    hops: &[Hop],
+    steps: &[PassStep],
+    config: &PassThroughConfig,
+    window: PassThroughWindow,
+) -> Vec<AnomalyRecord> {
+    let mut anomalies = Vec::new();
+    let last = &steps[steps.len() - 1];
+    let forward_ratio = last.outgoing.amount / last.incoming.amount;
+
+    let mut flags = vec![format!(
+        "pass_through_{:.0}_{}_in_{}s",
+        last.outgoing.amount, transfer.token_symbol, last.elapsed_secs
+    )];
+    if last.fresh {
+        flags.push("fresh_wallet_pass_through".to_string());
+    }
+
+    anomalies.push(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::RapidPassThrough,
+        risk_score: if last.fresh { 70.0 } else { 50.0 },
+        flags,
+        details: serde_json::json!({
+            "token": transfer.token_symbol,
+            "wallet": hex::encode(&transfer.from_address),
+            "in_transfer_id": last.incoming.id,
+            "received": last.incoming.amount,
+            "forwarded": last.outgoing.amount,
+            "forward_ratio": forward_ratio,
+            "elapsed_secs": last.elapsed_secs,
+            "elapsed_blocks": last.elapsed_blocks,
+            "fresh_wallet": last.fresh,
+            "wallet_first_seen": last.wallet_first_seen,
+            "window_secs": window.window_secs,
+            "window_blocks": window.window_blocks,
+            "consecutive_hops": steps.len(),
+        }),
+        address: Some(transfer.from_address.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    });
+
+    if steps.len() == config.layering_min_hops as usize {
+        let fresh_count = steps.iter().filter(|s| s.fresh).count();
+        let first = &hops[0];
+        let total_elapsed = (transfer.block_timestamp - first.block_timestamp).num_seconds();
+        let path: Vec<serde_json::Value> = steps
+            .iter()
+            .map(|s| {
+                serde_json::json!({
+                    "address": hex::encode(&s.incoming.to_address),
+                    "in_transfer_id": s.incoming.id,
+                    "out_transfer_id": s.outgoing.id,
+                    "received": s.incoming.amount,
+                    "forwarded": s.outgoing.amount,
+                    "elapsed_secs": s.elapsed_secs,
+                    "elapsed_blocks": s.elapsed_blocks,
+                    "fresh_wallet": s.fresh,
+                })
+            })
+            .collect();
+
+        let mut flags = vec![format!("layering_{}_hops_in_{}s", steps.len(), total_elapsed)];
+        if fresh_count > 0 {
+            flags.push(format!("layering_{}_fresh_wallets", fresh_count));
+        }
+
+        anomalies.push(AnomalyRecord {
+            chain_id: transfer.chain_id,
+            anomaly_type: AnomalyType::Layering,
+            risk_score: if fresh_count * 2 > steps.len() { 85.0 } else { 75.0 },
+            flags,
+            details: serde_json::json!({
+                "token": transfer.token_symbol,
+                "hops": steps.len(),
+                "origin": hex::encode(&first.from_address),
+                "destination": hex::encode(&transfer.to_address),
+                "initial_amount": first.amount,
+                "final_amount": last.outgoing.amount,
+                "total_elapsed_secs": total_elapsed,
+                "fresh_wallets": fresh_count,
+                "path": path,
+            }),
+            address: Some(first.from_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
+            tx_hash: transfer.tx_hash.clone(),
+            log_index: transfer.log_index,
+        });
+    }
+
+    anomalies
+}
+
+/// A wallet receiving `incoming` and passing it on as `outgoing`.
+struct PassStep<'a> {
+    incoming: &'a Hop,
+    outgoing: &'a Hop,
+    elapsed_secs: i64,
+    elapsed_blocks: i64,
+    fresh: bool,
+    wallet_first_seen: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+
+    fn hop(id: i64, from: u8, to: u8, amount: f64, minute: i64) -> Hop {
+        Hop {
+            id,
+            from_address: vec![from; 20],
+            to_address: vec![to; 20],
+            amount,
+            block_number: 100 + minute * 5,
+            block_timestamp: DateTime::<Utc>::UNIX_EPOCH
+                + Duration::days(10)
+                + Duration::minutes(minute),
+        }
+    }
+
+    #[test]
+    fn test_pass_steps_and_layering_on_the_run_length() {
+        let config = PassThroughConfig::default();
+        let window = PassThroughWindow {
+            window_secs: config.window_secs,
+            window_blocks: None,
+        };
+        // 1 -> 2 -> 3 -> 4 -> 5, each wallet forwarding within a few minutes
+        let hops = [
+            hop(10, 1, 2, 5_000.0, 0),
+            hop(11, 2, 3, 4_990.0, 2),
+            hop(12, 3, 4, 4_980.0, 5),
+            hop(13, 4, 5, 4_975.0, 9),
+        ];
+        // Wallet 3 appeared an hour before it received the funds, wallet 2 long before
+        let first_seen = HashMap::from([
+            ((1, vec![2; 20]), DateTime::<Utc>::UNIX_EPOCH),
+            (
+                (1, vec![3; 20]),
+                hops[1].block_timestamp - Duration::hours(1),
+            ),
+        ]);
+        let steps = pass_steps(
+            &hops,
+            1,
+            &first_seen,
+            Duration::seconds(config.fresh_wallet_secs as i64),
+        );
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps.iter().map(|s| s.fresh).collect::<Vec<_>>(),
+            [false, true, false]
+        );
+        assert_eq!(steps[1].elapsed_secs, 180);
+        assert_eq!(steps[1].elapsed_blocks, 15);
+        assert!(steps[2].wallet_first_seen.is_none());
+
+        let transfer = StablecoinTransfer {
+            id: Some(13),
+            tx_hash: vec![1; 32],
+            token_address: vec![6; 20],
+            token_symbol: "USDT".to_string(),
+            block_timestamp: hops[3].block_timestamp,
+            ..test_transfer(hops[3].block_number, 4, 5, 4_975_000_000)
+        };
+        let anomalies = pass_through_anomalies(&transfer, &hops, &steps, &config, window);
+        assert_eq!(anomalies.len(), 2);
+        assert_eq!(anomalies[0].flags, ["pass_through_4975_USDT_in_240s"]);
+        assert_eq!(anomalies[0].risk_score, 50.0);
+        assert_eq!(anomalies[1].anomaly_type.as_str(), "layering");
+        assert_eq!(anomalies[1].address, Some(vec![1; 20]));
+        assert_eq!(
+            anomalies[1].flags,
+            ["layering_3_hops_in_540s", "layering_1_fresh_wallets"]
+        );
+
+        // A run one hop short of layering only raises the pass-through
+        let anomalies = pass_through_anomalies(&transfer, &hops[1..], &steps[1..], &config, window);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].risk_score, 50.0);
+    }
+}
@@ -0,0 +1,504 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::config::PeelChainConfig;
+use crate::entity::label_store::EntityLabelStore;
+use crate::graph::tracker::{self, EdgeProbe};
+use crate::indexer::types::StablecoinTransfer;
+
+use super::rules::{raw_to_human, usd_amount};
+use super::types::{AnomalyRecord, AnomalyType};
+
+/// One transfer in a peel chain.
+struct Link {
+    id: i64,
+    from_address: Vec<u8>,
+    to_address: Vec<u8>,
+    amount: f64,
+    block_timestamp: DateTime<Utc>,
+}
+
+/// A stored transfer reached walking a chain from the candidate at `candidate`.
+#[derive(sqlx::FromRow)]
+struct LinkRow {
+    candidate: i64,
+    id: i64,
+    from_address: Vec<u8>,
+    to_address: Vec<u8>,
+    amount: BigDecimal,
+    block_timestamp: DateTime<Utc>,
+}
+
+/// Another outgoing transfer of a hop wallet, kept back from what the chain forwarded.
+#[derive(sqlx::FromRow)]
+struct PeelRow {
+    candidate: i64,
+    hop: i64,
+    id: i64,
+    to_address: Vec<u8>,
+    amount: BigDecimal,
+}
+
+/// Detect peel chains ending in the batch's transfers.
+///
+/// A hop is a wallet that received an amount and forwarded between `min_forward_ratio`
+/// and all of it (same token, within `max_hop_secs`), keeping the difference. The chain is
+/// walked backwards from the transfer, taking the latest qualifying incoming transfer at
+/// each wallet. One anomaly is raised per chain, on the transfer that brings it to
+/// `min_hops`; its path also follows any later hops already stored, so replaying the
+/// anomaly stage over older data records the full chain. Each step runs as one query for
+/// all the batch's candidates.
+pub async fn check_peel_chains(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    config: &PeelChainConfig,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let candidates: Vec<&StablecoinTransfer> = transfers
+        .iter()
+        .filter(|t| t.id.is_some() && usd_amount(t).is_some_and(|usd| usd >= config.min_amount))
+        .collect();
+
+    // Graph edges are updated before anomaly detection, so a sender with no incoming
+    // edge active in the hop window cannot be part of a chain.
+    let probes: Vec<EdgeProbe> = candidates
+        .iter()
+        .map(|t| EdgeProbe {
+            chain_id: t.chain_id,
+            address: &t.from_address,
+            at: t.block_timestamp,
+            window_secs: config.max_hop_secs,
+        })
+        .collect();
+    let fed = tracker::recent_incoming_edges(pool, &probes).await?;
+    let candidates: Vec<&StablecoinTransfer> = candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| fed.contains(i))
+        .map(|(_, t)| t)
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut chains: Vec<Vec<Link>> = candidates.iter().map(|_| Vec::new()).collect();
+    for row in walk(pool, &candidates, config, Walk::Backward).await? {
+        let decimals = candidates[row.candidate as usize].token_decimals;
+        chains[row.candidate as usize].push(to_link(row, decimals));
+    }
+
+    // Rows run from the chain's first transfer to this one; each row after the first
+    // adds one peeling wallet.
+    let (candidates, mut chains): (Vec<&StablecoinTransfer>, Vec<Vec<Link>>) = candidates
+        .into_iter()
+        .zip(chains)
+        .filter(|(_, links)| links.len() == config.min_hops as usize + 1)
+        .unzip();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let remaining = config.max_hops.saturating_sub(config.min_hops);
+    if remaining > 0 {
+        for row in walk(pool, &candidates, config, Walk::Forward(remaining)).await? {
+            let decimals = candidates[row.candidate as usize].token_decimals;
+            chains[row.candidate as usize].push(to_link(row, decimals));
+        }
+    }
+
+    let mut peels: Vec<Vec<PeelRow>> = candidates.iter().map(|_| Vec::new()).collect();
+    for row in fetch_peels(pool, &candidates, &chains, config.max_hop_secs).await? {
+        peels[row.candidate as usize].push(row);
+    }
+
+    Ok(candidates
+        .into_iter()
+        .zip(chains)
+        .zip(peels)
+        .map(|((transfer, links), peels)| {
+            peel_anomaly(transfer, &links, &peels, label_store, config)
+        })
+        .collect())
+}
+
+/// Build the anomaly for a chain ending in `transfer`, from its links in order and the
+/// peels of each hop wallet.
+fn peel_anomaly(
+    transfer: &StablecoinTransfer,
+    links: &[Link],
+    peels: &[PeelRow],
+    label_store: &EntityLabelStore,
+    config: &PeelChainConfig,
+) -> AnomalyRecord {
+    let decimals = transfer.token_decimals;
+    let mut path = Vec::new();
+    let mut destination_entities = Vec::new();
+    let mut total_peeled = 0.0;
+    let mut sanctioned_destination = false;
+
+    for (i, pair) in links.windows(2).enumerate() {
+        let (incoming, outgoing) = (&pair[0], &pair[1]);
+        let peeled = incoming.amount - outgoing.amount;
+        total_peeled += peeled;
+
+        let peel_transfers: Vec<serde_json::Value> = peels
+            .iter()
+            .filter(|peel| peel.hop == i as i64)
+            .map(|peel| {
+                let entities = entity_json(label_store, &peel.to_address);
+                if !entities.is_empty() {
+                    sanctioned_destination |= label_store.is_sanctioned(&peel.to_address);
+                    destination_entities.push(serde_json::json!({
+                        "address": hex::encode(&peel.to_address),
+                        "via": "peel",
+                        "hop": i,
+                        "entities": entities,
+                    }));
+                }
+                serde_json::json!({
+                    "transfer_id": peel.id,
+                    "to": hex::encode(&peel.to_address),
+                    "amount": raw_to_human(&peel.amount, decimals),
+                    "entities": entities,
+                })
+            })
+            .collect();
+
+        path.push(serde_json::json!({
+            "hop": i,
+            "address": hex::encode(&incoming.to_address),
+            "received": incoming.amount,
+            "forwarded": outgoing.amount,
+            "peeled": peeled,
+            "in_transfer_id": incoming.id,
+            "out_transfer_id": outgoing.id,
+            "peel_transfers": peel_transfers,
+        }));
+    }
+
+    let first = &links[0];
+    let last = &links[links.len() - 1];
+    let hops = links.len() - 1;
+
+    let final_entities = entity_json(label_store, &last.to_address);
+    if !final_entities.is_empty() {
+        sanctioned_destination |= label_store.is_sanctioned(&last.to_address);
+        destination_entities.push(serde_json::json!({
+            "address": hex::encode(&last.to_address),
+            "via": "final",
+            "hop": hops,
+            "entities": final_entities,
+        }));
+    }
+
+    let extra_hops = hops - config.min_hops as usize;
+    let risk = if sanctioned_destination {
+        90.0
+    } else {
+        (60.0 + 3.0 * extra_hops as f32).min(85.0)
+    };
+
+    let mut flags = vec![format!(
+        "peel_chain_{}_hops_{:.0}_{}_peeled",
+        hops, total_peeled, transfer.token_symbol
+    )];
+    if sanctioned_destination {
+        flags.push("peel_chain_reaches_sanctioned_entity".to_string());
+    }
+
+    AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::PeelChain,
+        risk_score: risk,
+        flags,
+        details: serde_json::json!({
+            "token": transfer.token_symbol,
+            "hops": hops,
+            "origin": hex::encode(&first.from_address),
+            "destination": hex::encode(&last.to_address),
+            "initial_amount": first.amount,
+            "final_amount": last.amount,
+            "total_peeled": total_peeled,
+            "first_seen": first.block_timestamp,
+            "last_seen": last.block_timestamp,
+            "transfer_ids": links.iter().map(|l| l.id).collect::<Vec<_>>(),
+            "path": path,
+            "destination_entities": destination_entities,
+        }),
+        address: Some(first.from_address.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    }
+}
+
+/// Which way to walk a chain from its candidate transfer.
+#[derive(Debug, Clone, Copy)]
+enum Walk {
+    /// To the chain's first transfer, taking the latest qualifying incoming transfer at
+    /// each wallet. Rows come oldest first, ending with the candidate.
+    Backward,
+    /// Through up to this many hops already stored past the candidate, taking the earliest
+    /// qualifying outgoing transfer at each wallet. Rows come in order, without the candidate.
+    Forward(u32),
+}
+
+/// Walk the chains of all candidates in one recursive query.
+async fn walk(
+    pool: &PgPool,
+    candidates: &[&StablecoinTransfer],
+    config: &PeelChainConfig,
+    direction: Walk,
+) -> eyre::Result<Vec<LinkRow>> {
+    let ids: Vec<i64> = candidates.iter().filter_map(|t| t.id).collect();
+    let chain_ids: Vec<i64> = candidates.iter().map(|t| t.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> = candidates.iter().map(|t| t.block_timestamp).collect();
+
+    let (sql, max_depth) = match direction {
+        Walk::Backward => (
+            "WITH RECURSIVE hops AS (
+                 SELECT c.n AS candidate, 0 AS depth, t.chain_id, t.token_address, t.id,
+                        t.from_address, t.to_address, t.amount, t.block_timestamp,
+                        ARRAY[t.to_address, t.from_address] AS seen
+                 FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[])
+                      WITH ORDINALITY AS c(id, chain_id, at, n)
+                 JOIN transfers t
+                   ON t.id = c.id AND t.chain_id = c.chain_id AND t.block_timestamp = c.at
+                 UNION ALL
+                 SELECT h.candidate, h.depth + 1, h.chain_id, h.token_address, p.id,
+                        p.from_address, p.to_address, p.amount, p.block_timestamp,
+                        array_append(h.seen, p.from_address)
+                 FROM hops h
+                 CROSS JOIN LATERAL (
+                     SELECT id, from_address, to_address, amount, block_timestamp
+                     FROM transfers
+                     WHERE chain_id = h.chain_id AND token_address = h.token_address
+                       AND to_address = h.from_address
+                       AND (block_timestamp, id) < (h.block_timestamp, h.id)
+                       AND block_timestamp >= h.block_timestamp - make_interval(secs => $4)
+                       AND amount > h.amount AND h.amount >= amount * $5
+                     ORDER BY block_timestamp DESC, id DESC
+                     LIMIT 1
+                 ) p
+                 WHERE h.depth < $6 AND NOT (p.from_address = ANY(h.seen))
+             )
+             SELECT candidate - 1 AS candidate, id, from_address, to_address, amount,
+                    block_timestamp
+             FROM hops ORDER BY candidate, depth DESC",
+            config.max_hops,
+        ),
+        Walk::Forward(max_depth) => (
+            "WITH RECURSIVE hops AS (
+                 SELECT c.n AS candidate, 0 AS depth, t.chain_id, t.token_address, t.id,
+                        t.from_address, t.to_address, t.amount, t.block_timestamp,
+                        ARRAY[t.from_address, t.to_address] AS seen
+                 FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[])
+                      WITH ORDINALITY AS c(id, chain_id, at, n)
+                 JOIN transfers t
+                   ON t.id = c.id AND t.chain_id = c.chain_id AND t.block_timestamp = c.at
+                 UNION ALL
+                 SELECT h.candidate, h.depth + 1, h.chain_id, h.token_address, n.id,
+                        n.from_address, n.to_address, n.amount, n.block_timestamp,
+                        array_append(h.seen, n.to_address)
+                 FROM hops h
+                 CROSS JOIN LATERAL (
+                     SELECT id, from_address, to_address, amount, block_timestamp
+                     FROM transfers
+                     WHERE chain_id = h.chain_id AND token_address = h.token_address
+                       AND from_address = h.to_address
+                       AND (block_timestamp, id) > (h.block_timestamp, h.id)
+                       AND block_timestamp <= h.block_timestamp + make_interval(secs => $4)
+                       AND amount < h.amount AND amount >= h.amount * $5
+                     ORDER BY block_timestamp, id
+                     LIMIT 1
+                 ) n
+                 WHERE h.depth < $6 AND NOT (n.to_address = ANY(h.seen))
+             )
+             SELECT candidate - 1 AS candidate, id, from_address, to_address, amount,
+                    block_timestamp
+             FROM hops WHERE depth > 0 ORDER BY candidate, depth",
+            max_depth,
+        ),
+    };
+
+    let rows: Vec<LinkRow> = sqlx::query_as(sql)
+        .bind(&ids)
+        .bind(&chain_ids)
+        .bind(&timestamps)
+        .bind(config.max_hop_secs as f64)
+        .bind(BigDecimal::try_from(config.min_forward_ratio)?)
+        .bind(max_depth as i32)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Other outgoing transfers of the same token from each hop wallet of each chain, between
+/// receiving the chain's funds and `max_hop_secs` after forwarding them.
+async fn fetch_peels(
+    pool: &PgPool,
+    candidates: &[&StablecoinTransfer],
+    chains: &[Vec<Link>],
+    max_hop_secs: u64,
+) -> eyre::Result<Vec<PeelRow>> {
+    let window = Duration::seconds(max_hop_secs as i64);
+    let mut indexes = Vec::new();
+    let mut hops = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut tokens = Vec::new();
+    let mut wallets = Vec::new();
+    let mut lows = Vec::new();
+    let mut highs = Vec::new();
+    for (i, (transfer, links)) in candidates.iter().zip(chains).enumerate() {
+        for (hop, pair) in links.windows(2).enumerate() {
+            indexes.push(i as i64);
+            hops.push(hop as i64);
+            chain_ids.push(transfer.chain_id);
+            tokens.push(transfer.token_address.as_slice());
+            wallets.push(pair[0].to_address.as_slice());
+            lows.push(pair[0].block_timestamp);
+            highs.push(pair[1].block_timestamp + window);
+        }
+    }
+
+    let rows: Vec<PeelRow> = sqlx::query_as(
+        "SELECT w.candidate, w.hop, t.id, t.to_address, t.amount
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::BYTEA[], $5::BYTEA[],
+                     $6::TIMESTAMPTZ[], $7::TIMESTAMPTZ[])
+              AS w(candidate, hop, chain_id, token, address, low, high)
+         JOIN transfers t
+           ON t.chain_id = w.chain_id AND t.token_address = w.token AND t.from_address = w.address
+          AND t.block_timestamp >= w.low AND t.block_timestamp <= w.high
+         ORDER BY w.candidate, w.hop, t.block_timestamp, t.id",
+    )
+    .bind(&indexes)
+    .bind(&hops)
+    .bind(&chain_ids)
+    .bind(&tokens)
+    .bind(&wallets)
+    .bind(&lows)
+    .bind(&highs)
+    .fetch_all(pool)
+    .await?;
+
+    // The chain's own transfers are not peels
+    let transfer_ids: Vec<HashSet<i64>> = chains
+        .iter()
+        .map(|links| links.iter().map(|l| l.id).collect())
+        .collect();
+    Ok(rows
+        .into_iter()
+        .filter(|row| !transfer_ids[row.candidate as usize].contains(&row.id))
+        .collect())
+}
+
+fn to_link(row: LinkRow, decimals: i16) -> Link {
+    Link {
+        id: row.id,
+        from_address: row.from_address,
+        to_address: row.to_address,
+        amount: raw_to_human(&row.amount, decimals),
+        block_timestamp: row.block_timestamp,
+    }
+}
+
+/// Distinct (name, type) labels of an address; the store can hold one label per chain.
+fn entity_json(label_store: &EntityLabelStore, address: &[u8]) -> Vec<serde_json::Value> {
+    let mut seen = HashSet::new();
+    label_store
+        .lookup(address)
+        .unwrap_or_default()
+        .iter()
+        .filter(|l| seen.insert((l.entity_name.as_str(), l.entity_type.as_str())))
+        .map(|l| {
+            serde_json::json!({
+                "entity_name": l.entity_name,
+                "entity_type": l.entity_type,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use crate::entity::label_store::EntityLabel;
+
+    fn link(id: i64, from: u8, to: u8, amount: f64, minute: i64) -> Link {
+        Link {
+            id,
+            from_address: vec![from; 20],
+            to_address: vec![to; 20],
+            amount,
+            block_timestamp: DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(minute),
+        }
+    }
+
+    #[test]
+    fn test_peel_steps_from_links_and_peels() {
+        let config = PeelChainConfig {
+            min_hops: 2,
+            ..PeelChainConfig::default()
+        };
+        // 1 -> 2 -> 3 -> 4, wallets 2 and 3 each keeping back part of what they received
+        let links = [
+            link(10, 1, 2, 10_000.0, 0),
+            link(11, 2, 3, 9_000.0, 10),
+            link(12, 3, 4, 8_500.0, 20),
+        ];
+        let peel = |hop, id, to: u8| PeelRow {
+            candidate: 0,
+            hop,
+            id,
+            to_address: vec![to; 20],
+            amount: BigDecimal::from(1_000_000_000),
+        };
+        let peels = [peel(0, 20, 9), peel(1, 21, 8)];
+
+        let mut label_store = EntityLabelStore::default();
+        label_store.insert_memory(EntityLabel {
+            id: 1,
+            address: vec![8; 20],
+            chain_id: None,
+            entity_name: "Mixer".to_string(),
+            entity_type: "sanctioned".to_string(),
+            label_source: "ofac_sdn".to_string(),
+            confidence: 1.0,
+        });
+
+        let transfer = StablecoinTransfer {
+            id: Some(12),
+            tx_hash: vec![1; 32],
+            token_address: vec![5; 20],
+            block_timestamp: links[2].block_timestamp,
+            ..test_transfer(100, 3, 4, 8_500_000_000)
+        };
+        let anomaly = peel_anomaly(&transfer, &links, &peels, &label_store, &config);
+
+        let path = anomaly.details["path"].as_array().unwrap();
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[0]["peeled"], 1_000.0);
+        assert_eq!(path[1]["peeled"], 500.0);
+        assert_eq!(path[0]["peel_transfers"][0]["transfer_id"], 20);
+        assert_eq!(path[1]["peel_transfers"][0]["amount"], 1_000.0);
+        assert_eq!(anomaly.details["total_peeled"], 1_500.0);
+        assert_eq!(
+            anomaly.details["transfer_ids"],
+            serde_json::json!([10, 11, 12])
+        );
+        assert_eq!(anomaly.address, Some(vec![1; 20]));
+        assert_eq!(
+            anomaly.flags,
+            [
+                "peel_chain_2_hops_1500_USDC_peeled",
+                "peel_chain_reaches_sanctioned_entity"
+            ]
+        );
+        assert_eq!(anomaly.risk_score, 90.0);
+    }
+}
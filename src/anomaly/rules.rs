@@ -1,12 +1,17 @@
 use bigdecimal::BigDecimal;
 use bigdecimal::ToPrimitive;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
+use crate::config::{BaselineConfig, SanctionsExposureRuleConfig, StructuringConfig};
 use crate::entity::label_store::EntityLabelStore;
+use crate::graph::exposure::WalletExposure;
 use crate::indexer::types::StablecoinTransfer;
+use crate::wallet::baseline::{self, WalletBaseline};
 use crate::wallet::first_seen::NewWalletEvent;
 
+use super::activity::ActivityWindows;
 use super::types::{AnomalyRecord, AnomalyType};
 
 /// Check if a transfer exceeds the large transfer threshold for its token.
@@ -14,19 +19,13 @@ pub fn check_large_transfer(
     transfer: &StablecoinTransfer,
     thresholds: &HashMap<String, f64>,
 ) -> Option<AnomalyRecord> {
-    let threshold = thresholds
-        .get(&transfer.token_symbol)
-        .or_else(|| thresholds.get("default"))
-        .copied()
-        .unwrap_or(100_000.0);
-
-    // Convert raw amount to human-readable using decimals
-    let human_amount = raw_to_human(&transfer.amount, transfer.token_decimals);
+    let threshold = large_transfer_threshold(thresholds, &transfer.token_symbol);
+    let usd = usd_amount(transfer)?;
 
-    if human_amount >= threshold {
-        let risk = if human_amount >= threshold * 10.0 {
+    if usd >= threshold {
+        let risk = if usd >= threshold * 10.0 {
             80.0
-        } else if human_amount >= threshold * 5.0 {
+        } else if usd >= threshold * 5.0 {
             60.0
         } else {
             40.0
@@ -37,15 +36,18 @@ pub fn check_large_transfer(
             anomaly_type: AnomalyType::LargeTransfer,
             risk_score: risk,
             flags: vec![format!(
-                "transfer_amount_{:.0}_{}_exceeds_{:.0}",
-                human_amount, transfer.token_symbol, threshold
+                "transfer_usd_{:.0}_{}_exceeds_{:.0}",
+                usd, transfer.token_symbol, threshold
             )],
             details: serde_json::json!({
-                "amount": human_amount,
+                "amount": raw_to_human(&transfer.amount, transfer.token_decimals),
+                "amount_usd": usd,
                 "token": transfer.token_symbol,
                 "threshold": threshold,
             }),
             address: None,
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
             tx_hash: transfer.tx_hash.clone(),
             log_index: transfer.log_index,
         });
@@ -54,53 +56,253 @@ pub fn check_large_transfer(
     None
 }
 
-/// Check if the sender has exceeded the velocity limit (too many transfers in a window).
-pub async fn check_velocity(
-    pool: &PgPool,
+/// Large-transfer threshold in USD for a token: its own entry, else `default`, else 100k.
+fn large_transfer_threshold(thresholds: &HashMap<String, f64>, token_symbol: &str) -> f64 {
+    thresholds
+        .get(token_symbol)
+        .or_else(|| thresholds.get("default"))
+        .copied()
+        .unwrap_or(100_000.0)
+}
+
+/// Band `[floor, threshold)` a transfer must fall in to count towards structuring, or
+/// `None` when it is outside it.
+fn structuring_band(
     transfer: &StablecoinTransfer,
-    window_secs: u64,
-    max_transfers: u32,
-) -> eyre::Result<Option<AnomalyRecord>> {
-    let count: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM transfers
-         WHERE from_address = $1
-         AND chain_id = $2
-         AND block_timestamp > $3 - make_interval(secs => $4)",
+    thresholds: &HashMap<String, f64>,
+    config: &StructuringConfig,
+) -> Option<(f64, f64)> {
+    let threshold = large_transfer_threshold(thresholds, &transfer.token_symbol);
+    let floor = threshold * (1.0 - config.band);
+    let usd = usd_amount(transfer)?;
+    (usd >= floor && usd < threshold).then_some((floor, threshold))
+}
+
+/// An in-band transfer found for one side of a structuring candidate.
+#[derive(Debug, sqlx::FromRow)]
+struct BandRow {
+    candidate: i64,
+    receiver: bool,
+    id: i64,
+    usd: f64,
+}
+
+/// Check for structuring: repeated transfers just under the large-transfer threshold from
+/// the same sender, or into the same receiver, within a rolling window. Only transfers that
+/// fall in the band are checked, with one query for the whole batch. Evidence lists the
+/// contributing transfers per side.
+pub async fn check_structuring(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    thresholds: &HashMap<String, f64>,
+    config: &StructuringConfig,
+) -> eyre::Result<Vec<AnomalyRecord>> {
+    let candidates: Vec<(&StablecoinTransfer, f64, f64)> = transfers
+        .iter()
+        .filter_map(|t| {
+            structuring_band(t, thresholds, config).map(|(floor, threshold)| (t, floor, threshold))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let chain_ids: Vec<i64> = candidates.iter().map(|(t, ..)| t.chain_id).collect();
+    let tokens: Vec<&[u8]> = candidates
+        .iter()
+        .map(|(t, ..)| t.token_address.as_slice())
+        .collect();
+    let senders: Vec<&[u8]> = candidates
+        .iter()
+        .map(|(t, ..)| t.from_address.as_slice())
+        .collect();
+    let receivers: Vec<&[u8]> = candidates
+        .iter()
+        .map(|(t, ..)| t.to_address.as_slice())
+        .collect();
+    let timestamps: Vec<DateTime<Utc>> =
+        candidates.iter().map(|(t, ..)| t.block_timestamp).collect();
+    let floors: Vec<f64> = candidates.iter().map(|(_, floor, _)| *floor).collect();
+    let ceilings: Vec<f64> = candidates
+        .iter()
+        .map(|(.., threshold)| *threshold)
+        .collect();
+
+    let rows: Vec<BandRow> = sqlx::query_as(
+        "WITH c AS (
+             SELECT * FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::BYTEA[], $4::BYTEA[],
+                                  $5::TIMESTAMPTZ[], $6::FLOAT8[], $7::FLOAT8[])
+                  WITH ORDINALITY AS c(chain_id, token, sender, receiver, at, floor, threshold, n)
+         ),
+         band AS (
+             SELECT c.n, FALSE AS receiver, t.id, t.block_timestamp, t.amount_usd::FLOAT8 AS usd
+             FROM c JOIN transfers t
+               ON t.chain_id = c.chain_id AND t.token_address = c.token
+              AND t.from_address = c.sender
+              AND t.block_timestamp > c.at - make_interval(secs => $8)
+              AND t.block_timestamp <= c.at
+             UNION ALL
+             SELECT c.n, TRUE, t.id, t.block_timestamp, t.amount_usd::FLOAT8
+             FROM c JOIN transfers t
+               ON t.chain_id = c.chain_id AND t.token_address = c.token
+              AND t.to_address = c.receiver
+              AND t.block_timestamp > c.at - make_interval(secs => $8)
+              AND t.block_timestamp <= c.at
+         )
+         SELECT band.n - 1 AS candidate, band.receiver, band.id, band.usd
+         FROM band JOIN c ON c.n = band.n
+         WHERE band.usd >= c.floor AND band.usd < c.threshold
+         ORDER BY band.n, band.receiver, band.block_timestamp, band.id",
     )
-    .bind(&transfer.from_address)
-    .bind(transfer.chain_id)
-    .bind(transfer.block_timestamp)
-    .bind(window_secs as f64)
-    .fetch_one(pool)
+    .bind(&chain_ids)
+    .bind(&tokens)
+    .bind(&senders)
+    .bind(&receivers)
+    .bind(&timestamps)
+    .bind(&floors)
+    .bind(&ceilings)
+    .bind(config.window_secs as f64)
+    .fetch_all(pool)
     .await?;
 
-    if count.0 > max_transfers as i64 {
-        let risk = if count.0 > (max_transfers * 5) as i64 {
+    // (sender rows, receiver rows) per candidate, each as (id, usd)
+    let mut found: Vec<[Vec<(i64, f64)>; 2]> = vec![Default::default(); candidates.len()];
+    for row in rows {
+        found[row.candidate as usize][row.receiver as usize].push((row.id, row.usd));
+    }
+
+    Ok(candidates
+        .iter()
+        .zip(found)
+        .filter_map(|(&(transfer, floor, threshold), [sent, received])| {
+            structuring_anomaly(transfer, floor, threshold, config, sent, received)
+        })
+        .collect())
+}
+
+/// Build the structuring anomaly for an in-band transfer from the in-band transfers of its
+/// sender and receiver within the window, as (id, usd). Each side needs `min_count` of them.
+fn structuring_anomaly(
+    transfer: &StablecoinTransfer,
+    floor: f64,
+    threshold: f64,
+    config: &StructuringConfig,
+    sent: Vec<(i64, f64)>,
+    received: Vec<(i64, f64)>,
+) -> Option<AnomalyRecord> {
+    let mut sides = Vec::new();
+    let mut flags = Vec::new();
+    let mut max_count = 0usize;
+    let mut max_combined = 0.0f64;
+
+    for (side, address, rows) in [
+        ("sender", &transfer.from_address, sent),
+        ("receiver", &transfer.to_address, received),
+    ] {
+        if rows.len() < config.min_count as usize {
+            continue;
+        }
+
+        let combined: f64 = rows.iter().map(|(_, amount)| amount).sum();
+        max_count = max_count.max(rows.len());
+        max_combined = max_combined.max(combined);
+        flags.push(format!(
+            "structuring_{}_{}_transfers_{:.0}_usd_{}",
+            side,
+            rows.len(),
+            combined,
+            transfer.token_symbol
+        ));
+        sides.push((side, address, rows, combined));
+    }
+
+    let (_, first_address, _, _) = sides.first()?;
+    let address = (*first_address).clone();
+
+    let risk = if max_combined >= threshold * 5.0 {
+        85.0
+    } else if max_count >= config.min_count as usize * 2 {
+        75.0
+    } else {
+        60.0
+    };
+
+    let evidence: Vec<serde_json::Value> = sides
+        .iter()
+        .map(|(side, address, rows, combined)| {
+            serde_json::json!({
+                "side": side,
+                "address": hex::encode(address),
+                "transfer_ids": rows.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+                "transfer_count": rows.len(),
+                "combined_usd": combined,
+            })
+        })
+        .collect();
+
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::Structuring,
+        risk_score: risk,
+        flags,
+        details: serde_json::json!({
+            "token": transfer.token_symbol,
+            "threshold": threshold,
+            "band_floor": floor,
+            "window_secs": config.window_secs,
+            "evidence": evidence,
+        }),
+        address: Some(address),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
+/// Check if the sender has exceeded the velocity limit (too many transfers in a window).
+pub fn check_velocity(
+    transfer: &StablecoinTransfer,
+    windows: &ActivityWindows,
+    window_secs: u64,
+    max_transfers: u32,
+) -> Option<AnomalyRecord> {
+    let count = windows.sent_count(
+        &transfer.from_address,
+        transfer.chain_id,
+        transfer.block_timestamp,
+        window_secs,
+    ) as i64;
+
+    if count > max_transfers as i64 {
+        let risk = if count > (max_transfers * 5) as i64 {
             70.0
         } else {
             50.0
         };
 
-        return Ok(Some(AnomalyRecord {
+        return Some(AnomalyRecord {
             chain_id: transfer.chain_id,
             anomaly_type: AnomalyType::Velocity,
             risk_score: risk,
             flags: vec![format!(
                 "velocity_{}_transfers_in_{}_secs",
-                count.0, window_secs
+                count, window_secs
             )],
             details: serde_json::json!({
-                "transfer_count": count.0,
+                "transfer_count": count,
                 "window_secs": window_secs,
                 "max_allowed": max_transfers,
             }),
             address: Some(transfer.from_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
             tx_hash: transfer.tx_hash.clone(),
             log_index: transfer.log_index,
-        }));
+        });
     }
 
-    Ok(None)
+    None
 }
 
 /// Check if either counterparty is on a sanctions list.
@@ -129,6 +331,8 @@ pub fn check_sanctioned_counterparty(
                 "sanctioned_address": hex::encode(flagged_address),
             }),
             address: Some(flagged_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
             tx_hash: transfer.tx_hash.clone(),
             log_index: transfer.log_index,
         });
@@ -137,6 +341,156 @@ pub fn check_sanctioned_counterparty(
     None
 }
 
+/// Check for indirect sanctions exposure: the sender holds funds that trace back to a
+/// sanctioned address, or the receiver passes funds on towards one. Direct interaction
+/// is left to `check_sanctioned_counterparty`.
+pub fn check_sanctions_exposure(
+    transfer: &StablecoinTransfer,
+    label_store: &EntityLabelStore,
+    exposures: &HashMap<(i64, Vec<u8>), WalletExposure>,
+    config: &SanctionsExposureRuleConfig,
+) -> Option<AnomalyRecord> {
+    if label_store.is_sanctioned(&transfer.from_address)
+        || label_store.is_sanctioned(&transfer.to_address)
+    {
+        return None;
+    }
+
+    let lookup = |address: &Vec<u8>| exposures.get(&(transfer.chain_id, address.clone()));
+    let sender = lookup(&transfer.from_address)
+        .filter(|e| e.inbound >= config.min_exposure)
+        .map(|e| ("from", &transfer.from_address, "inbound", e.inbound, e.inbound_hops));
+    let receiver = lookup(&transfer.to_address)
+        .filter(|e| e.outbound >= config.min_exposure)
+        .map(|e| ("to", &transfer.to_address, "outbound", e.outbound, e.outbound_hops));
+
+    let sides: Vec<_> = sender.into_iter().chain(receiver).collect();
+    let (_, address, _, max_exposure, _) = sides
+        .iter()
+        .copied()
+        .max_by(|a, b| a.3.total_cmp(&b.3))?;
+
+    let flags = sides
+        .iter()
+        .map(|(side, _, direction, exposure, hops)| {
+            format!(
+                "sanctions_exposure_{}_{}_{:.0}pct_{}_hops",
+                side,
+                direction,
+                exposure * 100.0,
+                hops.map_or("unknown".to_string(), |h| h.to_string())
+            )
+        })
+        .collect();
+    let evidence: Vec<serde_json::Value> = sides
+        .iter()
+        .map(|(side, address, direction, exposure, hops)| {
+            serde_json::json!({
+                "side": side,
+                "address": hex::encode(address),
+                "direction": direction,
+                "exposure": exposure,
+                "nearest_hops": hops,
+            })
+        })
+        .collect();
+
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::SanctionsExposure,
+        // Below the 95 of direct sanctioned interaction
+        risk_score: (50.0 + 40.0 * max_exposure as f32).min(90.0),
+        flags,
+        details: serde_json::json!({
+            "min_exposure": config.min_exposure,
+            "exposures": evidence,
+        }),
+        address: Some(address.clone()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
+/// Check a transfer against the rolling baselines of the wallets on both sides. Fires when
+/// the amount is far above what the wallet usually moves or the wallet's day is far busier
+/// than usual. A first transfer with a counterparty raises the score but never fires alone.
+/// Baselines are in USD, so unpriced transfers are not checked.
+pub fn check_baseline_deviation(
+    transfer: &StablecoinTransfer,
+    baselines: &HashMap<(i64, Vec<u8>), WalletBaseline>,
+    config: &BaselineConfig,
+) -> Option<AnomalyRecord> {
+    let amount = usd_amount(transfer)?;
+
+    let mut best: Option<(f32, &[u8])> = None;
+    let mut flags = Vec::new();
+    let mut evidence = Vec::new();
+    for (side, (wallet, counterparty)) in ["from", "to"].into_iter().zip(baseline::sides(transfer)) {
+        let Some(deviation) = baselines
+            .get(&(transfer.chain_id, wallet.to_vec()))
+            .and_then(|b| b.deviation(amount, &counterparty, transfer.block_timestamp, config))
+        else {
+            continue;
+        };
+
+        let amount_spike = deviation.amount_z >= config.amount_z_threshold;
+        let daily_spike = deviation.daily_ratio >= config.daily_count_multiple;
+        if !amount_spike && !daily_spike {
+            continue;
+        }
+
+        let mut risk = 40.0;
+        if amount_spike {
+            risk += 10.0 * (deviation.amount_z - config.amount_z_threshold) as f32;
+            flags.push(format!("{}_amount_{:.1}_sigma", side, deviation.amount_z));
+        }
+        if daily_spike {
+            risk += 15.0;
+            flags.push(format!("{}_daily_count_{:.1}x", side, deviation.daily_ratio));
+        }
+        if deviation.new_counterparty {
+            risk += 10.0;
+            flags.push(format!("{}_new_counterparty", side));
+        }
+        let risk = risk.min(85.0);
+        if best.is_none_or(|(r, _)| risk > r) {
+            best = Some((risk, wallet));
+        }
+
+        evidence.push(serde_json::json!({
+            "side": side,
+            "wallet": hex::encode(wallet),
+            "amount": amount,
+            "typical_amount": deviation.typical_amount,
+            "amount_z": deviation.amount_z,
+            "daily_ratio": deviation.daily_ratio,
+            "daily_count_mean": deviation.daily_count_mean,
+            "new_counterparty": deviation.new_counterparty,
+            "history": deviation.history,
+        }));
+    }
+
+    let (risk_score, address) = best?;
+    Some(AnomalyRecord {
+        chain_id: transfer.chain_id,
+        anomaly_type: AnomalyType::BaselineDeviation,
+        risk_score,
+        flags,
+        details: serde_json::json!({
+            "amount_z_threshold": config.amount_z_threshold,
+            "daily_count_multiple": config.daily_count_multiple,
+            "deviations": evidence,
+        }),
+        address: Some(address.to_vec()),
+        transfer_id: transfer.id,
+        block_timestamp: transfer.block_timestamp,
+        tx_hash: transfer.tx_hash.clone(),
+        log_index: transfer.log_index,
+    })
+}
+
 /// Check if the transfer amount is a suspiciously round number.
 pub fn check_round_number(
     transfer: &StablecoinTransfer,
@@ -144,7 +498,7 @@ pub fn check_round_number(
 ) -> Option<AnomalyRecord> {
     let human_amount = raw_to_human(&transfer.amount, transfer.token_decimals);
 
-    // Only flag amounts above $1000
+    // Roundness is judged in the token's own units, so only flag amounts above 1000 tokens
     if human_amount < 1000.0 {
         return None;
     }
@@ -176,6 +530,8 @@ pub fn check_round_number(
                         "token": transfer.token_symbol,
                     }),
                     address: None,
+                    transfer_id: transfer.id,
+                    block_timestamp: transfer.block_timestamp,
                     tx_hash: transfer.tx_hash.clone(),
                     log_index: transfer.log_index,
                 });
@@ -192,9 +548,9 @@ pub fn check_new_wallet_large_receive(
     new_wallets: &[NewWalletEvent],
     threshold_usd: f64,
 ) -> Option<AnomalyRecord> {
-    let human_amount = raw_to_human(&transfer.amount, transfer.token_decimals);
+    let usd = usd_amount(transfer)?;
 
-    if human_amount < threshold_usd {
+    if usd < threshold_usd {
         return None;
     }
 
@@ -206,7 +562,7 @@ pub fn check_new_wallet_large_receive(
     });
 
     if is_new {
-        let risk = if human_amount >= threshold_usd * 10.0 {
+        let risk = if usd >= threshold_usd * 10.0 {
             80.0
         } else {
             60.0
@@ -218,14 +574,18 @@ pub fn check_new_wallet_large_receive(
             risk_score: risk,
             flags: vec![format!(
                 "new_wallet_received_{:.0}_{}",
-                human_amount, transfer.token_symbol
+                raw_to_human(&transfer.amount, transfer.token_decimals),
+                transfer.token_symbol
             )],
             details: serde_json::json!({
-                "amount": human_amount,
+                "amount": raw_to_human(&transfer.amount, transfer.token_decimals),
+                "amount_usd": usd,
                 "token": transfer.token_symbol,
                 "new_wallet": hex::encode(&transfer.to_address),
             }),
             address: Some(transfer.to_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
             tx_hash: transfer.tx_hash.clone(),
             log_index: transfer.log_index,
         });
@@ -235,51 +595,104 @@ pub fn check_new_wallet_large_receive(
 }
 
 /// Check if an address is active on multiple chains within a short window.
-pub async fn check_cross_chain_activity(
-    pool: &PgPool,
+pub fn check_cross_chain_activity(
     transfer: &StablecoinTransfer,
+    windows: &ActivityWindows,
     window_secs: u64,
-) -> eyre::Result<Option<AnomalyRecord>> {
-    // Count distinct chains this address has been active on recently
-    let count: (i64,) = sqlx::query_as(
-        "SELECT COUNT(DISTINCT chain_id) FROM transfers
-         WHERE (from_address = $1 OR to_address = $1)
-         AND block_timestamp > $2 - make_interval(secs => $3)",
-    )
-    .bind(&transfer.from_address)
-    .bind(transfer.block_timestamp)
-    .bind(window_secs as f64)
-    .fetch_one(pool)
-    .await?;
+) -> Option<AnomalyRecord> {
+    let count =
+        windows.active_chains(&transfer.from_address, transfer.block_timestamp, window_secs) as i64;
 
-    if count.0 >= 3 {
-        let risk = if count.0 >= 5 { 50.0 } else { 30.0 };
+    if count >= 3 {
+        let risk = if count >= 5 { 50.0 } else { 30.0 };
 
-        return Ok(Some(AnomalyRecord {
+        return Some(AnomalyRecord {
             chain_id: transfer.chain_id,
             anomaly_type: AnomalyType::CrossChainActivity,
             risk_score: risk,
             flags: vec![format!(
                 "active_on_{}_chains_in_{}_secs",
-                count.0, window_secs
+                count, window_secs
             )],
             details: serde_json::json!({
-                "chain_count": count.0,
+                "chain_count": count,
                 "window_secs": window_secs,
                 "address": hex::encode(&transfer.from_address),
             }),
             address: Some(transfer.from_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
             tx_hash: transfer.tx_hash.clone(),
             log_index: transfer.log_index,
-        }));
+        });
     }
 
-    Ok(None)
+    None
+}
+
+/// Tokens are minted from and burned to the zero address.
+pub(crate) const ZERO_ADDRESS: [u8; 20] = [0u8; 20];
+
+/// USD value of a transfer, `None` until it has been priced. A token's nominal amount is
+/// only a USD value for tokens pegged to USD, which pricing values itself, so USD rules
+/// skip unpriced transfers rather than guess.
+pub(crate) fn usd_amount(transfer: &StablecoinTransfer) -> Option<f64> {
+    transfer.amount_usd.as_ref().and_then(|usd| usd.to_f64())
 }
 
 /// Convert a raw token amount to human-readable using token decimals.
-fn raw_to_human(amount: &BigDecimal, decimals: i16) -> f64 {
+pub(crate) fn raw_to_human(amount: &BigDecimal, decimals: i16) -> f64 {
     let divisor = BigDecimal::from(10u64.pow(decimals as u32));
     let result = amount / divisor;
     result.to_f64().unwrap_or(0.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use chrono::Utc;
+
+    fn transfer(usd: i64) -> StablecoinTransfer {
+        StablecoinTransfer {
+            id: Some(1),
+            token_symbol: "USDT".to_string(),
+            block_timestamp: Utc::now(),
+            amount_usd: Some(BigDecimal::from(usd)),
+            ..test_transfer(100, 3, 4, usd * 1_000_000)
+        }
+    }
+
+    #[test]
+    fn test_structuring_band_edges_and_min_count() {
+        let thresholds = HashMap::from([("default".to_string(), 10_000.0)]);
+        let config = StructuringConfig::default();
+
+        // The band is [9000, 10000): the floor counts, the threshold is a large transfer
+        assert_eq!(
+            structuring_band(&transfer(9_000), &thresholds, &config),
+            Some((9_000.0, 10_000.0))
+        );
+        assert!(structuring_band(&transfer(8_999), &thresholds, &config).is_none());
+        assert!(structuring_band(&transfer(10_000), &thresholds, &config).is_none());
+
+        let in_band = transfer(9_500);
+        let rows = |n: i64| (1..=n).map(|id| (id, 9_500.0)).collect::<Vec<_>>();
+        assert!(
+            structuring_anomaly(&in_band, 9_000.0, 10_000.0, &config, rows(2), rows(2)).is_none()
+        );
+
+        let anomaly =
+            structuring_anomaly(&in_band, 9_000.0, 10_000.0, &config, rows(2), rows(3)).unwrap();
+        assert_eq!(anomaly.address, Some(in_band.to_address.clone()));
+        assert_eq!(anomaly.flags, ["structuring_receiver_3_transfers_28500_usd_USDT"]);
+        assert_eq!(anomaly.risk_score, 60.0);
+
+        // Five times the threshold combined scores highest; the sender side comes first
+        let anomaly =
+            structuring_anomaly(&in_band, 9_000.0, 10_000.0, &config, rows(6), rows(3)).unwrap();
+        assert_eq!(anomaly.address, Some(in_band.from_address.clone()));
+        assert_eq!(anomaly.flags.len(), 2);
+        assert_eq!(anomaly.risk_score, 85.0);
+    }
+}
@@ -0,0 +1,408 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::config::{ChainConfig, SuppressionConfig};
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+use super::types::AnomalyRecord;
+
+/// An active suppression rule. Unset scope fields match anything.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SuppressionRule {
+    pub id: i64,
+    pub address: Option<Vec<u8>>,
+    /// Lowercased.
+    pub entity_name: Option<String>,
+    pub entity_type: Option<String>,
+    pub chain_id: Option<i64>,
+    pub anomaly_types: Vec<String>,
+}
+
+impl SuppressionRule {
+    /// Whether the rule covers an anomaly whose subjects are `addresses`.
+    pub fn matches(
+        &self,
+        anomaly: &AnomalyRecord,
+        addresses: &[&[u8]],
+        label_store: &EntityLabelStore,
+    ) -> bool {
+        if self.chain_id.is_some_and(|c| c != anomaly.chain_id) {
+            return false;
+        }
+        if !self.anomaly_types.is_empty()
+            && !self
+                .anomaly_types
+                .iter()
+                .any(|t| t == anomaly.anomaly_type.as_str())
+        {
+            return false;
+        }
+        if let Some(address) = &self.address {
+            if !addresses.contains(&address.as_slice()) {
+                return false;
+            }
+        }
+        if self.entity_name.is_none() && self.entity_type.is_none() {
+            return true;
+        }
+
+        addresses
+            .iter()
+            .filter_map(|address| label_store.lookup(address))
+            .flatten()
+            .filter(|label| label.chain_id.is_none_or(|c| c == anomaly.chain_id))
+            .any(|label| {
+                self.entity_name
+                    .as_ref()
+                    .is_none_or(|name| label.entity_name.to_lowercase() == *name)
+                    && self
+                        .entity_type
+                        .as_ref()
+                        .is_none_or(|t| label.entity_type == *t)
+            })
+    }
+}
+
+/// Result of a suppression rule change from the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionOutcome {
+    Applied,
+    NotFound,
+    /// Rules from config are changed by editing the config.
+    ConfigManaged,
+}
+
+/// A new suppression rule from the API.
+#[derive(Debug, Clone)]
+pub struct NewSuppression {
+    pub address: Option<Vec<u8>>,
+    pub entity_name: Option<String>,
+    pub entity_type: Option<String>,
+    pub chain_id: Option<i64>,
+    pub anomaly_types: Vec<String>,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Split anomalies into those to persist and those held back by a rule, paired with
+/// the first matching rule's id. The subjects of an anomaly are its address, or both
+/// parties of its transfer when it has none.
+pub fn partition(
+    rules: &[SuppressionRule],
+    anomalies: Vec<AnomalyRecord>,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+) -> (Vec<AnomalyRecord>, Vec<(i64, AnomalyRecord)>) {
+    if rules.is_empty() {
+        return (anomalies, Vec::new());
+    }
+
+    let by_id: HashMap<i64, &StablecoinTransfer> = transfers
+        .iter()
+        .filter_map(|t| t.id.map(|id| (id, t)))
+        .collect();
+
+    let mut kept = Vec::new();
+    let mut suppressed = Vec::new();
+    for anomaly in anomalies {
+        let addresses: Vec<&[u8]> = match (&anomaly.address, anomaly.transfer_id) {
+            (Some(address), _) => vec![address.as_slice()],
+            (None, Some(id)) => by_id
+                .get(&id)
+                .map(|t| vec![t.from_address.as_slice(), t.to_address.as_slice()])
+                .unwrap_or_default(),
+            (None, None) => Vec::new(),
+        };
+        match rules
+            .iter()
+            .find(|rule| rule.matches(&anomaly, &addresses, label_store))
+        {
+            Some(rule) => suppressed.push((rule.id, anomaly)),
+            None => kept.push(anomaly),
+        }
+    }
+    (kept, suppressed)
+}
+
+/// Rules that are neither disabled nor expired.
+pub async fn load_active(pool: &PgPool) -> eyre::Result<Vec<SuppressionRule>> {
+    let mut rules: Vec<SuppressionRule> = sqlx::query_as(
+        "SELECT id, address, entity_name, entity_type, chain_id, anomaly_types
+             FROM suppression_rules
+             WHERE disabled_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+             ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for rule in &mut rules {
+        rule.entity_name = rule.entity_name.take().map(|n| n.to_lowercase());
+    }
+    Ok(rules)
+}
+
+/// Record suppressed anomalies for audit. A hit already recorded for the same transfer
+/// and type is kept as it is. Returns the number of new hits.
+pub async fn record_hits(pool: &PgPool, hits: &[(i64, AnomalyRecord)]) -> eyre::Result<u64> {
+    if hits.is_empty() {
+        return Ok(0);
+    }
+
+    let rule_ids: Vec<i64> = hits.iter().map(|(id, _)| *id).collect();
+    let transfer_ids: Vec<Option<i64>> = hits.iter().map(|(_, a)| a.transfer_id).collect();
+    let chain_ids: Vec<i64> = hits.iter().map(|(_, a)| a.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> = hits.iter().map(|(_, a)| a.block_timestamp).collect();
+    let anomaly_types: Vec<&str> = hits.iter().map(|(_, a)| a.anomaly_type.as_str()).collect();
+    let risk_scores: Vec<f32> = hits.iter().map(|(_, a)| a.risk_score).collect();
+    let addresses: Vec<Option<&[u8]>> = hits.iter().map(|(_, a)| a.address.as_deref()).collect();
+    let flags: Vec<serde_json::Value> = hits
+        .iter()
+        .map(|(_, a)| serde_json::json!(a.flags))
+        .collect();
+    let details: Vec<&serde_json::Value> = hits.iter().map(|(_, a)| &a.details).collect();
+
+    let result = sqlx::query(
+        "INSERT INTO suppressed_anomalies (rule_id, transfer_id, chain_id, block_timestamp,
+                                           anomaly_type, risk_score, address, flags, details)
+         SELECT i.rule_id, i.transfer_id, i.chain_id, i.block_timestamp, i.anomaly_type,
+                i.risk_score, i.address, ARRAY(SELECT jsonb_array_elements_text(i.flags)),
+                i.details
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::TIMESTAMPTZ[], $5::TEXT[],
+                     $6::REAL[], $7::BYTEA[], $8::JSONB[], $9::JSONB[])
+              AS i(rule_id, transfer_id, chain_id, block_timestamp, anomaly_type, risk_score,
+                   address, flags, details)
+         ON CONFLICT (transfer_id, anomaly_type, chain_id, block_timestamp) DO NOTHING",
+    )
+    .bind(&rule_ids)
+    .bind(&transfer_ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&anomaly_types)
+    .bind(&risk_scores)
+    .bind(&addresses)
+    .bind(&flags)
+    .bind(&details)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Make the stored config rules match the config: upsert each by name and disable
+/// config rules that were removed. Hit history stays attached to the rule's id.
+pub async fn sync_config_rules(
+    pool: &PgPool,
+    rules: &[SuppressionConfig],
+    chains: &[ChainConfig],
+) -> eyre::Result<()> {
+    let mut tx = pool.begin().await?;
+    for rule in rules {
+        let address = rule
+            .address
+            .as_ref()
+            .map(|a| hex::decode(a.trim_start_matches("0x")))
+            .transpose()?;
+        let chain_id = rule
+            .chain
+            .as_ref()
+            .and_then(|name| chains.iter().find(|c| &c.name == name))
+            .map(|c| c.chain_id as i64);
+        sqlx::query(
+            "INSERT INTO suppression_rules (name, source, address, entity_name, entity_type,
+                                            chain_id, anomaly_types, reason, expires_at, created_by)
+             VALUES ($1, 'config', $2, $3, $4, $5, $6, $7, $8, 'config')
+             ON CONFLICT (name) WHERE source = 'config' DO UPDATE SET
+                address = EXCLUDED.address, entity_name = EXCLUDED.entity_name,
+                entity_type = EXCLUDED.entity_type, chain_id = EXCLUDED.chain_id,
+                anomaly_types = EXCLUDED.anomaly_types, reason = EXCLUDED.reason,
+                expires_at = EXCLUDED.expires_at, disabled_at = NULL, disabled_by = NULL,
+                updated_at = NOW()",
+        )
+        .bind(&rule.name)
+        .bind(&address)
+        .bind(&rule.entity)
+        .bind(&rule.entity_type)
+        .bind(chain_id)
+        .bind(&rule.anomaly_types)
+        .bind(&rule.reason)
+        .bind(rule.expires_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let names: Vec<&str> = rules.iter().map(|r| r.name.as_str()).collect();
+    sqlx::query(
+        "UPDATE suppression_rules SET disabled_at = NOW(), disabled_by = 'config', updated_at = NOW()
+         WHERE source = 'config' AND disabled_at IS NULL AND NOT (name = ANY($1))",
+    )
+    .bind(&names)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Store a rule created through the API. Returns its id.
+pub async fn create_rule(pool: &PgPool, actor: &str, rule: &NewSuppression) -> eyre::Result<i64> {
+    let (id,): (i64,) = sqlx::query_as(
+        "INSERT INTO suppression_rules (address, entity_name, entity_type, chain_id,
+                                        anomaly_types, reason, expires_at, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+    )
+    .bind(&rule.address)
+    .bind(&rule.entity_name)
+    .bind(&rule.entity_type)
+    .bind(rule.chain_id)
+    .bind(&rule.anomaly_types)
+    .bind(&rule.reason)
+    .bind(rule.expires_at)
+    .bind(actor)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(id)
+}
+
+/// Change an API rule's reason and/or expiry. `expires_at` of `Some(None)` removes the expiry.
+pub async fn update_rule(
+    pool: &PgPool,
+    id: i64,
+    reason: Option<&str>,
+    expires_at: Option<Option<DateTime<Utc>>>,
+) -> eyre::Result<SuppressionOutcome> {
+    if let Some(outcome) = check_api_rule(pool, id).await? {
+        return Ok(outcome);
+    }
+    sqlx::query(
+        "UPDATE suppression_rules
+         SET reason = COALESCE($2, reason),
+             expires_at = CASE WHEN $3 THEN $4 ELSE expires_at END,
+             updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(reason)
+    .bind(expires_at.is_some())
+    .bind(expires_at.flatten())
+    .execute(pool)
+    .await?;
+    Ok(SuppressionOutcome::Applied)
+}
+
+/// Disable an API rule. Its hits are kept.
+pub async fn disable_rule(pool: &PgPool, id: i64, actor: &str) -> eyre::Result<SuppressionOutcome> {
+    if let Some(outcome) = check_api_rule(pool, id).await? {
+        return Ok(outcome);
+    }
+    sqlx::query(
+        "UPDATE suppression_rules SET disabled_at = NOW(), disabled_by = $2, updated_at = NOW()
+         WHERE id = $1 AND disabled_at IS NULL",
+    )
+    .bind(id)
+    .bind(actor)
+    .execute(pool)
+    .await?;
+    Ok(SuppressionOutcome::Applied)
+}
+
+/// None when the rule exists and came from the API, otherwise why it cannot be changed.
+async fn check_api_rule(pool: &PgPool, id: i64) -> eyre::Result<Option<SuppressionOutcome>> {
+    let source: Option<String> =
+        sqlx::query_scalar("SELECT source FROM suppression_rules WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(match source.as_deref() {
+        None => Some(SuppressionOutcome::NotFound),
+        Some("config") => Some(SuppressionOutcome::ConfigManaged),
+        Some(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::types::AnomalyType;
+    use crate::entity::label_store::EntityLabel;
+
+    fn anomaly(anomaly_type: AnomalyType, address: Option<u8>) -> AnomalyRecord {
+        AnomalyRecord {
+            transfer_id: Some(1),
+            chain_id: 1,
+            block_timestamp: Utc::now(),
+            anomaly_type,
+            risk_score: 50.0,
+            flags: Vec::new(),
+            details: serde_json::json!({}),
+            address: address.map(|a| vec![a]),
+            tx_hash: vec![0x01],
+            log_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_rules_scope_by_address_entity_chain_and_type() {
+        let mut labels = EntityLabelStore::default();
+        labels.insert_memory(EntityLabel {
+            id: 1,
+            address: vec![0xee],
+            chain_id: None,
+            entity_name: "Binance 14".into(),
+            entity_type: "exchange".into(),
+            label_source: "config".into(),
+            confidence: 1.0,
+        });
+        let rules = vec![
+            SuppressionRule {
+                id: 1,
+                address: None,
+                entity_name: None,
+                entity_type: Some("exchange".into()),
+                chain_id: Some(1),
+                anomaly_types: vec!["velocity".into(), "fan_out".into()],
+            },
+            SuppressionRule {
+                id: 2,
+                address: Some(vec![0xaa]),
+                entity_name: None,
+                entity_type: None,
+                chain_id: None,
+                anomaly_types: Vec::new(),
+            },
+        ];
+
+        let exchange_velocity = anomaly(AnomalyType::Velocity, Some(0xee));
+        assert!(rules[0].matches(&exchange_velocity, &[&[0xee]], &labels));
+        let exchange_sanctions = anomaly(AnomalyType::SanctionedCounterparty, Some(0xee));
+        assert!(!rules[0].matches(&exchange_sanctions, &[&[0xee]], &labels));
+        let other_chain = AnomalyRecord {
+            chain_id: 10,
+            ..exchange_velocity.clone()
+        };
+        assert!(!rules[0].matches(&other_chain, &[&[0xee]], &labels));
+
+        let by_name = SuppressionRule {
+            entity_name: Some("binance 14".into()),
+            entity_type: None,
+            ..rules[0].clone()
+        };
+        assert!(by_name.matches(&exchange_velocity, &[&[0xee]], &labels));
+
+        // Address rules cover every type; anomalies without an address use both parties
+        let round = anomaly(AnomalyType::RoundNumber, None);
+        assert!(rules[1].matches(&round, &[&[0xbb], &[0xaa]], &labels));
+        assert!(!rules[1].matches(&round, &[&[0xbb], &[0xcc]], &labels));
+
+        let (kept, suppressed) = partition(
+            &rules,
+            vec![exchange_velocity, exchange_sanctions],
+            &[],
+            &labels,
+        );
+        assert_eq!(kept.len(), 1);
+        assert_eq!(suppressed[0].0, 1);
+    }
+}
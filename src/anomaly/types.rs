@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde_json::Value as JsonValue;
 
 /// Types of anomalies the engine can detect.
@@ -9,10 +10,52 @@ pub enum AnomalyType {
     RoundNumber,
     NewWalletLargeReceive,
     CrossChainActivity,
+    Structuring,
+    PeelChain,
+    RapidPassThrough,
+    Layering,
+    FanIn,
+    FanOut,
+    FlashLoanProfit,
+    StablecoinDepeg,
+    LargeMint,
+    IssuanceToUnknownWallet,
+    BurnAfterRiskyInflow,
+    SanctionsExposure,
+    BaselineDeviation,
+    /// Raised by a custom rule; carries the rule name.
+    Custom(String),
 }
 
 impl AnomalyType {
-    pub fn as_str(&self) -> &'static str {
+    /// Names of the built-in types, which custom rules may not reuse.
+    pub const BUILTIN: [&'static str; 19] = [
+        "large_transfer",
+        "velocity",
+        "sanctioned_counterparty",
+        "round_number",
+        "new_wallet_large_receive",
+        "cross_chain_activity",
+        "structuring",
+        "peel_chain",
+        "rapid_pass_through",
+        "layering",
+        "fan_in",
+        "fan_out",
+        "flash_loan_profit",
+        "stablecoin_depeg",
+        "large_mint",
+        "issuance_to_unknown_wallet",
+        "burn_after_risky_inflow",
+        "sanctions_exposure",
+        "baseline_deviation",
+    ];
+
+    /// Types scored against rolling state that a replay cannot reconstruct.
+    /// Re-enrichment leaves stored anomalies of these types untouched.
+    pub const LIVE_ONLY: [&'static str; 1] = ["baseline_deviation"];
+
+    pub fn as_str(&self) -> &str {
         match self {
             Self::LargeTransfer => "large_transfer",
             Self::Velocity => "velocity",
@@ -20,6 +63,20 @@ impl AnomalyType {
             Self::RoundNumber => "round_number",
             Self::NewWalletLargeReceive => "new_wallet_large_receive",
             Self::CrossChainActivity => "cross_chain_activity",
+            Self::Structuring => "structuring",
+            Self::PeelChain => "peel_chain",
+            Self::RapidPassThrough => "rapid_pass_through",
+            Self::Layering => "layering",
+            Self::FanIn => "fan_in",
+            Self::FanOut => "fan_out",
+            Self::FlashLoanProfit => "flash_loan_profit",
+            Self::StablecoinDepeg => "stablecoin_depeg",
+            Self::LargeMint => "large_mint",
+            Self::IssuanceToUnknownWallet => "issuance_to_unknown_wallet",
+            Self::BurnAfterRiskyInflow => "burn_after_risky_inflow",
+            Self::SanctionsExposure => "sanctions_exposure",
+            Self::BaselineDeviation => "baseline_deviation",
+            Self::Custom(name) => name,
         }
     }
 }
@@ -33,6 +90,9 @@ pub struct AnomalyRecord {
     pub flags: Vec<String>,
     pub details: JsonValue,
     pub address: Option<Vec<u8>>,
+    pub transfer_id: Option<i64>,
+    /// Timestamp of the transfer; anomalies are partitioned alongside their transfers.
+    pub block_timestamp: DateTime<Utc>,
     pub tx_hash: Vec<u8>,
     pub log_index: i32,
 }
@@ -1,8 +1,13 @@
 use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::Json;
+use sqlx::PgPool;
 use std::sync::Arc;
 
+use crate::anomaly::cases::{self, ReviewOutcome, ReviewStatus, ReviewUpdate};
+use crate::anomaly::suppression::{self, NewSuppression, SuppressionOutcome};
+use crate::db::store::Store;
+
 use super::queries;
 use super::types::*;
 use super::AppState;
@@ -18,6 +23,60 @@ fn api_error(status: StatusCode, msg: impl Into<String>) -> (StatusCode, Json<Er
     )
 }
 
+fn parse_actor(actor: &str) -> Result<&str, (StatusCode, Json<ErrorResponse>)> {
+    match actor.trim() {
+        "" => Err(api_error(StatusCode::BAD_REQUEST, "actor is required")),
+        actor => Ok(actor),
+    }
+}
+
+fn parse_update(body: ReviewUpdateRequest) -> Result<ReviewUpdate, (StatusCode, Json<ErrorResponse>)> {
+    let status = match body.status.as_deref() {
+        Some(s) => Some(ReviewStatus::parse(s).ok_or_else(|| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "unknown status '{}'; expected open, triaged, escalated, false_positive or closed",
+                    s
+                ),
+            )
+        })?),
+        None => None,
+    };
+    if body.title.as_deref().is_some_and(|t| t.trim().is_empty()) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "title must not be empty"));
+    }
+    Ok(ReviewUpdate {
+        status,
+        assignee: body.assignee.map(|a| Some(a).filter(|a| !a.is_empty())),
+        title: body.title,
+    })
+}
+
+/// Map a workflow write to a response: 404 when the subject is missing,
+/// 409 for a disallowed status change.
+fn check_outcome(outcome: ReviewOutcome, subject: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match outcome {
+        ReviewOutcome::Applied => Ok(()),
+        ReviewOutcome::NotFound => Err(api_error(StatusCode::NOT_FOUND, format!("{} not found", subject))),
+        ReviewOutcome::InvalidTransition(from, to) => Err(api_error(
+            StatusCode::CONFLICT,
+            format!("cannot move {} from {} to {}", subject, from.as_str(), to.as_str()),
+        )),
+    }
+}
+
+fn internal(e: eyre::Report) -> (StatusCode, Json<ErrorResponse>) {
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// The Postgres pool for queries that only exist as SQL; 501 on other stores.
+fn postgres<S: Store>(state: &AppState<S>) -> Result<&PgPool, (StatusCode, Json<ErrorResponse>)> {
+    state.postgres.as_ref().ok_or_else(|| {
+        api_error(StatusCode::NOT_IMPLEMENTED, "not available without Postgres")
+    })
+}
+
 fn parse_address(hex: &str) -> Result<Vec<u8>, (StatusCode, Json<ErrorResponse>)> {
     hex_to_bytes(hex).map_err(|e| api_error(StatusCode::BAD_REQUEST, e))
 }
@@ -26,15 +85,53 @@ fn parse_address(hex: &str) -> Result<Vec<u8>, (StatusCode, Json<ErrorResponse>)
 // Health & Stats
 // ============================================================
 
-pub async fn health(State(state): State<Arc<AppState>>) -> ApiResult<HealthResponse> {
-    queries::get_health(&state.pool)
+pub async fn health<S: Store>(State(state): State<Arc<AppState<S>>>) -> ApiResult<HealthResponse> {
+    queries::get_health(&state.store)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn stats<S: Store>(State(state): State<Arc<AppState<S>>>) -> ApiResult<StatsResponse> {
+    queries::get_stats(postgres(&state)?)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// ============================================================
+// Enrichment Control
+// ============================================================
+
+pub async fn pause_enrichment<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(chain_id): Path<i64>,
+) -> ApiResult<EnrichmentStatus> {
+    queries::set_enrichment_paused(&state.store, chain_id, true)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn resume_enrichment<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(chain_id): Path<i64>,
+) -> ApiResult<EnrichmentStatus> {
+    queries::set_enrichment_paused(&state.store, chain_id, false)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn stats(State(state): State<Arc<AppState>>) -> ApiResult<StatsResponse> {
-    queries::get_stats(&state.pool)
+pub async fn replay_enrichment<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(chain_id): Path<i64>,
+    Json(body): Json<ReplayRequest>,
+) -> ApiResult<EnrichmentStatus> {
+    if body.from_block < 0 {
+        return Err(api_error(StatusCode::BAD_REQUEST, "from_block must be non-negative"));
+    }
+    queries::replay_enrichment(&state.store, chain_id, body.from_block)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
@@ -44,52 +141,62 @@ pub async fn stats(State(state): State<Arc<AppState>>) -> ApiResult<StatsRespons
 // Wallet
 // ============================================================
 
-pub async fn wallet_profile(
-    State(state): State<Arc<AppState>>,
+pub async fn wallet_profile<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
     Query(params): Query<ChainFilter>,
 ) -> ApiResult<WalletProfileResponse> {
     let addr = parse_address(&address)?;
-    queries::get_wallet_profile(&state.pool, &addr, params.chain_id)
+    queries::get_wallet_profile(&state.store, state.postgres.as_ref(), &addr, params.chain_id)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn wallet_journey(
-    State(state): State<Arc<AppState>>,
+pub async fn list_wallet_risk<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<WalletRiskParams>,
+) -> ApiResult<WalletRiskResponse> {
+    queries::get_wallet_risk(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
+
+pub async fn wallet_journey<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
     Query(params): Query<PaginatedChainFilter>,
 ) -> ApiResult<WalletJourneyResponse> {
     let addr = parse_address(&address)?;
     let limit = params.limit.unwrap_or(100).min(1000);
     let offset = params.offset.unwrap_or(0);
-    queries::get_wallet_journey(&state.pool, &addr, params.chain_id, limit, offset)
+    queries::get_wallet_journey(postgres(&state)?, &addr, params.chain_id, limit, offset)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn wallet_fingerprint(
-    State(state): State<Arc<AppState>>,
+pub async fn wallet_fingerprint<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
     Query(params): Query<ChainFilter>,
 ) -> ApiResult<FingerprintResponse> {
     let addr = parse_address(&address)?;
-    queries::get_wallet_fingerprint(&state.pool, &addr, params.chain_id)
+    queries::get_wallet_fingerprint(postgres(&state)?, &addr, params.chain_id)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn similar_wallets(
-    State(state): State<Arc<AppState>>,
+pub async fn similar_wallets<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
     Query(params): Query<SimilarParams>,
 ) -> ApiResult<SimilarWalletsResponse> {
     let addr = parse_address(&address)?;
     let limit = params.limit.unwrap_or(20).min(100);
-    queries::get_similar_wallets(&state.pool, &addr, params.chain_id, limit)
+    queries::get_similar_wallets(postgres(&state)?, &addr, params.chain_id, limit)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
@@ -99,11 +206,11 @@ pub async fn similar_wallets(
 // Transfers
 // ============================================================
 
-pub async fn list_transfers(
-    State(state): State<Arc<AppState>>,
+pub async fn list_transfers<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Query(params): Query<TransferParams>,
 ) -> ApiResult<TransfersResponse> {
-    queries::get_transfers(&state.pool, &params)
+    queries::get_transfers(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
@@ -113,36 +220,315 @@ pub async fn list_transfers(
 // Anomalies
 // ============================================================
 
-pub async fn list_anomalies(
-    State(state): State<Arc<AppState>>,
+pub async fn list_anomalies<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Query(params): Query<AnomalyParams>,
 ) -> ApiResult<AnomaliesResponse> {
-    queries::get_anomalies(&state.pool, &params)
+    queries::get_anomalies(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
+pub async fn list_anomaly_groups<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<AnomalyGroupParams>,
+) -> ApiResult<AnomalyGroupsResponse> {
+    queries::get_anomaly_groups(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
+
+pub async fn anomaly_detail<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(anomaly_id): Path<i64>,
+) -> ApiResult<AnomalyDetailResponse> {
+    queries::get_anomaly(postgres(&state)?, anomaly_id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "anomaly not found"))
+}
+
+pub async fn update_anomaly<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(anomaly_id): Path<i64>,
+    Json(body): Json<ReviewUpdateRequest>,
+) -> ApiResult<AnomalyDetailResponse> {
+    let actor = parse_actor(&body.actor)?.to_string();
+    if body.title.is_some() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "anomalies have no title"));
+    }
+    let update = parse_update(body)?;
+    let outcome = cases::update_anomaly(postgres(&state)?, anomaly_id, &actor, &update)
+        .await
+        .map_err(internal)?;
+    check_outcome(outcome, "anomaly")?;
+    anomaly_detail(State(state), Path(anomaly_id)).await
+}
+
+pub async fn add_anomaly_note<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(anomaly_id): Path<i64>,
+    Json(body): Json<NoteRequest>,
+) -> ApiResult<AnomalyDetailResponse> {
+    let actor = parse_actor(&body.actor)?;
+    if body.body.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "note body must not be empty"));
+    }
+    cases::add_anomaly_note(postgres(&state)?, anomaly_id, actor, &body.body)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "anomaly not found"))?;
+    anomaly_detail(State(state), Path(anomaly_id)).await
+}
+
+// ============================================================
+// Cases
+// ============================================================
+
+pub async fn list_cases<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<CaseParams>,
+) -> ApiResult<CasesResponse> {
+    queries::get_cases(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
+
+pub async fn create_case<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Json(body): Json<CreateCaseRequest>,
+) -> ApiResult<CaseDetailResponse> {
+    let actor = parse_actor(&body.actor)?;
+    if body.title.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "title must not be empty"));
+    }
+    let assignee = body.assignee.as_deref().filter(|a| !a.is_empty());
+    let case_id = cases::create_case(
+        postgres(&state)?,
+        actor,
+        &body.title,
+        &body.description,
+        assignee,
+        &body.anomaly_ids,
+    )
+    .await
+    .map_err(internal)?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "one or more anomalies not found"))?;
+    case_detail(State(state), Path(case_id)).await
+}
+
+pub async fn case_detail<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(case_id): Path<i64>,
+) -> ApiResult<CaseDetailResponse> {
+    queries::get_case(postgres(&state)?, case_id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "case not found"))
+}
+
+pub async fn update_case<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(case_id): Path<i64>,
+    Json(body): Json<ReviewUpdateRequest>,
+) -> ApiResult<CaseDetailResponse> {
+    let actor = parse_actor(&body.actor)?.to_string();
+    let update = parse_update(body)?;
+    let outcome = cases::update_case(postgres(&state)?, case_id, &actor, &update)
+        .await
+        .map_err(internal)?;
+    check_outcome(outcome, "case")?;
+    case_detail(State(state), Path(case_id)).await
+}
+
+pub async fn add_case_note<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(case_id): Path<i64>,
+    Json(body): Json<NoteRequest>,
+) -> ApiResult<CaseDetailResponse> {
+    let actor = parse_actor(&body.actor)?;
+    if body.body.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "note body must not be empty"));
+    }
+    cases::add_case_note(postgres(&state)?, case_id, actor, &body.body)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "case not found"))?;
+    case_detail(State(state), Path(case_id)).await
+}
+
+pub async fn link_case_anomalies<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(case_id): Path<i64>,
+    Json(body): Json<LinkAnomaliesRequest>,
+) -> ApiResult<CaseDetailResponse> {
+    let actor = parse_actor(&body.actor)?;
+    let outcome = cases::link_anomalies(postgres(&state)?, case_id, actor, &body.anomaly_ids)
+        .await
+        .map_err(internal)?;
+    check_outcome(outcome, "case or anomaly")?;
+    case_detail(State(state), Path(case_id)).await
+}
+
+pub async fn unlink_case_anomaly<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path((case_id, anomaly_id)): Path<(i64, i64)>,
+    Query(params): Query<ActorParams>,
+) -> ApiResult<CaseDetailResponse> {
+    let actor = parse_actor(&params.actor)?;
+    let outcome = cases::unlink_anomaly(postgres(&state)?, case_id, anomaly_id, actor)
+        .await
+        .map_err(internal)?;
+    check_outcome(outcome, "anomaly in case")?;
+    case_detail(State(state), Path(case_id)).await
+}
+
+// ============================================================
+// Suppressions
+// ============================================================
+
+/// Map a suppression rule change to a response: 404 when the rule is missing,
+/// 409 for rules that come from config.
+fn check_suppression(outcome: SuppressionOutcome) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match outcome {
+        SuppressionOutcome::Applied => Ok(()),
+        SuppressionOutcome::NotFound => Err(api_error(StatusCode::NOT_FOUND, "suppression not found")),
+        SuppressionOutcome::ConfigManaged => Err(api_error(
+            StatusCode::CONFLICT,
+            "suppression is defined in config; change it there",
+        )),
+    }
+}
+
+pub async fn list_suppressions<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<SuppressionParams>,
+) -> ApiResult<SuppressionsResponse> {
+    queries::get_suppressions(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(internal)
+}
+
+pub async fn create_suppression<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Json(body): Json<CreateSuppressionRequest>,
+) -> ApiResult<SuppressionDetailResponse> {
+    let actor = parse_actor(&body.actor)?;
+    if body.reason.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "reason must not be empty"));
+    }
+    if body.expires_at.is_some_and(|at| at <= chrono::Utc::now()) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "expires_at is in the past"));
+    }
+    let address = body.address.as_deref().map(parse_address).transpose()?;
+    if address.as_ref().is_some_and(|a| a.len() != 20) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "address must be 20 bytes"));
+    }
+    let entity_name = body.entity.filter(|e| !e.trim().is_empty());
+    let entity_type = body.entity_type.filter(|t| !t.trim().is_empty());
+    if address.is_none()
+        && entity_name.is_none()
+        && entity_type.is_none()
+        && body.chain_id.is_none()
+        && body.anomaly_types.is_empty()
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "set at least one of address, entity, entity_type, chain_id or anomaly_types",
+        ));
+    }
+
+    let rule = NewSuppression {
+        address,
+        entity_name,
+        entity_type,
+        chain_id: body.chain_id,
+        anomaly_types: body.anomaly_types,
+        reason: body.reason,
+        expires_at: body.expires_at,
+    };
+    let rule_id = suppression::create_rule(postgres(&state)?, actor, &rule)
+        .await
+        .map_err(internal)?;
+    suppression_detail(State(state), Path(rule_id)).await
+}
+
+pub async fn suppression_detail<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(rule_id): Path<i64>,
+) -> ApiResult<SuppressionDetailResponse> {
+    queries::get_suppression(postgres(&state)?, rule_id)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "suppression not found"))
+}
+
+pub async fn update_suppression<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(rule_id): Path<i64>,
+    Json(body): Json<UpdateSuppressionRequest>,
+) -> ApiResult<SuppressionDetailResponse> {
+    if body.reason.as_deref().is_some_and(|r| r.trim().is_empty()) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "reason must not be empty"));
+    }
+    if body.clear_expiry && body.expires_at.is_some() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "expires_at and clear_expiry cannot both be set",
+        ));
+    }
+    let expires_at = match (body.expires_at, body.clear_expiry) {
+        (Some(at), _) => Some(Some(at)),
+        (None, true) => Some(None),
+        (None, false) => None,
+    };
+    let outcome = suppression::update_rule(postgres(&state)?, rule_id, body.reason.as_deref(), expires_at)
+        .await
+        .map_err(internal)?;
+    check_suppression(outcome)?;
+    suppression_detail(State(state), Path(rule_id)).await
+}
+
+pub async fn disable_suppression<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Path(rule_id): Path<i64>,
+    Query(params): Query<ActorParams>,
+) -> ApiResult<SuppressionDetailResponse> {
+    let actor = parse_actor(&params.actor)?;
+    let outcome = suppression::disable_rule(postgres(&state)?, rule_id, actor)
+        .await
+        .map_err(internal)?;
+    check_suppression(outcome)?;
+    suppression_detail(State(state), Path(rule_id)).await
+}
+
 // ============================================================
 // Entities
 // ============================================================
 
-pub async fn list_entities(
-    State(state): State<Arc<AppState>>,
+pub async fn list_entities<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Query(params): Query<EntityParams>,
 ) -> ApiResult<EntitiesResponse> {
-    queries::get_entities(&state.pool, &params)
+    queries::get_entities(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn entity_by_address(
-    State(state): State<Arc<AppState>>,
+pub async fn entity_by_address<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
 ) -> ApiResult<EntitiesResponse> {
     let addr = parse_address(&address)?;
-    queries::get_entity_by_address(&state.pool, &addr)
+    queries::get_entity_by_address(postgres(&state)?, &addr)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
@@ -152,34 +538,48 @@ pub async fn entity_by_address(
 // DeFi Events
 // ============================================================
 
-pub async fn wallet_defi(
-    State(state): State<Arc<AppState>>,
+pub async fn wallet_defi<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(address): Path<String>,
     Query(params): Query<DefiParams>,
 ) -> ApiResult<DefiEventsResponse> {
     let addr = parse_address(&address)?;
-    queries::get_wallet_defi(&state.pool, &addr, &params)
+    queries::get_wallet_defi(postgres(&state)?, &addr, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn list_defi_events(
-    State(state): State<Arc<AppState>>,
+pub async fn list_defi_events<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Query(params): Query<DefiParams>,
 ) -> ApiResult<DefiEventsResponse> {
-    queries::get_defi_events(&state.pool, &params)
+    queries::get_defi_events(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn tx_context(
-    State(state): State<Arc<AppState>>,
+pub async fn tx_context<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(tx_hash): Path<String>,
 ) -> ApiResult<TxContextResponse> {
     let hash_bytes = parse_address(&tx_hash)?;
-    queries::get_tx_context(&state.pool, &hash_bytes)
+    queries::get_tx_context(postgres(&state)?, &hash_bytes)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// ============================================================
+// Stablecoin Prices
+// ============================================================
+
+pub async fn list_stablecoin_prices<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<StablecoinPriceParams>,
+) -> ApiResult<StablecoinPricesResponse> {
+    queries::get_stablecoin_prices(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
@@ -189,13 +589,63 @@ pub async fn tx_context(
 // Cluster
 // ============================================================
 
-pub async fn cluster_detail(
-    State(state): State<Arc<AppState>>,
+pub async fn cluster_detail<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
     Path(cluster_id): Path<i64>,
     Query(params): Query<ChainFilter>,
 ) -> ApiResult<ClusterResponse> {
     let chain_id = params.chain_id.unwrap_or(1);
-    queries::get_cluster(&state.pool, cluster_id, chain_id)
+    queries::get_cluster(postgres(&state)?, cluster_id, chain_id)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// ============================================================
+// Time Series
+// ============================================================
+
+pub async fn volume_series<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<TimeSeriesParams>,
+) -> ApiResult<TimeSeriesResponse<VolumePoint>> {
+    queries::get_volume_series(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn entity_volume_series<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<TimeSeriesParams>,
+) -> ApiResult<TimeSeriesResponse<EntityVolumePoint>> {
+    queries::get_entity_volume_series(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn unique_addresses_series<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<TimeSeriesParams>,
+) -> ApiResult<TimeSeriesResponse<UniqueAddressesPoint>> {
+    if params.interval == Interval::Hour {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "unique addresses are tracked per day; use interval=day or interval=week",
+        ));
+    }
+    queries::get_unique_addresses_series(postgres(&state)?, &params)
+        .await
+        .map(Json)
+        .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn anomaly_series<S: Store>(
+    State(state): State<Arc<AppState<S>>>,
+    Query(params): Query<TimeSeriesParams>,
+) -> ApiResult<TimeSeriesResponse<AnomalyCountPoint>> {
+    queries::get_anomaly_series(postgres(&state)?, &params)
         .await
         .map(Json)
         .map_err(|e| api_error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
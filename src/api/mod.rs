@@ -2,63 +2,204 @@ pub mod handlers;
 pub mod queries;
 pub mod types;
 
-use axum::{routing::get, Router};
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
 use sqlx::PgPool;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 
+use crate::db::store::Store;
+
 #[derive(Clone)]
-pub struct AppState {
-    pub pool: PgPool,
+pub struct AppState<S> {
+    pub store: S,
+    /// Pool for the endpoints that only exist as SQL; they answer 501 without one.
+    pub postgres: Option<PgPool>,
 }
 
-pub fn router(pool: PgPool) -> Router {
-    let state = Arc::new(AppState { pool });
+pub fn router<S: Store + 'static>(store: S, postgres: Option<PgPool>) -> Router {
+    let state = Arc::new(AppState { store, postgres });
 
     Router::new()
-        .route("/api/v1/health", get(handlers::health))
-        .route("/api/v1/stats", get(handlers::stats))
-        .route("/api/v1/wallet/{address}", get(handlers::wallet_profile))
+        .route("/api/v1/health", get(handlers::health::<S>))
+        .route("/api/v1/stats", get(handlers::stats::<S>))
+        .route(
+            "/api/v1/enrichment/{chain_id}/pause",
+            post(handlers::pause_enrichment::<S>),
+        )
+        .route(
+            "/api/v1/enrichment/{chain_id}/resume",
+            post(handlers::resume_enrichment::<S>),
+        )
+        .route(
+            "/api/v1/enrichment/{chain_id}/replay",
+            post(handlers::replay_enrichment::<S>),
+        )
+        .route("/api/v1/wallets/risk", get(handlers::list_wallet_risk::<S>))
+        .route("/api/v1/wallet/{address}", get(handlers::wallet_profile::<S>))
         .route(
             "/api/v1/wallet/{address}/journey",
-            get(handlers::wallet_journey),
+            get(handlers::wallet_journey::<S>),
         )
         .route(
             "/api/v1/wallet/{address}/fingerprint",
-            get(handlers::wallet_fingerprint),
+            get(handlers::wallet_fingerprint::<S>),
         )
         .route(
             "/api/v1/wallet/{address}/similar",
-            get(handlers::similar_wallets),
+            get(handlers::similar_wallets::<S>),
+        )
+        .route("/api/v1/transfers", get(handlers::list_transfers::<S>))
+        .route("/api/v1/anomalies", get(handlers::list_anomalies::<S>))
+        .route(
+            "/api/v1/anomaly-groups",
+            get(handlers::list_anomaly_groups::<S>),
+        )
+        .route(
+            "/api/v1/anomalies/{id}",
+            get(handlers::anomaly_detail::<S>).patch(handlers::update_anomaly::<S>),
         )
-        .route("/api/v1/transfers", get(handlers::list_transfers))
-        .route("/api/v1/anomalies", get(handlers::list_anomalies))
-        .route("/api/v1/entities", get(handlers::list_entities))
+        .route(
+            "/api/v1/anomalies/{id}/notes",
+            post(handlers::add_anomaly_note::<S>),
+        )
+        .route(
+            "/api/v1/cases",
+            get(handlers::list_cases::<S>).post(handlers::create_case::<S>),
+        )
+        .route(
+            "/api/v1/cases/{id}",
+            get(handlers::case_detail::<S>).patch(handlers::update_case::<S>),
+        )
+        .route("/api/v1/cases/{id}/notes", post(handlers::add_case_note::<S>))
+        .route(
+            "/api/v1/cases/{id}/anomalies",
+            post(handlers::link_case_anomalies::<S>),
+        )
+        .route(
+            "/api/v1/cases/{id}/anomalies/{anomaly_id}",
+            delete(handlers::unlink_case_anomaly::<S>),
+        )
+        .route(
+            "/api/v1/suppressions",
+            get(handlers::list_suppressions::<S>).post(handlers::create_suppression::<S>),
+        )
+        .route(
+            "/api/v1/suppressions/{id}",
+            get(handlers::suppression_detail::<S>)
+                .patch(handlers::update_suppression::<S>)
+                .delete(handlers::disable_suppression::<S>),
+        )
+        .route("/api/v1/entities", get(handlers::list_entities::<S>))
         .route(
             "/api/v1/entities/{address}",
-            get(handlers::entity_by_address),
+            get(handlers::entity_by_address::<S>),
         )
         .route(
             "/api/v1/wallet/{address}/defi",
-            get(handlers::wallet_defi),
+            get(handlers::wallet_defi::<S>),
+        )
+        .route("/api/v1/defi/events", get(handlers::list_defi_events::<S>))
+        .route("/api/v1/tx/{tx_hash}", get(handlers::tx_context::<S>))
+        .route(
+            "/api/v1/prices/stablecoins",
+            get(handlers::list_stablecoin_prices::<S>),
         )
-        .route("/api/v1/defi/events", get(handlers::list_defi_events))
-        .route("/api/v1/tx/{tx_hash}", get(handlers::tx_context))
         .route(
             "/api/v1/cluster/{cluster_id}",
-            get(handlers::cluster_detail),
+            get(handlers::cluster_detail::<S>),
         )
+        .route("/api/v1/timeseries/volume", get(handlers::volume_series::<S>))
+        .route(
+            "/api/v1/timeseries/entities",
+            get(handlers::entity_volume_series::<S>),
+        )
+        .route(
+            "/api/v1/timeseries/addresses",
+            get(handlers::unique_addresses_series::<S>),
+        )
+        .route("/api/v1/timeseries/anomalies", get(handlers::anomaly_series::<S>))
         .with_state(state)
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
 }
 
-pub async fn serve(pool: PgPool, host: &str, port: u16) -> eyre::Result<()> {
-    let app = router(pool);
+pub async fn serve<S: Store + 'static>(
+    store: S,
+    postgres: Option<PgPool>,
+    host: &str,
+    port: u16,
+) -> eyre::Result<()> {
+    let app = router(store, postgres);
     let addr = format!("{}:{}", host, port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     tracing::info!(%addr, "API server listening");
     axum::serve(listener, app).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStore;
+    use crate::indexer::types::test_transfer;
+    use serde_json::Value;
+
+
+    async fn spawn(store: MemoryStore) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(store, None)).await });
+        format!("http://{}/api/v1", addr)
+    }
+
+    async fn json(response: reqwest::Response) -> Value {
+        assert!(response.status().is_success(), "{}", response.status());
+        serde_json::from_str(&response.text().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_serves_health_enrichment_and_wallets() {
+        let store = MemoryStore::new();
+        let mut batch = vec![
+            test_transfer(100, 1, 2, 1_000_000),
+            test_transfer(101, 1, 2, 1_000_000),
+        ];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        store.upsert_indexer_state(1, 101, None).await.unwrap();
+        store.update_graph_edges(&batch[..1]).await.unwrap();
+        store.advance_enrichment_checkpoint(1, 0, 1, 100).await.unwrap();
+
+        let base = spawn(store).await;
+        let client = reqwest::Client::new();
+
+        let health = json(client.get(format!("{}/health", base)).send().await.unwrap()).await;
+        assert_eq!(health["total_transfers"], 2);
+        let enrichment = &health["indexed_chains"][0]["enrichment"];
+        assert_eq!(enrichment["last_enriched_block"], 100);
+        assert_eq!(enrichment["pending_transfers"], 1);
+        assert_eq!(enrichment["lag_blocks"], 1);
+
+        let url = format!("{}/enrichment/1/pause", base);
+        let paused = json(client.post(url).send().await.unwrap()).await;
+        assert_eq!(paused["paused"], true);
+
+        let url = format!("{}/enrichment/1/replay", base);
+        let body = r#"{"from_block": 100}"#;
+        let request = client.post(url).header("content-type", "application/json").body(body);
+        let replayed = json(request.send().await.unwrap()).await;
+        assert_eq!(replayed["pending_transfers"], 2);
+        assert_eq!(replayed["last_enriched_block"], Value::Null);
+
+        let wallet = format!("{}/wallet/0x{}", base, hex::encode([1; 20]));
+        let profile = json(client.get(wallet).send().await.unwrap()).await;
+        assert_eq!(profile["graph_summary"]["outgoing_count"], 0);
+
+        // Queries that only exist as SQL are unavailable
+        let stats = client.get(format!("{}/stats", base)).send().await.unwrap();
+        assert_eq!(stats.status(), 501);
+    }
+}
@@ -2,36 +2,83 @@ use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 
+use crate::db::store::Store;
+use crate::entity::label_store::EntityLabel;
+use crate::graph::exposure;
+use crate::graph::tracker::GraphEdge;
+use crate::wallet::first_seen::NewWalletEvent;
+
 use super::types::*;
 
 // ============================================================
 // Health & Stats
 // ============================================================
 
-pub async fn get_health(pool: &PgPool) -> eyre::Result<HealthResponse> {
-    let (total_transfers,): (i64,) =
-        sqlx::query_as("SELECT COUNT(*) FROM transfers")
-            .fetch_one(pool)
-            .await?;
+pub async fn get_health<S: Store>(store: &S) -> eyre::Result<HealthResponse> {
+    let total_transfers = store.count_transfers().await?;
+    let chains = store.get_indexer_states().await?;
 
-    let chains: Vec<(i64, i64)> =
-        sqlx::query_as("SELECT chain_id, last_indexed_block FROM indexer_state ORDER BY chain_id")
-            .fetch_all(pool)
-            .await?;
+    let mut indexed_chains = Vec::with_capacity(chains.len());
+    for (chain_id, last_block) in chains {
+        indexed_chains.push(ChainStatus {
+            chain_id,
+            last_block,
+            enrichment: get_enrichment_status(store, chain_id).await?,
+        });
+    }
 
     Ok(HealthResponse {
         status: "ok".to_string(),
         total_transfers,
-        indexed_chains: chains
-            .into_iter()
-            .map(|(chain_id, last_block)| ChainStatus {
-                chain_id,
-                last_block,
-            })
-            .collect(),
+        indexed_chains,
     })
 }
 
+/// Enrichment progress for a chain, measured against the ingestion checkpoint.
+pub async fn get_enrichment_status<S: Store>(store: &S, chain_id: i64) -> eyre::Result<EnrichmentStatus> {
+    let checkpoint = store.get_enrichment_state(chain_id).await?;
+    let last_indexed = store.get_last_indexed_block(chain_id).await?;
+    let pending_transfers = store
+        .count_transfers_after(chain_id, checkpoint.last_transfer_id)
+        .await?;
+
+    // Blocks without transfers need no enrichment, so a drained queue means no lag.
+    let lag_blocks = match (pending_transfers, last_indexed) {
+        (0, _) | (_, None) => 0,
+        (_, Some(head)) => head as i64 - checkpoint.last_block.unwrap_or(0),
+    };
+
+    Ok(EnrichmentStatus {
+        chain_id,
+        last_enriched_block: checkpoint.last_block,
+        lag_blocks,
+        pending_transfers,
+        paused: checkpoint.paused,
+    })
+}
+
+pub async fn set_enrichment_paused<S: Store>(
+    store: &S,
+    chain_id: i64,
+    paused: bool,
+) -> eyre::Result<EnrichmentStatus> {
+    store.set_enrichment_paused(chain_id, paused).await?;
+    get_enrichment_status(store, chain_id).await
+}
+
+/// Rewind the enrichment checkpoint to re-run the pipeline from `from_block`.
+/// The store takes the replayed range out of graph edges and rollups first, so the
+/// worker adds it once.
+pub async fn replay_enrichment<S: Store>(
+    store: &S,
+    chain_id: i64,
+    from_block: i64,
+) -> eyre::Result<EnrichmentStatus> {
+    let queued = store.rewind_enrichment_checkpoint(chain_id, from_block).await?;
+    tracing::info!(chain_id, from_block, queued, "Enrichment replay requested");
+    get_enrichment_status(store, chain_id).await
+}
+
 pub async fn get_stats(pool: &PgPool) -> eyre::Result<StatsResponse> {
     let (total_transfers,): (i64,) =
         sqlx::query_as("SELECT COUNT(*) FROM transfers")
@@ -75,41 +122,25 @@ pub async fn get_stats(pool: &PgPool) -> eyre::Result<StatsResponse> {
 // Wallet Profile
 // ============================================================
 
-pub async fn get_wallet_profile(
-    pool: &PgPool,
+/// Wallet profile. First sighting, labels and graph summary come from the store; the
+/// cluster, anomalies, exposure and risk scores only exist in Postgres.
+pub async fn get_wallet_profile<S: Store>(
+    store: &S,
+    postgres: Option<&PgPool>,
     address: &[u8],
     chain_id: Option<i64>,
 ) -> eyre::Result<WalletProfileResponse> {
     let hex_addr = bytes_to_hex(address);
 
-    // First seen
-    let first_seen: Option<(i64, DateTime<Utc>, i64, String)> = sqlx::query_as(
-        "SELECT chain_id, first_seen_at, first_block, first_direction
-         FROM wallet_first_seen WHERE address = $1
-         ORDER BY first_seen_at ASC LIMIT 1",
-    )
-    .bind(address)
-    .fetch_optional(pool)
-    .await?;
+    let first_seen = store.get_first_seen(address).await?;
+    let labels = store.get_address_labels(address, chain_id).await?;
 
-    // Labels
-    let labels: Vec<(String, String, String, f32)> = if let Some(cid) = chain_id {
-        sqlx::query_as(
-            "SELECT entity_name, entity_type, label_source, confidence
-             FROM entity_labels WHERE address = $1 AND (chain_id = $2 OR chain_id IS NULL)",
-        )
-        .bind(address)
-        .bind(cid)
-        .fetch_all(pool)
-        .await?
-    } else {
-        sqlx::query_as(
-            "SELECT entity_name, entity_type, label_source, confidence
-             FROM entity_labels WHERE address = $1",
-        )
-        .bind(address)
-        .fetch_all(pool)
-        .await?
+    // Graph summary
+    let outgoing = store.get_outgoing_edges(address, None).await?;
+    let incoming = store.get_incoming_edges(address, None).await?;
+
+    let Some(pool) = postgres else {
+        return Ok(wallet_profile(hex_addr, first_seen, labels, &outgoing, &incoming));
     };
 
     // Cluster
@@ -120,35 +151,6 @@ pub async fn get_wallet_profile(
     .fetch_optional(pool)
     .await?;
 
-    // Graph summary
-    let (outgoing_count,): (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM wallet_graph_edges WHERE source_address = $1",
-    )
-    .bind(address)
-    .fetch_one(pool)
-    .await?;
-
-    let (incoming_count,): (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM wallet_graph_edges WHERE dest_address = $1",
-    )
-    .bind(address)
-    .fetch_one(pool)
-    .await?;
-
-    let total_sent: Option<(BigDecimal,)> = sqlx::query_as(
-        "SELECT COALESCE(SUM(total_amount), 0) FROM wallet_graph_edges WHERE source_address = $1",
-    )
-    .bind(address)
-    .fetch_optional(pool)
-    .await?;
-
-    let total_received: Option<(BigDecimal,)> = sqlx::query_as(
-        "SELECT COALESCE(SUM(total_amount), 0) FROM wallet_graph_edges WHERE dest_address = $1",
-    )
-    .bind(address)
-    .fetch_optional(pool)
-    .await?;
-
     // Anomaly stats
     let (anomaly_count, max_risk): (i64, Option<f32>) = sqlx::query_as(
         "SELECT COUNT(*), MAX(risk_score) FROM anomalies WHERE address = $1",
@@ -157,32 +159,164 @@ pub async fn get_wallet_profile(
     .fetch_one(pool)
     .await?;
 
+    // Sanctions exposure, per chain
+    let mut sanctions_exposure: Vec<SanctionsExposureInfo> =
+        exposure::load_exposures(pool, &[address])
+            .await?
+            .into_values()
+            .filter(|e| chain_id.is_none_or(|cid| e.chain_id == cid))
+            .map(|e| SanctionsExposureInfo {
+                chain_id: e.chain_id,
+                inbound: e.inbound,
+                inbound_hops: e.inbound_hops,
+                outbound: e.outbound,
+                outbound_hops: e.outbound_hops,
+                computed_at: e.computed_at,
+            })
+            .collect();
+    sanctions_exposure.sort_by_key(|e| e.chain_id);
+
+    // Risk scores, per chain
+    let risk: Vec<WalletRiskInfo> = sqlx::query_as(&format!(
+        "SELECT {} FROM wallet_risk_scores
+         WHERE address = $1 AND ($2::BIGINT IS NULL OR chain_id = $2)
+         ORDER BY chain_id",
+        RISK_COLUMNS
+    ))
+    .bind(address)
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(risk_entry)
+    .collect();
+    let risk_score = risk.iter().map(|r| r.score).reduce(f64::max);
+
     Ok(WalletProfileResponse {
-        address: hex_addr,
-        first_seen: first_seen.map(|(cid, at, block, dir)| FirstSeenInfo {
-            chain_id: cid,
-            at,
-            block,
-            direction: dir,
+        cluster_id: cluster.map(|(id,)| id),
+        anomaly_count,
+        max_risk_score: max_risk.unwrap_or(0.0) as f64,
+        sanctions_exposure,
+        risk_score,
+        risk,
+        ..wallet_profile(hex_addr, first_seen, labels, &outgoing, &incoming)
+    })
+}
+
+/// Profile from the store's data alone, with no Postgres-only sections.
+fn wallet_profile(
+    address: String,
+    first_seen: Option<NewWalletEvent>,
+    labels: Vec<EntityLabel>,
+    outgoing: &[GraphEdge],
+    incoming: &[GraphEdge],
+) -> WalletProfileResponse {
+    let total = |edges: &[GraphEdge]| -> BigDecimal { edges.iter().map(|e| &e.total_amount).sum() };
+
+    WalletProfileResponse {
+        address,
+        first_seen: first_seen.map(|e| FirstSeenInfo {
+            chain_id: e.chain_id,
+            at: e.first_seen_at,
+            block: e.first_block,
+            direction: e.direction,
         }),
         labels: labels
             .into_iter()
-            .map(|(name, etype, source, conf)| LabelInfo {
-                entity_name: name,
-                entity_type: etype,
-                source,
-                confidence: conf,
+            .map(|l| LabelInfo {
+                entity_name: l.entity_name,
+                entity_type: l.entity_type,
+                source: l.label_source,
+                confidence: l.confidence,
             })
             .collect(),
-        cluster_id: cluster.map(|(id,)| id),
+        cluster_id: None,
         graph_summary: GraphSummary {
-            outgoing_count,
-            incoming_count,
-            total_sent: total_sent.map(|(v,)| v).unwrap_or_default(),
-            total_received: total_received.map(|(v,)| v).unwrap_or_default(),
+            outgoing_count: outgoing.len() as i64,
+            incoming_count: incoming.len() as i64,
+            total_sent: total(outgoing),
+            total_received: total(incoming),
         },
-        anomaly_count,
-        max_risk_score: max_risk.unwrap_or(0.0) as f64,
+        anomaly_count: 0,
+        max_risk_score: 0.0,
+        sanctions_exposure: Vec::new(),
+        risk_score: None,
+        risk: Vec::new(),
+    }
+}
+
+const RISK_COLUMNS: &str = "address, chain_id, score, anomaly_factor, exposure_factor, label_factor, \
+                            counterparty_factor, age_factor, breakdown, computed_at";
+
+type RiskRow = (
+    Vec<u8>,
+    i64,
+    f32,
+    f32,
+    f32,
+    f32,
+    f32,
+    f32,
+    serde_json::Value,
+    DateTime<Utc>,
+);
+
+fn risk_entry(row: RiskRow) -> WalletRiskInfo {
+    let (address, chain_id, score, anomalies, exposure, labels, counterparties, age, breakdown, computed_at) =
+        row;
+    WalletRiskInfo {
+        address: bytes_to_hex(&address),
+        chain_id,
+        score: score as f64,
+        factors: RiskFactorsInfo {
+            anomalies: anomalies as f64,
+            sanctions_exposure: exposure as f64,
+            entity_labels: labels as f64,
+            counterparties: counterparties as f64,
+            wallet_age: age as f64,
+        },
+        breakdown,
+        computed_at,
+    }
+}
+
+/// Scored wallets, riskiest first.
+pub async fn get_wallet_risk(
+    pool: &PgPool,
+    params: &WalletRiskParams,
+) -> eyre::Result<WalletRiskResponse> {
+    let limit = params.limit.unwrap_or(50).min(1000);
+    let offset = params.offset.unwrap_or(0);
+    let min_score: Option<f32> = params.min_score.map(|s| s as f32);
+
+    let filter = "WHERE ($1::BIGINT IS NULL OR chain_id = $1)
+                    AND ($2::REAL IS NULL OR score >= $2)";
+
+    let (total,): (i64,) =
+        sqlx::query_as(&format!("SELECT COUNT(*) FROM wallet_risk_scores {}", filter))
+            .bind(params.chain_id)
+            .bind(min_score)
+            .fetch_one(pool)
+            .await?;
+
+    let rows: Vec<RiskRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM wallet_risk_scores {}
+         ORDER BY score DESC, address
+         LIMIT $3 OFFSET $4",
+        RISK_COLUMNS, filter
+    ))
+    .bind(params.chain_id)
+    .bind(min_score)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(WalletRiskResponse {
+        wallets: rows.into_iter().map(risk_entry).collect(),
+        total,
+        limit,
+        offset,
     })
 }
 
@@ -190,6 +324,19 @@ pub async fn get_wallet_profile(
 // Wallet Journey
 // ============================================================
 
+/// Row shape of a wallet journey entry.
+type JourneyRow = (
+    DateTime<Utc>,   // block_timestamp
+    Vec<u8>,         // from_address
+    Vec<u8>,         // to_address
+    Option<String>,  // entity_name
+    Option<String>,  // entity_type
+    BigDecimal,      // amount
+    String,          // token_symbol
+    i64,             // chain_id
+    Vec<u8>,         // tx_hash
+);
+
 pub async fn get_wallet_journey(
     pool: &PgPool,
     address: &[u8],
@@ -219,17 +366,7 @@ pub async fn get_wallet_journey(
     };
 
     // Journey entries with entity labels on the counterparty side
-    let rows: Vec<(
-        DateTime<Utc>,   // block_timestamp
-        Vec<u8>,         // from_address
-        Vec<u8>,         // to_address
-        Option<String>,  // entity_name
-        Option<String>,  // entity_type
-        BigDecimal,      // amount
-        String,          // token_symbol
-        i64,             // chain_id
-        Vec<u8>,         // tx_hash
-    )> = if let Some(cid) = chain_id {
+    let rows: Vec<JourneyRow> = if let Some(cid) = chain_id {
         sqlx::query_as(
             "SELECT t.block_timestamp, t.from_address, t.to_address,
                     el.entity_name, el.entity_type,
@@ -310,6 +447,16 @@ pub async fn get_wallet_journey(
 // Wallet Fingerprint
 // ============================================================
 
+/// Transfer count, average amount, first and last activity, active days and chains used.
+type FingerprintStatsRow = (
+    i64,
+    Option<BigDecimal>,
+    Option<DateTime<Utc>>,
+    Option<DateTime<Utc>>,
+    i64,
+    i64,
+);
+
 pub async fn get_wallet_fingerprint(
     pool: &PgPool,
     address: &[u8],
@@ -350,18 +497,17 @@ pub async fn get_wallet_fingerprint(
     .await?;
 
     // Transfer stats
-    let stats: Option<(i64, Option<BigDecimal>, Option<DateTime<Utc>>, Option<DateTime<Utc>>, i64, i64)> =
-        sqlx::query_as(
-            "SELECT COUNT(*), AVG(amount),
-                    MIN(block_timestamp), MAX(block_timestamp),
-                    COUNT(DISTINCT DATE(block_timestamp)),
-                    COUNT(DISTINCT chain_id)
-             FROM transfers
-             WHERE from_address = $1 OR to_address = $1",
-        )
-        .bind(address)
-        .fetch_optional(pool)
-        .await?;
+    let stats: Option<FingerprintStatsRow> = sqlx::query_as(
+        "SELECT COUNT(*), AVG(amount),
+                MIN(block_timestamp), MAX(block_timestamp),
+                COUNT(DISTINCT DATE(block_timestamp)),
+                COUNT(DISTINCT chain_id)
+         FROM transfers
+         WHERE from_address = $1 OR to_address = $1",
+    )
+    .bind(address)
+    .fetch_optional(pool)
+    .await?;
 
     let (total_transfers, avg_amount, first_act, last_act, active_days, chains_used) =
         stats.unwrap_or((0, None, None, None, 0, 0));
@@ -534,6 +680,20 @@ pub async fn get_similar_wallets(
 // Transfers
 // ============================================================
 
+/// Row shape of the queries building a `TransferEntry`.
+type TransferEntryRow = (
+    i64,
+    i64,
+    i64,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    BigDecimal,
+    Option<BigDecimal>,
+    String,
+    DateTime<Utc>,
+);
+
 pub async fn get_transfers(
     pool: &PgPool,
     params: &TransferParams,
@@ -554,6 +714,7 @@ pub async fn get_transfers(
     let min_amount_bd: Option<BigDecimal> = params
         .min_amount
         .map(|a| BigDecimal::try_from(a).unwrap_or_default());
+    let min_wallet_risk: Option<f32> = params.min_wallet_risk.map(|r| r as f32);
 
     // Use a single parameterized query with optional conditions via COALESCE/IS NULL trick
     let (total,): (i64,) = sqlx::query_as(
@@ -562,9 +723,14 @@ pub async fn get_transfers(
            AND ($2::BYTEA IS NULL OR t.from_address = $2)
            AND ($3::BYTEA IS NULL OR t.to_address = $3)
            AND ($4::TEXT IS NULL OR t.token_symbol = $4)
-           AND ($5::NUMERIC IS NULL OR t.amount >= $5)
+           AND ($5::NUMERIC IS NULL
+                OR t.amount_usd >= $5)
            AND ($6::TIMESTAMPTZ IS NULL OR t.block_timestamp >= $6)
-           AND ($7::TIMESTAMPTZ IS NULL OR t.block_timestamp <= $7)",
+           AND ($7::TIMESTAMPTZ IS NULL OR t.block_timestamp <= $7)
+           AND ($8::REAL IS NULL OR EXISTS (
+                 SELECT 1 FROM wallet_risk_scores r
+                 WHERE r.chain_id = t.chain_id AND r.address IN (t.from_address, t.to_address)
+                   AND r.score >= $8))",
     )
     .bind(params.chain_id)
     .bind(&from_bytes)
@@ -573,23 +739,30 @@ pub async fn get_transfers(
     .bind(&min_amount_bd)
     .bind(since)
     .bind(until)
+    .bind(min_wallet_risk)
     .fetch_one(pool)
     .await?;
 
-    let rows: Vec<(i64, i64, i64, Vec<u8>, Vec<u8>, Vec<u8>, BigDecimal, String, DateTime<Utc>)> =
+    let rows: Vec<TransferEntryRow> =
         sqlx::query_as(
             "SELECT t.id, t.chain_id, t.block_number, t.tx_hash,
-                    t.from_address, t.to_address, t.amount, t.token_symbol, t.block_timestamp
+                    t.from_address, t.to_address, t.amount, t.amount_usd, t.token_symbol,
+                    t.block_timestamp
              FROM transfers t
              WHERE ($1::BIGINT IS NULL OR t.chain_id = $1)
                AND ($2::BYTEA IS NULL OR t.from_address = $2)
                AND ($3::BYTEA IS NULL OR t.to_address = $3)
                AND ($4::TEXT IS NULL OR t.token_symbol = $4)
-               AND ($5::NUMERIC IS NULL OR t.amount >= $5)
+               AND ($5::NUMERIC IS NULL
+                OR t.amount_usd >= $5)
                AND ($6::TIMESTAMPTZ IS NULL OR t.block_timestamp >= $6)
                AND ($7::TIMESTAMPTZ IS NULL OR t.block_timestamp <= $7)
+               AND ($8::REAL IS NULL OR EXISTS (
+                     SELECT 1 FROM wallet_risk_scores r
+                     WHERE r.chain_id = t.chain_id AND r.address IN (t.from_address, t.to_address)
+                       AND r.score >= $8))
              ORDER BY t.block_timestamp DESC
-             LIMIT $8 OFFSET $9",
+             LIMIT $9 OFFSET $10",
         )
         .bind(params.chain_id)
         .bind(&from_bytes)
@@ -598,6 +771,7 @@ pub async fn get_transfers(
         .bind(&min_amount_bd)
         .bind(since)
         .bind(until)
+        .bind(min_wallet_risk)
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
@@ -605,7 +779,7 @@ pub async fn get_transfers(
 
     let transfers = rows
         .into_iter()
-        .map(|(id, cid, block, tx, from, to, amount, token, ts)| TransferEntry {
+        .map(|(id, cid, block, tx, from, to, amount, usd, token, ts)| TransferEntry {
             id,
             chain_id: cid,
             block_number: block,
@@ -613,6 +787,7 @@ pub async fn get_transfers(
             from_address: bytes_to_hex(&from),
             to_address: bytes_to_hex(&to),
             amount,
+            amount_usd: usd,
             token,
             timestamp: ts,
             from_entity: None,
@@ -632,6 +807,56 @@ pub async fn get_transfers(
 // Anomalies
 // ============================================================
 
+/// Columns selected for an `AnomalyEntry`, in `AnomalyRow` order.
+const ANOMALY_COLUMNS: &str = "id, chain_id, anomaly_type, risk_score, flags, address, detected_at, \
+                               resolved, status, assignee, case_id, group_id";
+
+type AnomalyRow = (
+    i64,
+    i64,
+    String,
+    f32,
+    Vec<String>,
+    Option<Vec<u8>>,
+    DateTime<Utc>,
+    bool,
+    String,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+);
+
+fn anomaly_entry(row: AnomalyRow) -> AnomalyEntry {
+    let (
+        id,
+        chain_id,
+        anomaly_type,
+        risk,
+        flags,
+        addr,
+        detected_at,
+        resolved,
+        status,
+        assignee,
+        case_id,
+        group_id,
+    ) = row;
+    AnomalyEntry {
+        id,
+        chain_id,
+        anomaly_type,
+        risk_score: risk as f64,
+        flags,
+        address: addr.map(|a| bytes_to_hex(&a)).unwrap_or_default(),
+        detected_at,
+        resolved,
+        status,
+        assignee,
+        case_id,
+        group_id,
+    }
+}
+
 pub async fn get_anomalies(
     pool: &PgPool,
     params: &AnomalyParams,
@@ -641,71 +866,567 @@ pub async fn get_anomalies(
 
     let addr_bytes = params.address.as_ref().and_then(|a| hex_to_bytes(a).ok());
     let min_risk_f32: Option<f32> = params.min_risk.map(|r| r as f32);
+    let min_wallet_risk: Option<f32> = params.min_wallet_risk.map(|r| r as f32);
+
+    let filter = "WHERE ($1::BIGINT IS NULL OR chain_id = $1)
+                    AND ($2::TEXT IS NULL OR anomaly_type = $2)
+                    AND ($3::REAL IS NULL OR risk_score >= $3)
+                    AND ($4::BYTEA IS NULL OR address = $4)
+                    AND ($5::BOOL IS NULL OR resolved = $5)
+                    AND ($6::TEXT IS NULL OR status = $6)
+                    AND ($7::TEXT IS NULL OR assignee = $7)
+                    AND ($8::BIGINT IS NULL OR case_id = $8)
+                    AND ($9::BIGINT IS NULL OR group_id = $9)
+                    AND ($10::REAL IS NULL OR EXISTS (
+                          SELECT 1 FROM wallet_risk_scores r
+                          WHERE r.address = anomalies.address AND r.chain_id = anomalies.chain_id
+                            AND r.score >= $10))";
+
+    let (total,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM anomalies {}", filter))
+        .bind(params.chain_id)
+        .bind(&params.anomaly_type)
+        .bind(min_risk_f32)
+        .bind(&addr_bytes)
+        .bind(params.resolved)
+        .bind(&params.status)
+        .bind(&params.assignee)
+        .bind(params.case_id)
+        .bind(params.group_id)
+        .bind(min_wallet_risk)
+        .fetch_one(pool)
+        .await?;
 
-    let (total,): (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM anomalies
-         WHERE ($1::BIGINT IS NULL OR chain_id = $1)
-           AND ($2::TEXT IS NULL OR anomaly_type = $2)
-           AND ($3::REAL IS NULL OR risk_score >= $3)
-           AND ($4::BYTEA IS NULL OR address = $4)
-           AND ($5::BOOL IS NULL OR resolved = $5)",
-    )
+    let rows: Vec<AnomalyRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM anomalies {}
+         ORDER BY risk_score DESC, detected_at DESC
+         LIMIT $11 OFFSET $12",
+        ANOMALY_COLUMNS, filter
+    ))
     .bind(params.chain_id)
     .bind(&params.anomaly_type)
     .bind(min_risk_f32)
     .bind(&addr_bytes)
     .bind(params.resolved)
-    .fetch_one(pool)
+    .bind(&params.status)
+    .bind(&params.assignee)
+    .bind(params.case_id)
+    .bind(params.group_id)
+    .bind(min_wallet_risk)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
     .await?;
 
-    let rows: Vec<(i64, i64, String, f32, Vec<String>, Option<Vec<u8>>, DateTime<Utc>, bool)> =
+    Ok(AnomaliesResponse {
+        anomalies: rows.into_iter().map(anomaly_entry).collect(),
+        total,
+        limit,
+        offset,
+    })
+}
+
+/// One anomaly with its details, notes and audit history. None if it does not exist.
+pub async fn get_anomaly(pool: &PgPool, anomaly_id: i64) -> eyre::Result<Option<AnomalyDetailResponse>> {
+    let row: Option<AnomalyRow> =
+        sqlx::query_as(&format!("SELECT {} FROM anomalies WHERE id = $1", ANOMALY_COLUMNS))
+            .bind(anomaly_id)
+            .fetch_optional(pool)
+            .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let (transfer_id, tx_hash, details): (Option<i64>, Option<Vec<u8>>, Option<serde_json::Value>) =
         sqlx::query_as(
-            "SELECT id, chain_id, anomaly_type, risk_score, flags, address, detected_at, resolved
-             FROM anomalies
-             WHERE ($1::BIGINT IS NULL OR chain_id = $1)
-               AND ($2::TEXT IS NULL OR anomaly_type = $2)
-               AND ($3::REAL IS NULL OR risk_score >= $3)
-               AND ($4::BYTEA IS NULL OR address = $4)
-               AND ($5::BOOL IS NULL OR resolved = $5)
-             ORDER BY risk_score DESC, detected_at DESC
-             LIMIT $6 OFFSET $7",
+            "SELECT a.transfer_id, t.tx_hash, a.details
+             FROM anomalies a
+             LEFT JOIN transfers t
+               ON t.id = a.transfer_id AND t.chain_id = a.chain_id
+              AND t.block_timestamp = a.block_timestamp
+             WHERE a.id = $1",
         )
-        .bind(params.chain_id)
-        .bind(&params.anomaly_type)
-        .bind(min_risk_f32)
-        .bind(&addr_bytes)
-        .bind(params.resolved)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(pool)
+        .bind(anomaly_id)
+        .fetch_one(pool)
         .await?;
 
-    let anomalies = rows
+    let notes = get_notes(pool, "anomaly_id = $1", anomaly_id).await?;
+    let history = get_audit(pool, "anomaly_id = $1", anomaly_id).await?;
+
+    Ok(Some(AnomalyDetailResponse {
+        anomaly: anomaly_entry(row),
+        transfer_id,
+        tx_hash: tx_hash.map(|h| bytes_to_hex(&h)),
+        details,
+        notes,
+        history,
+    }))
+}
+
+/// Row shape of an `AnomalyGroupEntry`.
+type AnomalyGroupRow = (
+    i64,
+    i64,
+    Vec<u8>,
+    String,
+    DateTime<Utc>,
+    DateTime<Utc>,
+    i32,
+    f32,
+    Option<i64>,
+    Option<f32>,
+    Vec<String>,
+    serde_json::Value,
+);
+
+/// Anomaly groups, most recently active first.
+pub async fn get_anomaly_groups(
+    pool: &PgPool,
+    params: &AnomalyGroupParams,
+) -> eyre::Result<AnomalyGroupsResponse> {
+    let limit = params.limit.unwrap_or(50).min(1000);
+    let offset = params.offset.unwrap_or(0);
+
+    let addr_bytes = params.address.as_ref().and_then(|a| hex_to_bytes(a).ok());
+
+    let filter = "WHERE ($1::BIGINT IS NULL OR chain_id = $1)
+                    AND ($2::TEXT IS NULL OR anomaly_type = $2)
+                    AND ($3::BYTEA IS NULL OR address = $3)
+                    AND ($4::INT IS NULL OR anomaly_count >= $4)";
+
+    let (total,): (i64,) =
+        sqlx::query_as(&format!("SELECT COUNT(*) FROM anomaly_groups {}", filter))
+            .bind(params.chain_id)
+            .bind(&params.anomaly_type)
+            .bind(&addr_bytes)
+            .bind(params.min_count)
+            .fetch_one(pool)
+            .await?;
+
+    let rows: Vec<AnomalyGroupRow> = sqlx::query_as(&format!(
+        "SELECT id, chain_id, address, anomaly_type, first_seen, last_seen, anomaly_count,
+                max_risk_score, latest_anomaly_id, latest_risk_score, latest_flags, latest_details
+         FROM anomaly_groups {}
+         ORDER BY last_seen DESC, id DESC
+         LIMIT $5 OFFSET $6",
+        filter
+    ))
+    .bind(params.chain_id)
+    .bind(&params.anomaly_type)
+    .bind(&addr_bytes)
+    .bind(params.min_count)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let groups = rows
+        .into_iter()
+        .map(
+            |(
+                id,
+                chain_id,
+                address,
+                anomaly_type,
+                first_seen,
+                last_seen,
+                anomaly_count,
+                max_risk,
+                latest_anomaly_id,
+                latest_risk,
+                latest_flags,
+                latest_details,
+            )| AnomalyGroupEntry {
+                id,
+                chain_id,
+                address: bytes_to_hex(&address),
+                anomaly_type,
+                first_seen,
+                last_seen,
+                anomaly_count,
+                max_risk_score: max_risk as f64,
+                latest_anomaly_id,
+                latest_risk_score: latest_risk.map(|r| r as f64),
+                latest_flags,
+                latest_details,
+            },
+        )
+        .collect();
+
+    Ok(AnomalyGroupsResponse {
+        groups,
+        total,
+        limit,
+        offset,
+    })
+}
+
+// ============================================================
+// Cases
+// ============================================================
+
+/// Columns selected for a `CaseEntry`; expects `anomaly_cases c`.
+const CASE_COLUMNS: &str = "c.id, c.title, c.description, c.status, c.assignee, c.created_by, \
+                            c.created_at, c.updated_at,
+                            (SELECT COUNT(*) FROM anomalies a WHERE a.case_id = c.id),
+                            (SELECT MAX(risk_score)::FLOAT8 FROM anomalies a WHERE a.case_id = c.id)";
+
+type CaseRow = (
+    i64,
+    String,
+    String,
+    String,
+    Option<String>,
+    String,
+    DateTime<Utc>,
+    DateTime<Utc>,
+    i64,
+    Option<f64>,
+);
+
+fn case_entry(row: CaseRow) -> CaseEntry {
+    let (
+        id,
+        title,
+        description,
+        status,
+        assignee,
+        created_by,
+        created_at,
+        updated_at,
+        anomaly_count,
+        max_risk_score,
+    ) = row;
+    CaseEntry {
+        id,
+        title,
+        description,
+        status,
+        assignee,
+        created_by,
+        created_at,
+        updated_at,
+        anomaly_count,
+        max_risk_score,
+    }
+}
+
+pub async fn get_cases(pool: &PgPool, params: &CaseParams) -> eyre::Result<CasesResponse> {
+    let limit = params.limit.unwrap_or(50).min(1000);
+    let offset = params.offset.unwrap_or(0);
+
+    let filter = "WHERE ($1::TEXT IS NULL OR c.status = $1)
+                    AND ($2::TEXT IS NULL OR c.assignee = $2)";
+
+    let (total,): (i64,) =
+        sqlx::query_as(&format!("SELECT COUNT(*) FROM anomaly_cases c {}", filter))
+            .bind(&params.status)
+            .bind(&params.assignee)
+            .fetch_one(pool)
+            .await?;
+
+    let rows: Vec<CaseRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM anomaly_cases c {}
+         ORDER BY c.updated_at DESC, c.id DESC
+         LIMIT $3 OFFSET $4",
+        CASE_COLUMNS, filter
+    ))
+    .bind(&params.status)
+    .bind(&params.assignee)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(CasesResponse {
+        cases: rows.into_iter().map(case_entry).collect(),
+        total,
+        limit,
+        offset,
+    })
+}
+
+/// One case with its anomalies, notes and audit history. None if it does not exist.
+pub async fn get_case(pool: &PgPool, case_id: i64) -> eyre::Result<Option<CaseDetailResponse>> {
+    let row: Option<CaseRow> =
+        sqlx::query_as(&format!("SELECT {} FROM anomaly_cases c WHERE c.id = $1", CASE_COLUMNS))
+            .bind(case_id)
+            .fetch_optional(pool)
+            .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let anomalies: Vec<AnomalyRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM anomalies WHERE case_id = $1 ORDER BY risk_score DESC, detected_at DESC",
+        ANOMALY_COLUMNS
+    ))
+    .bind(case_id)
+    .fetch_all(pool)
+    .await?;
+
+    let notes = get_notes(pool, "case_id = $1", case_id).await?;
+    // Anomalies moved out to another case are recorded against the new case
+    let history = get_audit(
+        pool,
+        "case_id = $1 OR (action = 'case' AND old_value = $1::TEXT)",
+        case_id,
+    )
+    .await?;
+
+    Ok(Some(CaseDetailResponse {
+        case: case_entry(row),
+        anomalies: anomalies.into_iter().map(anomaly_entry).collect(),
+        notes,
+        history,
+    }))
+}
+
+async fn get_notes(pool: &PgPool, condition: &str, id: i64) -> eyre::Result<Vec<NoteEntry>> {
+    let rows: Vec<(i64, String, String, DateTime<Utc>)> = sqlx::query_as(&format!(
+        "SELECT id, author, body, created_at FROM analyst_notes WHERE {} ORDER BY id",
+        condition
+    ))
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
         .into_iter()
-        .map(|(id, cid, atype, risk, flags, addr, ts, resolved)| AnomalyEntry {
+        .map(|(id, author, body, created_at)| NoteEntry {
             id,
-            chain_id: cid,
-            anomaly_type: atype,
-            risk_score: risk as f64,
-            flags,
-            address: addr.map(|a| bytes_to_hex(&a)).unwrap_or_default(),
-            detected_at: ts,
-            resolved,
+            author,
+            body,
+            created_at,
         })
-        .collect();
+        .collect())
+}
 
-    Ok(AnomaliesResponse {
-        anomalies,
+/// Row shape of a review audit log entry.
+type AuditRow = (
+    i64,
+    Option<i64>,
+    Option<i64>,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    DateTime<Utc>,
+);
+
+async fn get_audit(pool: &PgPool, condition: &str, id: i64) -> eyre::Result<Vec<AuditEntry>> {
+    let rows: Vec<AuditRow> = sqlx::query_as(&format!(
+        "SELECT id, anomaly_id, case_id, actor, action, old_value, new_value, created_at
+         FROM review_audit_log WHERE {} ORDER BY id",
+        condition
+    ))
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, anomaly_id, case_id, actor, action, old_value, new_value, created_at)| {
+                AuditEntry {
+                    id,
+                    anomaly_id,
+                    case_id,
+                    actor,
+                    action,
+                    old_value,
+                    new_value,
+                    created_at,
+                }
+            },
+        )
+        .collect())
+}
+
+// ============================================================
+// Suppressions
+// ============================================================
+
+const SUPPRESSION_COLUMNS: &str = "r.id, r.name, r.source, r.address, r.entity_name, r.entity_type, \
+                                   r.chain_id, r.anomaly_types, r.reason, r.expires_at, \
+                                   r.created_by, r.created_at, r.disabled_at, r.disabled_by,
+                                   (SELECT COUNT(*) FROM suppressed_anomalies s WHERE s.rule_id = r.id),
+                                   (SELECT MAX(suppressed_at) FROM suppressed_anomalies s WHERE s.rule_id = r.id)";
+
+type SuppressionRow = (
+    i64,
+    Option<String>,
+    String,
+    Option<Vec<u8>>,
+    Option<String>,
+    Option<String>,
+    Option<i64>,
+    Vec<String>,
+    String,
+    Option<DateTime<Utc>>,
+    String,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+    Option<String>,
+    i64,
+    Option<DateTime<Utc>>,
+);
+
+fn suppression_entry(row: SuppressionRow) -> SuppressionEntry {
+    let (
+        id,
+        name,
+        source,
+        address,
+        entity,
+        entity_type,
+        chain_id,
+        anomaly_types,
+        reason,
+        expires_at,
+        created_by,
+        created_at,
+        disabled_at,
+        disabled_by,
+        hit_count,
+        last_hit_at,
+    ) = row;
+    SuppressionEntry {
+        id,
+        name,
+        source,
+        address: address.as_deref().map(bytes_to_hex),
+        entity,
+        entity_type,
+        chain_id,
+        anomaly_types,
+        reason,
+        expires_at,
+        active: disabled_at.is_none() && expires_at.is_none_or(|e| e > Utc::now()),
+        created_by,
+        created_at,
+        disabled_at,
+        disabled_by,
+        hit_count,
+        last_hit_at,
+    }
+}
+
+pub async fn get_suppressions(
+    pool: &PgPool,
+    params: &SuppressionParams,
+) -> eyre::Result<SuppressionsResponse> {
+    let limit = params.limit.unwrap_or(50).min(1000);
+    let offset = params.offset.unwrap_or(0);
+
+    let filter = "WHERE ($1 OR (r.disabled_at IS NULL AND (r.expires_at IS NULL OR r.expires_at > NOW())))
+                    AND ($2::BIGINT IS NULL OR r.chain_id IS NULL OR r.chain_id = $2)";
+
+    let (total,): (i64,) =
+        sqlx::query_as(&format!("SELECT COUNT(*) FROM suppression_rules r {}", filter))
+            .bind(params.include_inactive)
+            .bind(params.chain_id)
+            .fetch_one(pool)
+            .await?;
+
+    let rows: Vec<SuppressionRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM suppression_rules r {}
+         ORDER BY r.id DESC
+         LIMIT $3 OFFSET $4",
+        SUPPRESSION_COLUMNS, filter
+    ))
+    .bind(params.include_inactive)
+    .bind(params.chain_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(SuppressionsResponse {
+        suppressions: rows.into_iter().map(suppression_entry).collect(),
         total,
         limit,
         offset,
     })
 }
 
+/// Row shape of a `SuppressedAnomalyEntry`.
+type SuppressedHitRow = (
+    Option<i64>,
+    i64,
+    DateTime<Utc>,
+    String,
+    f32,
+    Option<Vec<u8>>,
+    Vec<String>,
+    serde_json::Value,
+    DateTime<Utc>,
+);
+
+/// One rule with its most recent hits. None if it does not exist.
+pub async fn get_suppression(
+    pool: &PgPool,
+    rule_id: i64,
+) -> eyre::Result<Option<SuppressionDetailResponse>> {
+    let row: Option<SuppressionRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM suppression_rules r WHERE r.id = $1",
+        SUPPRESSION_COLUMNS
+    ))
+    .bind(rule_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let hits: Vec<SuppressedHitRow> = sqlx::query_as(
+        "SELECT transfer_id, chain_id, block_timestamp, anomaly_type, risk_score, address,
+                flags, details, suppressed_at
+         FROM suppressed_anomalies WHERE rule_id = $1
+         ORDER BY suppressed_at DESC, id DESC
+         LIMIT 100",
+    )
+    .bind(rule_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(SuppressionDetailResponse {
+        suppression: suppression_entry(row),
+        recent_hits: hits
+            .into_iter()
+            .map(
+                |(
+                    transfer_id,
+                    chain_id,
+                    block_timestamp,
+                    anomaly_type,
+                    risk_score,
+                    address,
+                    flags,
+                    details,
+                    suppressed_at,
+                )| SuppressedAnomalyEntry {
+                    transfer_id,
+                    chain_id,
+                    block_timestamp,
+                    anomaly_type,
+                    risk_score,
+                    address: address.as_deref().map(bytes_to_hex),
+                    flags,
+                    details,
+                    suppressed_at,
+                },
+            )
+            .collect(),
+    }))
+}
+
 // ============================================================
 // Entities
 // ============================================================
 
+/// Row shape of an `EntityEntry`.
+type EntityLabelRow = (Vec<u8>, Option<i64>, String, String, String, f32);
+
 pub async fn get_entities(
     pool: &PgPool,
     params: &EntityParams,
@@ -725,7 +1446,7 @@ pub async fn get_entities(
     .fetch_one(pool)
     .await?;
 
-    let rows: Vec<(Vec<u8>, Option<i64>, String, String, String, f32)> = sqlx::query_as(
+    let rows: Vec<EntityLabelRow> = sqlx::query_as(
         "SELECT address, chain_id, entity_name, entity_type, label_source, confidence
          FROM entity_labels
          WHERE ($1::TEXT IS NULL OR entity_type = $1)
@@ -760,7 +1481,7 @@ pub async fn get_entity_by_address(
     pool: &PgPool,
     address: &[u8],
 ) -> eyre::Result<EntitiesResponse> {
-    let rows: Vec<(Vec<u8>, Option<i64>, String, String, String, f32)> = sqlx::query_as(
+    let rows: Vec<EntityLabelRow> = sqlx::query_as(
         "SELECT address, chain_id, entity_name, entity_type, label_source, confidence
          FROM entity_labels WHERE address = $1",
     )
@@ -788,6 +1509,25 @@ pub async fn get_entity_by_address(
 // DeFi Events
 // ============================================================
 
+/// Row shape of the queries building a `DefiEventEntry`.
+type DefiEventRow = (
+    i64,
+    i64,
+    i64,
+    Vec<u8>,
+    i32,
+    String,
+    String,
+    Vec<u8>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<BigDecimal>,
+    Option<BigDecimal>,
+    DateTime<Utc>,
+    Option<serde_json::Value>,
+);
+
 pub async fn get_defi_events(
     pool: &PgPool,
     params: &DefiParams,
@@ -823,23 +1563,7 @@ pub async fn get_defi_events(
     .fetch_one(pool)
     .await?;
 
-    let rows: Vec<(
-        i64,
-        i64,
-        i64,
-        Vec<u8>,
-        i32,
-        String,
-        String,
-        Vec<u8>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<BigDecimal>,
-        Option<BigDecimal>,
-        DateTime<Utc>,
-        Option<serde_json::Value>,
-    )> = sqlx::query_as(
+    let rows: Vec<DefiEventRow> = sqlx::query_as(
         "SELECT id, chain_id, block_number, tx_hash, log_index,
                 protocol, event_type, contract_address, account,
                 token_in, token_out, amount_in, amount_out,
@@ -933,23 +1657,7 @@ pub async fn get_wallet_defi(
     .fetch_one(pool)
     .await?;
 
-    let rows: Vec<(
-        i64,
-        i64,
-        i64,
-        Vec<u8>,
-        i32,
-        String,
-        String,
-        Vec<u8>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<BigDecimal>,
-        Option<BigDecimal>,
-        DateTime<Utc>,
-        Option<serde_json::Value>,
-    )> = sqlx::query_as(
+    let rows: Vec<DefiEventRow> = sqlx::query_as(
         "SELECT id, chain_id, block_number, tx_hash, log_index,
                 protocol, event_type, contract_address, account,
                 token_in, token_out, amount_in, amount_out,
@@ -1015,10 +1723,10 @@ pub async fn get_tx_context(
     let hex_hash = bytes_to_hex(tx_hash);
 
     // Fetch transfers for this tx
-    let transfer_rows: Vec<(i64, i64, i64, Vec<u8>, Vec<u8>, Vec<u8>, BigDecimal, String, DateTime<Utc>)> =
+    let transfer_rows: Vec<TransferEntryRow> =
         sqlx::query_as(
             "SELECT id, chain_id, block_number, tx_hash,
-                    from_address, to_address, amount, token_symbol, block_timestamp
+                    from_address, to_address, amount, amount_usd, token_symbol, block_timestamp
              FROM transfers WHERE tx_hash = $1
              ORDER BY log_index ASC",
         )
@@ -1028,7 +1736,7 @@ pub async fn get_tx_context(
 
     let transfers = transfer_rows
         .into_iter()
-        .map(|(id, cid, block, tx, from, to, amount, token, ts)| TransferEntry {
+        .map(|(id, cid, block, tx, from, to, amount, usd, token, ts)| TransferEntry {
             id,
             chain_id: cid,
             block_number: block,
@@ -1036,6 +1744,7 @@ pub async fn get_tx_context(
             from_address: bytes_to_hex(&from),
             to_address: bytes_to_hex(&to),
             amount,
+            amount_usd: usd,
             token,
             timestamp: ts,
             from_entity: None,
@@ -1044,23 +1753,7 @@ pub async fn get_tx_context(
         .collect();
 
     // Fetch DeFi events for this tx
-    let defi_rows: Vec<(
-        i64,
-        i64,
-        i64,
-        Vec<u8>,
-        i32,
-        String,
-        String,
-        Vec<u8>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<Vec<u8>>,
-        Option<BigDecimal>,
-        Option<BigDecimal>,
-        DateTime<Utc>,
-        Option<serde_json::Value>,
-    )> = sqlx::query_as(
+    let defi_rows: Vec<DefiEventRow> = sqlx::query_as(
         "SELECT id, chain_id, block_number, tx_hash, log_index,
                 protocol, event_type, contract_address, account,
                 token_in, token_out, amount_in, amount_out,
@@ -1104,6 +1797,110 @@ pub async fn get_tx_context(
     })
 }
 
+// ============================================================
+// Stablecoin Prices
+// ============================================================
+
+/// Row shape of a `StablecoinPriceEntry`.
+type StablecoinPriceRow = (
+    i64,
+    Vec<u8>,
+    String,
+    i64,
+    i32,
+    DateTime<Utc>,
+    DateTime<Utc>,
+    BigDecimal,
+    Option<BigDecimal>,
+    Option<f64>,
+    BigDecimal,
+    BigDecimal,
+    i32,
+);
+
+pub async fn get_stablecoin_prices(
+    pool: &PgPool,
+    params: &StablecoinPriceParams,
+) -> eyre::Result<StablecoinPricesResponse> {
+    let limit = params.limit.unwrap_or(50).min(1000);
+    let offset = params.offset.unwrap_or(0);
+
+    let since: Option<DateTime<Utc>> = params
+        .since
+        .as_ref()
+        .and_then(|s| s.parse::<DateTime<Utc>>().ok());
+    let until: Option<DateTime<Utc>> = params
+        .until
+        .as_ref()
+        .and_then(|u| u.parse::<DateTime<Utc>>().ok());
+
+    let (total,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM stablecoin_prices
+         WHERE ($1::BIGINT IS NULL OR chain_id = $1)
+           AND ($2::TEXT IS NULL OR UPPER(token_symbol) = UPPER($2))
+           AND ($3::TIMESTAMPTZ IS NULL OR ended_at >= $3)
+           AND ($4::TIMESTAMPTZ IS NULL OR started_at <= $4)",
+    )
+    .bind(params.chain_id)
+    .bind(&params.token)
+    .bind(since)
+    .bind(until)
+    .fetch_one(pool)
+    .await?;
+
+    let rows: Vec<StablecoinPriceRow> = sqlx::query_as(
+        "SELECT chain_id, token_address, token_symbol, window_start, window_blocks,
+                started_at, ended_at, price_usd, peg_usd,
+                (price_usd / NULLIF(peg_usd, 0) - 1)::FLOAT8,
+                token_volume, usd_volume, swap_count
+         FROM stablecoin_prices
+         WHERE ($1::BIGINT IS NULL OR chain_id = $1)
+           AND ($2::TEXT IS NULL OR UPPER(token_symbol) = UPPER($2))
+           AND ($3::TIMESTAMPTZ IS NULL OR ended_at >= $3)
+           AND ($4::TIMESTAMPTZ IS NULL OR started_at <= $4)
+         ORDER BY started_at DESC, chain_id, token_symbol
+         LIMIT $5 OFFSET $6",
+    )
+    .bind(params.chain_id)
+    .bind(&params.token)
+    .bind(since)
+    .bind(until)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    let prices = rows
+        .into_iter()
+        .map(
+            |(cid, token, symbol, start, blocks, started, ended, price, peg, dev, vol, usd, swaps)| {
+                StablecoinPriceEntry {
+                    chain_id: cid,
+                    token_address: bytes_to_hex(&token),
+                    token_symbol: symbol,
+                    window_start_block: start,
+                    window_blocks: blocks,
+                    started_at: started,
+                    ended_at: ended,
+                    price_usd: price,
+                    peg_usd: peg,
+                    deviation: dev,
+                    token_volume: vol,
+                    usd_volume: usd,
+                    swap_count: swaps,
+                }
+            },
+        )
+        .collect();
+
+    Ok(StablecoinPricesResponse {
+        prices,
+        total,
+        limit,
+        offset,
+    })
+}
+
 // ============================================================
 // Cluster
 // ============================================================
@@ -1131,3 +1928,209 @@ pub async fn get_cluster(
         size,
     })
 }
+
+// ============================================================
+// Time Series
+// ============================================================
+
+/// Series window from the params, defaulting to the 30 days up to now.
+fn series_window(params: &TimeSeriesParams) -> (DateTime<Utc>, DateTime<Utc>) {
+    let until = params
+        .until
+        .as_ref()
+        .and_then(|u| u.parse::<DateTime<Utc>>().ok())
+        .unwrap_or_else(Utc::now);
+    let since = params
+        .since
+        .as_ref()
+        .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        .unwrap_or(until - chrono::Duration::days(30));
+    (since, until)
+}
+
+pub async fn get_volume_series(
+    pool: &PgPool,
+    params: &TimeSeriesParams,
+) -> eyre::Result<TimeSeriesResponse<VolumePoint>> {
+    let (since, until) = series_window(params);
+
+    let rows: Vec<(DateTime<Utc>, i64, String, i64, BigDecimal)> = sqlx::query_as(
+        "SELECT date_trunc($1, bucket, 'UTC') AS b, chain_id, token_symbol,
+                SUM(transfer_count)::BIGINT, SUM(volume)
+         FROM rollup_token_volume
+         WHERE bucket >= date_trunc($1, $2, 'UTC') AND bucket <= $3
+           AND ($4::BIGINT IS NULL OR chain_id = $4)
+           AND ($5::TEXT IS NULL OR token_symbol = $5)
+         GROUP BY 1, 2, 3
+         ORDER BY 1, 2, 3",
+    )
+    .bind(params.interval.as_str())
+    .bind(since)
+    .bind(until)
+    .bind(params.chain_id)
+    .bind(&params.token)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(TimeSeriesResponse {
+        interval: params.interval.as_str(),
+        since,
+        until,
+        points: rows
+            .into_iter()
+            .map(|(bucket, chain_id, token, transfer_count, volume)| VolumePoint {
+                bucket,
+                chain_id,
+                token,
+                transfer_count,
+                volume,
+            })
+            .collect(),
+    })
+}
+
+/// Bucket, chain, entity label id, name, type, transfer count and volumes in and out.
+type EntityVolumeRow = (DateTime<Utc>, i64, i32, String, String, i64, BigDecimal, BigDecimal);
+
+pub async fn get_entity_volume_series(
+    pool: &PgPool,
+    params: &TimeSeriesParams,
+) -> eyre::Result<TimeSeriesResponse<EntityVolumePoint>> {
+    let (since, until) = series_window(params);
+
+    let rows: Vec<EntityVolumeRow> = sqlx::query_as(
+        "SELECT date_trunc($1, r.bucket, 'UTC') AS b, r.chain_id, r.entity_label_id,
+                el.entity_name, el.entity_type, SUM(r.transfer_count)::BIGINT,
+                SUM(r.volume_in), SUM(r.volume_out)
+         FROM rollup_entity_volume r
+         JOIN entity_labels el ON el.id = r.entity_label_id
+         WHERE r.bucket >= date_trunc($1, $2, 'UTC') AND r.bucket <= $3
+           AND ($4::BIGINT IS NULL OR r.chain_id = $4)
+           AND ($5::INTEGER IS NULL OR r.entity_label_id = $5)
+         GROUP BY 1, 2, 3, 4, 5
+         ORDER BY 1, 2, 3",
+    )
+    .bind(params.interval.as_str())
+    .bind(since)
+    .bind(until)
+    .bind(params.chain_id)
+    .bind(params.entity_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(TimeSeriesResponse {
+        interval: params.interval.as_str(),
+        since,
+        until,
+        points: rows
+            .into_iter()
+            .map(
+                |(bucket, chain_id, entity_id, entity_name, entity_type, transfer_count, volume_in, volume_out)| {
+                    EntityVolumePoint {
+                        bucket,
+                        chain_id,
+                        entity_id,
+                        entity_name,
+                        entity_type,
+                        transfer_count,
+                        volume_in,
+                        volume_out,
+                    }
+                },
+            )
+            .collect(),
+    })
+}
+
+/// Unique senders and receivers per day or week. Hourly uniques are not tracked.
+pub async fn get_unique_addresses_series(
+    pool: &PgPool,
+    params: &TimeSeriesParams,
+) -> eyre::Result<TimeSeriesResponse<UniqueAddressesPoint>> {
+    let (since, until) = series_window(params);
+
+    // Daily counters are maintained directly; weekly uniques need the daily membership
+    let sql = match params.interval {
+        Interval::Week => {
+            "SELECT date_trunc('week', day)::TIMESTAMP AT TIME ZONE 'UTC' AS b, chain_id,
+                    COUNT(DISTINCT address) FILTER (WHERE role = 'sender'),
+                    COUNT(DISTINCT address) FILTER (WHERE role = 'receiver')
+             FROM rollup_daily_addresses
+             WHERE day >= date_trunc('week', $1 AT TIME ZONE 'UTC')::DATE
+               AND day <= ($2 AT TIME ZONE 'UTC')::DATE
+               AND ($3::BIGINT IS NULL OR chain_id = $3)
+             GROUP BY 1, 2
+             ORDER BY 1, 2"
+        }
+        _ => {
+            "SELECT day::TIMESTAMP AT TIME ZONE 'UTC', chain_id, senders, receivers
+             FROM rollup_daily_unique_addresses
+             WHERE day >= ($1 AT TIME ZONE 'UTC')::DATE
+               AND day <= ($2 AT TIME ZONE 'UTC')::DATE
+               AND ($3::BIGINT IS NULL OR chain_id = $3)
+             ORDER BY 1, 2"
+        }
+    };
+
+    let rows: Vec<(DateTime<Utc>, i64, i64, i64)> = sqlx::query_as(sql)
+        .bind(since)
+        .bind(until)
+        .bind(params.chain_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(TimeSeriesResponse {
+        interval: params.interval.as_str(),
+        since,
+        until,
+        points: rows
+            .into_iter()
+            .map(|(bucket, chain_id, senders, receivers)| UniqueAddressesPoint {
+                bucket,
+                chain_id,
+                senders,
+                receivers,
+            })
+            .collect(),
+    })
+}
+
+pub async fn get_anomaly_series(
+    pool: &PgPool,
+    params: &TimeSeriesParams,
+) -> eyre::Result<TimeSeriesResponse<AnomalyCountPoint>> {
+    let (since, until) = series_window(params);
+
+    let rows: Vec<(DateTime<Utc>, i64, String, i64)> = sqlx::query_as(
+        "SELECT date_trunc($1, bucket, 'UTC') AS b, chain_id, anomaly_type,
+                SUM(anomaly_count)::BIGINT
+         FROM rollup_anomalies
+         WHERE bucket >= date_trunc($1, $2, 'UTC') AND bucket <= $3
+           AND ($4::BIGINT IS NULL OR chain_id = $4)
+           AND ($5::TEXT IS NULL OR anomaly_type = $5)
+         GROUP BY 1, 2, 3
+         ORDER BY 1, 2, 3",
+    )
+    .bind(params.interval.as_str())
+    .bind(since)
+    .bind(until)
+    .bind(params.chain_id)
+    .bind(&params.anomaly_type)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(TimeSeriesResponse {
+        interval: params.interval.as_str(),
+        since,
+        until,
+        points: rows
+            .into_iter()
+            .map(|(bucket, chain_id, anomaly_type, count)| AnomalyCountPoint {
+                bucket,
+                chain_id,
+                anomaly_type,
+                count,
+            })
+            .collect(),
+    })
+}
@@ -43,9 +43,12 @@ pub struct TransferParams {
     pub from: Option<String>,
     pub to: Option<String>,
     pub token: Option<String>,
+    /// USD value. Transfers not yet priced never match.
     pub min_amount: Option<f64>,
     pub since: Option<String>,
     pub until: Option<String>,
+    /// Either party's risk score on the transfer's chain is at least this.
+    pub min_wallet_risk: Option<f64>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
@@ -58,10 +61,53 @@ pub struct AnomalyParams {
     pub min_risk: Option<f64>,
     pub address: Option<String>,
     pub resolved: Option<bool>,
+    pub status: Option<String>,
+    pub assignee: Option<String>,
+    pub case_id: Option<i64>,
+    pub group_id: Option<i64>,
+    /// The flagged address's risk score on the anomaly's chain is at least this.
+    pub min_wallet_risk: Option<f64>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct WalletRiskParams {
+    pub chain_id: Option<i64>,
+    pub min_score: Option<f64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnomalyGroupParams {
+    pub chain_id: Option<i64>,
+    #[serde(rename = "type")]
+    pub anomaly_type: Option<String>,
+    pub address: Option<String>,
+    pub min_count: Option<i32>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CaseParams {
+    pub status: Option<String>,
+    pub assignee: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActorParams {
+    pub actor: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    pub from_block: i64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct EntityParams {
     #[serde(rename = "type")]
@@ -86,6 +132,16 @@ pub struct HealthResponse {
 pub struct ChainStatus {
     pub chain_id: i64,
     pub last_block: i64,
+    pub enrichment: EnrichmentStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnrichmentStatus {
+    pub chain_id: i64,
+    pub last_enriched_block: Option<i64>,
+    pub lag_blocks: i64,
+    pub pending_transfers: i64,
+    pub paused: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -112,6 +168,10 @@ pub struct WalletProfileResponse {
     pub graph_summary: GraphSummary,
     pub anomaly_count: i64,
     pub max_risk_score: f64,
+    pub sanctions_exposure: Vec<SanctionsExposureInfo>,
+    /// Highest score across chains, None until the wallet has been scored.
+    pub risk_score: Option<f64>,
+    pub risk: Vec<WalletRiskInfo>,
 }
 
 #[derive(Debug, Serialize)]
@@ -130,6 +190,45 @@ pub struct LabelInfo {
     pub confidence: f32,
 }
 
+/// Indirect exposure to sanctioned addresses on one chain, each between 0 and 1.
+#[derive(Debug, Serialize)]
+pub struct SanctionsExposureInfo {
+    pub chain_id: i64,
+    pub inbound: f64,
+    pub inbound_hops: Option<i16>,
+    pub outbound: f64,
+    pub outbound_hops: Option<i16>,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Stored risk score of a wallet on one chain, with each factor between 0 and 1.
+#[derive(Debug, Serialize)]
+pub struct WalletRiskInfo {
+    pub address: String,
+    pub chain_id: i64,
+    pub score: f64,
+    pub factors: RiskFactorsInfo,
+    pub breakdown: serde_json::Value,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RiskFactorsInfo {
+    pub anomalies: f64,
+    pub sanctions_exposure: f64,
+    pub entity_labels: f64,
+    pub counterparties: f64,
+    pub wallet_age: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletRiskResponse {
+    pub wallets: Vec<WalletRiskInfo>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct GraphSummary {
     pub outgoing_count: i64,
@@ -209,6 +308,7 @@ pub struct TransferEntry {
     pub from_address: String,
     pub to_address: String,
     pub amount: BigDecimal,
+    pub amount_usd: Option<BigDecimal>,
     pub token: String,
     pub timestamp: DateTime<Utc>,
     pub from_entity: Option<String>,
@@ -233,6 +333,132 @@ pub struct AnomalyEntry {
     pub address: String,
     pub detected_at: DateTime<Utc>,
     pub resolved: bool,
+    pub status: String,
+    pub assignee: Option<String>,
+    pub case_id: Option<i64>,
+    pub group_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyDetailResponse {
+    pub anomaly: AnomalyEntry,
+    pub transfer_id: Option<i64>,
+    pub tx_hash: Option<String>,
+    pub details: Option<serde_json::Value>,
+    pub notes: Vec<NoteEntry>,
+    pub history: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyGroupsResponse {
+    pub groups: Vec<AnomalyGroupEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Repeated anomalies of one type on one address, with the latest evidence.
+#[derive(Debug, Serialize)]
+pub struct AnomalyGroupEntry {
+    pub id: i64,
+    pub chain_id: i64,
+    pub address: String,
+    pub anomaly_type: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub anomaly_count: i32,
+    pub max_risk_score: f64,
+    pub latest_anomaly_id: Option<i64>,
+    pub latest_risk_score: Option<f64>,
+    pub latest_flags: Vec<String>,
+    pub latest_details: serde_json::Value,
+}
+
+// ============================================================
+// Cases
+// ============================================================
+
+/// Workflow change for an anomaly or case. Omitted fields are left as they are;
+/// an empty `assignee` unassigns. `title` applies to cases only.
+#[derive(Debug, Deserialize)]
+pub struct ReviewUpdateRequest {
+    pub actor: String,
+    pub status: Option<String>,
+    pub assignee: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteRequest {
+    pub actor: String,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCaseRequest {
+    pub actor: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub anomaly_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkAnomaliesRequest {
+    pub actor: String,
+    pub anomaly_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CasesResponse {
+    pub cases: Vec<CaseEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaseEntry {
+    pub id: i64,
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    pub assignee: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub anomaly_count: i64,
+    pub max_risk_score: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaseDetailResponse {
+    pub case: CaseEntry,
+    pub anomalies: Vec<AnomalyEntry>,
+    pub notes: Vec<NoteEntry>,
+    pub history: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteEntry {
+    pub id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub anomaly_id: Option<i64>,
+    pub case_id: Option<i64>,
+    pub actor: String,
+    pub action: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize)]
@@ -259,6 +485,93 @@ pub struct ClusterResponse {
     pub size: usize,
 }
 
+// ============================================================
+// Suppressions
+// ============================================================
+
+#[derive(Debug, Deserialize)]
+pub struct SuppressionParams {
+    /// Also list disabled and expired rules.
+    #[serde(default)]
+    pub include_inactive: bool,
+    pub chain_id: Option<i64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A new suppression rule. At least one scope field must be set; unset ones match anything.
+#[derive(Debug, Deserialize)]
+pub struct CreateSuppressionRequest {
+    pub actor: String,
+    pub address: Option<String>,
+    pub entity: Option<String>,
+    pub entity_type: Option<String>,
+    pub chain_id: Option<i64>,
+    #[serde(default)]
+    pub anomaly_types: Vec<String>,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Change to an API rule. Omitted fields are left as they are; `clear_expiry` makes
+/// the rule permanent.
+#[derive(Debug, Deserialize)]
+pub struct UpdateSuppressionRequest {
+    pub reason: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub clear_expiry: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuppressionsResponse {
+    pub suppressions: Vec<SuppressionEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuppressionEntry {
+    pub id: i64,
+    pub name: Option<String>,
+    pub source: String,
+    pub address: Option<String>,
+    pub entity: Option<String>,
+    pub entity_type: Option<String>,
+    pub chain_id: Option<i64>,
+    pub anomaly_types: Vec<String>,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub active: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub disabled_by: Option<String>,
+    pub hit_count: i64,
+    pub last_hit_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuppressionDetailResponse {
+    pub suppression: SuppressionEntry,
+    /// Most recent anomalies the rule held back.
+    pub recent_hits: Vec<SuppressedAnomalyEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuppressedAnomalyEntry {
+    pub transfer_id: Option<i64>,
+    pub chain_id: i64,
+    pub block_timestamp: DateTime<Utc>,
+    pub anomaly_type: String,
+    pub risk_score: f32,
+    pub address: Option<String>,
+    pub flags: Vec<String>,
+    pub details: serde_json::Value,
+    pub suppressed_at: DateTime<Utc>,
+}
+
 // ============================================================
 // DeFi Events
 // ============================================================
@@ -309,6 +622,133 @@ pub struct TxContextResponse {
     pub defi_events: Vec<DefiEventEntry>,
 }
 
+// ============================================================
+// Stablecoin Prices
+// ============================================================
+
+#[derive(Debug, Deserialize)]
+pub struct StablecoinPriceParams {
+    pub chain_id: Option<i64>,
+    /// Token symbol, e.g. `USDT`.
+    pub token: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StablecoinPricesResponse {
+    pub prices: Vec<StablecoinPriceEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Swap-implied price of a token over one block window.
+#[derive(Debug, Serialize)]
+pub struct StablecoinPriceEntry {
+    pub chain_id: i64,
+    pub token_address: String,
+    pub token_symbol: String,
+    pub window_start_block: i64,
+    pub window_blocks: i32,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub price_usd: BigDecimal,
+    pub peg_usd: Option<BigDecimal>,
+    /// `price_usd / peg_usd - 1`, absent without a peg.
+    pub deviation: Option<f64>,
+    pub token_volume: BigDecimal,
+    pub usd_volume: BigDecimal,
+    pub swap_count: i32,
+}
+
+// ============================================================
+// Time Series
+// ============================================================
+
+/// Bucket width for time-series endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl Interval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeSeriesParams {
+    pub chain_id: Option<i64>,
+    #[serde(default)]
+    pub interval: Interval,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Token symbol (volume series)
+    pub token: Option<String>,
+    /// Entity label id (entity series)
+    pub entity_id: Option<i32>,
+    /// Anomaly type (anomaly series)
+    #[serde(rename = "type")]
+    pub anomaly_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeSeriesResponse<T> {
+    pub interval: &'static str,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub points: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VolumePoint {
+    pub bucket: DateTime<Utc>,
+    pub chain_id: i64,
+    pub token: String,
+    pub transfer_count: i64,
+    pub volume: BigDecimal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityVolumePoint {
+    pub bucket: DateTime<Utc>,
+    pub chain_id: i64,
+    pub entity_id: i32,
+    pub entity_name: String,
+    pub entity_type: String,
+    pub transfer_count: i64,
+    pub volume_in: BigDecimal,
+    pub volume_out: BigDecimal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UniqueAddressesPoint {
+    pub bucket: DateTime<Utc>,
+    pub chain_id: i64,
+    pub senders: i64,
+    pub receivers: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyCountPoint {
+    pub bucket: DateTime<Utc>,
+    pub chain_id: i64,
+    pub anomaly_type: String,
+    pub count: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
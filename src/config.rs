@@ -1,5 +1,5 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -14,6 +14,20 @@ pub struct Config {
     #[serde(default)]
     pub anomaly_detection: AnomalyDetectionConfig,
     #[serde(default)]
+    pub enrichment: EnrichmentConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    #[serde(default)]
+    pub sanctions_exposure: SanctionsExposureConfig,
+    #[serde(default)]
+    pub risk_score: RiskScoreConfig,
+    #[serde(default)]
+    pub alerting: AlertingConfig,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+    #[serde(default)]
     pub api: ApiConfig,
 }
 
@@ -63,6 +77,16 @@ pub struct TokenConfig {
     pub symbol: String,
     pub address: String,
     pub decimals: u8,
+    /// Registry currency the token is pegged to.
+    #[serde(default = "default_token_currency")]
+    pub currency: String,
+    /// Not pegged to any currency, so only priced from DEX swaps.
+    #[serde(default)]
+    pub floating: bool,
+}
+
+fn default_token_currency() -> String {
+    "USD".to_string()
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -151,6 +175,7 @@ pub struct CustomWatchlistConfig {
 pub struct AnomalyDetectionConfig {
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// USD value per token symbol, or `default`.
     #[serde(default)]
     pub large_transfer_thresholds: HashMap<String, f64>,
     #[serde(default)]
@@ -161,6 +186,33 @@ pub struct AnomalyDetectionConfig {
     pub new_wallet: NewWalletAnomalyConfig,
     #[serde(default)]
     pub cross_chain: CrossChainConfig,
+    #[serde(default)]
+    pub structuring: StructuringConfig,
+    #[serde(default)]
+    pub peel_chain: PeelChainConfig,
+    #[serde(default)]
+    pub pass_through: PassThroughConfig,
+    #[serde(default)]
+    pub fan_in: FanPatternConfig,
+    #[serde(default)]
+    pub fan_out: FanPatternConfig,
+    #[serde(default)]
+    pub flash_loan: FlashLoanConfig,
+    #[serde(default)]
+    pub depeg: DepegConfig,
+    #[serde(default)]
+    pub issuance: IssuanceConfig,
+    #[serde(default)]
+    pub sanctions_exposure: SanctionsExposureRuleConfig,
+    #[serde(default)]
+    pub baseline: BaselineConfig,
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
+    /// Suppression rules for known benign activity, synced to the database at startup.
+    #[serde(default)]
+    pub suppressions: Vec<SuppressionConfig>,
+    /// TOML file of analyst-written rules, run alongside the built-in ones.
+    pub custom_rules_path: Option<String>,
 }
 
 impl Default for AnomalyDetectionConfig {
@@ -172,6 +224,19 @@ impl Default for AnomalyDetectionConfig {
             round_number: RoundNumberConfig::default(),
             new_wallet: NewWalletAnomalyConfig::default(),
             cross_chain: CrossChainConfig::default(),
+            structuring: StructuringConfig::default(),
+            peel_chain: PeelChainConfig::default(),
+            pass_through: PassThroughConfig::default(),
+            fan_in: FanPatternConfig::default(),
+            fan_out: FanPatternConfig::default(),
+            flash_loan: FlashLoanConfig::default(),
+            depeg: DepegConfig::default(),
+            issuance: IssuanceConfig::default(),
+            sanctions_exposure: SanctionsExposureRuleConfig::default(),
+            baseline: BaselineConfig::default(),
+            aggregation: AggregationConfig::default(),
+            suppressions: Vec::new(),
+            custom_rules_path: None,
         }
     }
 }
@@ -255,51 +320,1370 @@ fn default_cross_chain_window() -> u64 {
     1800
 }
 
-// ============================================================
-// API Config
-// ============================================================
+/// Repeated transfers just under the large-transfer threshold. A transfer is in the band
+/// when its amount is at least `threshold * (1 - band)` and below `threshold`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct StructuringConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_structuring_window")]
+    pub window_secs: u64,
+    #[serde(default = "default_structuring_band")]
+    pub band: f64,
+    /// In-band transfers from one sender (or into one receiver) within the window,
+    /// including the one being checked.
+    #[serde(default = "default_structuring_min_count")]
+    pub min_count: u32,
+}
+
+impl Default for StructuringConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 86400,
+            band: 0.1,
+            min_count: 3,
+        }
+    }
+}
+
+fn default_structuring_window() -> u64 {
+    86400
+}
+
+fn default_structuring_band() -> f64 {
+    0.1
+}
+
+fn default_structuring_min_count() -> u32 {
+    3
+}
 
+/// Runs of hops where each wallet forwards at least `min_forward_ratio` of what it
+/// received to the next one and keeps the rest.
 #[derive(Debug, Deserialize, Clone)]
-pub struct ApiConfig {
+pub struct PeelChainConfig {
     #[serde(default = "default_true")]
     pub enabled: bool,
-    #[serde(default = "default_api_port")]
-    pub port: u16,
-    #[serde(default = "default_api_host")]
-    pub host: String,
-    pub exchange_wallets_path: Option<String>,
+    /// Peeling wallets needed before the chain is reported.
+    #[serde(default = "default_peel_min_hops")]
+    pub min_hops: u32,
+    /// Longest chain followed, counting hops before and after detection.
+    #[serde(default = "default_peel_max_hops")]
+    pub max_hops: u32,
+    #[serde(default = "default_peel_min_forward_ratio")]
+    pub min_forward_ratio: f64,
+    /// Longest a wallet may hold the funds before forwarding them.
+    #[serde(default = "default_peel_max_hop_secs")]
+    pub max_hop_secs: u64,
+    /// Transfers worth less than this in USD are not checked.
+    #[serde(default = "default_peel_min_amount")]
+    pub min_amount: f64,
 }
 
-impl Default for ApiConfig {
+impl Default for PeelChainConfig {
     fn default() -> Self {
         Self {
             enabled: true,
-            port: 3000,
-            host: "0.0.0.0".to_string(),
-            exchange_wallets_path: None,
+            min_hops: 4,
+            max_hops: 20,
+            min_forward_ratio: 0.8,
+            max_hop_secs: 86400,
+            min_amount: 1000.0,
         }
     }
 }
 
-fn default_api_port() -> u16 {
-    3000
+fn default_peel_min_hops() -> u32 {
+    4
 }
 
-fn default_api_host() -> String {
-    "0.0.0.0".to_string()
+fn default_peel_max_hops() -> u32 {
+    20
 }
 
-impl Config {
-    pub fn load(path: &str) -> eyre::Result<Self> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| eyre::eyre!("Failed to read config file '{}': {}", path, e))?;
-        let config: Config = toml::from_str(&content)
-            .map_err(|e| eyre::eyre!("Failed to parse config file '{}': {}", path, e))?;
-        config.validate()?;
-        Ok(config)
+fn default_peel_min_forward_ratio() -> f64 {
+    0.8
+}
+
+fn default_peel_max_hop_secs() -> u64 {
+    86400
+}
+
+fn default_peel_min_amount() -> f64 {
+    1000.0
+}
+
+/// Wallets forwarding nearly all of an amount soon after receiving it. Successive
+/// pass-through hops are reported as layering once there are `layering_min_hops` of them.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PassThroughConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_pass_through_window")]
+    pub window_secs: u64,
+    /// Optional block limit on top of `window_secs`; both must hold.
+    pub window_blocks: Option<u64>,
+    #[serde(default = "default_pass_through_ratio")]
+    pub min_forward_ratio: f64,
+    /// Transfers worth less than this in USD are not checked.
+    #[serde(default = "default_pass_through_min_amount")]
+    pub min_amount: f64,
+    /// A wallet first seen at most this long before receiving the funds counts as fresh.
+    #[serde(default = "default_fresh_wallet_secs")]
+    pub fresh_wallet_secs: u64,
+    #[serde(default = "default_layering_min_hops")]
+    pub layering_min_hops: u32,
+    /// Per-chain window overrides, keyed by chain name.
+    #[serde(default)]
+    pub chains: HashMap<String, PassThroughWindowConfig>,
+}
+
+impl Default for PassThroughConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 600,
+            window_blocks: None,
+            min_forward_ratio: 0.95,
+            min_amount: 1000.0,
+            fresh_wallet_secs: 86400,
+            layering_min_hops: 3,
+            chains: HashMap::new(),
+        }
     }
+}
 
-    fn validate(&self) -> eyre::Result<()> {
+#[derive(Debug, Deserialize, Clone)]
+pub struct PassThroughWindowConfig {
+    pub window_secs: Option<u64>,
+    pub window_blocks: Option<u64>,
+}
+
+fn default_pass_through_window() -> u64 {
+    600
+}
+
+fn default_pass_through_ratio() -> f64 {
+    0.95
+}
+
+fn default_pass_through_min_amount() -> f64 {
+    1000.0
+}
+
+fn default_fresh_wallet_secs() -> u64 {
+    86400
+}
+
+fn default_layering_min_hops() -> u32 {
+    3
+}
+
+/// Many distinct senders paying one address (fan-in) or one address paying many
+/// receivers (fan-out) within the window.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FanPatternConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_fan_window")]
+    pub window_secs: u64,
+    #[serde(default = "default_fan_min_counterparties")]
+    pub min_counterparties: u32,
+    /// Threshold for addresses with an entity label, e.g. exchanges that fan in by design.
+    #[serde(default = "default_fan_labelled_min_counterparties")]
+    pub labelled_min_counterparties: u32,
+    /// Combined USD value over the window.
+    #[serde(default = "default_fan_min_total")]
+    pub min_total_amount: f64,
+    /// Largest share of the combined amount one counterparty may account for.
+    #[serde(default = "default_fan_max_top_share")]
+    pub max_top_share: f64,
+}
+
+impl Default for FanPatternConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 3600,
+            min_counterparties: 20,
+            labelled_min_counterparties: 500,
+            min_total_amount: 10000.0,
+            max_top_share: 0.5,
+        }
+    }
+}
+
+fn default_fan_window() -> u64 {
+    3600
+}
+
+fn default_fan_min_counterparties() -> u32 {
+    20
+}
+
+fn default_fan_labelled_min_counterparties() -> u32 {
+    500
+}
+
+fn default_fan_min_total() -> f64 {
+    10000.0
+}
+
+fn default_fan_max_top_share() -> f64 {
+    0.5
+}
+
+/// Transactions that take a flash loan, swap through at least `min_pools` pools and repay
+/// it, leaving at least `min_profit_usd` of watched tokens with a wallet first seen within
+/// `fresh_wallet_secs`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FlashLoanConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_flash_loan_min_pools")]
+    pub min_pools: u32,
+    #[serde(default = "default_flash_loan_min_profit")]
+    pub min_profit_usd: f64,
+    #[serde(default = "default_flash_loan_fresh_wallet_secs")]
+    pub fresh_wallet_secs: u64,
+}
+
+impl Default for FlashLoanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_pools: 2,
+            min_profit_usd: 50000.0,
+            fresh_wallet_secs: 86400,
+        }
+    }
+}
+
+fn default_flash_loan_min_pools() -> u32 {
+    2
+}
+
+fn default_flash_loan_min_profit() -> f64 {
+    50000.0
+}
+
+fn default_flash_loan_fresh_wallet_secs() -> u64 {
+    86400
+}
+
+/// Pegged tokens whose swap-implied price in a block window strays more than `band` (a
+/// fraction of the peg) from their FX peg. Windows with less than `min_usd_volume` or
+/// fewer than `min_swaps` swaps are too thin to judge.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DepegConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_depeg_band")]
+    pub band: f64,
+    #[serde(default = "default_depeg_min_usd_volume")]
+    pub min_usd_volume: f64,
+    #[serde(default = "default_depeg_min_swaps")]
+    pub min_swaps: u32,
+}
+
+impl Default for DepegConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            band: 0.01,
+            min_usd_volume: 10000.0,
+            min_swaps: 3,
+        }
+    }
+}
+
+fn default_depeg_band() -> f64 {
+    0.01
+}
+
+fn default_depeg_min_usd_volume() -> f64 {
+    10000.0
+}
+
+fn default_depeg_min_swaps() -> u32 {
+    3
+}
+
+/// Mints (transfers from the zero address) and burns (transfers to it) of watched tokens.
+/// Flags mints of at least `min_mint_usd`; mints and issuer treasury payouts of at least
+/// `min_distribution_usd` to a wallet without a `known_recipient_types` label; and burns of
+/// at least `min_burn_usd` by a wallet that received `min_risky_inflow_usd` from risky
+/// sources within `burn_lookback_secs`. A source is risky when labelled with one of
+/// `risky_entity_types` or scored at least `min_source_risk_score`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct IssuanceConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_min_mint_usd")]
+    pub min_mint_usd: f64,
+    #[serde(default = "default_min_distribution_usd")]
+    pub min_distribution_usd: f64,
+    /// Entity types of issuer treasuries, whose payouts count like mints.
+    #[serde(default = "default_treasury_entity_types")]
+    pub treasury_entity_types: Vec<String>,
+    #[serde(default = "default_known_recipient_types")]
+    pub known_recipient_types: Vec<String>,
+    #[serde(default = "default_min_burn_usd")]
+    pub min_burn_usd: f64,
+    #[serde(default = "default_burn_lookback_secs")]
+    pub burn_lookback_secs: u64,
+    #[serde(default = "default_min_risky_inflow_usd")]
+    pub min_risky_inflow_usd: f64,
+    #[serde(default = "default_risky_entity_types")]
+    pub risky_entity_types: Vec<String>,
+    #[serde(default = "default_min_source_risk_score")]
+    pub min_source_risk_score: f32,
+}
+
+impl Default for IssuanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_mint_usd: 10000000.0,
+            min_distribution_usd: 1000000.0,
+            treasury_entity_types: default_treasury_entity_types(),
+            known_recipient_types: default_known_recipient_types(),
+            min_burn_usd: 100000.0,
+            burn_lookback_secs: 86400,
+            min_risky_inflow_usd: 100000.0,
+            risky_entity_types: default_risky_entity_types(),
+            min_source_risk_score: 75.0,
+        }
+    }
+}
+
+fn default_min_mint_usd() -> f64 {
+    10000000.0
+}
+
+fn default_min_distribution_usd() -> f64 {
+    1000000.0
+}
+
+fn default_treasury_entity_types() -> Vec<String> {
+    vec!["issuer".to_string()]
+}
+
+fn default_known_recipient_types() -> Vec<String> {
+    vec!["exchange".to_string(), "issuer".to_string()]
+}
+
+fn default_min_burn_usd() -> f64 {
+    100000.0
+}
+
+fn default_burn_lookback_secs() -> u64 {
+    86400
+}
+
+fn default_min_risky_inflow_usd() -> f64 {
+    100000.0
+}
+
+fn default_risky_entity_types() -> Vec<String> {
+    vec!["sanctioned".to_string(), "mixer".to_string()]
+}
+
+fn default_min_source_risk_score() -> f32 {
+    75.0
+}
+
+/// Transfers involving a wallet with indirect sanctions exposure at or above
+/// `min_exposure` (0..1), as computed by the exposure stage.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SanctionsExposureRuleConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_min_exposure")]
+    pub min_exposure: f64,
+}
+
+impl Default for SanctionsExposureRuleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_exposure: 0.1,
+        }
+    }
+}
+
+fn default_min_exposure() -> f64 {
+    0.1
+}
+
+/// Rolling per-wallet baselines and the rule scoring transfers against them.
+/// A transfer is flagged when its amount is `amount_z_threshold` standard deviations
+/// above the wallet's typical (log) amount, or its day is `daily_count_multiple` times
+/// busier than usual.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BaselineConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Weight of each new transfer in the amount statistics.
+    #[serde(default = "default_baseline_alpha")]
+    pub alpha: f64,
+    /// Weight of each completed day in the daily count mean.
+    #[serde(default = "default_baseline_daily_alpha")]
+    pub daily_alpha: f64,
+    /// Transfers seen before a wallet is scored.
+    #[serde(default = "default_baseline_min_history")]
+    pub min_history: u32,
+    #[serde(default = "default_baseline_z_threshold")]
+    pub amount_z_threshold: f64,
+    #[serde(default = "default_baseline_daily_multiple")]
+    pub daily_count_multiple: f64,
+    #[serde(default = "default_baseline_max_counterparties")]
+    pub max_counterparties: usize,
+}
+
+impl Default for BaselineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            alpha: 0.05,
+            daily_alpha: 0.1,
+            min_history: 20,
+            amount_z_threshold: 3.0,
+            daily_count_multiple: 5.0,
+            max_counterparties: 32,
+        }
+    }
+}
+
+fn default_baseline_alpha() -> f64 {
+    0.05
+}
+
+fn default_baseline_daily_alpha() -> f64 {
+    0.1
+}
+
+fn default_baseline_min_history() -> u32 {
+    20
+}
+
+fn default_baseline_z_threshold() -> f64 {
+    3.0
+}
+
+fn default_baseline_daily_multiple() -> f64 {
+    5.0
+}
+
+fn default_baseline_max_counterparties() -> usize {
+    32
+}
+
+/// Groups repeated anomalies of one type on one address. An anomaly joins the address's
+/// latest group of that type when it falls within the window of the group's last anomaly,
+/// otherwise it starts a new group.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AggregationConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_aggregation_window_secs")]
+    pub window_secs: u64,
+    /// Per anomaly type overrides of `window_secs`.
+    #[serde(default)]
+    pub windows: HashMap<String, u64>,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 3600,
+            windows: HashMap::new(),
+        }
+    }
+}
+
+impl AggregationConfig {
+    pub fn window_for(&self, anomaly_type: &str) -> u64 {
+        self.windows.get(anomaly_type).copied().unwrap_or(self.window_secs)
+    }
+}
+
+fn default_aggregation_window_secs() -> u64 {
+    3600
+}
+
+/// Holds back anomalies matching known benign activity. At least one of the scope fields
+/// must be set; unset ones match anything.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SuppressionConfig {
+    /// Identifies the rule across restarts so its hit history is kept.
+    pub name: String,
+    pub address: Option<String>,
+    /// Entity name, matched case-insensitively.
+    pub entity: Option<String>,
+    pub entity_type: Option<String>,
+    /// Chain name.
+    pub chain: Option<String>,
+    /// Empty matches every anomaly type.
+    #[serde(default)]
+    pub anomaly_types: Vec<String>,
+    pub reason: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// ============================================================
+// Custom Anomaly Rules
+// ============================================================
+
+/// Contents of the file at `anomaly_detection.custom_rules_path`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CustomRulesFile {
+    #[serde(default)]
+    pub rules: Vec<CustomRuleConfig>,
+}
+
+/// An analyst-written anomaly rule. `when` and `score` are rule-language expressions
+/// (see `anomaly::dsl`) and `flags` are templates with `{identifier}` placeholders.
+/// Any change to the serialized definition requires a new `version`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CustomRuleConfig {
+    /// Stored as the anomaly type.
+    pub name: String,
+    pub version: u32,
+    #[serde(default = "default_true", skip_serializing)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing)]
+    pub description: String,
+    pub when: String,
+    pub score: String,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Side whose address the anomaly is recorded against.
+    #[serde(default)]
+    pub address: Option<RuleSide>,
+    #[serde(default)]
+    pub aggregates: Vec<RuleAggregateConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSide {
+    From,
+    To,
+}
+
+impl RuleSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::From => "from",
+            Self::To => "to",
+        }
+    }
+}
+
+/// A windowed aggregate over one side's transfers, ending at the transfer being checked
+/// (inclusive). Exposed to the rule's expressions under `name`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RuleAggregateConfig {
+    pub name: String,
+    pub kind: AggregateKind,
+    pub side: RuleSide,
+    #[serde(default)]
+    pub direction: FlowDirection,
+    pub window_secs: u64,
+    /// Only count transfers of the same token symbol.
+    #[serde(default)]
+    pub same_token: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateKind {
+    /// Number of transfers.
+    Count,
+    /// Total amount in token units.
+    Sum,
+    /// Total USD value.
+    SumUsd,
+    /// Distinct counterparties.
+    Counterparties,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowDirection {
+    Out,
+    In,
+    #[default]
+    Any,
+}
+
+impl FlowDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Out => "out",
+            Self::In => "in",
+            Self::Any => "any",
+        }
+    }
+}
+
+// ============================================================
+// Enrichment Worker Config
+// ============================================================
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EnrichmentConfig {
+    #[serde(default = "default_enrichment_batch_size")]
+    pub batch_size: i64,
+    #[serde(default = "default_enrichment_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+impl Default for EnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+fn default_enrichment_batch_size() -> i64 {
+    500
+}
+
+fn default_enrichment_poll_interval_ms() -> u64 {
+    1000
+}
+
+// ============================================================
+// Storage Config
+// ============================================================
+
+/// What retention does with partitions older than the window.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    /// Detach from the parent tables, leaving the tables in place.
+    Detach,
+    /// Detach and move into the `archive` schema.
+    #[default]
+    Archive,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageConfig {
+    /// Monthly partitions to create ahead of the current month.
+    #[serde(default = "default_partition_months_ahead")]
+    pub partition_months_ahead: u32,
+    /// Months of data kept attached, counting back from the current month. 0 keeps everything.
+    #[serde(default)]
+    pub retention_months: u32,
+    #[serde(default)]
+    pub retention_action: RetentionAction,
+    #[serde(default = "default_maintenance_interval_secs")]
+    pub maintenance_interval_secs: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            partition_months_ahead: 2,
+            retention_months: 0,
+            retention_action: RetentionAction::Archive,
+            maintenance_interval_secs: 3600,
+        }
+    }
+}
+
+fn default_partition_months_ahead() -> u32 {
+    2
+}
+
+fn default_maintenance_interval_secs() -> u64 {
+    3600
+}
+
+// ============================================================
+// Parquet Export Config
+// ============================================================
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExportConfig {
+    /// Run the export on a schedule alongside the indexer.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_export_output_dir")]
+    pub output_dir: String,
+    #[serde(default = "default_export_interval_secs")]
+    pub interval_secs: u64,
+    /// Rows fetched per query; each batch is written as one file per date.
+    #[serde(default = "default_export_batch_size")]
+    pub batch_size: i64,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            output_dir: default_export_output_dir(),
+            interval_secs: 3600,
+            batch_size: 100_000,
+        }
+    }
+}
+
+fn default_export_output_dir() -> String {
+    "exports".to_string()
+}
+
+fn default_export_interval_secs() -> u64 {
+    3600
+}
+
+fn default_export_batch_size() -> i64 {
+    100_000
+}
+
+// ============================================================
+// Sanctions Exposure Config
+// ============================================================
+
+/// Indirect exposure to sanctioned addresses, propagated over `wallet_graph_edges`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SanctionsExposureConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_exposure_max_hops")]
+    pub max_hops: u32,
+    /// Multiplier applied per hop beyond the first.
+    #[serde(default = "default_exposure_decay")]
+    pub decay: f64,
+    /// Propagated shares below this are dropped, bounding the walk through busy hubs.
+    #[serde(default = "default_exposure_min_weight")]
+    pub min_weight: f64,
+    /// Minimum time between recomputations of one chain.
+    #[serde(default = "default_exposure_min_interval")]
+    pub min_interval_secs: u64,
+}
+
+impl Default for SanctionsExposureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_hops: 3,
+            decay: 0.5,
+            min_weight: 0.0001,
+            min_interval_secs: 60,
+        }
+    }
+}
+
+fn default_exposure_max_hops() -> u32 {
+    3
+}
+
+fn default_exposure_decay() -> f64 {
+    0.5
+}
+
+fn default_exposure_min_weight() -> f64 {
+    0.0001
+}
+
+fn default_exposure_min_interval() -> u64 {
+    60
+}
+
+// ============================================================
+// Risk Score Config
+// ============================================================
+
+/// Per-address risk score. Each factor is scaled to 0..1 and weighted; the score is
+/// `100 * (1 - product(1 - weight * factor))`, so any single factor at full weight
+/// can reach 100 and independent factors compound.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RiskScoreConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Anomalies lose half their weight every half-life.
+    #[serde(default = "default_risk_anomaly_half_life_secs")]
+    pub anomaly_half_life_secs: u64,
+    /// Decayed anomaly risk at which the anomaly factor reaches 1 - 1/e.
+    #[serde(default = "default_risk_anomaly_scale")]
+    pub anomaly_scale: f64,
+    /// Wallets younger than this get a wallet-age factor falling linearly from 1 to 0.
+    #[serde(default = "default_risk_new_wallet_days")]
+    pub new_wallet_days: f64,
+    /// Scores older than this are recomputed so decay and exposure changes show up.
+    #[serde(default = "default_risk_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+    /// Stale scores recomputed per enrichment batch.
+    #[serde(default = "default_risk_refresh_batch")]
+    pub refresh_batch: i64,
+    #[serde(default)]
+    pub weights: RiskWeights,
+    /// Risk of each entity type between 0 and 1, scaled by label confidence. Applies to
+    /// the address's own labels and to its counterparties'. Unlisted types carry no risk.
+    #[serde(default = "default_risk_entity_types")]
+    pub entity_types: HashMap<String, f64>,
+}
+
+impl Default for RiskScoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            anomaly_half_life_secs: 604_800,
+            anomaly_scale: 100.0,
+            new_wallet_days: 30.0,
+            refresh_interval_secs: 21_600,
+            refresh_batch: 500,
+            weights: RiskWeights::default(),
+            entity_types: default_risk_entity_types(),
+        }
+    }
+}
+
+/// Largest share of the score each factor can supply on its own, between 0 and 1.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RiskWeights {
+    #[serde(default = "default_risk_weight_anomalies")]
+    pub anomalies: f64,
+    #[serde(default = "default_risk_weight_exposure")]
+    pub sanctions_exposure: f64,
+    #[serde(default = "default_risk_weight_labels")]
+    pub entity_labels: f64,
+    #[serde(default = "default_risk_weight_counterparties")]
+    pub counterparties: f64,
+    #[serde(default = "default_risk_weight_wallet_age")]
+    pub wallet_age: f64,
+}
+
+impl Default for RiskWeights {
+    fn default() -> Self {
+        Self {
+            anomalies: 0.8,
+            sanctions_exposure: 0.9,
+            entity_labels: 1.0,
+            counterparties: 0.6,
+            wallet_age: 0.3,
+        }
+    }
+}
+
+fn default_risk_anomaly_half_life_secs() -> u64 {
+    604_800
+}
+
+fn default_risk_anomaly_scale() -> f64 {
+    100.0
+}
+
+fn default_risk_new_wallet_days() -> f64 {
+    30.0
+}
+
+fn default_risk_refresh_interval_secs() -> u64 {
+    21_600
+}
+
+fn default_risk_refresh_batch() -> i64 {
+    500
+}
+
+fn default_risk_entity_types() -> HashMap<String, f64> {
+    HashMap::from([("sanctioned".to_string(), 1.0), ("mixer".to_string(), 0.8)])
+}
+
+fn default_risk_weight_anomalies() -> f64 {
+    0.8
+}
+
+fn default_risk_weight_exposure() -> f64 {
+    0.9
+}
+
+fn default_risk_weight_labels() -> f64 {
+    1.0
+}
+
+fn default_risk_weight_counterparties() -> f64 {
+    0.6
+}
+
+fn default_risk_weight_wallet_age() -> f64 {
+    0.3
+}
+
+// ============================================================
+// Alerting Config
+// ============================================================
+
+/// Outbound alert delivery. Each new anomaly is checked against every route and
+/// queued once per matching sink, immediately or for that sink's daily digest.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AlertingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_alert_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Attempts before a delivery is marked failed.
+    #[serde(default = "default_alert_max_attempts")]
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled on each further attempt up to `max_backoff_secs`.
+    #[serde(default = "default_alert_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+    #[serde(default = "default_alert_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "default_alert_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// UTC hour at which held digest alerts are sent.
+    #[serde(default = "default_alert_digest_hour_utc")]
+    pub digest_hour_utc: u32,
+    /// Minimum time between notifications to a sink for the same anomaly group.
+    #[serde(default = "default_alert_cooldown_secs")]
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub sinks: Vec<WebhookSinkConfig>,
+    #[serde(default)]
+    pub routes: Vec<AlertRouteConfig>,
+}
+
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_secs: 5,
+            max_attempts: 8,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 3600,
+            request_timeout_secs: 10,
+            digest_hour_utc: 8,
+            cooldown_secs: 3600,
+            sinks: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+fn default_alert_poll_interval_secs() -> u64 {
+    5
+}
+
+fn default_alert_max_attempts() -> i32 {
+    8
+}
+
+fn default_alert_initial_backoff_secs() -> u64 {
+    10
+}
+
+fn default_alert_max_backoff_secs() -> u64 {
+    3600
+}
+
+fn default_alert_request_timeout_secs() -> u64 {
+    10
+}
+
+fn default_alert_digest_hour_utc() -> u32 {
+    8
+}
+
+fn default_alert_cooldown_secs() -> u64 {
+    3600
+}
+
+/// An HTTP endpoint receiving alerts as signed JSON POSTs.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookSinkConfig {
+    pub name: String,
+    pub url: String,
+    /// Environment variable holding the HMAC signing secret.
+    pub secret_env: String,
+}
+
+/// Whether a route delivers each alert as it happens or batches it into the daily digest.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMode {
+    #[default]
+    Immediate,
+    Digest,
+}
+
+/// Sends matching anomalies to a sink. Empty lists match everything.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AlertRouteConfig {
+    pub sink: String,
+    #[serde(default)]
+    pub mode: AlertMode,
+    #[serde(default)]
+    pub anomaly_types: Vec<String>,
+    #[serde(default)]
+    pub min_risk: f32,
+    /// Chain names as configured in [[chains]].
+    #[serde(default)]
+    pub chains: Vec<String>,
+    /// Entity names or types, matched case-insensitively against either side of the transfer.
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// Overrides `alerting.cooldown_secs` for this route.
+    pub cooldown_secs: Option<u64>,
+}
+
+// ============================================================
+// Pricing Config
+// ============================================================
+
+/// USD valuation of transfers from FX rates for each token's currency and DEX swap prices.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PricingConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// CSV of `currency,valid_from,usd_rate` rows, upserted into `fx_rates` at startup.
+    pub fx_rates_path: Option<String>,
+    /// How often rates written directly to `fx_rates` are picked up.
+    #[serde(default = "default_fx_refresh_secs")]
+    pub fx_refresh_secs: u64,
+    /// Tokens valued at $1 when pricing other tokens from swaps against them.
+    #[serde(default = "default_reference_tokens")]
+    pub reference_tokens: Vec<String>,
+    /// Hourly DEX buckets with less reference volume than this are ignored.
+    #[serde(default = "default_dex_min_usd_volume")]
+    pub dex_min_usd_volume: f64,
+    /// A DEX bucket older than this no longer prices a transfer.
+    #[serde(default = "default_dex_max_age_secs")]
+    pub dex_max_age_secs: u64,
+    /// Pegged tokens take the DEX price over their peg once they trade this far from it.
+    #[serde(default = "default_depeg_threshold")]
+    pub depeg_threshold: f64,
+    /// Blocks per window of the swap-implied stablecoin price series.
+    #[serde(default = "default_price_window_blocks")]
+    pub window_blocks: u64,
+    /// Per-chain window overrides, keyed by chain name.
+    #[serde(default)]
+    pub chains: HashMap<String, PriceWindowConfig>,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fx_rates_path: None,
+            fx_refresh_secs: 300,
+            reference_tokens: default_reference_tokens(),
+            dex_min_usd_volume: 1000.0,
+            dex_max_age_secs: 86400,
+            depeg_threshold: 0.02,
+            window_blocks: 50,
+            chains: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PriceWindowConfig {
+    pub window_blocks: Option<u64>,
+}
+
+fn default_fx_refresh_secs() -> u64 {
+    300
+}
+
+fn default_reference_tokens() -> Vec<String> {
+    vec!["USDC".to_string(), "USDT".to_string()]
+}
+
+fn default_dex_min_usd_volume() -> f64 {
+    1000.0
+}
+
+fn default_dex_max_age_secs() -> u64 {
+    86400
+}
+
+fn default_depeg_threshold() -> f64 {
+    0.02
+}
+
+fn default_price_window_blocks() -> u64 {
+    50
+}
+
+// ============================================================
+// API Config
+// ============================================================
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_api_port")]
+    pub port: u16,
+    #[serde(default = "default_api_host")]
+    pub host: String,
+    pub exchange_wallets_path: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 3000,
+            host: "0.0.0.0".to_string(),
+            exchange_wallets_path: None,
+        }
+    }
+}
+
+fn default_api_port() -> u16 {
+    3000
+}
+
+fn default_api_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+impl Config {
+    pub fn load(path: &str) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| eyre::eyre!("Failed to read config file '{}': {}", path, e))?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| eyre::eyre!("Failed to parse config file '{}': {}", path, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A copy of this config with `[anomaly_detection]` replaced by the table of the same
+    /// name in another TOML file, such as a candidate copy of the config file.
+    pub fn with_anomaly_detection(&self, path: &str) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| eyre::eyre!("Failed to read '{}': {}", path, e))?;
+        let mut table: toml::Table = toml::from_str(&content)
+            .map_err(|e| eyre::eyre!("Failed to parse '{}': {}", path, e))?;
+        let section = table
+            .remove("anomaly_detection")
+            .ok_or_else(|| eyre::eyre!("'{}' has no [anomaly_detection] table", path))?;
+
+        let mut config = self.clone();
+        config.anomaly_detection = section
+            .try_into()
+            .map_err(|e| eyre::eyre!("Invalid [anomaly_detection] in '{}': {}", path, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> eyre::Result<()> {
+        let structuring = &self.anomaly_detection.structuring;
+        if !(structuring.band > 0.0 && structuring.band < 1.0) {
+            return Err(eyre::eyre!("anomaly_detection.structuring.band must be between 0 and 1"));
+        }
+
+        let peel = &self.anomaly_detection.peel_chain;
+        if peel.min_hops < 2 || peel.max_hops < peel.min_hops {
+            return Err(eyre::eyre!(
+                "anomaly_detection.peel_chain needs 2 <= min_hops <= max_hops"
+            ));
+        }
+        if !(peel.min_forward_ratio > 0.0 && peel.min_forward_ratio < 1.0) {
+            return Err(eyre::eyre!(
+                "anomaly_detection.peel_chain.min_forward_ratio must be between 0 and 1"
+            ));
+        }
+
+        let pass_through = &self.anomaly_detection.pass_through;
+        if !(pass_through.min_forward_ratio > 0.0 && pass_through.min_forward_ratio <= 1.0) {
+            return Err(eyre::eyre!(
+                "anomaly_detection.pass_through.min_forward_ratio must be in (0, 1]"
+            ));
+        }
+        if pass_through.layering_min_hops < 2 {
+            return Err(eyre::eyre!(
+                "anomaly_detection.pass_through.layering_min_hops must be at least 2"
+            ));
+        }
+        for (name, fan) in [
+            ("fan_in", &self.anomaly_detection.fan_in),
+            ("fan_out", &self.anomaly_detection.fan_out),
+        ] {
+            if fan.min_counterparties < 2 || fan.labelled_min_counterparties < 2 {
+                return Err(eyre::eyre!(
+                    "anomaly_detection.{} counterparty thresholds must be at least 2",
+                    name
+                ));
+            }
+            if !(fan.max_top_share > 0.0 && fan.max_top_share <= 1.0) {
+                return Err(eyre::eyre!(
+                    "anomaly_detection.{}.max_top_share must be in (0, 1]",
+                    name
+                ));
+            }
+        }
+        if self.anomaly_detection.flash_loan.min_pools == 0 {
+            return Err(eyre::eyre!("anomaly_detection.flash_loan.min_pools must be at least 1"));
+        }
+        let band = self.anomaly_detection.depeg.band;
+        if !(band > 0.0 && band < 1.0) {
+            return Err(eyre::eyre!("anomaly_detection.depeg.band must be between 0 and 1"));
+        }
+        let baseline = &self.anomaly_detection.baseline;
+        let unit = |alpha: f64| alpha > 0.0 && alpha < 1.0;
+        if !unit(baseline.alpha) || !unit(baseline.daily_alpha) {
+            return Err(eyre::eyre!(
+                "anomaly_detection.baseline alpha and daily_alpha must be between 0 and 1"
+            ));
+        }
+        let aggregation = &self.anomaly_detection.aggregation;
+        if aggregation.window_secs == 0 || aggregation.windows.values().any(|w| *w == 0) {
+            return Err(eyre::eyre!("anomaly_detection.aggregation windows must be positive"));
+        }
+
+        let mut suppression_names = HashSet::new();
+        for rule in &self.anomaly_detection.suppressions {
+            if !suppression_names.insert(rule.name.as_str()) {
+                return Err(eyre::eyre!("suppression '{}' is defined twice", rule.name));
+            }
+            if rule.address.is_none()
+                && rule.entity.is_none()
+                && rule.entity_type.is_none()
+                && rule.chain.is_none()
+                && rule.anomaly_types.is_empty()
+            {
+                return Err(eyre::eyre!("suppression '{}' has no scope", rule.name));
+            }
+            if rule.reason.trim().is_empty() {
+                return Err(eyre::eyre!("suppression '{}' needs a reason", rule.name));
+            }
+            if let Some(address) = &rule.address {
+                let valid = address.len() == 42
+                    && address.starts_with("0x")
+                    && hex::decode(&address[2..]).is_ok();
+                if !valid {
+                    return Err(eyre::eyre!(
+                        "suppression '{}' has invalid address '{}'",
+                        rule.name,
+                        address
+                    ));
+                }
+            }
+            if let Some(chain) = &rule.chain {
+                if !self.chains.iter().any(|c| &c.name == chain) {
+                    return Err(eyre::eyre!(
+                        "suppression '{}' uses unknown chain '{}'",
+                        rule.name,
+                        chain
+                    ));
+                }
+            }
+        }
+
+        let risk = &self.risk_score;
+        let weights = &risk.weights;
+        let fraction = |v: f64| (0.0..=1.0).contains(&v);
+        if ![
+            weights.anomalies,
+            weights.sanctions_exposure,
+            weights.entity_labels,
+            weights.counterparties,
+            weights.wallet_age,
+        ]
+        .into_iter()
+        .chain(risk.entity_types.values().copied())
+        .all(fraction)
+        {
+            return Err(eyre::eyre!(
+                "risk_score weights and entity_types must be between 0 and 1"
+            ));
+        }
+        if risk.anomaly_half_life_secs == 0 || risk.anomaly_scale <= 0.0 {
+            return Err(eyre::eyre!(
+                "risk_score anomaly_half_life_secs and anomaly_scale must be positive"
+            ));
+        }
+
+        let alerting = &self.alerting;
+        if alerting.digest_hour_utc > 23 || alerting.max_attempts < 1 {
+            return Err(eyre::eyre!(
+                "alerting needs digest_hour_utc between 0 and 23 and max_attempts of at least 1"
+            ));
+        }
+        let mut sink_names = HashSet::new();
+        for sink in &alerting.sinks {
+            if !sink_names.insert(sink.name.as_str()) {
+                return Err(eyre::eyre!("alerting sink '{}' is defined twice", sink.name));
+            }
+        }
+        for route in &alerting.routes {
+            if !sink_names.contains(route.sink.as_str()) {
+                return Err(eyre::eyre!("alerting route uses unknown sink '{}'", route.sink));
+            }
+            if let Some(chain) = route
+                .chains
+                .iter()
+                .find(|name| !self.chains.iter().any(|c| &c.name == *name))
+            {
+                return Err(eyre::eyre!("alerting route uses unknown chain '{}'", chain));
+            }
+        }
+
+        let pricing = &self.pricing;
+        if !(pricing.depeg_threshold > 0.0 && pricing.depeg_threshold < 1.0) {
+            return Err(eyre::eyre!("pricing.depeg_threshold must be between 0 and 1"));
+        }
+        let windows = pricing.chains.values().filter_map(|c| c.window_blocks);
+        if std::iter::once(pricing.window_blocks).chain(windows).any(|w| w == 0) {
+            return Err(eyre::eyre!("pricing.window_blocks must be at least 1"));
+        }
+        for name in pricing.chains.keys() {
+            if !self.chains.iter().any(|c| &c.name == name) {
+                return Err(eyre::eyre!(
+                    "pricing.chains.{} is not a configured chain",
+                    name
+                ));
+            }
+        }
+        for chain in &self.chains {
+            for token in &chain.tokens {
+                let registered = token.currency == "USD"
+                    || self.fiat_currencies.is_empty()
+                    || self.fiat_currencies.iter().any(|c| c.code == token.currency);
+                if !registered {
+                    return Err(eyre::eyre!(
+                        "{} on chain '{}' uses currency '{}' missing from fiat_currencies",
+                        token.symbol,
+                        chain.name,
+                        token.currency
+                    ));
+                }
+                if pricing.reference_tokens.contains(&token.symbol)
+                    && (token.currency != "USD" || token.floating)
+                {
+                    return Err(eyre::eyre!(
+                        "pricing reference token {} must be pegged to USD",
+                        token.symbol
+                    ));
+                }
+            }
+        }
+
+        let exposure = &self.sanctions_exposure;
+        if exposure.max_hops == 0 || !(exposure.decay > 0.0 && exposure.decay <= 1.0) {
+            return Err(eyre::eyre!(
+                "sanctions_exposure needs max_hops >= 1 and decay in (0, 1]"
+            ));
+        }
+        for name in pass_through.chains.keys() {
+            if !self.chains.iter().any(|c| &c.name == name) {
+                return Err(eyre::eyre!(
+                    "anomaly_detection.pass_through.chains.{} is not a configured chain",
+                    name
+                ));
+            }
+        }
         if self.chains.is_empty() {
             return Err(eyre::eyre!("At least one chain must be configured"));
         }
@@ -369,6 +1753,13 @@ decimals = 6
             fiat_currencies: vec![],
             entity_attribution: EntityAttributionConfig::default(),
             anomaly_detection: AnomalyDetectionConfig::default(),
+            enrichment: EnrichmentConfig::default(),
+            storage: StorageConfig::default(),
+            export: ExportConfig::default(),
+            sanctions_exposure: SanctionsExposureConfig::default(),
+            risk_score: RiskScoreConfig::default(),
+            alerting: AlertingConfig::default(),
+            pricing: PricingConfig::default(),
             api: ApiConfig::default(),
         };
         assert!(config.validate().is_err());
@@ -394,6 +1785,8 @@ decimals = 6
                     symbol: "BAD".to_string(),
                     address: "not-an-address".to_string(),
                     decimals: 6,
+                    currency: "USD".to_string(),
+                    floating: false,
                 }],
                 decode_defi: true,
             }],
@@ -401,6 +1794,13 @@ decimals = 6
             fiat_currencies: vec![],
             entity_attribution: EntityAttributionConfig::default(),
             anomaly_detection: AnomalyDetectionConfig::default(),
+            enrichment: EnrichmentConfig::default(),
+            storage: StorageConfig::default(),
+            export: ExportConfig::default(),
+            sanctions_exposure: SanctionsExposureConfig::default(),
+            risk_score: RiskScoreConfig::default(),
+            alerting: AlertingConfig::default(),
+            pricing: PricingConfig::default(),
             api: ApiConfig::default(),
         };
         assert!(config.validate().is_err());
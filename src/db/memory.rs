@@ -0,0 +1,713 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+use bigdecimal::BigDecimal;
+
+use super::repository::EnrichmentCheckpoint;
+use super::store::Store;
+use crate::entity::label_store::{EntityLabel, LabelSeed};
+use crate::graph::tracker::{EdgeRebuild, GraphEdge};
+use crate::indexer::defi_decoder::DefiEvent;
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
+use crate::wallet::first_seen::NewWalletEvent;
+
+/// In-process [`Store`] for integration tests and running the indexer without Postgres.
+/// Mirrors the Postgres semantics the indexer relies on: duplicate transfers are skipped
+/// but still get their stored id, ids only grow, and the checkpoint advance is a compare-and-set.
+/// Nothing is persisted.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+/// Block hash and parent hash by block number.
+type BlockHashes = BTreeMap<i64, (Vec<u8>, Vec<u8>)>;
+
+/// Graph edge key: chain, source and destination.
+type EdgeKey = (i64, Vec<u8>, Vec<u8>);
+
+#[derive(Default)]
+struct MemoryState {
+    next_transfer_id: i64,
+    transfers: BTreeMap<i64, StablecoinTransfer>,
+    transfer_ids: HashMap<(i64, Vec<u8>, i32), i64>,
+    defi_events: Vec<DefiEvent>,
+    defi_keys: HashSet<(i64, Vec<u8>, i32)>,
+    indexer_state: HashMap<i64, (i64, Option<Vec<u8>>)>,
+    block_hashes: HashMap<i64, BlockHashes>,
+    enrichment: HashMap<i64, EnrichmentCheckpoint>,
+    known_tokens: HashMap<(i64, Vec<u8>), (String, i16)>,
+    labels: Vec<EntityLabel>,
+    entity_flags: HashSet<(i64, i32, String)>,
+    first_seen: HashMap<(Vec<u8>, i64), NewWalletEvent>,
+    /// Transfer count and total amount per edge.
+    edges: BTreeMap<EdgeKey, (i64, BigDecimal)>,
+    /// Last transfer id added to the edges, per chain.
+    edges_marks: HashMap<i64, i64>,
+}
+
+impl MemoryState {
+    fn add_edge(&mut self, transfer: &StablecoinTransfer) {
+        let key = (
+            transfer.chain_id,
+            transfer.from_address.clone(),
+            transfer.to_address.clone(),
+        );
+        let (count, total) = self.edges.entry(key).or_insert((0, BigDecimal::from(0)));
+        *count += 1;
+        *total += &transfer.amount;
+    }
+
+    fn edges_mark(&self, chain_id: i64) -> i64 {
+        self.edges_marks.get(&chain_id).copied().unwrap_or(0)
+    }
+
+    /// The transfers of a batch the graph edges still have to add, like
+    /// `repository::claim_stage_batch`: stored, above the chain's edges mark and following
+    /// on from the checkpoint. Moves the mark to the end of the batch.
+    fn claim_edges(&mut self, transfers: &[StablecoinTransfer]) -> Vec<StablecoinTransfer> {
+        let mut by_chain: HashMap<i64, Vec<i64>> = HashMap::new();
+        for t in transfers {
+            if let Some(id) = t.id {
+                by_chain.entry(t.chain_id).or_default().push(id);
+            }
+        }
+
+        let mut claimed = HashSet::new();
+        for (chain_id, ids) in by_chain {
+            let first = ids.iter().copied().min().unwrap_or_default();
+            let last = ids.iter().copied().max().unwrap_or_default();
+            let checkpoint = self
+                .enrichment
+                .get(&chain_id)
+                .map_or(0, |c| c.last_transfer_id);
+            let skipped = self
+                .transfers
+                .range(checkpoint + 1..first)
+                .any(|(_, t)| t.chain_id == chain_id);
+            if checkpoint >= first || skipped {
+                continue;
+            }
+
+            let mark = self.edges_mark(chain_id);
+            claimed.extend(
+                ids.into_iter()
+                    .filter(|id| *id > mark && self.transfers.contains_key(id)),
+            );
+            self.edges_marks.insert(chain_id, mark.max(last));
+        }
+
+        transfers
+            .iter()
+            .filter(|t| t.id.is_some_and(|id| claimed.contains(&id)))
+            .cloned()
+            .collect()
+    }
+
+    /// Take the transfers at or after `from_block` out of the graph edges, keeping only the
+    /// transfers before it already added, like the Postgres rollback and replay do.
+    fn rewind_edges(&mut self, chain_id: i64, from_block: i64) {
+        let enriched = self.edges_mark(chain_id);
+        let pairs: HashSet<(Vec<u8>, Vec<u8>)> = self
+            .transfers
+            .values()
+            .filter(|t| t.chain_id == chain_id && t.block_number >= from_block)
+            .map(|t| (t.from_address.clone(), t.to_address.clone()))
+            .collect();
+
+        for (from, to) in &pairs {
+            self.edges.remove(&(chain_id, from.clone(), to.clone()));
+        }
+        let kept: Vec<StablecoinTransfer> = self
+            .transfers
+            .range(..=enriched)
+            .map(|(_, t)| t)
+            .filter(|t| t.chain_id == chain_id && t.block_number < from_block)
+            .filter(|t| pairs.contains(&(t.from_address.clone(), t.to_address.clone())))
+            .cloned()
+            .collect();
+        for t in &kept {
+            self.add_edge(t);
+        }
+    }
+
+    fn edges_of(&self, address: &[u8], chain_id: Option<i64>, outgoing: bool) -> Vec<GraphEdge> {
+        let mut edges: Vec<GraphEdge> = self
+            .edges
+            .iter()
+            .filter(|((chain, _, _), _)| chain_id.is_none_or(|c| c == *chain))
+            .filter_map(|((chain, source, dest), (count, total))| {
+                let (near, far) = if outgoing { (source, dest) } else { (dest, source) };
+                (near.as_slice() == address).then(|| GraphEdge {
+                    dest_address: far.clone(),
+                    chain_id: *chain,
+                    transfer_count: *count,
+                    total_amount: total.clone(),
+                })
+            })
+            .collect();
+        edges.sort_by(|a, b| b.total_amount.cmp(&a.total_amount));
+        edges
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stored transfers for a chain, in id order.
+    pub fn transfers(&self, chain_id: i64) -> Vec<StablecoinTransfer> {
+        self.lock()
+            .transfers
+            .values()
+            .filter(|t| t.chain_id == chain_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Stored DeFi events for a chain, in insertion order.
+    pub fn defi_events(&self, chain_id: i64) -> Vec<DefiEvent> {
+        self.lock()
+            .defi_events
+            .iter()
+            .filter(|e| e.chain_id == chain_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Symbol and decimals of a seeded token.
+    pub fn known_token(&self, chain_id: i64, token_address: &[u8]) -> Option<(String, i16)> {
+        self.lock()
+            .known_tokens
+            .get(&(chain_id, token_address.to_vec()))
+            .cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        // A panic while holding the lock cannot leave the maps half-updated in a way
+        // later callers care about, so recover the guard instead of propagating the poison.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Store for MemoryStore {
+    async fn insert_transfers_batch(
+        &self,
+        transfers: &mut [StablecoinTransfer],
+    ) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let mut inserted = 0u64;
+
+        for t in transfers.iter_mut() {
+            let key = (t.chain_id, t.tx_hash.clone(), t.log_index);
+            let id = match state.transfer_ids.get(&key) {
+                Some(&id) => id,
+                None => {
+                    state.next_transfer_id += 1;
+                    let id = state.next_transfer_id;
+                    t.id = Some(id);
+                    state.transfer_ids.insert(key, id);
+                    state.transfers.insert(id, t.clone());
+                    inserted += 1;
+                    id
+                }
+            };
+            t.id = Some(id);
+        }
+
+        Ok(inserted)
+    }
+
+    async fn insert_defi_events_batch(&self, events: &[DefiEvent]) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let mut inserted = 0u64;
+
+        for event in events {
+            if state
+                .defi_keys
+                .insert((event.chain_id, event.tx_hash.clone(), event.log_index))
+            {
+                state.defi_events.push(event.clone());
+                inserted += 1;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    async fn get_last_indexed_block(&self, chain_id: i64) -> eyre::Result<Option<u64>> {
+        Ok(self
+            .lock()
+            .indexer_state
+            .get(&chain_id)
+            .map(|(block, _)| *block as u64))
+    }
+
+    async fn upsert_indexer_state(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: Option<&[u8]>,
+    ) -> eyre::Result<()> {
+        self.lock()
+            .indexer_state
+            .insert(chain_id, (block_number, block_hash.map(<[u8]>::to_vec)));
+        Ok(())
+    }
+
+    async fn upsert_block_hash(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: &[u8],
+        parent_hash: &[u8],
+    ) -> eyre::Result<()> {
+        self.lock()
+            .block_hashes
+            .entry(chain_id)
+            .or_default()
+            .insert(block_number, (block_hash.to_vec(), parent_hash.to_vec()));
+        Ok(())
+    }
+
+    async fn get_block_hash(&self, chain_id: i64, block_number: i64) -> eyre::Result<Option<Vec<u8>>> {
+        Ok(self
+            .lock()
+            .block_hashes
+            .get(&chain_id)
+            .and_then(|hashes| hashes.get(&block_number))
+            .map(|(hash, _)| hash.clone()))
+    }
+
+    async fn delete_transfers_from_block(&self, chain_id: i64, from_block: i64) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let doomed: HashSet<i64> = state
+            .transfers
+            .values()
+            .filter(|t| t.chain_id == chain_id && t.block_number >= from_block)
+            .filter_map(|t| t.id)
+            .collect();
+
+        state.rewind_edges(chain_id, from_block);
+        state
+            .entity_flags
+            .retain(|(transfer_id, _, _)| !doomed.contains(transfer_id));
+        for id in &doomed {
+            if let Some(t) = state.transfers.remove(id) {
+                state.transfer_ids.remove(&(t.chain_id, t.tx_hash, t.log_index));
+            }
+        }
+
+        Ok(doomed.len() as u64)
+    }
+
+    async fn delete_defi_events_from_block(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let before = state.defi_events.len();
+        let (kept, removed): (Vec<DefiEvent>, Vec<DefiEvent>) = std::mem::take(&mut state.defi_events)
+            .into_iter()
+            .partition(|e| e.chain_id != chain_id || e.block_number < from_block);
+
+        for e in removed {
+            state.defi_keys.remove(&(e.chain_id, e.tx_hash, e.log_index));
+        }
+        state.defi_events = kept;
+
+        Ok((before - state.defi_events.len()) as u64)
+    }
+
+    async fn delete_block_hashes_from(&self, chain_id: i64, from_block: i64) -> eyre::Result<()> {
+        if let Some(hashes) = self.lock().block_hashes.get_mut(&chain_id) {
+            hashes.split_off(&from_block);
+        }
+        Ok(())
+    }
+
+    async fn prune_block_hashes(&self, chain_id: i64, below_block: i64) -> eyre::Result<()> {
+        if let Some(hashes) = self.lock().block_hashes.get_mut(&chain_id) {
+            *hashes = hashes.split_off(&below_block);
+        }
+        Ok(())
+    }
+
+    async fn get_enrichment_state(&self, chain_id: i64) -> eyre::Result<EnrichmentCheckpoint> {
+        Ok(self
+            .lock()
+            .enrichment
+            .get(&chain_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn advance_enrichment_checkpoint(
+        &self,
+        chain_id: i64,
+        expected_transfer_id: i64,
+        transfer_id: i64,
+        block_number: i64,
+    ) -> eyre::Result<bool> {
+        let mut state = self.lock();
+        let checkpoint = state.enrichment.entry(chain_id).or_default();
+        if checkpoint.last_transfer_id != expected_transfer_id {
+            return Ok(false);
+        }
+
+        checkpoint.last_transfer_id = transfer_id;
+        checkpoint.last_block = Some(block_number);
+        Ok(true)
+    }
+
+    async fn set_enrichment_paused(&self, chain_id: i64, paused: bool) -> eyre::Result<()> {
+        self.lock().enrichment.entry(chain_id).or_default().paused = paused;
+        Ok(())
+    }
+
+    async fn rewind_enrichment_checkpoint(&self, chain_id: i64, from_block: i64) -> eyre::Result<i64> {
+        let mut state = self.lock();
+        let before = state
+            .transfers
+            .values()
+            .filter(|t| t.chain_id == chain_id && t.block_number < from_block);
+        let last_id = before.clone().filter_map(|t| t.id).max().unwrap_or(0);
+        let last_block = before.map(|t| t.block_number).max();
+
+        // Like the Postgres rewind, a replay never moves the checkpoint or mark forward
+        let checkpoint = state.enrichment.entry(chain_id).or_default();
+        if last_id < checkpoint.last_transfer_id {
+            checkpoint.last_transfer_id = last_id;
+            checkpoint.last_block = last_block;
+        }
+        let last_id = checkpoint.last_transfer_id;
+        let mark = state.edges_mark(chain_id).min(last_id);
+        state.edges_marks.insert(chain_id, mark);
+        state.rewind_edges(chain_id, from_block);
+
+        let queued = state
+            .transfers
+            .range(last_id + 1..)
+            .filter(|(_, t)| t.chain_id == chain_id)
+            .count() as i64;
+
+        Ok(queued)
+    }
+
+    async fn get_transfers_after(
+        &self,
+        chain_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> eyre::Result<Vec<(i64, StablecoinTransfer)>> {
+        Ok(self
+            .lock()
+            .transfers
+            .range(after_id + 1..)
+            .filter(|(_, t)| t.chain_id == chain_id)
+            .take(limit.max(0) as usize)
+            .map(|(&id, t)| (id, t.clone()))
+            .collect())
+    }
+
+    async fn upsert_known_token(
+        &self,
+        chain_id: i64,
+        token_address: &[u8],
+        symbol: &str,
+        decimals: i16,
+    ) -> eyre::Result<()> {
+        self.lock().known_tokens.insert(
+            (chain_id, token_address.to_vec()),
+            (symbol.to_string(), decimals),
+        );
+        Ok(())
+    }
+
+    async fn get_indexer_states(&self) -> eyre::Result<Vec<(i64, i64)>> {
+        let mut states: Vec<(i64, i64)> = self
+            .lock()
+            .indexer_state
+            .iter()
+            .map(|(&chain_id, (block, _))| (chain_id, *block))
+            .collect();
+        states.sort_unstable();
+        Ok(states)
+    }
+
+    async fn count_transfers(&self) -> eyre::Result<i64> {
+        Ok(self.lock().transfers.len() as i64)
+    }
+
+    async fn count_transfers_after(&self, chain_id: i64, after_id: i64) -> eyre::Result<i64> {
+        Ok(self
+            .lock()
+            .transfers
+            .range(after_id + 1..)
+            .filter(|(_, t)| t.chain_id == chain_id)
+            .count() as i64)
+    }
+
+    async fn load_entity_labels(&self) -> eyre::Result<Vec<EntityLabel>> {
+        Ok(self.lock().labels.clone())
+    }
+
+    async fn get_address_labels(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> eyre::Result<Vec<EntityLabel>> {
+        Ok(self
+            .lock()
+            .labels
+            .iter()
+            .filter(|l| l.address == address)
+            .filter(|l| chain_id.is_none() || l.chain_id.is_none() || l.chain_id == chain_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_entity_label(&self, seed: &LabelSeed<'_>) -> eyre::Result<i32> {
+        let mut state = self.lock();
+        let existing = state.labels.iter_mut().find(|l| {
+            l.address == seed.address
+                && l.chain_id == seed.chain_id
+                && l.label_source == seed.label_source
+                && l.entity_name == seed.entity_name
+        });
+        if let Some(label) = existing {
+            label.entity_type = seed.entity_type.to_string();
+            label.confidence = seed.confidence;
+            return Ok(label.id);
+        }
+
+        let id = state.labels.len() as i32 + 1;
+        state.labels.push(EntityLabel {
+            id,
+            address: seed.address.to_vec(),
+            chain_id: seed.chain_id,
+            entity_name: seed.entity_name.to_string(),
+            entity_type: seed.entity_type.to_string(),
+            label_source: seed.label_source.to_string(),
+            confidence: seed.confidence,
+        });
+        Ok(id)
+    }
+
+    async fn insert_entity_flags(&self, flags: &[(TransferKey, i32, &str)]) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let inserted = flags
+            .iter()
+            .filter(|(key, label_id, side)| {
+                state
+                    .entity_flags
+                    .insert((key.id, *label_id, side.to_string()))
+            })
+            .count();
+        Ok(inserted as u64)
+    }
+
+    async fn load_known_wallets(&self) -> eyre::Result<Vec<(Vec<u8>, i64)>> {
+        Ok(self.lock().first_seen.keys().cloned().collect())
+    }
+
+    async fn insert_first_seen(&self, events: &[NewWalletEvent]) -> eyre::Result<()> {
+        let mut state = self.lock();
+        for event in events {
+            state
+                .first_seen
+                .entry((event.address.clone(), event.chain_id))
+                .or_insert_with(|| event.clone());
+        }
+        Ok(())
+    }
+
+    async fn get_first_seen(&self, address: &[u8]) -> eyre::Result<Option<NewWalletEvent>> {
+        Ok(self
+            .lock()
+            .first_seen
+            .values()
+            .filter(|e| e.address == address)
+            .min_by_key(|e| e.first_seen_at)
+            .cloned())
+    }
+
+    async fn update_graph_edges(&self, transfers: &[StablecoinTransfer]) -> eyre::Result<u64> {
+        let mut state = self.lock();
+        let mut written = HashSet::new();
+        let claimed = state.claim_edges(transfers);
+        for t in &claimed {
+            state.add_edge(t);
+            written.insert((t.chain_id, &t.from_address, &t.to_address));
+        }
+        Ok(written.len() as u64)
+    }
+
+    async fn rebuild_graph_edges(
+        &self,
+        chain_id: i64,
+        pairs: &[(Vec<u8>, Vec<u8>)],
+        dry_run: bool,
+    ) -> eyre::Result<EdgeRebuild> {
+        let mut state = self.lock();
+        let enriched = state.edges_mark(chain_id);
+        let mut outcome = EdgeRebuild::default();
+
+        for (source, dest) in pairs {
+            let mut rebuilt: Option<(i64, BigDecimal)> = None;
+            for t in state.transfers.range(..=enriched).map(|(_, t)| t) {
+                if t.chain_id == chain_id && &t.from_address == source && &t.to_address == dest {
+                    let (count, total) = rebuilt.get_or_insert((0, BigDecimal::from(0)));
+                    *count += 1;
+                    *total += &t.amount;
+                }
+            }
+            let Some(rebuilt) = rebuilt else { continue };
+
+            let key = (chain_id, source.clone(), dest.clone());
+            match state.edges.get(&key) {
+                None => outcome.created += 1,
+                Some(stored) if *stored != rebuilt => outcome.changed += 1,
+                Some(_) => {
+                    outcome.unchanged += 1;
+                    continue;
+                }
+            }
+            if !dry_run {
+                state.edges.insert(key, rebuilt);
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    async fn get_outgoing_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> eyre::Result<Vec<GraphEdge>> {
+        Ok(self.lock().edges_of(address, chain_id, true))
+    }
+
+    async fn get_incoming_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> eyre::Result<Vec<GraphEdge>> {
+        Ok(self.lock().edges_of(address, chain_id, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use bigdecimal::BigDecimal;
+
+    fn transfer(block_number: i64, log_index: i32) -> StablecoinTransfer {
+        StablecoinTransfer {
+            log_index,
+            ..test_transfer(block_number, 1, 2, 1_000_000)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_transfers_keep_their_id() {
+        let store = MemoryStore::new();
+        let mut first = vec![transfer(100, 0), transfer(100, 1)];
+        assert_eq!(store.insert_transfers_batch(&mut first).await.unwrap(), 2);
+
+        let mut replay = vec![transfer(100, 1), transfer(101, 0)];
+        assert_eq!(store.insert_transfers_batch(&mut replay).await.unwrap(), 1);
+        assert_eq!(replay[0].id, first[1].id);
+        assert_eq!(replay[1].id, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_reorg_rollback_and_checkpoint_rewind() {
+        let store = MemoryStore::new();
+        let mut batch = vec![transfer(100, 0), transfer(101, 0), transfer(102, 0)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        assert!(store.advance_enrichment_checkpoint(1, 0, 3, 102).await.unwrap());
+        assert!(!store.advance_enrichment_checkpoint(1, 0, 3, 102).await.unwrap());
+
+        assert_eq!(store.delete_transfers_from_block(1, 101).await.unwrap(), 2);
+        assert_eq!(store.rewind_enrichment_checkpoint(1, 101).await.unwrap(), 0);
+
+        let checkpoint = store.get_enrichment_state(1).await.unwrap();
+        assert_eq!(checkpoint.last_transfer_id, 1);
+        assert_eq!(checkpoint.last_block, Some(100));
+
+        // Re-ingested blocks get fresh ids above the rewound checkpoint
+        let mut reorged = vec![transfer(101, 0)];
+        store.insert_transfers_batch(&mut reorged).await.unwrap();
+        let pending = store.get_transfers_after(1, 1, 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, 4);
+    }
+
+    #[tokio::test]
+    async fn test_retried_batch_adds_edges_once() {
+        let store = MemoryStore::new();
+        let mut batch = vec![transfer(100, 0), transfer(101, 0)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+
+        // A later stage failed, so the checkpoint stays put and the batch runs again
+        assert_eq!(store.update_graph_edges(&batch).await.unwrap(), 1);
+        assert_eq!(store.update_graph_edges(&batch).await.unwrap(), 0);
+        let edges = store.get_outgoing_edges(&[1; 20], Some(1)).await.unwrap();
+        assert_eq!(edges[0].transfer_count, 2);
+
+        // A batch loaded before a replay rewound the checkpoint no longer follows it
+        assert!(store.advance_enrichment_checkpoint(1, 0, 2, 101).await.unwrap());
+        let mut next = vec![transfer(102, 0)];
+        store.insert_transfers_batch(&mut next).await.unwrap();
+        store.rewind_enrichment_checkpoint(1, 101).await.unwrap();
+        assert_eq!(store.update_graph_edges(&next).await.unwrap(), 0);
+
+        let pending: Vec<StablecoinTransfer> = store
+            .get_transfers_after(1, 1, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        store.update_graph_edges(&pending).await.unwrap();
+        let edges = store.get_outgoing_edges(&[1; 20], Some(1)).await.unwrap();
+        assert_eq!(edges[0].transfer_count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_batch_loaded_before_reorg_skips_rolled_back_transfers() {
+        let store = MemoryStore::new();
+        let mut batch = vec![transfer(100, 0), transfer(101, 0), transfer(102, 0)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+
+        store.delete_transfers_from_block(1, 101).await.unwrap();
+        assert_eq!(store.update_graph_edges(&batch).await.unwrap(), 1);
+        let edges = store.get_outgoing_edges(&[1; 20], Some(1)).await.unwrap();
+        assert_eq!(edges[0].transfer_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_edges_stops_at_checkpoint() {
+        let store = MemoryStore::new();
+        let mut batch = vec![transfer(100, 0), transfer(101, 0), transfer(102, 0)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        store.update_graph_edges(&batch[..2]).await.unwrap();
+        assert!(store.advance_enrichment_checkpoint(1, 0, 2, 101).await.unwrap());
+
+        // The third transfer is past the checkpoint, so the worker still adds it later
+        let pairs = vec![(vec![1; 20], vec![2; 20])];
+        let outcome = store.rebuild_graph_edges(1, &pairs, false).await.unwrap();
+        assert_eq!(outcome.unchanged, 1);
+        assert_eq!(outcome.changed, 0);
+
+        store.update_graph_edges(&batch[2..]).await.unwrap();
+        let edges = store.get_outgoing_edges(&[1; 20], Some(1)).await.unwrap();
+        assert_eq!(edges[0].transfer_count, 3);
+        assert_eq!(edges[0].total_amount, BigDecimal::from(3_000_000));
+    }
+}
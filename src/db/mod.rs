@@ -1 +1,4 @@
+pub mod memory;
+pub mod partitions;
 pub mod repository;
+pub mod store;
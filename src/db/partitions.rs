@@ -0,0 +1,219 @@
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{RetentionAction, StorageConfig};
+
+/// Create any missing chain/month partitions for rows about to be inserted.
+/// `ensure_monthly_partitions` only takes DDL locks when a partition is actually missing.
+pub async fn ensure_partitions_for(
+    pool: &PgPool,
+    chain_ids: &[i64],
+    timestamps: &[DateTime<Utc>],
+) -> eyre::Result<()> {
+    sqlx::query(
+        "SELECT ensure_monthly_partitions(m.chain_id, m.month)
+         FROM (
+             SELECT DISTINCT chain_id, date_trunc('month', ts AT TIME ZONE 'UTC')::DATE AS month
+             FROM UNNEST($1::BIGINT[], $2::TIMESTAMPTZ[]) AS i(chain_id, ts)
+         ) m",
+    )
+    .bind(chain_ids)
+    .bind(timestamps)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Create partitions for the current month and the next `months_ahead` months.
+pub async fn ensure_upcoming_partitions(
+    pool: &PgPool,
+    chain_id: i64,
+    months_ahead: u32,
+    now: DateTime<Utc>,
+) -> eyre::Result<()> {
+    for month in upcoming_months(now, months_ahead) {
+        sqlx::query("SELECT ensure_monthly_partitions($1, $2)")
+            .bind(chain_id)
+            .bind(month)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Months with a partition still attached for a chain, oldest first.
+pub async fn list_partition_months(pool: &PgPool, chain_id: i64) -> eyre::Result<Vec<NaiveDate>> {
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT c.relname::TEXT
+         FROM pg_inherits i
+         JOIN pg_class c ON c.oid = i.inhrelid
+         JOIN pg_class p ON p.oid = i.inhparent
+         WHERE p.relname IN (format('transfers_c%s', $1::BIGINT), format('defi_events_c%s', $1::BIGINT))",
+    )
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await?;
+
+    let months: BTreeSet<NaiveDate> = names
+        .iter()
+        .filter_map(|name| partition_month(name, chain_id))
+        .collect();
+    Ok(months.into_iter().collect())
+}
+
+/// Detach (or archive) every month of a chain that lies entirely before the retention window.
+/// With `retention_months = 3` in May, February and older are retired.
+/// Returns the months retired on this pass.
+pub async fn apply_retention(
+    pool: &PgPool,
+    chain_id: i64,
+    config: &StorageConfig,
+    now: DateTime<Utc>,
+) -> eyre::Result<Vec<NaiveDate>> {
+    let Some(cutoff) = retention_cutoff(now, config.retention_months) else {
+        return Ok(Vec::new());
+    };
+    let archive = config.retention_action == RetentionAction::Archive;
+    let mut retired = Vec::new();
+
+    for month in list_partition_months(pool, chain_id).await? {
+        if month >= cutoff {
+            break;
+        }
+
+        // One transaction per month so a failure never leaves a month half-detached
+        let mut tx = pool.begin().await?;
+        let (detached,): (i32,) = sqlx::query_as("SELECT retire_monthly_partitions($1, $2, $3)")
+            .bind(chain_id)
+            .bind(month)
+            .bind(archive)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        if detached > 0 {
+            tracing::info!(
+                chain_id,
+                month = %month.format("%Y-%m"),
+                partitions = detached,
+                action = ?config.retention_action,
+                "Retired partitions"
+            );
+            retired.push(month);
+        }
+    }
+
+    Ok(retired)
+}
+
+/// Partition maintenance loop: keeps partitions created ahead of time and applies retention.
+pub async fn run_partition_maintenance(
+    chain_ids: Vec<i64>,
+    config: StorageConfig,
+    pool: PgPool,
+    shutdown: CancellationToken,
+) -> eyre::Result<()> {
+    let interval = Duration::from_secs(config.maintenance_interval_secs);
+    tracing::info!(
+        retention_months = config.retention_months,
+        action = ?config.retention_action,
+        "Starting partition maintenance"
+    );
+
+    while !shutdown.is_cancelled() {
+        let now = Utc::now();
+        for &chain_id in &chain_ids {
+            if let Err(e) =
+                ensure_upcoming_partitions(&pool, chain_id, config.partition_months_ahead, now).await
+            {
+                tracing::error!(chain_id, error = %e, "Failed to create upcoming partitions");
+            }
+            if let Err(e) = apply_retention(&pool, chain_id, &config, now).await {
+                tracing::error!(chain_id, error = %e, "Failed to apply partition retention");
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    tracing::info!("Partition maintenance stopped");
+    Ok(())
+}
+
+/// The current month and the next `months_ahead` months.
+fn upcoming_months(now: DateTime<Utc>, months_ahead: u32) -> Vec<NaiveDate> {
+    let current = month_start(now);
+    (0..=months_ahead)
+        .map(|offset| current + Months::new(offset))
+        .collect()
+}
+
+/// Oldest month kept attached when `retention_months` months are kept, counting the current
+/// one. None when retention is off.
+fn retention_cutoff(now: DateTime<Utc>, retention_months: u32) -> Option<NaiveDate> {
+    let kept_before_current = retention_months.checked_sub(1)?;
+    Some(month_start(now) - Months::new(kept_before_current))
+}
+
+/// Month of a `<table>_c<chain_id>_<YYYYMM>` partition of `chain_id`, as named by
+/// `ensure_monthly_partitions`. None for any other table.
+fn partition_month(name: &str, chain_id: i64) -> Option<NaiveDate> {
+    let (chain_part, month) = name.rsplit_once('_')?;
+    if !chain_part.ends_with(&format!("_c{}", chain_id)) || month.len() != 6 {
+        return None;
+    }
+    NaiveDate::parse_from_str(&format!("{}01", month), "%Y%m%d").ok()
+}
+
+/// First day of the UTC month containing `ts`.
+fn month_start(ts: DateTime<Utc>) -> NaiveDate {
+    NaiveDate::from_ymd_opt(ts.year(), ts.month(), 1).expect("first of month is always valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_upcoming_months_cross_the_year() {
+        let now = Utc.with_ymd_and_hms(2024, 11, 30, 23, 59, 59).unwrap();
+        assert_eq!(
+            upcoming_months(now, 2),
+            vec![date(2024, 11, 1), date(2024, 12, 1), date(2025, 1, 1)]
+        );
+        assert_eq!(upcoming_months(now, 0), vec![date(2024, 11, 1)]);
+    }
+
+    #[test]
+    fn test_retention_keeps_months_counting_the_current_one() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 15, 0, 0, 0).unwrap();
+        assert_eq!(retention_cutoff(now, 0), None);
+        assert_eq!(retention_cutoff(now, 1), Some(date(2024, 5, 1)));
+        // With 3 months in May, March is the oldest kept and February is retired
+        assert_eq!(retention_cutoff(now, 3), Some(date(2024, 3, 1)));
+        assert_eq!(retention_cutoff(now, 6), Some(date(2023, 12, 1)));
+    }
+
+    #[test]
+    fn test_partition_month_parses_only_the_chains_month_partitions() {
+        assert_eq!(partition_month("transfers_c1_202401", 1), Some(date(2024, 1, 1)));
+        assert_eq!(partition_month("defi_events_c137_202412", 137), Some(date(2024, 12, 1)));
+        assert_eq!(partition_month("transfers_c137_202412", 37), None);
+        assert_eq!(partition_month("transfers_c1", 1), None);
+        assert_eq!(partition_month("transfers_c1_default", 1), None);
+        assert_eq!(partition_month("transfers_c1_202413", 1), None);
+    }
+}
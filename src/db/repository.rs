@@ -1,45 +1,133 @@
-use sqlx::PgPool;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+
+use super::partitions;
 
 use crate::indexer::defi_decoder::DefiEvent;
 use crate::indexer::types::StablecoinTransfer;
 
-/// Insert a batch of transfers using multi-row INSERT with ON CONFLICT DO NOTHING.
-/// Chunks into groups of 1000 to stay within PostgreSQL parameter limits.
+/// A chunk of transfers as the column arrays bound to the UNNEST insert, in column order.
+struct TransferColumns<'a> {
+    chain_ids: Vec<i64>,
+    block_numbers: Vec<i64>,
+    block_hashes: Vec<&'a [u8]>,
+    tx_hashes: Vec<&'a [u8]>,
+    log_indexes: Vec<i32>,
+    token_addresses: Vec<&'a [u8]>,
+    from_addresses: Vec<&'a [u8]>,
+    to_addresses: Vec<&'a [u8]>,
+    amounts: Vec<&'a BigDecimal>,
+    symbols: Vec<&'a str>,
+    decimals: Vec<i16>,
+    timestamps: Vec<DateTime<Utc>>,
+}
+
+impl<'a> TransferColumns<'a> {
+    fn new(chunk: &'a [StablecoinTransfer]) -> Self {
+        Self {
+            chain_ids: chunk.iter().map(|t| t.chain_id).collect(),
+            block_numbers: chunk.iter().map(|t| t.block_number).collect(),
+            block_hashes: chunk.iter().map(|t| t.block_hash.as_slice()).collect(),
+            tx_hashes: chunk.iter().map(|t| t.tx_hash.as_slice()).collect(),
+            log_indexes: chunk.iter().map(|t| t.log_index).collect(),
+            token_addresses: chunk.iter().map(|t| t.token_address.as_slice()).collect(),
+            from_addresses: chunk.iter().map(|t| t.from_address.as_slice()).collect(),
+            to_addresses: chunk.iter().map(|t| t.to_address.as_slice()).collect(),
+            amounts: chunk.iter().map(|t| &t.amount).collect(),
+            symbols: chunk.iter().map(|t| t.token_symbol.as_str()).collect(),
+            decimals: chunk.iter().map(|t| t.token_decimals).collect(),
+            timestamps: chunk.iter().map(|t| t.block_timestamp).collect(),
+        }
+    }
+}
+
+/// Give each transfer the id of its returned row, as (id, chain_id, tx_hash, log_index,
+/// is_new). Returns the number of newly inserted rows.
+fn assign_transfer_ids(
+    chunk: &mut [StablecoinTransfer],
+    rows: Vec<(i64, i64, Vec<u8>, i32, bool)>,
+) -> u64 {
+    let mut inserted = 0u64;
+    let mut ids: HashMap<(i64, Vec<u8>, i32), i64> = HashMap::with_capacity(rows.len());
+    for (id, chain_id, tx_hash, log_index, is_new) in rows {
+        if is_new {
+            inserted += 1;
+        }
+        ids.insert((chain_id, tx_hash, log_index), id);
+    }
+
+    for t in chunk.iter_mut() {
+        t.id = ids
+            .get(&(t.chain_id, t.tx_hash.clone(), t.log_index))
+            .copied();
+    }
+
+    inserted
+}
+
+/// Insert a batch of transfers with one UNNEST statement per chunk, skipping duplicates.
+/// Assigns the database id to every transfer in the batch, whether it was newly inserted
+/// or already stored. Returns the number of newly inserted rows.
 pub async fn insert_transfers_batch(
     pool: &PgPool,
-    transfers: &[StablecoinTransfer],
-) -> eyre::Result<()> {
-    if transfers.is_empty() {
-        return Ok(());
-    }
+    transfers: &mut [StablecoinTransfer],
+) -> eyre::Result<u64> {
+    let mut inserted = 0u64;
+
+    for chunk in transfers.chunks_mut(5000) {
+        let columns = TransferColumns::new(chunk);
+
+        partitions::ensure_partitions_for(pool, &columns.chain_ids, &columns.timestamps).await?;
+
+        // The outer SELECT sees the pre-insert snapshot, so existing rows and newly
+        // inserted rows are returned by exactly one branch each.
+        let rows: Vec<(i64, i64, Vec<u8>, i32, bool)> = sqlx::query_as(
+            "WITH input AS (
+                SELECT * FROM UNNEST(
+                    $1::BIGINT[], $2::BIGINT[], $3::BYTEA[], $4::BYTEA[], $5::INTEGER[],
+                    $6::BYTEA[], $7::BYTEA[], $8::BYTEA[], $9::NUMERIC[], $10::TEXT[],
+                    $11::SMALLINT[], $12::TIMESTAMPTZ[]
+                ) AS i(chain_id, block_number, block_hash, tx_hash, log_index, token_address,
+                       from_address, to_address, amount, token_symbol, token_decimals,
+                       block_timestamp)
+            ),
+            ins AS (
+                INSERT INTO transfers (chain_id, block_number, block_hash, tx_hash, log_index,
+                                       token_address, from_address, to_address, amount,
+                                       token_symbol, token_decimals, block_timestamp)
+                SELECT * FROM input
+                ON CONFLICT (chain_id, tx_hash, log_index, block_timestamp) DO NOTHING
+                RETURNING id, chain_id, tx_hash, log_index
+            )
+            SELECT id, chain_id, tx_hash, log_index, TRUE FROM ins
+            UNION ALL
+            SELECT t.id, t.chain_id, t.tx_hash, t.log_index, FALSE
+            FROM transfers t
+            JOIN input i
+              ON t.chain_id = i.chain_id AND t.tx_hash = i.tx_hash AND t.log_index = i.log_index
+             AND t.block_timestamp = i.block_timestamp",
+        )
+        .bind(&columns.chain_ids)
+        .bind(&columns.block_numbers)
+        .bind(&columns.block_hashes)
+        .bind(&columns.tx_hashes)
+        .bind(&columns.log_indexes)
+        .bind(&columns.token_addresses)
+        .bind(&columns.from_addresses)
+        .bind(&columns.to_addresses)
+        .bind(&columns.amounts)
+        .bind(&columns.symbols)
+        .bind(&columns.decimals)
+        .bind(&columns.timestamps)
+        .fetch_all(pool)
+        .await?;
 
-    for chunk in transfers.chunks(1000) {
-        let mut query_builder: sqlx::QueryBuilder<sqlx::Postgres> = sqlx::QueryBuilder::new(
-            "INSERT INTO transfers (chain_id, block_number, block_hash, tx_hash, log_index, \
-             token_address, from_address, to_address, amount, token_symbol, token_decimals, \
-             block_timestamp) ",
-        );
-
-        query_builder.push_values(chunk, |mut b, t| {
-            b.push_bind(t.chain_id)
-                .push_bind(t.block_number)
-                .push_bind(&t.block_hash)
-                .push_bind(&t.tx_hash)
-                .push_bind(t.log_index)
-                .push_bind(&t.token_address)
-                .push_bind(&t.from_address)
-                .push_bind(&t.to_address)
-                .push_bind(&t.amount)
-                .push_bind(&t.token_symbol)
-                .push_bind(t.token_decimals)
-                .push_bind(t.block_timestamp);
-        });
-
-        query_builder.push(" ON CONFLICT (chain_id, tx_hash, log_index) DO NOTHING");
-        query_builder.build().execute(pool).await?;
+        inserted += assign_transfer_ids(chunk, rows);
     }
 
-    Ok(())
+    Ok(inserted)
 }
 
 /// Get the last indexed block number for a chain. Returns None if never indexed.
@@ -57,6 +145,17 @@ pub async fn get_last_indexed_block(
     Ok(row.map(|(b,)| b as u64))
 }
 
+/// Last indexed block of every chain, as (chain_id, block), in chain order.
+pub async fn get_indexer_states(pool: &PgPool) -> eyre::Result<Vec<(i64, i64)>> {
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT chain_id, last_indexed_block FROM indexer_state ORDER BY chain_id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
 /// Upsert the indexer checkpoint for a chain.
 pub async fn upsert_indexer_state(
     pool: &PgPool,
@@ -122,7 +221,7 @@ pub async fn get_block_hash(
 
 /// Delete all transfers at or above a block number (reorg rollback).
 pub async fn delete_transfers_from_block(
-    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
     chain_id: i64,
     from_block: i64,
 ) -> eyre::Result<u64> {
@@ -131,7 +230,7 @@ pub async fn delete_transfers_from_block(
     )
     .bind(chain_id)
     .bind(from_block)
-    .execute(pool)
+    .execute(&mut **tx)
     .await?;
 
     Ok(result.rows_affected())
@@ -171,44 +270,66 @@ pub async fn prune_block_hashes(
     Ok(())
 }
 
-/// Insert a batch of DeFi events using multi-row INSERT with ON CONFLICT DO NOTHING.
+/// Insert a batch of DeFi events with one UNNEST statement per chunk, skipping duplicates.
+/// Returns the number of newly inserted rows.
 pub async fn insert_defi_events_batch(
     pool: &PgPool,
     events: &[DefiEvent],
-) -> eyre::Result<()> {
-    if events.is_empty() {
-        return Ok(());
-    }
+) -> eyre::Result<u64> {
+    let mut inserted = 0u64;
 
-    for chunk in events.chunks(500) {
-        let mut query_builder: sqlx::QueryBuilder<sqlx::Postgres> = sqlx::QueryBuilder::new(
-            "INSERT INTO defi_events (chain_id, block_number, tx_hash, log_index, \
-             protocol, event_type, contract_address, account, token_in, token_out, \
-             amount_in, amount_out, block_timestamp, raw_data) ",
-        );
-
-        query_builder.push_values(chunk, |mut b, e| {
-            b.push_bind(e.chain_id)
-                .push_bind(e.block_number)
-                .push_bind(&e.tx_hash)
-                .push_bind(e.log_index)
-                .push_bind(&e.protocol)
-                .push_bind(&e.event_type)
-                .push_bind(&e.contract_address)
-                .push_bind(&e.account)
-                .push_bind(&e.token_in)
-                .push_bind(&e.token_out)
-                .push_bind(&e.amount_in)
-                .push_bind(&e.amount_out)
-                .push_bind(e.block_timestamp)
-                .push_bind(&e.raw_data);
-        });
-
-        query_builder.push(" ON CONFLICT (chain_id, tx_hash, log_index) DO NOTHING");
-        query_builder.build().execute(pool).await?;
+    for chunk in events.chunks(5000) {
+        let chain_ids: Vec<i64> = chunk.iter().map(|e| e.chain_id).collect();
+        let block_numbers: Vec<i64> = chunk.iter().map(|e| e.block_number).collect();
+        let tx_hashes: Vec<&[u8]> = chunk.iter().map(|e| e.tx_hash.as_slice()).collect();
+        let log_indexes: Vec<i32> = chunk.iter().map(|e| e.log_index).collect();
+        let protocols: Vec<&str> = chunk.iter().map(|e| e.protocol.as_str()).collect();
+        let event_types: Vec<&str> = chunk.iter().map(|e| e.event_type.as_str()).collect();
+        let contracts: Vec<&[u8]> = chunk.iter().map(|e| e.contract_address.as_slice()).collect();
+        let accounts: Vec<Option<&[u8]>> = chunk.iter().map(|e| e.account.as_deref()).collect();
+        let tokens_in: Vec<Option<&[u8]>> = chunk.iter().map(|e| e.token_in.as_deref()).collect();
+        let tokens_out: Vec<Option<&[u8]>> = chunk.iter().map(|e| e.token_out.as_deref()).collect();
+        let amounts_in: Vec<Option<&BigDecimal>> = chunk.iter().map(|e| e.amount_in.as_ref()).collect();
+        let amounts_out: Vec<Option<&BigDecimal>> =
+            chunk.iter().map(|e| e.amount_out.as_ref()).collect();
+        let timestamps: Vec<DateTime<Utc>> = chunk.iter().map(|e| e.block_timestamp).collect();
+        let raw_data: Vec<Option<&serde_json::Value>> =
+            chunk.iter().map(|e| e.raw_data.as_ref()).collect();
+
+        partitions::ensure_partitions_for(pool, &chain_ids, &timestamps).await?;
+
+        let result = sqlx::query(
+            "INSERT INTO defi_events (chain_id, block_number, tx_hash, log_index,
+                                      protocol, event_type, contract_address, account, token_in,
+                                      token_out, amount_in, amount_out, block_timestamp, raw_data)
+             SELECT * FROM UNNEST(
+                 $1::BIGINT[], $2::BIGINT[], $3::BYTEA[], $4::INTEGER[], $5::TEXT[], $6::TEXT[],
+                 $7::BYTEA[], $8::BYTEA[], $9::BYTEA[], $10::BYTEA[], $11::NUMERIC[],
+                 $12::NUMERIC[], $13::TIMESTAMPTZ[], $14::JSONB[]
+             )
+             ON CONFLICT (chain_id, tx_hash, log_index, block_timestamp) DO NOTHING",
+        )
+        .bind(&chain_ids)
+        .bind(&block_numbers)
+        .bind(&tx_hashes)
+        .bind(&log_indexes)
+        .bind(&protocols)
+        .bind(&event_types)
+        .bind(&contracts)
+        .bind(&accounts)
+        .bind(&tokens_in)
+        .bind(&tokens_out)
+        .bind(&amounts_in)
+        .bind(&amounts_out)
+        .bind(&timestamps)
+        .bind(&raw_data)
+        .execute(pool)
+        .await?;
+
+        inserted += result.rows_affected();
     }
 
-    Ok(())
+    Ok(inserted)
 }
 
 /// Delete all DeFi events at or above a block number (reorg rollback).
@@ -228,6 +349,392 @@ pub async fn delete_defi_events_from_block(
     Ok(result.rows_affected())
 }
 
+/// Enrichment progress for a single chain.
+#[derive(Debug, Clone, Default)]
+pub struct EnrichmentCheckpoint {
+    pub last_transfer_id: i64,
+    pub last_block: Option<i64>,
+    pub paused: bool,
+}
+
+/// Get the enrichment checkpoint for a chain. A chain that was never enriched starts at zero.
+pub async fn get_enrichment_state(
+    pool: &PgPool,
+    chain_id: i64,
+) -> eyre::Result<EnrichmentCheckpoint> {
+    let row: Option<(i64, Option<i64>, bool)> = sqlx::query_as(
+        "SELECT last_enriched_transfer_id, last_enriched_block, paused
+         FROM enrichment_state WHERE chain_id = $1",
+    )
+    .bind(chain_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row
+        .map(|(last_transfer_id, last_block, paused)| EnrichmentCheckpoint {
+            last_transfer_id,
+            last_block,
+            paused,
+        })
+        .unwrap_or_default())
+}
+
+/// Advance the enrichment checkpoint, but only if it still points at `expected_transfer_id`.
+/// Returns false when the checkpoint was moved concurrently (e.g. by a replay request).
+pub async fn advance_enrichment_checkpoint(
+    pool: &PgPool,
+    chain_id: i64,
+    expected_transfer_id: i64,
+    transfer_id: i64,
+    block_number: i64,
+) -> eyre::Result<bool> {
+    let result = sqlx::query(
+        "INSERT INTO enrichment_state (chain_id, last_enriched_transfer_id, last_enriched_block, updated_at)
+         VALUES ($1, $3, $4, NOW())
+         ON CONFLICT (chain_id) DO UPDATE
+         SET last_enriched_transfer_id = $3, last_enriched_block = $4, updated_at = NOW()
+         WHERE enrichment_state.last_enriched_transfer_id = $2",
+    )
+    .bind(chain_id)
+    .bind(expected_transfer_id)
+    .bind(transfer_id)
+    .bind(block_number)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// An enrichment stage whose writes add to stored totals. Each keeps a high-water transfer
+/// id next to the checkpoint, see `claim_stage_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdditiveStage {
+    Edges,
+    Rollups,
+}
+
+impl AdditiveStage {
+    fn column(self) -> &'static str {
+        match self {
+            AdditiveStage::Edges => "edges_transfer_id",
+            AdditiveStage::Rollups => "rollups_transfer_id",
+        }
+    }
+}
+
+/// Lock a chain's enrichment state until `tx` ends, creating it if needed. The worker's
+/// additive stages, replays and reorg rollbacks all take it, so they never interleave.
+pub async fn lock_enrichment_state(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+) -> eyre::Result<()> {
+    sqlx::query(
+        "INSERT INTO enrichment_state (chain_id) VALUES ($1)
+         ON CONFLICT (chain_id) DO NOTHING",
+    )
+    .bind(chain_id)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query("SELECT 1 FROM enrichment_state WHERE chain_id = $1 FOR UPDATE")
+        .bind(chain_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Lock the enrichment state of the batch's chains until `tx` ends and return the transfers
+/// `stage` still has to add: those still stored and above the stage's high-water mark. The
+/// mark moves to the end of the batch, so a batch retried after a later stage failed is not
+/// counted twice. A batch that no longer follows the checkpoint, because a replay moved it,
+/// yields nothing; the worker loads it again from the new checkpoint.
+pub async fn claim_stage_batch<'a>(
+    tx: &mut Transaction<'_, Postgres>,
+    stage: AdditiveStage,
+    transfers: &'a [StablecoinTransfer],
+) -> eyre::Result<Vec<&'a StablecoinTransfer>> {
+    let mut by_chain: HashMap<i64, Vec<i64>> = HashMap::new();
+    for t in transfers {
+        if let Some(id) = t.id {
+            by_chain.entry(t.chain_id).or_default().push(id);
+        }
+    }
+
+    let mut claimed = HashSet::new();
+    for (chain_id, ids) in by_chain {
+        let first = ids.iter().copied().min().unwrap_or_default();
+        let last = ids.iter().copied().max().unwrap_or_default();
+
+        lock_enrichment_state(tx, chain_id).await?;
+        let (checkpoint, mark): (i64, i64) = sqlx::query_as(&format!(
+            "SELECT last_enriched_transfer_id, {} FROM enrichment_state WHERE chain_id = $1",
+            stage.column()
+        ))
+        .bind(chain_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        let skipped: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM transfers WHERE chain_id = $1 AND id > $2 AND id < $3)",
+        )
+        .bind(chain_id)
+        .bind(checkpoint)
+        .bind(first)
+        .fetch_one(&mut **tx)
+        .await?;
+        if checkpoint >= first || skipped {
+            continue;
+        }
+
+        let stored: Vec<i64> = sqlx::query_scalar(
+            "SELECT id FROM transfers WHERE chain_id = $1 AND id = ANY($2) AND id > $3",
+        )
+        .bind(chain_id)
+        .bind(&ids)
+        .bind(mark)
+        .fetch_all(&mut **tx)
+        .await?;
+        claimed.extend(stored);
+
+        sqlx::query(&format!(
+            "UPDATE enrichment_state SET {0} = GREATEST({0}, $2), updated_at = NOW()
+             WHERE chain_id = $1",
+            stage.column()
+        ))
+        .bind(chain_id)
+        .bind(last)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(transfers
+        .iter()
+        .filter(|t| t.id.is_some_and(|id| claimed.contains(&id)))
+        .collect())
+}
+
+/// Pause or resume the enrichment worker for a chain. Ingestion is unaffected.
+pub async fn set_enrichment_paused(
+    pool: &PgPool,
+    chain_id: i64,
+    paused: bool,
+) -> eyre::Result<()> {
+    sqlx::query(
+        "INSERT INTO enrichment_state (chain_id, paused, updated_at)
+         VALUES ($1, $2, NOW())
+         ON CONFLICT (chain_id) DO UPDATE
+         SET paused = $2, updated_at = NOW()",
+    )
+    .bind(chain_id)
+    .bind(paused)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Rewind the enrichment checkpoint so that every transfer at or above `from_block`
+/// is enriched again. The additive stages' marks move with it. Neither ever moves forward,
+/// so a `from_block` past the checkpoint leaves it as is. Expects the row locked by
+/// `lock_enrichment_state`. Returns the new checkpoint.
+pub async fn rewind_enrichment_checkpoint(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+    from_block: i64,
+) -> eyre::Result<i64> {
+    let (last_id, last_block): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT MAX(id), MAX(block_number) FROM transfers
+         WHERE chain_id = $1 AND block_number < $2",
+    )
+    .bind(chain_id)
+    .bind(from_block)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let (checkpoint,): (i64,) = sqlx::query_as(
+        "UPDATE enrichment_state
+         SET last_enriched_transfer_id = LEAST(last_enriched_transfer_id, $2),
+             last_enriched_block = CASE WHEN last_enriched_transfer_id > $2 THEN $3
+                                        ELSE last_enriched_block END,
+             edges_transfer_id = LEAST(edges_transfer_id, $2),
+             rollups_transfer_id = LEAST(rollups_transfer_id, $2),
+             updated_at = NOW()
+         WHERE chain_id = $1
+         RETURNING last_enriched_transfer_id",
+    )
+    .bind(chain_id)
+    .bind(last_id.unwrap_or(0))
+    .bind(last_block)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(checkpoint)
+}
+
+/// Number of stored transfers across all chains.
+pub async fn count_transfers(pool: &PgPool) -> eyre::Result<i64> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM transfers")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Number of a chain's stored transfers with an id above `after_id`.
+pub async fn count_transfers_after(pool: &PgPool, chain_id: i64, after_id: i64) -> eyre::Result<i64> {
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM transfers WHERE chain_id = $1 AND id > $2",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Fetch committed transfers for a chain with an id above `after_id`, in id order.
+/// Returns (transfer id, transfer) pairs.
+pub async fn get_transfers_after(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Vec<(i64, StablecoinTransfer)>> {
+    let rows: Vec<TransferRow> = sqlx::query_as(
+        "SELECT id, block_number, block_hash, tx_hash, log_index, token_address,
+                from_address, to_address, amount, token_symbol, token_decimals, block_timestamp,
+                amount_usd
+         FROM transfers
+         WHERE chain_id = $1 AND id > $2
+         ORDER BY id ASC
+         LIMIT $3",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| transfer_from_row(chain_id, row))
+        .collect())
+}
+
+/// Row shape shared by the transfer replay queries.
+type TransferRow = (
+    i64,
+    i64,
+    Vec<u8>,
+    Vec<u8>,
+    i32,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    BigDecimal,
+    String,
+    i16,
+    DateTime<Utc>,
+    Option<BigDecimal>,
+);
+
+fn transfer_from_row(chain_id: i64, row: TransferRow) -> (i64, StablecoinTransfer) {
+    let (id, block, block_hash, tx, li, token, from, to, amount, symbol, decimals, ts, usd) = row;
+    (
+        id,
+        StablecoinTransfer {
+            id: Some(id),
+            chain_id,
+            block_number: block,
+            block_hash,
+            tx_hash: tx,
+            log_index: li,
+            token_address: token,
+            from_address: from,
+            to_address: to,
+            amount,
+            token_symbol: symbol,
+            token_decimals: decimals,
+            block_timestamp: ts,
+            amount_usd: usd,
+        },
+    )
+}
+
+/// Optional block and time bounds of a transfer replay, all inclusive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferRange {
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Fetch stored transfers for a chain within `range`, paging by transfer id.
+/// Returns (transfer id, transfer) pairs in id order.
+pub async fn get_transfers_in_range(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    range: &TransferRange,
+    limit: i64,
+) -> eyre::Result<Vec<(i64, StablecoinTransfer)>> {
+    let rows: Vec<TransferRow> = sqlx::query_as(
+        "SELECT id, block_number, block_hash, tx_hash, log_index, token_address,
+                from_address, to_address, amount, token_symbol, token_decimals, block_timestamp,
+                amount_usd
+         FROM transfers
+         WHERE chain_id = $1 AND id > $2
+           AND ($3::BIGINT IS NULL OR block_number >= $3)
+           AND ($4::BIGINT IS NULL OR block_number <= $4)
+           AND ($5::TIMESTAMPTZ IS NULL OR block_timestamp >= $5)
+           AND ($6::TIMESTAMPTZ IS NULL OR block_timestamp <= $6)
+         ORDER BY id ASC
+         LIMIT $7",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(range.from_block)
+    .bind(range.to_block)
+    .bind(range.since)
+    .bind(range.until)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| transfer_from_row(chain_id, row))
+        .collect())
+}
+
+/// Count stored transfers for a chain within `range`.
+pub async fn count_transfers_in_range(
+    pool: &PgPool,
+    chain_id: i64,
+    range: &TransferRange,
+) -> eyre::Result<i64> {
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM transfers
+         WHERE chain_id = $1
+           AND ($2::BIGINT IS NULL OR block_number >= $2)
+           AND ($3::BIGINT IS NULL OR block_number <= $3)
+           AND ($4::TIMESTAMPTZ IS NULL OR block_timestamp >= $4)
+           AND ($5::TIMESTAMPTZ IS NULL OR block_timestamp <= $5)",
+    )
+    .bind(chain_id)
+    .bind(range.from_block)
+    .bind(range.to_block)
+    .bind(range.since)
+    .bind(range.until)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
 /// Seed a known token into the database (idempotent).
 pub async fn upsert_known_token(
     pool: &PgPool,
@@ -251,3 +758,103 @@ pub async fn upsert_known_token(
 
     Ok(())
 }
+
+/// Last exported row id for a dataset on a chain. A dataset that was never exported starts at zero.
+pub async fn get_export_mark(pool: &PgPool, dataset: &str, chain_id: i64) -> eyre::Result<i64> {
+    let row: Option<(i64,)> = sqlx::query_as(
+        "SELECT last_exported_id FROM export_state WHERE dataset = $1 AND chain_id = $2",
+    )
+    .bind(dataset)
+    .bind(chain_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(id,)| id).unwrap_or(0))
+}
+
+/// Advance the export high-water mark once a batch's files are on disk.
+pub async fn advance_export_mark(
+    pool: &PgPool,
+    dataset: &str,
+    chain_id: i64,
+    last_exported_id: i64,
+    rows: i64,
+) -> eyre::Result<()> {
+    sqlx::query(
+        "INSERT INTO export_state (dataset, chain_id, last_exported_id, rows_exported, updated_at)
+         VALUES ($1, $2, $3, $4, NOW())
+         ON CONFLICT (dataset, chain_id) DO UPDATE
+         SET last_exported_id = GREATEST(export_state.last_exported_id, $3),
+             rows_exported = export_state.rows_exported + $4,
+             updated_at = NOW()",
+    )
+    .bind(dataset)
+    .bind(chain_id)
+    .bind(last_exported_id)
+    .bind(rows)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Export high-water marks for a dataset, keyed by chain.
+pub async fn get_export_marks(pool: &PgPool, dataset: &str) -> eyre::Result<Vec<(i64, i64, i64)>> {
+    let rows: Vec<(i64, i64, i64)> = sqlx::query_as(
+        "SELECT chain_id, last_exported_id, rows_exported
+         FROM export_state WHERE dataset = $1 ORDER BY chain_id",
+    )
+    .bind(dataset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+
+    fn transfer(tx: u8, log_index: i32) -> StablecoinTransfer {
+        StablecoinTransfer {
+            block_number: 100 + tx as i64,
+            log_index,
+            ..test_transfer(tx as i64, 1, 2, tx as i64 * 10)
+        }
+    }
+
+    #[test]
+    fn test_transfer_columns_line_up_per_row() {
+        let chunk = vec![transfer(1, 0), transfer(2, 3)];
+        let columns = TransferColumns::new(&chunk);
+
+        assert_eq!(columns.block_numbers, vec![101, 102]);
+        assert_eq!(columns.tx_hashes, vec![&[1u8; 32][..], &[2u8; 32][..]]);
+        assert_eq!(columns.log_indexes, vec![0, 3]);
+        assert_eq!(columns.amounts, vec![&BigDecimal::from(10), &BigDecimal::from(20)]);
+        assert_eq!(columns.timestamps[1], chunk[1].block_timestamp);
+        for len in [
+            columns.chain_ids.len(),
+            columns.block_hashes.len(),
+            columns.token_addresses.len(),
+            columns.from_addresses.len(),
+            columns.to_addresses.len(),
+            columns.symbols.len(),
+            columns.decimals.len(),
+        ] {
+            assert_eq!(len, chunk.len());
+        }
+    }
+
+    #[test]
+    fn test_assign_transfer_ids_counts_only_new_rows() {
+        let mut chunk = vec![transfer(1, 0), transfer(1, 1), transfer(2, 0)];
+        // The second log was already stored; the third row is missing from the result
+        let rows = vec![(7, 1, vec![1; 32], 0, true), (3, 1, vec![1; 32], 1, false)];
+
+        assert_eq!(assign_transfer_ids(&mut chunk, rows), 1);
+        let ids: Vec<Option<i64>> = chunk.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![Some(7), Some(3), None]);
+    }
+}
@@ -0,0 +1,554 @@
+use std::future::Future;
+
+use sqlx::PgPool;
+
+use super::repository::{self, EnrichmentCheckpoint};
+use crate::anomaly::engine;
+use crate::enrichment::rollup;
+use crate::entity::label_store::{self, EntityLabel, LabelSeed};
+use crate::entity::matcher;
+use crate::graph::tracker::{self, EdgeRebuild, GraphEdge};
+use crate::indexer::defi_decoder::DefiEvent;
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
+use crate::wallet::first_seen::{self, NewWalletEvent};
+
+/// Storage operations for ingestion, the enrichment checkpoint and the first enrichment
+/// stages: new wallets, entity attribution and graph edges.
+///
+/// `PgPool` is the production implementation and delegates to [`repository`] and the
+/// stage modules; [`MemoryStore`](super::memory::MemoryStore) keeps everything in process
+/// for tests and local runs. The later stages only exist as SQL and take a `PgPool`
+/// alongside the store: pricing, exposure, anomaly detection, rollups and risk scores are
+/// skipped without one, and the API serves only health, enrichment control and wallet
+/// profiles (without clusters, anomalies or risk), answering 501 elsewhere.
+pub trait Store: Send + Sync {
+    /// Insert transfers, skipping duplicates, and assign each its stored id.
+    /// Returns the number of newly inserted rows.
+    fn insert_transfers_batch(
+        &self,
+        transfers: &mut [StablecoinTransfer],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    /// Insert DeFi events, skipping duplicates. Returns the number of newly inserted rows.
+    fn insert_defi_events_batch(
+        &self,
+        events: &[DefiEvent],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    fn get_last_indexed_block(
+        &self,
+        chain_id: i64,
+    ) -> impl Future<Output = eyre::Result<Option<u64>>> + Send;
+
+    fn upsert_indexer_state(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: Option<&[u8]>,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    fn upsert_block_hash(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: &[u8],
+        parent_hash: &[u8],
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    fn get_block_hash(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+    ) -> impl Future<Output = eyre::Result<Option<Vec<u8>>>> + Send;
+
+    /// Reorg rollback. Rows derived from the deleted transfers go with them, atomically and
+    /// without interleaving with the enrichment worker's writes on the chain. Anomalies an
+    /// analyst has triaged are kept, detached from their transfer and flagged `reorged`.
+    fn delete_transfers_from_block(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    fn delete_defi_events_from_block(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    fn delete_block_hashes_from(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    fn prune_block_hashes(
+        &self,
+        chain_id: i64,
+        below_block: i64,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    fn get_enrichment_state(
+        &self,
+        chain_id: i64,
+    ) -> impl Future<Output = eyre::Result<EnrichmentCheckpoint>> + Send;
+
+    /// Compare-and-set on the checkpoint; false when it no longer points at `expected_transfer_id`.
+    fn advance_enrichment_checkpoint(
+        &self,
+        chain_id: i64,
+        expected_transfer_id: i64,
+        transfer_id: i64,
+        block_number: i64,
+    ) -> impl Future<Output = eyre::Result<bool>> + Send;
+
+    fn set_enrichment_paused(
+        &self,
+        chain_id: i64,
+        paused: bool,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    /// Rewind the checkpoint to just before `from_block`, taking the replayed range out of
+    /// additive derived rows so it is counted once, atomically like the reorg rollback.
+    /// A `from_block` past the checkpoint leaves it where it is. Returns the number of
+    /// transfers queued.
+    fn rewind_enrichment_checkpoint(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<i64>> + Send;
+
+    fn get_transfers_after(
+        &self,
+        chain_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> impl Future<Output = eyre::Result<Vec<(i64, StablecoinTransfer)>>> + Send;
+
+    fn upsert_known_token(
+        &self,
+        chain_id: i64,
+        token_address: &[u8],
+        symbol: &str,
+        decimals: i16,
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    /// Last indexed block of every chain, as (chain_id, block), in chain order.
+    fn get_indexer_states(&self) -> impl Future<Output = eyre::Result<Vec<(i64, i64)>>> + Send;
+
+    fn count_transfers(&self) -> impl Future<Output = eyre::Result<i64>> + Send;
+
+    /// Number of a chain's transfers with an id above `after_id`.
+    fn count_transfers_after(
+        &self,
+        chain_id: i64,
+        after_id: i64,
+    ) -> impl Future<Output = eyre::Result<i64>> + Send;
+
+    fn load_entity_labels(&self) -> impl Future<Output = eyre::Result<Vec<EntityLabel>>> + Send;
+
+    /// Labels of one address, limited to those that apply on `chain_id` when given.
+    fn get_address_labels(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<EntityLabel>>> + Send;
+
+    /// Insert or update a label. Returns its id.
+    fn upsert_entity_label(
+        &self,
+        seed: &LabelSeed<'_>,
+    ) -> impl Future<Output = eyre::Result<i32>> + Send;
+
+    /// Attach labels to stored transfers as (transfer, label id, side), skipping duplicates.
+    fn insert_entity_flags(
+        &self,
+        flags: &[(TransferKey, i32, &str)],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    /// Every (address, chain_id) with a first-seen record.
+    fn load_known_wallets(&self) -> impl Future<Output = eyre::Result<Vec<(Vec<u8>, i64)>>> + Send;
+
+    /// Record first sightings, keeping the earliest one per address and chain.
+    fn insert_first_seen(
+        &self,
+        events: &[NewWalletEvent],
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+
+    /// Earliest sighting of an address on any chain.
+    fn get_first_seen(
+        &self,
+        address: &[u8],
+    ) -> impl Future<Output = eyre::Result<Option<NewWalletEvent>>> + Send;
+
+    /// Add a batch to the wallet graph edges. Returns the number of edges written.
+    fn update_graph_edges(
+        &self,
+        transfers: &[StablecoinTransfer],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+
+    /// Recompute the given (source, dest) edges of a chain from the enriched transfers.
+    /// With `dry_run`, only reports how the stored edges differ.
+    fn rebuild_graph_edges(
+        &self,
+        chain_id: i64,
+        pairs: &[(Vec<u8>, Vec<u8>)],
+        dry_run: bool,
+    ) -> impl Future<Output = eyre::Result<EdgeRebuild>> + Send;
+
+    /// Edges out of an address, largest total first.
+    fn get_outgoing_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<GraphEdge>>> + Send;
+
+    /// Edges into an address, largest total first. `dest_address` holds the sender.
+    fn get_incoming_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<GraphEdge>>> + Send;
+}
+
+impl Store for PgPool {
+    fn insert_transfers_batch(
+        &self,
+        transfers: &mut [StablecoinTransfer],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send {
+        repository::insert_transfers_batch(self, transfers)
+    }
+
+    fn insert_defi_events_batch(
+        &self,
+        events: &[DefiEvent],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send {
+        repository::insert_defi_events_batch(self, events)
+    }
+
+    fn get_last_indexed_block(
+        &self,
+        chain_id: i64,
+    ) -> impl Future<Output = eyre::Result<Option<u64>>> + Send {
+        repository::get_last_indexed_block(self, chain_id)
+    }
+
+    fn upsert_indexer_state(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: Option<&[u8]>,
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::upsert_indexer_state(self, chain_id, block_number, block_hash)
+    }
+
+    fn upsert_block_hash(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+        block_hash: &[u8],
+        parent_hash: &[u8],
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::upsert_block_hash(self, chain_id, block_number, block_hash, parent_hash)
+    }
+
+    fn get_block_hash(
+        &self,
+        chain_id: i64,
+        block_number: i64,
+    ) -> impl Future<Output = eyre::Result<Option<Vec<u8>>>> + Send {
+        repository::get_block_hash(self, chain_id, block_number)
+    }
+
+    async fn delete_transfers_from_block(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> eyre::Result<u64> {
+        // The rolled-back transfers' graph edges and rollup buckets are rebuilt from what
+        // remains, under the enrichment lock so the worker cannot add a batch loaded before.
+        // Anomalies analysts have worked on outlive their transfers.
+        let mut tx = self.begin().await?;
+        repository::lock_enrichment_state(&mut tx, chain_id).await?;
+        let span = rollup::block_span(&mut tx, chain_id, from_block).await?;
+        tracker::rewind_edges(&mut tx, chain_id, from_block).await?;
+        engine::detach_triaged_anomalies(&mut tx, chain_id, from_block).await?;
+        let deleted = repository::delete_transfers_from_block(&mut tx, chain_id, from_block).await?;
+        if let Some((since, until)) = span {
+            rollup::rebuild_rollups(&mut tx, chain_id, since, until, false).await?;
+        }
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    fn delete_defi_events_from_block(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<u64>> + Send {
+        repository::delete_defi_events_from_block(self, chain_id, from_block)
+    }
+
+    fn delete_block_hashes_from(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::delete_block_hashes_from(self, chain_id, from_block)
+    }
+
+    fn prune_block_hashes(
+        &self,
+        chain_id: i64,
+        below_block: i64,
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::prune_block_hashes(self, chain_id, below_block)
+    }
+
+    fn get_enrichment_state(
+        &self,
+        chain_id: i64,
+    ) -> impl Future<Output = eyre::Result<EnrichmentCheckpoint>> + Send {
+        repository::get_enrichment_state(self, chain_id)
+    }
+
+    fn advance_enrichment_checkpoint(
+        &self,
+        chain_id: i64,
+        expected_transfer_id: i64,
+        transfer_id: i64,
+        block_number: i64,
+    ) -> impl Future<Output = eyre::Result<bool>> + Send {
+        repository::advance_enrichment_checkpoint(
+            self,
+            chain_id,
+            expected_transfer_id,
+            transfer_id,
+            block_number,
+        )
+    }
+
+    fn set_enrichment_paused(
+        &self,
+        chain_id: i64,
+        paused: bool,
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::set_enrichment_paused(self, chain_id, paused)
+    }
+
+    async fn rewind_enrichment_checkpoint(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+    ) -> eyre::Result<i64> {
+        // Graph edges and rollups are additive, so the replayed range is taken out of them
+        let mut tx = self.begin().await?;
+        repository::lock_enrichment_state(&mut tx, chain_id).await?;
+        let span = rollup::block_span(&mut tx, chain_id, from_block).await?;
+        let checkpoint =
+            repository::rewind_enrichment_checkpoint(&mut tx, chain_id, from_block).await?;
+        tracker::rewind_edges(&mut tx, chain_id, from_block).await?;
+        if let Some((since, until)) = span {
+            rollup::rebuild_rollups(&mut tx, chain_id, since, until, false).await?;
+        }
+        tx.commit().await?;
+
+        repository::count_transfers_after(self, chain_id, checkpoint).await
+    }
+
+    fn get_transfers_after(
+        &self,
+        chain_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> impl Future<Output = eyre::Result<Vec<(i64, StablecoinTransfer)>>> + Send {
+        repository::get_transfers_after(self, chain_id, after_id, limit)
+    }
+
+    fn upsert_known_token(
+        &self,
+        chain_id: i64,
+        token_address: &[u8],
+        symbol: &str,
+        decimals: i16,
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        repository::upsert_known_token(self, chain_id, token_address, symbol, decimals)
+    }
+
+    fn get_indexer_states(&self) -> impl Future<Output = eyre::Result<Vec<(i64, i64)>>> + Send {
+        repository::get_indexer_states(self)
+    }
+
+    fn count_transfers(&self) -> impl Future<Output = eyre::Result<i64>> + Send {
+        repository::count_transfers(self)
+    }
+
+    fn count_transfers_after(
+        &self,
+        chain_id: i64,
+        after_id: i64,
+    ) -> impl Future<Output = eyre::Result<i64>> + Send {
+        repository::count_transfers_after(self, chain_id, after_id)
+    }
+
+    fn load_entity_labels(&self) -> impl Future<Output = eyre::Result<Vec<EntityLabel>>> + Send {
+        label_store::load_labels(self)
+    }
+
+    fn get_address_labels(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<EntityLabel>>> + Send {
+        label_store::labels_for_address(self, address, chain_id)
+    }
+
+    fn upsert_entity_label(
+        &self,
+        seed: &LabelSeed<'_>,
+    ) -> impl Future<Output = eyre::Result<i32>> + Send {
+        label_store::upsert_label(self, seed)
+    }
+
+    fn insert_entity_flags(
+        &self,
+        flags: &[(TransferKey, i32, &str)],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send {
+        matcher::insert_flags(self, flags)
+    }
+
+    fn load_known_wallets(&self) -> impl Future<Output = eyre::Result<Vec<(Vec<u8>, i64)>>> + Send {
+        first_seen::load_known(self)
+    }
+
+    fn insert_first_seen(
+        &self,
+        events: &[NewWalletEvent],
+    ) -> impl Future<Output = eyre::Result<()>> + Send {
+        first_seen::insert_first_seen(self, events)
+    }
+
+    fn get_first_seen(
+        &self,
+        address: &[u8],
+    ) -> impl Future<Output = eyre::Result<Option<NewWalletEvent>>> + Send {
+        first_seen::earliest_first_seen(self, address)
+    }
+
+    fn update_graph_edges(
+        &self,
+        transfers: &[StablecoinTransfer],
+    ) -> impl Future<Output = eyre::Result<u64>> + Send {
+        tracker::update_edges(self, transfers)
+    }
+
+    fn rebuild_graph_edges(
+        &self,
+        chain_id: i64,
+        pairs: &[(Vec<u8>, Vec<u8>)],
+        dry_run: bool,
+    ) -> impl Future<Output = eyre::Result<EdgeRebuild>> + Send {
+        tracker::rebuild_edges(self, chain_id, pairs, dry_run)
+    }
+
+    fn get_outgoing_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<GraphEdge>>> + Send {
+        tracker::get_outgoing_edges(self, address, chain_id)
+    }
+
+    fn get_incoming_edges(
+        &self,
+        address: &[u8],
+        chain_id: Option<i64>,
+    ) -> impl Future<Output = eyre::Result<Vec<GraphEdge>>> + Send {
+        tracker::get_incoming_edges(self, address, chain_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::types::{AnomalyRecord, AnomalyType};
+    use crate::config::AggregationConfig;
+    use crate::indexer::types::test_transfer;
+
+    const CHAIN_ID: i64 = 31_337;
+
+    fn anomaly(transfer: &StablecoinTransfer, anomaly_type: AnomalyType) -> AnomalyRecord {
+        AnomalyRecord {
+            chain_id: transfer.chain_id,
+            anomaly_type,
+            risk_score: 0.5,
+            flags: vec![],
+            details: serde_json::json!({}),
+            address: Some(transfer.from_address.clone()),
+            transfer_id: transfer.id,
+            block_timestamp: transfer.block_timestamp,
+            tx_hash: transfer.tx_hash.clone(),
+            log_index: transfer.log_index,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_reorg_keeps_triaged_anomalies() {
+        let pool = PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        sqlx::query("DELETE FROM transfers WHERE chain_id = $1")
+            .bind(CHAIN_ID)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM anomalies WHERE chain_id = $1")
+            .bind(CHAIN_ID)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut batch: Vec<StablecoinTransfer> = [100, 101]
+            .into_iter()
+            .map(|block| StablecoinTransfer {
+                chain_id: CHAIN_ID,
+                ..test_transfer(block, 1, 2, 1_000_000)
+            })
+            .collect();
+        pool.insert_transfers_batch(&mut batch).await.unwrap();
+        let anomalies = vec![
+            anomaly(&batch[1], AnomalyType::LargeTransfer),
+            anomaly(&batch[1], AnomalyType::RoundNumber),
+        ];
+        let (ids, _) = engine::persist_anomalies(&pool, anomalies, &AggregationConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        sqlx::query(
+            "UPDATE anomalies SET status = 'escalated' WHERE chain_id = $1 AND anomaly_type = $2",
+        )
+            .bind(CHAIN_ID)
+            .bind(AnomalyType::LargeTransfer.as_str())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(pool.delete_transfers_from_block(CHAIN_ID, 101).await.unwrap(), 1);
+
+        // The escalated anomaly survives without its transfer; the open one is gone
+        let kept: Vec<(String, Option<i64>, Vec<String>, serde_json::Value)> = sqlx::query_as(
+            "SELECT anomaly_type, transfer_id, flags, details FROM anomalies WHERE chain_id = $1",
+        )
+        .bind(CHAIN_ID)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(kept.len(), 1);
+        let (anomaly_type, transfer_id, flags, details) = &kept[0];
+        assert_eq!(anomaly_type, AnomalyType::LargeTransfer.as_str());
+        assert_eq!(*transfer_id, None);
+        assert_eq!(flags, &["reorged"]);
+        assert_eq!(details["reorged_transfer_id"], batch[1].id.unwrap());
+        assert_eq!(details["reorged_block"], 101);
+    }
+}
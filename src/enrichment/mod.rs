@@ -0,0 +1,3 @@
+pub mod reenrich;
+pub mod rollup;
+pub mod worker;
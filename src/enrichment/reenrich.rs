@@ -0,0 +1,493 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::anomaly::activity::ActivityWindows;
+use crate::anomaly::engine;
+use crate::anomaly::types::AnomalyType;
+use crate::db::repository::{self, TransferRange};
+use crate::db::store::Store;
+use crate::enrichment::rollup;
+use crate::entity::matcher;
+use crate::graph::exposure;
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
+use crate::pipeline::TransferPipeline;
+use crate::pricing;
+use crate::wallet::{first_seen, risk};
+
+/// A pipeline stage that can be rebuilt from stored transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Stage {
+    Prices,
+    Entities,
+    Anomalies,
+    Graph,
+    Rollups,
+    Risk,
+}
+
+/// Which stored transfers to replay and how.
+#[derive(Debug, Clone)]
+pub struct ReenrichOptions {
+    pub chain_id: i64,
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub stages: Vec<Stage>,
+    pub dry_run: bool,
+    pub batch_size: i64,
+}
+
+/// Differences between the stored enrichment and a fresh run of the selected stages.
+/// In dry-run mode nothing is written, so this is a preview of what a real run would change.
+#[derive(Debug, Default, Serialize)]
+pub struct ReenrichReport {
+    pub chain_id: i64,
+    pub dry_run: bool,
+    pub transfers_scanned: u64,
+    /// Transfers whose stored USD value changed.
+    pub amounts_repriced: u64,
+    pub entity_flags_added: u64,
+    pub entity_flags_removed: u64,
+    pub anomalies_added: BTreeMap<String, u64>,
+    pub anomalies_removed: BTreeMap<String, u64>,
+    pub anomalies_rescored: BTreeMap<String, u64>,
+    /// No longer detected, but kept because an analyst triaged them.
+    pub anomalies_stale_kept: BTreeMap<String, u64>,
+    /// Detected anomalies held back by a suppression rule.
+    pub anomalies_suppressed: BTreeMap<String, u64>,
+    pub graph_edges_created: u64,
+    pub graph_edges_changed: u64,
+    pub graph_edges_unchanged: u64,
+    pub exposures_recomputed: u64,
+    pub risk_scores_rebuilt: u64,
+    pub rollup_hours_rebuilt: u64,
+    pub rollup_days_rebuilt: u64,
+}
+
+/// The work a run does: the selected stages, narrowed to what the pipeline has enabled.
+/// Prices run either way, as without pricing USD-pegged tokens still get their face value.
+/// Exposure and risk scores are only recomputed from written results, so a dry run skips them.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Plan {
+    prices: bool,
+    entities: bool,
+    anomalies: bool,
+    graph: bool,
+    exposure: bool,
+    risk: bool,
+    rollups: bool,
+}
+
+impl Plan {
+    fn new(
+        options: &ReenrichOptions,
+        exposure_enabled: bool,
+        risk_enabled: bool,
+    ) -> Self {
+        let selected = |stage| options.stages.contains(&stage);
+        Self {
+            prices: selected(Stage::Prices),
+            entities: selected(Stage::Entities),
+            anomalies: selected(Stage::Anomalies),
+            graph: selected(Stage::Graph),
+            exposure: selected(Stage::Graph) && exposure_enabled && !options.dry_run,
+            risk: selected(Stage::Risk) && risk_enabled && !options.dry_run,
+            rollups: selected(Stage::Rollups),
+        }
+    }
+}
+
+/// Replay stored transfers for a chain and block/time range through the selected stages,
+/// rebuilding each transfer's USD value, `transfer_entity_flags`, `anomalies`,
+/// `wallet_graph_edges`, the risk scores of every party and the rollup buckets they fall in
+/// idempotently.
+///
+/// Running this twice with the same labels and thresholds changes nothing the second time.
+pub async fn reenrich(
+    pool: &PgPool,
+    pipeline: &TransferPipeline,
+    options: &ReenrichOptions,
+) -> eyre::Result<ReenrichReport> {
+    let plan = Plan::new(
+        options,
+        pipeline.exposure_tracker.enabled(),
+        pipeline.risk_config.enabled,
+    );
+    let mut report = ReenrichReport {
+        chain_id: options.chain_id,
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    let range = TransferRange {
+        from_block: options.from_block,
+        to_block: options.to_block,
+        since: options.since,
+        until: options.until,
+    };
+    let total = repository::count_transfers_in_range(pool, options.chain_id, &range).await?;
+
+    tracing::info!(
+        chain_id = options.chain_id,
+        total,
+        stages = ?options.stages,
+        dry_run = options.dry_run,
+        "Starting re-enrichment"
+    );
+
+    let mut rebuilt_pairs: HashSet<(Vec<u8>, Vec<u8>)> = HashSet::new();
+    let mut parties: HashSet<Vec<u8>> = HashSet::new();
+    let mut time_span: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    let mut after_id = 0i64;
+    // Filled from every chain's stored transfers as the replay moves through time
+    let mut windows = ActivityWindows::new(pipeline.anomaly_engine.config());
+
+    loop {
+        let mut batch = repository::get_transfers_in_range(
+            pool,
+            options.chain_id,
+            after_id,
+            &range,
+            options.batch_size,
+        )
+        .await?;
+
+        let Some((last_id, _)) = batch.last() else {
+            break;
+        };
+        after_id = *last_id;
+
+        // Later stages compare the fresh USD values, so prices go first
+        if plan.prices {
+            replay_prices(pool, pipeline, &mut batch, options.dry_run, &mut report).await?;
+        }
+
+        if plan.entities {
+            replay_entities(pool, pipeline, &batch, options.dry_run, &mut report).await?;
+        }
+
+        if plan.anomalies {
+            replay_anomalies(
+                pool,
+                pipeline,
+                &mut windows,
+                options.chain_id,
+                &batch,
+                options.dry_run,
+                &mut report,
+            )
+            .await?;
+        }
+
+        if plan.graph {
+            let pairs: Vec<(Vec<u8>, Vec<u8>)> = batch
+                .iter()
+                .map(|(_, t)| (t.from_address.clone(), t.to_address.clone()))
+                .filter(|pair| rebuilt_pairs.insert(pair.clone()))
+                .collect();
+            let outcome = pool
+                .rebuild_graph_edges(options.chain_id, &pairs, options.dry_run)
+                .await?;
+            report.graph_edges_created += outcome.created;
+            report.graph_edges_changed += outcome.changed;
+            report.graph_edges_unchanged += outcome.unchanged;
+        }
+
+        if plan.risk {
+            for (_, t) in &batch {
+                parties.insert(t.from_address.clone());
+                parties.insert(t.to_address.clone());
+            }
+        }
+
+        for (_, t) in &batch {
+            let ts = t.block_timestamp;
+            time_span = Some(match time_span {
+                Some((min, max)) => (min.min(ts), max.max(ts)),
+                None => (ts, ts),
+            });
+        }
+
+        report.transfers_scanned += batch.len() as u64;
+        let progress = if total > 0 {
+            (report.transfers_scanned as f64 / total as f64 * 100.0) as u32
+        } else {
+            100
+        };
+
+        tracing::info!(
+            chain_id = options.chain_id,
+            scanned = report.transfers_scanned,
+            total,
+            progress = %format!("{}%", progress),
+            "Re-enrichment progress"
+        );
+    }
+
+    // Rebuilt edges change the weights sanctions exposure is computed from
+    if plan.exposure {
+        report.exposures_recomputed =
+            exposure::recompute_chain(pool, options.chain_id, pipeline.exposure_tracker.config())
+                .await?;
+    }
+
+    // Scores read the rebuilt anomalies and exposure, so they come after both
+    if plan.risk {
+        let parties: Vec<Vec<u8>> = parties.into_iter().collect();
+        for chunk in parties.chunks(1000) {
+            report.risk_scores_rebuilt += risk::score_addresses(
+                pool,
+                options.chain_id,
+                chunk,
+                &pipeline.entity_store,
+                &pipeline.risk_config,
+            )
+            .await?;
+        }
+    }
+
+    // Rollups are rebuilt after the other stages so they see the rebuilt flags and anomalies
+    if let (true, Some((since, until))) = (plan.rollups, time_span) {
+        let mut tx = pool.begin().await?;
+        repository::lock_enrichment_state(&mut tx, options.chain_id).await?;
+        let rebuilt =
+            rollup::rebuild_rollups(&mut tx, options.chain_id, since, until, options.dry_run)
+                .await?;
+        tx.commit().await?;
+        report.rollup_hours_rebuilt = rebuilt.hours;
+        report.rollup_days_rebuilt = rebuilt.days;
+    }
+
+    tracing::info!(
+        chain_id = options.chain_id,
+        scanned = report.transfers_scanned,
+        dry_run = options.dry_run,
+        "Re-enrichment complete"
+    );
+
+    Ok(report)
+}
+
+/// Re-value a batch in USD and store the values that changed.
+async fn replay_prices(
+    pool: &PgPool,
+    pipeline: &TransferPipeline,
+    batch: &mut [(i64, StablecoinTransfer)],
+    dry_run: bool,
+    report: &mut ReenrichReport,
+) -> eyre::Result<()> {
+    let transfers: Vec<StablecoinTransfer> = batch.iter().map(|(_, t)| t.clone()).collect();
+    if !dry_run {
+        pipeline.price_oracle.refresh_dex(pool, &transfers).await?;
+        pipeline
+            .price_oracle
+            .refresh_stable_prices(pool, &transfers)
+            .await?;
+    }
+    let values = pipeline.price_oracle.value(pool, &transfers).await?;
+
+    let mut changed = Vec::new();
+    for ((_, transfer), amount_usd) in batch.iter_mut().zip(values) {
+        if transfer.amount_usd != amount_usd {
+            if let Some(key) = transfer.key() {
+                changed.push((key, amount_usd.clone()));
+            }
+            transfer.amount_usd = amount_usd;
+        }
+    }
+    report.amounts_repriced += changed.len() as u64;
+
+    if !dry_run {
+        pricing::store_amounts(pool, &changed).await?;
+    }
+    Ok(())
+}
+
+/// Diff expected entity flags against stored ones and apply the difference.
+async fn replay_entities(
+    pool: &PgPool,
+    pipeline: &TransferPipeline,
+    batch: &[(i64, StablecoinTransfer)],
+    dry_run: bool,
+    report: &mut ReenrichReport,
+) -> eyre::Result<()> {
+    let ids: Vec<i64> = batch.iter().map(|(id, _)| *id).collect();
+    let stored = matcher::load_flags(pool, &ids).await?;
+
+    let mut expected: HashSet<(i64, i32, String)> = HashSet::new();
+    for (id, transfer) in batch {
+        for (label, side) in matcher::matching_labels(transfer, &pipeline.entity_store) {
+            expected.insert((*id, label.id, side.to_string()));
+        }
+    }
+
+    let keys: HashMap<i64, TransferKey> = batch
+        .iter()
+        .filter_map(|(id, t)| t.key().map(|key| (*id, key)))
+        .collect();
+    let added: Vec<(TransferKey, i32, &str)> = expected
+        .difference(&stored)
+        .filter_map(|(id, label, side)| Some((*keys.get(id)?, *label, side.as_str())))
+        .collect();
+    let removed: Vec<(i64, i32, &str)> = stored
+        .difference(&expected)
+        .map(|(id, label, side)| (*id, *label, side.as_str()))
+        .collect();
+
+    report.entity_flags_added += added.len() as u64;
+    report.entity_flags_removed += removed.len() as u64;
+
+    if !dry_run {
+        matcher::insert_flags(pool, &added).await?;
+        matcher::delete_flags(pool, &removed).await?;
+    }
+
+    Ok(())
+}
+
+/// Re-run the anomaly engine over a batch and reconcile the stored anomalies.
+/// Live-only types depend on state a replay cannot rebuild, so they are neither added nor removed.
+async fn replay_anomalies(
+    pool: &PgPool,
+    pipeline: &TransferPipeline,
+    windows: &mut ActivityWindows,
+    chain_id: i64,
+    batch: &[(i64, StablecoinTransfer)],
+    dry_run: bool,
+    report: &mut ReenrichReport,
+) -> eyre::Result<()> {
+    let ids: Vec<i64> = batch.iter().map(|(id, _)| *id).collect();
+    let transfers: Vec<StablecoinTransfer> = batch.iter().map(|(_, t)| t.clone()).collect();
+
+    // The tracker already knows every stored wallet, so rebuild first-seen events from the DB
+    let tx_hashes: Vec<Vec<u8>> = transfers.iter().map(|t| t.tx_hash.clone()).collect();
+    let new_wallets = first_seen::load_first_seen_events(pool, chain_id, &tx_hashes).await?;
+
+    windows.cover(pool, &transfers).await?;
+    let detected = pipeline
+        .anomaly_engine
+        .analyze_batch(pool, &transfers, &pipeline.entity_store, &new_wallets, windows)
+        .await?;
+    // The original run already counted these hits against their rules
+    let (detected, suppressed) = pipeline
+        .anomaly_engine
+        .suppress(pool, detected, &transfers, &pipeline.entity_store, false)
+        .await?;
+    for (_, anomaly) in &suppressed {
+        *report
+            .anomalies_suppressed
+            .entry(anomaly.anomaly_type.as_str().to_string())
+            .or_default() += 1;
+    }
+
+    let stored: HashMap<(i64, String), (f32, bool)> = engine::load_transfer_anomalies(pool, &ids)
+        .await?
+        .into_iter()
+        .map(|a| ((a.transfer_id, a.anomaly_type), (a.risk_score, a.triaged)))
+        .collect();
+
+    let mut expected: HashSet<(i64, String)> = HashSet::new();
+    let mut upserts = Vec::new();
+    for anomaly in detected {
+        let Some(transfer_id) = anomaly.transfer_id else {
+            continue;
+        };
+        let anomaly_type = anomaly.anomaly_type.as_str().to_string();
+        if AnomalyType::LIVE_ONLY.contains(&anomaly_type.as_str()) {
+            continue;
+        }
+        if !expected.insert((transfer_id, anomaly_type.clone())) {
+            continue;
+        }
+
+        match stored.get(&(transfer_id, anomaly_type.clone())) {
+            None => *report.anomalies_added.entry(anomaly_type).or_default() += 1,
+            Some((risk, _)) if *risk != anomaly.risk_score => {
+                *report.anomalies_rescored.entry(anomaly_type).or_default() += 1
+            }
+            Some(_) => {}
+        }
+        upserts.push(anomaly);
+    }
+
+    // Stale anomalies an analyst has worked on stay, with their notes and audit trail
+    let mut removed: Vec<(i64, String)> = Vec::new();
+    for (key, (_, triaged)) in stored {
+        if expected.contains(&key) || AnomalyType::LIVE_ONLY.contains(&key.1.as_str()) {
+            continue;
+        }
+        if triaged {
+            *report.anomalies_stale_kept.entry(key.1).or_default() += 1;
+        } else {
+            *report.anomalies_removed.entry(key.1.clone()).or_default() += 1;
+            removed.push(key);
+        }
+    }
+
+    if !dry_run {
+        engine::upsert_anomalies(pool, &upserts, pipeline.anomaly_engine.aggregation()).await?;
+        engine::delete_transfer_anomalies(pool, &removed).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    fn options(stages: &[Stage], dry_run: bool) -> ReenrichOptions {
+        ReenrichOptions {
+            chain_id: 1,
+            from_block: None,
+            to_block: None,
+            since: None,
+            until: None,
+            stages: stages.to_vec(),
+            dry_run,
+            batch_size: 50,
+        }
+    }
+
+    #[test]
+    fn test_plan_follows_selected_and_enabled_stages() {
+        let all = options(Stage::value_variants(), false);
+        let full = Plan::new(&all, true, true);
+        assert_eq!(
+            full,
+            Plan {
+                prices: true,
+                entities: true,
+                anomalies: true,
+                graph: true,
+                exposure: true,
+                risk: true,
+                rollups: true,
+            }
+        );
+
+        // Stages the pipeline has turned off are skipped even when selected
+        let disabled = Plan::new(&all, false, false);
+        assert!(!disabled.exposure && !disabled.risk);
+        assert!(disabled.prices && disabled.graph && disabled.rollups);
+
+        // A dry run still diffs every batch but recomputes nothing from the results
+        let dry = Plan::new(&options(Stage::value_variants(), true), true, true);
+        assert!(dry.prices && dry.entities && dry.anomalies && dry.graph && dry.rollups);
+        assert!(!dry.exposure && !dry.risk);
+
+        let graph_only = Plan::new(&options(&[Stage::Graph], false), true, true);
+        assert_eq!(
+            graph_only,
+            Plan {
+                graph: true,
+                exposure: true,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use bigdecimal::BigDecimal;
+use std::ops::Range;
+
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::db::repository::{self, AdditiveStage};
+use crate::entity::label_store::EntityLabelStore;
+use crate::entity::matcher;
+use crate::indexer::types::StablecoinTransfer;
+
+/// Add a just-enriched batch to the rollup tables. `anomaly_ids` are the anomalies the
+/// batch newly stored, so a replayed batch does not count its anomalies again.
+///
+/// Token and entity volumes are additive, so transfers already counted, e.g. by an earlier
+/// attempt at the same batch, are skipped; the replay endpoint and reorg rollback first
+/// rebuild the affected buckets with `rebuild_rollups`, which only counts transfers the
+/// worker has already added. Unique address counts are idempotent because each address is
+/// recorded once per day.
+pub async fn update_rollups(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    anomaly_ids: &[i64],
+) -> eyre::Result<()> {
+    if transfers.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    let transfers = repository::claim_stage_batch(&mut tx, AdditiveStage::Rollups, transfers).await?;
+
+    if !transfers.is_empty() {
+        add_token_volume(&mut tx, &transfers).await?;
+        add_entity_volume(&mut tx, &transfers, label_store).await?;
+        add_unique_addresses(&mut tx, &transfers).await?;
+    }
+    add_anomalies(&mut tx, anomaly_ids).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn add_token_volume(
+    tx: &mut Transaction<'_, Postgres>,
+    transfers: &[&StablecoinTransfer],
+) -> eyre::Result<()> {
+    let chain_ids: Vec<i64> = transfers.iter().map(|t| t.chain_id).collect();
+    let tokens: Vec<&[u8]> = transfers.iter().map(|t| t.token_address.as_slice()).collect();
+    let symbols: Vec<&str> = transfers.iter().map(|t| t.token_symbol.as_str()).collect();
+    let decimals: Vec<i16> = transfers.iter().map(|t| t.token_decimals).collect();
+    let amounts: Vec<&BigDecimal> = transfers.iter().map(|t| &t.amount).collect();
+    let timestamps: Vec<DateTime<Utc>> = transfers.iter().map(|t| t.block_timestamp).collect();
+
+    sqlx::query(
+        "INSERT INTO rollup_token_volume AS r
+                (bucket, chain_id, token_address, token_symbol, transfer_count, volume)
+         SELECT date_trunc('hour', i.ts, 'UTC'), i.chain_id, i.token_address, MAX(i.token_symbol),
+                COUNT(*), SUM(i.amount / power(10::NUMERIC, i.decimals))
+         FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::TEXT[], $4::SMALLINT[], $5::NUMERIC[],
+                     $6::TIMESTAMPTZ[])
+              AS i(chain_id, token_address, token_symbol, decimals, amount, ts)
+         GROUP BY 1, 2, 3
+         ON CONFLICT (bucket, chain_id, token_address) DO UPDATE
+         SET transfer_count = r.transfer_count + EXCLUDED.transfer_count,
+             volume = r.volume + EXCLUDED.volume",
+    )
+    .bind(&chain_ids)
+    .bind(&tokens)
+    .bind(&symbols)
+    .bind(&decimals)
+    .bind(&amounts)
+    .bind(&timestamps)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn add_entity_volume(
+    tx: &mut Transaction<'_, Postgres>,
+    transfers: &[&StablecoinTransfer],
+    label_store: &EntityLabelStore,
+) -> eyre::Result<()> {
+    let mut chain_ids = Vec::new();
+    let mut label_ids = Vec::new();
+    let mut sides = Vec::new();
+    let mut amounts_usd = Vec::new();
+    let mut timestamps = Vec::new();
+
+    for transfer in transfers {
+        for (label, side) in matcher::matching_labels(transfer, label_store) {
+            chain_ids.push(transfer.chain_id);
+            label_ids.push(label.id);
+            sides.push(side);
+            amounts_usd.push(transfer.amount_usd.as_ref());
+            timestamps.push(transfer.block_timestamp);
+        }
+    }
+
+    if label_ids.is_empty() {
+        return Ok(());
+    }
+
+    // Entities move several tokens, so their volumes are in USD; unpriced transfers are
+    // counted but add no volume
+    sqlx::query(
+        "INSERT INTO rollup_entity_volume AS r
+                (bucket, chain_id, entity_label_id, transfer_count, volume_in, volume_out)
+         SELECT date_trunc('hour', i.ts, 'UTC'), i.chain_id, i.entity_label_id, COUNT(*),
+                COALESCE(SUM(i.usd) FILTER (WHERE i.side = 'to'), 0),
+                COALESCE(SUM(i.usd) FILTER (WHERE i.side = 'from'), 0)
+         FROM UNNEST($1::BIGINT[], $2::INTEGER[], $3::TEXT[], $4::NUMERIC[], $5::TIMESTAMPTZ[])
+              AS i(chain_id, entity_label_id, side, usd, ts)
+         GROUP BY 1, 2, 3
+         ON CONFLICT (bucket, chain_id, entity_label_id) DO UPDATE
+         SET transfer_count = r.transfer_count + EXCLUDED.transfer_count,
+             volume_in = r.volume_in + EXCLUDED.volume_in,
+             volume_out = r.volume_out + EXCLUDED.volume_out",
+    )
+    .bind(&chain_ids)
+    .bind(&label_ids)
+    .bind(&sides)
+    .bind(&amounts_usd)
+    .bind(&timestamps)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn add_anomalies(
+    tx: &mut Transaction<'_, Postgres>,
+    anomaly_ids: &[i64],
+) -> eyre::Result<()> {
+    if anomaly_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "INSERT INTO rollup_anomalies AS r (bucket, chain_id, anomaly_type, anomaly_count)
+         SELECT date_trunc('hour', block_timestamp, 'UTC'), chain_id, anomaly_type, COUNT(*)
+         FROM anomalies
+         WHERE id = ANY($1)
+         GROUP BY 1, 2, 3
+         ON CONFLICT (bucket, chain_id, anomaly_type) DO UPDATE
+         SET anomaly_count = r.anomaly_count + EXCLUDED.anomaly_count",
+    )
+    .bind(anomaly_ids)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Record each sender and receiver once per day and bump the daily counters
+/// by the addresses that were not seen that day before.
+async fn add_unique_addresses(
+    tx: &mut Transaction<'_, Postgres>,
+    transfers: &[&StablecoinTransfer],
+) -> eyre::Result<()> {
+    let mut chain_ids = Vec::with_capacity(transfers.len() * 2);
+    let mut roles = Vec::with_capacity(transfers.len() * 2);
+    let mut addresses = Vec::with_capacity(transfers.len() * 2);
+    let mut timestamps = Vec::with_capacity(transfers.len() * 2);
+
+    for transfer in transfers {
+        for (role, address) in [("sender", &transfer.from_address), ("receiver", &transfer.to_address)] {
+            chain_ids.push(transfer.chain_id);
+            roles.push(role);
+            addresses.push(address.as_slice());
+            timestamps.push(transfer.block_timestamp);
+        }
+    }
+
+    sqlx::query(
+        "WITH seen AS (
+             INSERT INTO rollup_daily_addresses (day, chain_id, role, address)
+             SELECT DISTINCT (i.ts AT TIME ZONE 'UTC')::DATE, i.chain_id, i.role, i.address
+             FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::BYTEA[], $4::TIMESTAMPTZ[])
+                  AS i(chain_id, role, address, ts)
+             ON CONFLICT DO NOTHING
+             RETURNING day, chain_id, role
+         )
+         INSERT INTO rollup_daily_unique_addresses AS r (day, chain_id, senders, receivers)
+         SELECT day, chain_id, COUNT(*) FILTER (WHERE role = 'sender'),
+                COUNT(*) FILTER (WHERE role = 'receiver')
+         FROM seen
+         GROUP BY 1, 2
+         ON CONFLICT (day, chain_id) DO UPDATE
+         SET senders = r.senders + EXCLUDED.senders,
+             receivers = r.receivers + EXCLUDED.receivers",
+    )
+    .bind(&chain_ids)
+    .bind(&roles)
+    .bind(&addresses)
+    .bind(&timestamps)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Buckets covered by a rollup rebuild.
+#[derive(Debug, Default)]
+pub struct RollupRebuild {
+    pub hours: u64,
+    pub days: u64,
+}
+
+/// Time span of a chain's stored transfers at or after `from_block`: the rollup buckets to
+/// rebuild when those blocks are rolled back or replayed.
+pub async fn block_span(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+    from_block: i64,
+) -> eyre::Result<Option<(DateTime<Utc>, DateTime<Utc>)>> {
+    let (since, until): (Option<DateTime<Utc>>, Option<DateTime<Utc>>) = sqlx::query_as(
+        "SELECT MIN(block_timestamp), MAX(block_timestamp) FROM transfers
+         WHERE chain_id = $1 AND block_number >= $2",
+    )
+    .bind(chain_id)
+    .bind(from_block)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(since.zip(until))
+}
+
+/// Hour buckets and UTC days covering `since..=until`, as half-open ranges.
+fn rebuild_buckets(
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> eyre::Result<(Range<DateTime<Utc>>, Range<NaiveDate>)> {
+    let hours = since.duration_trunc(Duration::hours(1))?
+        ..until.duration_trunc(Duration::hours(1))? + Duration::hours(1);
+    let days = since.date_naive()..until.date_naive() + Duration::days(1);
+    Ok((hours, days))
+}
+
+/// Recompute every rollup bucket of a chain overlapping `since..=until` from stored transfers,
+/// entity flags and anomalies. Whole hours and days are rebuilt. Only transfers the
+/// enrichment worker has already added are counted, so it does not add them a second
+/// time; anomalies are counted from the table, as the worker only adds newly stored ones.
+/// Callers hold the chain's enrichment lock in `tx`.
+pub async fn rebuild_rollups(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    dry_run: bool,
+) -> eyre::Result<RollupRebuild> {
+    let (hours, days) = rebuild_buckets(since, until)?;
+    let (hour_start, hour_end) = (hours.start, hours.end);
+    let (day_start, day_end) = (days.start, days.end);
+
+    let rebuild = RollupRebuild {
+        hours: (hour_end - hour_start).num_hours() as u64,
+        days: (day_end - day_start).num_days() as u64,
+    };
+    if dry_run {
+        return Ok(rebuild);
+    }
+
+    for table in ["rollup_token_volume", "rollup_entity_volume", "rollup_anomalies"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE chain_id = $1 AND bucket >= $2 AND bucket < $3",
+            table
+        ))
+        .bind(chain_id)
+        .bind(hour_start)
+        .bind(hour_end)
+        .execute(&mut **tx)
+        .await?;
+    }
+    for table in ["rollup_daily_addresses", "rollup_daily_unique_addresses"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE chain_id = $1 AND day >= $2 AND day < $3",
+            table
+        ))
+        .bind(chain_id)
+        .bind(day_start)
+        .bind(day_end)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO rollup_token_volume
+                (bucket, chain_id, token_address, token_symbol, transfer_count, volume)
+         SELECT date_trunc('hour', t.block_timestamp, 'UTC'), t.chain_id, t.token_address,
+                MAX(t.token_symbol), COUNT(*), SUM(t.amount / power(10::NUMERIC, t.token_decimals))
+         FROM transfers t
+         JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.rollups_transfer_id
+         WHERE t.chain_id = $1 AND t.block_timestamp >= $2 AND t.block_timestamp < $3
+         GROUP BY 1, 2, 3",
+    )
+    .bind(chain_id)
+    .bind(hour_start)
+    .bind(hour_end)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO rollup_entity_volume
+                (bucket, chain_id, entity_label_id, transfer_count, volume_in, volume_out)
+         SELECT date_trunc('hour', t.block_timestamp, 'UTC'), t.chain_id, f.entity_label_id, COUNT(*),
+                COALESCE(SUM(t.amount_usd) FILTER (WHERE f.side = 'to'), 0),
+                COALESCE(SUM(t.amount_usd) FILTER (WHERE f.side = 'from'), 0)
+         FROM transfer_entity_flags f
+         JOIN transfers t
+           ON t.id = f.transfer_id AND t.chain_id = f.chain_id AND t.block_timestamp = f.block_timestamp
+         JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.rollups_transfer_id
+         WHERE f.chain_id = $1 AND f.block_timestamp >= $2 AND f.block_timestamp < $3
+         GROUP BY 1, 2, 3",
+    )
+    .bind(chain_id)
+    .bind(hour_start)
+    .bind(hour_end)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO rollup_anomalies (bucket, chain_id, anomaly_type, anomaly_count)
+         SELECT date_trunc('hour', block_timestamp, 'UTC'), chain_id, anomaly_type, COUNT(*)
+         FROM anomalies
+         WHERE chain_id = $1 AND block_timestamp >= $2 AND block_timestamp < $3
+         GROUP BY 1, 2, 3",
+    )
+    .bind(chain_id)
+    .bind(hour_start)
+    .bind(hour_end)
+    .execute(&mut **tx)
+    .await?;
+
+    // Day bounds are applied in UTC to match the day column
+    sqlx::query(
+        "INSERT INTO rollup_daily_addresses (day, chain_id, role, address)
+         SELECT (t.block_timestamp AT TIME ZONE 'UTC')::DATE, t.chain_id, r.role,
+                CASE r.role WHEN 'sender' THEN t.from_address ELSE t.to_address END
+         FROM transfers t
+         JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.rollups_transfer_id
+         CROSS JOIN (VALUES ('sender'), ('receiver')) AS r(role)
+         WHERE t.chain_id = $1
+           AND t.block_timestamp >= $2::DATE::TIMESTAMP AT TIME ZONE 'UTC'
+           AND t.block_timestamp < $3::DATE::TIMESTAMP AT TIME ZONE 'UTC'
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(chain_id)
+    .bind(day_start)
+    .bind(day_end)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO rollup_daily_unique_addresses (day, chain_id, senders, receivers)
+         SELECT day, chain_id, COUNT(*) FILTER (WHERE role = 'sender'),
+                COUNT(*) FILTER (WHERE role = 'receiver')
+         FROM rollup_daily_addresses
+         WHERE chain_id = $1 AND day >= $2 AND day < $3
+         GROUP BY 1, 2",
+    )
+    .bind(chain_id)
+    .bind(day_start)
+    .bind(day_end)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(rebuild)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rebuild_buckets_cover_whole_hours_and_days() {
+        let since = Utc.with_ymd_and_hms(2026, 10, 15, 23, 59, 59).unwrap();
+        let until = Utc.with_ymd_and_hms(2026, 10, 16, 1, 0, 0).unwrap();
+        let (hours, days) = rebuild_buckets(since, until).unwrap();
+
+        assert_eq!(
+            hours.start,
+            Utc.with_ymd_and_hms(2026, 10, 15, 23, 0, 0).unwrap()
+        );
+        assert_eq!(
+            hours.end,
+            Utc.with_ymd_and_hms(2026, 10, 16, 2, 0, 0).unwrap()
+        );
+        assert_eq!(days.start, NaiveDate::from_ymd_opt(2026, 10, 15).unwrap());
+        assert_eq!(days.end, NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+
+        // A single instant still covers its own hour and day
+        let (hours, days) = rebuild_buckets(until, until).unwrap();
+        assert_eq!((hours.end - hours.start).num_hours(), 1);
+        assert_eq!((days.end - days.start).num_days(), 1);
+    }
+}
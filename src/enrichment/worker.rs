@@ -0,0 +1,227 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::EnrichmentConfig;
+use crate::db::store::Store;
+use crate::indexer::types::StablecoinTransfer;
+use crate::pipeline::TransferPipeline;
+
+/// Enrichment worker for a single chain.
+///
+/// Consumes committed transfers in id order from the chain's `enrichment_state`
+/// checkpoint and runs them through the enrichment pipeline. Ingestion never waits
+/// on this loop, so a slow enrichment step only grows the lag reported by the health API.
+/// Each chain is ingested by a single task, so transfer ids commit in order per chain.
+pub async fn run_enrichment_worker<S: Store>(
+    chain_name: String,
+    chain_id: i64,
+    config: EnrichmentConfig,
+    store: S,
+    shutdown: CancellationToken,
+    pipeline: Arc<Mutex<TransferPipeline>>,
+) -> eyre::Result<()> {
+    let poll_interval = Duration::from_millis(config.poll_interval_ms);
+    tracing::info!(chain = %chain_name, chain_id, "Starting enrichment worker");
+
+    while !shutdown.is_cancelled() {
+        let idle = match enrich_next_batch(&chain_name, chain_id, &config, &store, &pipeline).await
+        {
+            Ok(processed) => processed == 0,
+            Err(e) => {
+                tracing::error!(chain = %chain_name, error = %e, "Enrichment batch failed, retrying");
+                true
+            }
+        };
+
+        if idle {
+            tokio::select! {
+                _ = tokio::time::sleep(poll_interval) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    }
+
+    tracing::info!(chain = %chain_name, "Enrichment worker stopped");
+    Ok(())
+}
+
+/// Enrich the next batch of transfers after the checkpoint.
+/// Returns the number of transfers processed (zero when paused or caught up).
+async fn enrich_next_batch<S: Store>(
+    chain_name: &str,
+    chain_id: i64,
+    config: &EnrichmentConfig,
+    store: &S,
+    pipeline: &Arc<Mutex<TransferPipeline>>,
+) -> eyre::Result<usize> {
+    let checkpoint = store.get_enrichment_state(chain_id).await?;
+    if checkpoint.paused {
+        return Ok(0);
+    }
+
+    let batch = store
+        .get_transfers_after(chain_id, checkpoint.last_transfer_id, config.batch_size)
+        .await?;
+
+    let Some((last_id, last_transfer)) = batch.last() else {
+        return Ok(0);
+    };
+    let last_id = *last_id;
+    let last_block = last_transfer.block_number;
+
+    let transfers: Vec<StablecoinTransfer> = batch.into_iter().map(|(_, t)| t).collect();
+
+    let result = {
+        let mut pl = pipeline.lock().await;
+        pl.enrich(store, &transfers).await?
+    };
+
+    if result.anomalies_detected > 0
+        || result.anomalies_suppressed > 0
+        || result.entities_attributed > 0
+    {
+        tracing::info!(
+            chain = %chain_name,
+            through_block = last_block,
+            priced = result.amounts_priced,
+            entities = result.entities_attributed,
+            new_wallets = result.new_wallets_found,
+            anomalies = result.anomalies_detected,
+            suppressed = result.anomalies_suppressed,
+            edges = result.graph_edges_updated,
+            exposures = result.exposures_updated,
+            baselines = result.baselines_updated,
+            alerts = result.alerts_queued,
+            risk_scores = result.risk_scores_updated,
+            "Enrichment complete"
+        );
+    }
+
+    let advanced = store
+        .advance_enrichment_checkpoint(chain_id, checkpoint.last_transfer_id, last_id, last_block)
+        .await?;
+
+    if !advanced {
+        tracing::info!(
+            chain = %chain_name,
+            "Enrichment checkpoint moved during batch, resuming from new position"
+        );
+    }
+
+    Ok(transfers.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use crate::api::queries;
+    use crate::config::Config;
+    use crate::db::memory::MemoryStore;
+    use bigdecimal::BigDecimal;
+
+    const CONFIG: &str = r#"
+        [database]
+        url = "postgres://unused"
+
+        [[entity_attribution.manual_labels]]
+        address = "0x0303030303030303030303030303030303030303"
+        entity_name = "Mixer"
+        entity_type = "mixer"
+
+        [[chains]]
+        name = "ethereum"
+        chain_id = 1
+        rpc_http = "http://unused"
+        tokens = []
+    "#;
+
+    #[tokio::test]
+    async fn test_ingest_enrich_and_query_on_memory_store() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let store = MemoryStore::new();
+        let pipeline = Arc::new(Mutex::new(
+            TransferPipeline::init(&store, None, &config).await.unwrap(),
+        ));
+        let enrich = || enrich_next_batch("ethereum", 1, &config.enrichment, &store, &pipeline);
+
+        // Ingest
+        let mut batch = vec![
+            test_transfer(1, 1, 2, 100),
+            test_transfer(2, 1, 3, 50),
+            test_transfer(3, 1, 2, 25),
+        ];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        store.upsert_indexer_state(1, 3, None).await.unwrap();
+        let status = queries::get_enrichment_status(&store, 1).await.unwrap();
+        assert_eq!((status.pending_transfers, status.lag_blocks), (3, 3));
+
+        // Enrich
+        assert_eq!(enrich().await.unwrap(), 3);
+        assert_eq!(enrich().await.unwrap(), 0);
+
+        // Query
+        let health = queries::get_health(&store).await.unwrap();
+        assert_eq!(health.total_transfers, 3);
+        let status = &health.indexed_chains[0].enrichment;
+        assert_eq!(
+            (status.pending_transfers, status.last_enriched_block),
+            (0, Some(3))
+        );
+
+        let sender = queries::get_wallet_profile(&store, None, &[1; 20], None)
+            .await
+            .unwrap();
+        assert_eq!(sender.first_seen.unwrap().block, 1);
+        assert_eq!(sender.graph_summary.outgoing_count, 2);
+        assert_eq!(sender.graph_summary.total_sent, BigDecimal::from(175));
+
+        let mixer = queries::get_wallet_profile(&store, None, &[3; 20], Some(1))
+            .await
+            .unwrap();
+        assert_eq!(mixer.labels.len(), 1);
+        assert_eq!(mixer.labels[0].entity_name, "Mixer");
+        assert_eq!(mixer.graph_summary.total_received, BigDecimal::from(50));
+
+        // A replay takes the range out of the edges, so re-enriching counts it once
+        let status = queries::replay_enrichment(&store, 1, 2).await.unwrap();
+        assert_eq!(status.pending_transfers, 2);
+        assert_eq!(enrich().await.unwrap(), 2);
+        let sender = queries::get_wallet_profile(&store, None, &[1; 20], None)
+            .await
+            .unwrap();
+        assert_eq!(sender.graph_summary.total_sent, BigDecimal::from(175));
+    }
+
+    #[tokio::test]
+    async fn test_replay_past_the_checkpoint_leaves_it_in_place() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let store = MemoryStore::new();
+        let pipeline = Arc::new(Mutex::new(
+            TransferPipeline::init(&store, None, &config).await.unwrap(),
+        ));
+        let enrich = || enrich_next_batch("ethereum", 1, &config.enrichment, &store, &pipeline);
+
+        let mut batch = vec![test_transfer(1, 1, 2, 100)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        assert_eq!(enrich().await.unwrap(), 1);
+        let mut batch = vec![test_transfer(2, 1, 3, 50), test_transfer(3, 1, 2, 25)];
+        store.insert_transfers_batch(&mut batch).await.unwrap();
+        store.upsert_indexer_state(1, 3, None).await.unwrap();
+
+        // Block 3 is past the checkpoint, so nothing is skipped or rewound
+        let status = queries::replay_enrichment(&store, 1, 3).await.unwrap();
+        assert_eq!(
+            (status.pending_transfers, status.last_enriched_block),
+            (2, Some(1))
+        );
+        assert_eq!(enrich().await.unwrap(), 2);
+        let sender = queries::get_wallet_profile(&store, None, &[1; 20], None)
+            .await
+            .unwrap();
+        assert_eq!(sender.graph_summary.outgoing_count, 2);
+        assert_eq!(sender.graph_summary.total_sent, BigDecimal::from(175));
+    }
+}
@@ -1,8 +1,10 @@
 use sqlx::PgPool;
 use std::collections::HashMap;
 
+use crate::db::store::Store;
+
 /// An entity label loaded from the database or config.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct EntityLabel {
     pub id: i32,
     pub address: Vec<u8>,
@@ -13,34 +15,31 @@ pub struct EntityLabel {
     pub confidence: f32,
 }
 
+/// A label to seed, with the metadata stored alongside it.
+#[derive(Debug, Clone)]
+pub struct LabelSeed<'a> {
+    pub address: &'a [u8],
+    pub chain_id: Option<i64>,
+    pub entity_name: &'a str,
+    pub entity_type: &'a str,
+    pub label_source: &'a str,
+    pub confidence: f32,
+    pub metadata: Option<serde_json::Value>,
+}
+
 /// In-memory index of entity labels keyed by address bytes.
 /// One address can have multiple labels (e.g., "Binance" from config + "Exchange" from heuristic).
+#[derive(Default)]
 pub struct EntityLabelStore {
     by_address: HashMap<Vec<u8>, Vec<EntityLabel>>,
 }
 
 impl EntityLabelStore {
-    /// Load all entity labels from the database into memory.
-    pub async fn load_from_db(pool: &PgPool) -> eyre::Result<Self> {
-        let rows: Vec<(i32, Vec<u8>, Option<i64>, String, String, String, f32)> = sqlx::query_as(
-            "SELECT id, address, chain_id, entity_name, entity_type, label_source, confidence
-             FROM entity_labels",
-        )
-        .fetch_all(pool)
-        .await?;
-
+    /// Load all entity labels from the store into memory.
+    pub async fn load<S: Store>(store: &S) -> eyre::Result<Self> {
         let mut by_address: HashMap<Vec<u8>, Vec<EntityLabel>> = HashMap::new();
-        for (id, address, chain_id, entity_name, entity_type, label_source, confidence) in rows {
-            let label = EntityLabel {
-                id,
-                address: address.clone(),
-                chain_id,
-                entity_name,
-                entity_type,
-                label_source,
-                confidence,
-            };
-            by_address.entry(address).or_default().push(label);
+        for label in store.load_entity_labels().await? {
+            by_address.entry(label.address.clone()).or_default().push(label);
         }
 
         tracing::info!(labels = by_address.len(), "Loaded entity label store");
@@ -72,46 +71,74 @@ impl EntityLabelStore {
             .push(label);
     }
 
-    /// Seed a label into the database and the in-memory store. Returns the label ID.
-    pub async fn seed_label(
-        &mut self,
-        pool: &PgPool,
-        address: &[u8],
-        chain_id: Option<i64>,
-        entity_name: &str,
-        entity_type: &str,
-        label_source: &str,
-        confidence: f32,
-        metadata: Option<serde_json::Value>,
-    ) -> eyre::Result<i32> {
-        let row: (i32,) = sqlx::query_as(
-            "INSERT INTO entity_labels (address, chain_id, entity_name, entity_type, label_source, confidence, metadata)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)
-             ON CONFLICT (address, chain_id, label_source, entity_name) DO UPDATE
-             SET entity_type = $4, confidence = $6, metadata = $7, updated_at = NOW()
-             RETURNING id",
-        )
-        .bind(address)
-        .bind(chain_id)
-        .bind(entity_name)
-        .bind(entity_type)
-        .bind(label_source)
-        .bind(confidence)
-        .bind(metadata)
-        .fetch_one(pool)
-        .await?;
+    /// Seed a label into the store and the in-memory index. Returns the label ID.
+    pub async fn seed_label<S: Store>(&mut self, store: &S, seed: LabelSeed<'_>) -> eyre::Result<i32> {
+        let id = store.upsert_entity_label(&seed).await?;
 
         let label = EntityLabel {
-            id: row.0,
-            address: address.to_vec(),
-            chain_id,
-            entity_name: entity_name.to_string(),
-            entity_type: entity_type.to_string(),
-            label_source: label_source.to_string(),
-            confidence,
+            id,
+            address: seed.address.to_vec(),
+            chain_id: seed.chain_id,
+            entity_name: seed.entity_name.to_string(),
+            entity_type: seed.entity_type.to_string(),
+            label_source: seed.label_source.to_string(),
+            confidence: seed.confidence,
         };
         self.insert_memory(label);
 
-        Ok(row.0)
+        Ok(id)
     }
 }
+
+/// Load every stored entity label.
+pub async fn load_labels(pool: &PgPool) -> eyre::Result<Vec<EntityLabel>> {
+    let rows: Vec<EntityLabel> = sqlx::query_as(
+        "SELECT id, address, chain_id, entity_name, entity_type, label_source, confidence
+         FROM entity_labels",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Labels of one address, limited to those that apply on `chain_id` when given.
+pub async fn labels_for_address(
+    pool: &PgPool,
+    address: &[u8],
+    chain_id: Option<i64>,
+) -> eyre::Result<Vec<EntityLabel>> {
+    let rows: Vec<EntityLabel> = sqlx::query_as(
+        "SELECT id, address, chain_id, entity_name, entity_type, label_source, confidence
+         FROM entity_labels
+         WHERE address = $1 AND ($2::BIGINT IS NULL OR chain_id = $2 OR chain_id IS NULL)",
+    )
+    .bind(address)
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Insert or update a label with its metadata. Returns the label ID.
+pub async fn upsert_label(pool: &PgPool, seed: &LabelSeed<'_>) -> eyre::Result<i32> {
+    let (id,): (i32,) = sqlx::query_as(
+        "INSERT INTO entity_labels (address, chain_id, entity_name, entity_type, label_source, confidence, metadata)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (address, chain_id, label_source, entity_name) DO UPDATE
+         SET entity_type = $4, confidence = $6, metadata = $7, updated_at = NOW()
+         RETURNING id",
+    )
+    .bind(seed.address)
+    .bind(seed.chain_id)
+    .bind(seed.entity_name)
+    .bind(seed.entity_type)
+    .bind(seed.label_source)
+    .bind(seed.confidence)
+    .bind(&seed.metadata)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(id)
+}
@@ -1,91 +1,130 @@
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use std::collections::HashSet;
 
-use crate::indexer::types::StablecoinTransfer;
+use crate::db::store::Store;
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
 
-use super::label_store::EntityLabelStore;
+use super::label_store::{EntityLabel, EntityLabelStore};
 
 /// Match a batch of transfers against known entity labels.
-/// For each transfer where from_address or to_address has a known label,
-/// insert a record into transfer_entity_flags.
-pub async fn attribute_entities(
-    pool: &PgPool,
+/// For each stored transfer where from_address or to_address has a known label,
+/// insert a record into transfer_entity_flags. All flags are written in one statement.
+pub async fn attribute_entities<S: Store>(
+    store: &S,
     transfers: &[StablecoinTransfer],
     label_store: &EntityLabelStore,
 ) -> eyre::Result<u64> {
-    let mut attributed = 0u64;
+    let mut flags: Vec<(TransferKey, i32, &str)> = Vec::new();
 
     for transfer in transfers {
-        // Check from_address
-        if let Some(labels) = label_store.lookup(&transfer.from_address) {
-            for label in labels {
-                // Check chain scope: label applies if chain_id is None (global) or matches
-                if label.chain_id.is_some() && label.chain_id != Some(transfer.chain_id) {
-                    continue;
-                }
-                insert_flag(pool, transfer, label.id, "from").await?;
-                attributed += 1;
-
-                tracing::debug!(
-                    entity = %label.entity_name,
-                    entity_type = %label.entity_type,
-                    side = "from",
-                    "Attributed entity to transfer"
-                );
-            }
+        let Some(key) = transfer.key() else {
+            continue;
+        };
+        for (label, side) in matching_labels(transfer, label_store) {
+            flags.push((key, label.id, side));
+
+            tracing::debug!(
+                entity = %label.entity_name,
+                entity_type = %label.entity_type,
+                side,
+                "Attributed entity to transfer"
+            );
         }
+    }
 
-        // Check to_address
-        if let Some(labels) = label_store.lookup(&transfer.to_address) {
+    store.insert_entity_flags(&flags).await?;
+    Ok(flags.len() as u64)
+}
+
+/// Labels that apply to either side of a transfer, paired with the side ("from" or "to").
+/// A label applies if its chain_id is None (global) or matches the transfer's chain.
+pub fn matching_labels<'a>(
+    transfer: &StablecoinTransfer,
+    label_store: &'a EntityLabelStore,
+) -> Vec<(&'a EntityLabel, &'static str)> {
+    let mut matches = Vec::new();
+
+    for (address, side) in [(&transfer.from_address, "from"), (&transfer.to_address, "to")] {
+        if let Some(labels) = label_store.lookup(address) {
             for label in labels {
                 if label.chain_id.is_some() && label.chain_id != Some(transfer.chain_id) {
                     continue;
                 }
-                insert_flag(pool, transfer, label.id, "to").await?;
-                attributed += 1;
-
-                tracing::debug!(
-                    entity = %label.entity_name,
-                    entity_type = %label.entity_type,
-                    side = "to",
-                    "Attributed entity to transfer"
-                );
+                matches.push((label, side));
             }
         }
     }
 
-    Ok(attributed)
+    matches
 }
 
-/// Insert a transfer_entity_flag record linking a transfer to an entity label.
-async fn insert_flag(
-    pool: &PgPool,
-    transfer: &StablecoinTransfer,
-    entity_label_id: i32,
-    side: &str,
-) -> eyre::Result<()> {
-    // Look up the transfer ID
-    let row: Option<(i64,)> = sqlx::query_as(
-        "SELECT id FROM transfers
-         WHERE chain_id = $1 AND tx_hash = $2 AND log_index = $3",
+/// Insert transfer_entity_flags records as (transfer, entity_label_id, side) in bulk.
+pub async fn insert_flags(pool: &PgPool, flags: &[(TransferKey, i32, &str)]) -> eyre::Result<u64> {
+    if flags.is_empty() {
+        return Ok(0);
+    }
+
+    let transfer_ids: Vec<i64> = flags.iter().map(|(key, _, _)| key.id).collect();
+    let chain_ids: Vec<i64> = flags.iter().map(|(key, _, _)| key.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> = flags.iter().map(|(key, _, _)| key.block_timestamp).collect();
+    let label_ids: Vec<i32> = flags.iter().map(|(_, label, _)| *label).collect();
+    let sides: Vec<&str> = flags.iter().map(|(_, _, side)| *side).collect();
+
+    let result = sqlx::query(
+        "INSERT INTO transfer_entity_flags (transfer_id, chain_id, block_timestamp, entity_label_id, side)
+         SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::INTEGER[], $5::TEXT[])
+         ON CONFLICT (transfer_id, entity_label_id, side, chain_id, block_timestamp) DO NOTHING",
     )
-    .bind(transfer.chain_id)
-    .bind(&transfer.tx_hash)
-    .bind(transfer.log_index)
-    .fetch_optional(pool)
+    .bind(&transfer_ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&label_ids)
+    .bind(&sides)
+    .execute(pool)
     .await?;
 
-    if let Some((transfer_id,)) = row {
-        sqlx::query(
-            "INSERT INTO transfer_entity_flags (transfer_id, entity_label_id, side)
-             VALUES ($1, $2, $3)
-             ON CONFLICT (transfer_id, entity_label_id, side) DO NOTHING",
-        )
-        .bind(transfer_id)
-        .bind(entity_label_id)
-        .bind(side)
-        .execute(pool)
-        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete transfer_entity_flags records as (transfer_id, entity_label_id, side) in bulk.
+pub async fn delete_flags(pool: &PgPool, flags: &[(i64, i32, &str)]) -> eyre::Result<u64> {
+    if flags.is_empty() {
+        return Ok(0);
     }
 
-    Ok(())
+    let transfer_ids: Vec<i64> = flags.iter().map(|(id, _, _)| *id).collect();
+    let label_ids: Vec<i32> = flags.iter().map(|(_, label, _)| *label).collect();
+    let sides: Vec<&str> = flags.iter().map(|(_, _, side)| *side).collect();
+
+    let result = sqlx::query(
+        "DELETE FROM transfer_entity_flags f
+         USING UNNEST($1::BIGINT[], $2::INTEGER[], $3::TEXT[]) AS k(transfer_id, entity_label_id, side)
+         WHERE f.transfer_id = k.transfer_id
+           AND f.entity_label_id = k.entity_label_id
+           AND f.side = k.side",
+    )
+    .bind(&transfer_ids)
+    .bind(&label_ids)
+    .bind(&sides)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Load the stored flags for a set of transfers as (transfer_id, entity_label_id, side).
+pub async fn load_flags(
+    pool: &PgPool,
+    transfer_ids: &[i64],
+) -> eyre::Result<HashSet<(i64, i32, String)>> {
+    let rows: Vec<(i64, i32, String)> = sqlx::query_as(
+        "SELECT transfer_id, entity_label_id, side FROM transfer_entity_flags
+         WHERE transfer_id = ANY($1)",
+    )
+    .bind(transfer_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().collect())
 }
@@ -2,7 +2,9 @@ use alloy::primitives::Address;
 use sqlx::PgPool;
 use std::str::FromStr;
 
-use super::label_store::EntityLabelStore;
+use crate::db::store::Store;
+
+use super::label_store::{EntityLabelStore, LabelSeed};
 
 /// A parsed OFAC SDN entry with crypto addresses.
 #[derive(Debug, Clone)]
@@ -52,8 +54,10 @@ pub fn parse_ofac_csv(path: &str) -> eyre::Result<Vec<OfacEntry>> {
 }
 
 /// Seed OFAC entries into the watchlist_entries table and entity_labels table.
-pub async fn seed_ofac_entries(
-    pool: &PgPool,
+/// The watchlist only lives in Postgres and is skipped without a pool.
+pub async fn seed_ofac_entries<S: Store>(
+    store: &S,
+    postgres: Option<&PgPool>,
     label_store: &mut EntityLabelStore,
     entries: &[OfacEntry],
 ) -> eyre::Result<usize> {
@@ -67,19 +71,21 @@ pub async fn seed_ofac_entries(
             };
             let addr_bytes = address.as_slice();
 
-            // Insert into watchlist_entries
-            sqlx::query(
-                "INSERT INTO watchlist_entries (list_name, address, entity_name, sdn_id, program)
-                 VALUES ('ofac_sdn', $1, $2, $3, $4)
-                 ON CONFLICT (list_name, address) DO UPDATE
-                 SET entity_name = $2, sdn_id = $3, program = $4",
-            )
-            .bind(addr_bytes)
-            .bind(&entry.entity_name)
-            .bind(&entry.sdn_id)
-            .bind(&entry.program)
-            .execute(pool)
-            .await?;
+            // Insert into watchlist_entries, which only Postgres keeps
+            if let Some(pool) = postgres {
+                sqlx::query(
+                    "INSERT INTO watchlist_entries (list_name, address, entity_name, sdn_id, program)
+                     VALUES ('ofac_sdn', $1, $2, $3, $4)
+                     ON CONFLICT (list_name, address) DO UPDATE
+                     SET entity_name = $2, sdn_id = $3, program = $4",
+                )
+                .bind(addr_bytes)
+                .bind(&entry.entity_name)
+                .bind(&entry.sdn_id)
+                .bind(&entry.program)
+                .execute(pool)
+                .await?;
+            }
 
             // Also create an entity label for fast in-memory lookups
             let metadata = serde_json::json!({
@@ -89,14 +95,16 @@ pub async fn seed_ofac_entries(
 
             label_store
                 .seed_label(
-                    pool,
-                    addr_bytes,
-                    None, // applies to all chains
-                    &entry.entity_name,
-                    "sanctioned",
-                    "ofac_sdn",
-                    1.0,
-                    Some(metadata),
+                    store,
+                    LabelSeed {
+                        address: addr_bytes,
+                        chain_id: None, // applies to all chains
+                        entity_name: &entry.entity_name,
+                        entity_type: "sanctioned",
+                        label_source: "ofac_sdn",
+                        confidence: 1.0,
+                        metadata: Some(metadata),
+                    },
                 )
                 .await?;
 
@@ -109,8 +117,8 @@ pub async fn seed_ofac_entries(
 }
 
 /// Seed manual labels from config into the database and label store.
-pub async fn seed_manual_labels(
-    pool: &PgPool,
+pub async fn seed_manual_labels<S: Store>(
+    store: &S,
     label_store: &mut EntityLabelStore,
     labels: &[crate::config::ManualLabelConfig],
 ) -> eyre::Result<usize> {
@@ -131,14 +139,16 @@ pub async fn seed_manual_labels(
 
         label_store
             .seed_label(
-                pool,
-                address.as_slice(),
-                label_cfg.chain_id,
-                &label_cfg.entity_name,
-                &label_cfg.entity_type,
-                &label_cfg.source,
-                label_cfg.confidence,
-                None,
+                store,
+                LabelSeed {
+                    address: address.as_slice(),
+                    chain_id: label_cfg.chain_id,
+                    entity_name: &label_cfg.entity_name,
+                    entity_type: &label_cfg.entity_type,
+                    label_source: &label_cfg.source,
+                    confidence: label_cfg.confidence,
+                    metadata: None,
+                },
             )
             .await?;
 
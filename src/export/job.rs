@@ -0,0 +1,237 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::ExportConfig;
+use crate::db::repository;
+use crate::export::parquet::{self, Dataset, SCHEMA_VERSION};
+
+/// Which datasets and chains to export, and where to.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub output_dir: PathBuf,
+    pub chain_ids: Vec<i64>,
+    pub datasets: Vec<Dataset>,
+    pub batch_size: i64,
+}
+
+/// Rows and files written for one dataset on one chain.
+#[derive(Debug, Default, Serialize)]
+pub struct DatasetReport {
+    pub dataset: &'static str,
+    pub chain_id: i64,
+    pub rows: u64,
+    pub files: u64,
+    pub last_exported_id: i64,
+}
+
+/// Summary of an export run.
+#[derive(Debug, Default, Serialize)]
+pub struct ExportReport {
+    pub output_dir: String,
+    pub datasets: Vec<DatasetReport>,
+}
+
+/// Export every selected dataset and chain from its high-water mark onwards.
+///
+/// Files land at `{dataset}/chain_id={chain}/date={YYYY-MM-DD}/part-{first_id}-{last_id}.parquet`.
+/// Names are derived from the batch's id range, so a run that dies after writing files but
+/// before advancing the mark rewrites the same files on retry instead of duplicating rows.
+/// Rows are exported once: later updates to an already-exported row (e.g. an anomaly
+/// resolved afterwards) are not re-exported.
+pub async fn run_export(pool: &PgPool, options: &ExportOptions) -> eyre::Result<ExportReport> {
+    let mut report = ExportReport {
+        output_dir: options.output_dir.display().to_string(),
+        ..Default::default()
+    };
+
+    for &dataset in &options.datasets {
+        for &chain_id in &options.chain_ids {
+            let entry = export_dataset(pool, options, dataset, chain_id).await?;
+            if entry.rows > 0 {
+                tracing::info!(
+                    dataset = dataset.as_str(),
+                    chain_id,
+                    rows = entry.rows,
+                    files = entry.files,
+                    last_exported_id = entry.last_exported_id,
+                    "Exported to Parquet"
+                );
+            }
+            report.datasets.push(entry);
+        }
+    }
+
+    write_manifest(pool, &options.output_dir).await?;
+    Ok(report)
+}
+
+async fn export_dataset(
+    pool: &PgPool,
+    options: &ExportOptions,
+    dataset: Dataset,
+    chain_id: i64,
+) -> eyre::Result<DatasetReport> {
+    let mut entry = DatasetReport {
+        dataset: dataset.as_str(),
+        chain_id,
+        last_exported_id: repository::get_export_mark(pool, dataset.as_str(), chain_id).await?,
+        ..Default::default()
+    };
+
+    while let Some(batch) =
+        parquet::fetch_batch(pool, dataset, chain_id, entry.last_exported_id, options.batch_size)
+            .await?
+    {
+        let dir = options
+            .output_dir
+            .join(dataset.as_str())
+            .join(format!("chain_id={}", chain_id));
+        let file_name = format!("part-{:012}-{:012}.parquet", batch.first_id, batch.last_id);
+        let files: Vec<(PathBuf, _)> = batch
+            .by_date
+            .into_iter()
+            .map(|(date, records)| {
+                let path = dir.join(format!("date={}", date.format("%Y-%m-%d"))).join(&file_name);
+                (path, records)
+            })
+            .collect();
+        let file_count = files.len() as u64;
+
+        // Parquet encoding is CPU-bound and the writer does blocking file IO
+        tokio::task::spawn_blocking(move || {
+            files
+                .iter()
+                .try_for_each(|(path, records)| parquet::write_parquet(path, records))
+        })
+        .await??;
+
+        repository::advance_export_mark(
+            pool,
+            dataset.as_str(),
+            chain_id,
+            batch.last_id,
+            batch.rows as i64,
+        )
+        .await?;
+
+        entry.rows += batch.rows as u64;
+        entry.files += file_count;
+        entry.last_exported_id = batch.last_id;
+
+        if (batch.rows as i64) < options.batch_size {
+            break;
+        }
+    }
+
+    Ok(entry)
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    format_version: u32,
+    generated_at: DateTime<Utc>,
+    path_template: &'static str,
+    compression: &'static str,
+    datasets: BTreeMap<&'static str, DatasetManifest>,
+}
+
+#[derive(Serialize)]
+struct DatasetManifest {
+    columns: Vec<ColumnManifest>,
+    chains: Vec<ChainManifest>,
+}
+
+#[derive(Serialize)]
+struct ColumnManifest {
+    name: String,
+    #[serde(rename = "type")]
+    data_type: String,
+    nullable: bool,
+}
+
+#[derive(Serialize)]
+struct ChainManifest {
+    chain_id: i64,
+    last_exported_id: i64,
+    rows_exported: i64,
+}
+
+/// Write `manifest.json` describing every dataset's schema and high-water marks.
+/// Covers all datasets, not just the ones exported on this run.
+async fn write_manifest(pool: &PgPool, output_dir: &Path) -> eyre::Result<()> {
+    let mut datasets = BTreeMap::new();
+    for dataset in Dataset::ALL {
+        let columns = dataset
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| ColumnManifest {
+                name: f.name().clone(),
+                data_type: f.data_type().to_string(),
+                nullable: f.is_nullable(),
+            })
+            .collect();
+        let chains = repository::get_export_marks(pool, dataset.as_str())
+            .await?
+            .into_iter()
+            .map(|(chain_id, last_exported_id, rows_exported)| ChainManifest {
+                chain_id,
+                last_exported_id,
+                rows_exported,
+            })
+            .collect();
+        datasets.insert(dataset.as_str(), DatasetManifest { columns, chains });
+    }
+
+    let manifest = Manifest {
+        format_version: SCHEMA_VERSION,
+        generated_at: Utc::now(),
+        path_template:
+            "{dataset}/chain_id={chain_id}/date={YYYY-MM-DD}/part-{first_id}-{last_id}.parquet",
+        compression: "snappy",
+        datasets,
+    };
+
+    std::fs::create_dir_all(output_dir)?;
+    let tmp = output_dir.join("manifest.json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&manifest)?)?;
+    std::fs::rename(&tmp, output_dir.join("manifest.json"))?;
+
+    Ok(())
+}
+
+/// Scheduled export loop: exports all datasets for the configured chains every interval.
+pub async fn run_export_scheduler(
+    chain_ids: Vec<i64>,
+    config: ExportConfig,
+    pool: PgPool,
+    shutdown: CancellationToken,
+) -> eyre::Result<()> {
+    let interval = Duration::from_secs(config.interval_secs);
+    let options = ExportOptions {
+        output_dir: PathBuf::from(&config.output_dir),
+        chain_ids,
+        datasets: Dataset::ALL.to_vec(),
+        batch_size: config.batch_size,
+    };
+    tracing::info!(output_dir = %config.output_dir, "Starting scheduled Parquet export");
+
+    while !shutdown.is_cancelled() {
+        if let Err(e) = run_export(&pool, &options).await {
+            tracing::error!(error = %e, "Parquet export failed");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    tracing::info!("Parquet export stopped");
+    Ok(())
+}
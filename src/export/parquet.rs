@@ -0,0 +1,597 @@
+use arrow::array::{
+    ArrayRef, BooleanArray, Decimal128Array, Float32Array, Int16Array, Int32Array, Int64Array,
+    ListBuilder, StringArray, StringBuilder, TimestampMicrosecondArray,
+};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, NaiveDate, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Bumped whenever a dataset's columns change; recorded in the manifest.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A table exported to Parquet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Dataset {
+    Transfers,
+    DefiEvents,
+    Anomalies,
+    EntityFlags,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 4] = [
+        Dataset::Transfers,
+        Dataset::DefiEvents,
+        Dataset::Anomalies,
+        Dataset::EntityFlags,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Transfers => "transfers",
+            Self::DefiEvents => "defi_events",
+            Self::Anomalies => "anomalies",
+            Self::EntityFlags => "entity_flags",
+        }
+    }
+
+    /// Arrow schema of the exported files. Hashes and addresses are 0x-prefixed hex,
+    /// raw token amounts are Decimal128(38, 0) and timestamps are UTC microseconds.
+    pub fn schema(&self) -> SchemaRef {
+        let fields = match self {
+            Self::Transfers => vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("chain_id", DataType::Int64, false),
+                Field::new("block_number", DataType::Int64, false),
+                Field::new("block_hash", DataType::Utf8, false),
+                Field::new("tx_hash", DataType::Utf8, false),
+                Field::new("log_index", DataType::Int32, false),
+                Field::new("token_address", DataType::Utf8, false),
+                Field::new("from_address", DataType::Utf8, false),
+                Field::new("to_address", DataType::Utf8, false),
+                Field::new("amount", amount_type(), true),
+                Field::new("token_symbol", DataType::Utf8, false),
+                Field::new("token_decimals", DataType::Int16, false),
+                Field::new("block_timestamp", timestamp_type(), false),
+            ],
+            Self::DefiEvents => vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("chain_id", DataType::Int64, false),
+                Field::new("block_number", DataType::Int64, false),
+                Field::new("tx_hash", DataType::Utf8, false),
+                Field::new("log_index", DataType::Int32, false),
+                Field::new("protocol", DataType::Utf8, false),
+                Field::new("event_type", DataType::Utf8, false),
+                Field::new("contract_address", DataType::Utf8, false),
+                Field::new("account", DataType::Utf8, true),
+                Field::new("token_in", DataType::Utf8, true),
+                Field::new("token_out", DataType::Utf8, true),
+                Field::new("amount_in", amount_type(), true),
+                Field::new("amount_out", amount_type(), true),
+                Field::new("block_timestamp", timestamp_type(), false),
+                Field::new("raw_data", DataType::Utf8, true),
+            ],
+            Self::Anomalies => vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("transfer_id", DataType::Int64, true),
+                Field::new("chain_id", DataType::Int64, false),
+                Field::new("block_timestamp", timestamp_type(), false),
+                Field::new("anomaly_type", DataType::Utf8, false),
+                Field::new("risk_score", DataType::Float32, false),
+                Field::new(
+                    "flags",
+                    DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                    false,
+                ),
+                Field::new("details", DataType::Utf8, true),
+                Field::new("address", DataType::Utf8, true),
+                Field::new("detected_at", timestamp_type(), false),
+                Field::new("resolved", DataType::Boolean, false),
+            ],
+            Self::EntityFlags => vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("transfer_id", DataType::Int64, false),
+                Field::new("chain_id", DataType::Int64, false),
+                Field::new("block_timestamp", timestamp_type(), false),
+                Field::new("entity_label_id", DataType::Int32, false),
+                Field::new("entity_name", DataType::Utf8, false),
+                Field::new("entity_type", DataType::Utf8, false),
+                Field::new("label_source", DataType::Utf8, false),
+                Field::new("side", DataType::Utf8, false),
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+}
+
+fn amount_type() -> DataType {
+    DataType::Decimal128(38, 0)
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+/// Rows of one dataset after a high-water mark, split by UTC date of `block_timestamp`.
+pub struct ExportBatch {
+    pub first_id: i64,
+    pub last_id: i64,
+    pub rows: usize,
+    pub by_date: BTreeMap<NaiveDate, RecordBatch>,
+}
+
+/// Fetch up to `limit` rows of a dataset for a chain with id above `after_id`.
+/// Returns None once the dataset is exhausted.
+pub async fn fetch_batch(
+    pool: &PgPool,
+    dataset: Dataset,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Option<ExportBatch>> {
+    let (ids, timestamps, columns) = match dataset {
+        Dataset::Transfers => fetch_transfers(pool, chain_id, after_id, limit).await?,
+        Dataset::DefiEvents => fetch_defi_events(pool, chain_id, after_id, limit).await?,
+        Dataset::Anomalies => fetch_anomalies(pool, chain_id, after_id, limit).await?,
+        Dataset::EntityFlags => fetch_entity_flags(pool, chain_id, after_id, limit).await?,
+    };
+
+    let (Some(&first_id), Some(&last_id)) = (ids.first(), ids.last()) else {
+        return Ok(None);
+    };
+
+    let batch = RecordBatch::try_new(dataset.schema(), columns)?;
+    let dates: Vec<NaiveDate> = timestamps.iter().map(|ts| ts.date_naive()).collect();
+
+    let mut by_date = BTreeMap::new();
+    let mut distinct: Vec<NaiveDate> = dates.clone();
+    distinct.sort();
+    distinct.dedup();
+    for date in distinct {
+        let mask: BooleanArray = dates.iter().map(|d| Some(*d == date)).collect();
+        by_date.insert(date, filter_record_batch(&batch, &mask)?);
+    }
+
+    Ok(Some(ExportBatch {
+        first_id,
+        last_id,
+        rows: ids.len(),
+        by_date,
+    }))
+}
+
+/// Write a record batch as a Snappy-compressed Parquet file.
+/// The file is written next to its destination and renamed into place, so readers
+/// never see a partial file and a retried export overwrites the same name.
+pub fn write_parquet(path: &Path, batch: &RecordBatch) -> eyre::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("parquet.tmp");
+    let file = std::fs::File::create(&tmp)?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+    writer.write(batch)?;
+    writer.close()?;
+    std::fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+type Columns = (Vec<i64>, Vec<DateTime<Utc>>, Vec<ArrayRef>);
+
+/// Row shape of an exported transfer.
+type TransferRow = (
+    i64,
+    i64,
+    Vec<u8>,
+    Vec<u8>,
+    i32,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    BigDecimal,
+    String,
+    i16,
+    DateTime<Utc>,
+);
+
+async fn fetch_transfers(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Columns> {
+    let rows: Vec<TransferRow> = sqlx::query_as(
+        "SELECT id, block_number, block_hash, tx_hash, log_index, token_address, from_address,
+                to_address, amount, token_symbol, token_decimals, block_timestamp
+         FROM transfers
+         WHERE chain_id = $1 AND id > $2
+         ORDER BY id
+         LIMIT $3",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    transfer_columns(chain_id, &rows)
+}
+
+/// Export columns of fetched transfers, in schema order.
+fn transfer_columns(chain_id: i64, rows: &[TransferRow]) -> eyre::Result<Columns> {
+    let ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+    let timestamps: Vec<DateTime<Utc>> = rows.iter().map(|r| r.11).collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(ids.clone())),
+        Arc::new(Int64Array::from(vec![chain_id; rows.len()])),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.1))),
+        hex_column(rows.iter().map(|r| Some(r.2.as_slice()))),
+        hex_column(rows.iter().map(|r| Some(r.3.as_slice()))),
+        Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.4))),
+        hex_column(rows.iter().map(|r| Some(r.5.as_slice()))),
+        hex_column(rows.iter().map(|r| Some(r.6.as_slice()))),
+        hex_column(rows.iter().map(|r| Some(r.7.as_slice()))),
+        amount_column(rows.iter().map(|r| Some(&r.8)))?,
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.9.as_str()))),
+        Arc::new(Int16Array::from_iter_values(rows.iter().map(|r| r.10))),
+        timestamp_column(timestamps.iter()),
+    ];
+
+    Ok((ids, timestamps, columns))
+}
+
+/// Row shape of an exported DeFi event.
+type DefiEventRow = (
+    i64,
+    i64,
+    Vec<u8>,
+    i32,
+    String,
+    String,
+    Vec<u8>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<BigDecimal>,
+    Option<BigDecimal>,
+    DateTime<Utc>,
+    Option<serde_json::Value>,
+);
+
+async fn fetch_defi_events(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Columns> {
+    let rows: Vec<DefiEventRow> = sqlx::query_as(
+        "SELECT id, block_number, tx_hash, log_index, protocol, event_type, contract_address,
+                account, token_in, token_out, amount_in, amount_out, block_timestamp, raw_data
+         FROM defi_events
+         WHERE chain_id = $1 AND id > $2
+         ORDER BY id
+         LIMIT $3",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    defi_event_columns(chain_id, &rows)
+}
+
+/// Export columns of fetched DeFi events, in schema order.
+fn defi_event_columns(chain_id: i64, rows: &[DefiEventRow]) -> eyre::Result<Columns> {
+    let ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+    let timestamps: Vec<DateTime<Utc>> = rows.iter().map(|r| r.12).collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(ids.clone())),
+        Arc::new(Int64Array::from(vec![chain_id; rows.len()])),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.1))),
+        hex_column(rows.iter().map(|r| Some(r.2.as_slice()))),
+        Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.3))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.4.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.5.as_str()))),
+        hex_column(rows.iter().map(|r| Some(r.6.as_slice()))),
+        hex_column(rows.iter().map(|r| r.7.as_deref())),
+        hex_column(rows.iter().map(|r| r.8.as_deref())),
+        hex_column(rows.iter().map(|r| r.9.as_deref())),
+        amount_column(rows.iter().map(|r| r.10.as_ref()))?,
+        amount_column(rows.iter().map(|r| r.11.as_ref()))?,
+        timestamp_column(timestamps.iter()),
+        Arc::new(StringArray::from(
+            rows.iter()
+                .map(|r| r.13.as_ref().map(|v| v.to_string()))
+                .collect::<Vec<_>>(),
+        )),
+    ];
+
+    Ok((ids, timestamps, columns))
+}
+
+/// Row shape of an exported anomaly.
+type AnomalyRow = (
+    i64,
+    Option<i64>,
+    DateTime<Utc>,
+    String,
+    f32,
+    Vec<String>,
+    Option<serde_json::Value>,
+    Option<Vec<u8>>,
+    DateTime<Utc>,
+    bool,
+);
+
+async fn fetch_anomalies(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Columns> {
+    let rows: Vec<AnomalyRow> = sqlx::query_as(
+        "SELECT id, transfer_id, block_timestamp, anomaly_type, risk_score, flags, details,
+                address, detected_at, resolved
+         FROM anomalies
+         WHERE chain_id = $1 AND id > $2
+         ORDER BY id
+         LIMIT $3",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    anomaly_columns(chain_id, &rows)
+}
+
+/// Export columns of fetched anomalies, in schema order.
+fn anomaly_columns(chain_id: i64, rows: &[AnomalyRow]) -> eyre::Result<Columns> {
+    let ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+    let timestamps: Vec<DateTime<Utc>> = rows.iter().map(|r| r.2).collect();
+
+    let mut flags = ListBuilder::new(StringBuilder::new());
+    for row in rows {
+        for flag in &row.5 {
+            flags.values().append_value(flag);
+        }
+        flags.append(true);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(ids.clone())),
+        Arc::new(Int64Array::from(rows.iter().map(|r| r.1).collect::<Vec<_>>())),
+        Arc::new(Int64Array::from(vec![chain_id; rows.len()])),
+        timestamp_column(timestamps.iter()),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.3.as_str()))),
+        Arc::new(Float32Array::from_iter_values(rows.iter().map(|r| r.4))),
+        Arc::new(flags.finish()),
+        Arc::new(StringArray::from(
+            rows.iter()
+                .map(|r| r.6.as_ref().map(|v| v.to_string()))
+                .collect::<Vec<_>>(),
+        )),
+        hex_column(rows.iter().map(|r| r.7.as_deref())),
+        timestamp_column(rows.iter().map(|r| &r.8)),
+        Arc::new(BooleanArray::from(rows.iter().map(|r| r.9).collect::<Vec<_>>())),
+    ];
+
+    Ok((ids, timestamps, columns))
+}
+
+/// Row shape of an exported entity flag, with its label.
+type EntityFlagRow = (i64, i64, DateTime<Utc>, i32, String, String, String, String);
+
+async fn fetch_entity_flags(
+    pool: &PgPool,
+    chain_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> eyre::Result<Columns> {
+    let rows: Vec<EntityFlagRow> = sqlx::query_as(
+        "SELECT f.id, f.transfer_id, f.block_timestamp, f.entity_label_id, el.entity_name,
+                el.entity_type, el.label_source, f.side
+         FROM transfer_entity_flags f
+         JOIN entity_labels el ON el.id = f.entity_label_id
+         WHERE f.chain_id = $1 AND f.id > $2
+         ORDER BY f.id
+         LIMIT $3",
+    )
+    .bind(chain_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    entity_flag_columns(chain_id, &rows)
+}
+
+/// Export columns of fetched entity flags, in schema order.
+fn entity_flag_columns(chain_id: i64, rows: &[EntityFlagRow]) -> eyre::Result<Columns> {
+    let ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+    let timestamps: Vec<DateTime<Utc>> = rows.iter().map(|r| r.2).collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(ids.clone())),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.1))),
+        Arc::new(Int64Array::from(vec![chain_id; rows.len()])),
+        timestamp_column(timestamps.iter()),
+        Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.3))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.4.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.5.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.6.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.7.as_str()))),
+    ];
+
+    Ok((ids, timestamps, columns))
+}
+
+fn hex_column<'a>(values: impl Iterator<Item = Option<&'a [u8]>>) -> ArrayRef {
+    Arc::new(StringArray::from(
+        values
+            .map(|v| v.map(|b| format!("0x{}", hex::encode(b))))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Raw token amounts are integers. A uint256 that does not fit Decimal128(38, 0) is exported
+/// as null rather than truncated, so one spam token cannot stall the export.
+fn amount_column<'a>(values: impl Iterator<Item = Option<&'a BigDecimal>>) -> eyre::Result<ArrayRef> {
+    let amounts: Vec<Option<i128>> = values
+        .map(|v| {
+            v.and_then(|d| {
+                let amount = d
+                    .with_scale(0)
+                    .to_i128()
+                    .filter(|n| n.unsigned_abs() < 10u128.pow(38));
+                if amount.is_none() {
+                    tracing::warn!(amount = %d, "Amount exceeds Decimal128(38, 0), exporting null");
+                }
+                amount
+            })
+        })
+        .collect();
+
+    Ok(Arc::new(
+        Decimal128Array::from(amounts).with_precision_and_scale(38, 0)?,
+    ))
+}
+
+fn timestamp_column<'a>(values: impl Iterator<Item = &'a DateTime<Utc>>) -> ArrayRef {
+    Arc::new(
+        TimestampMicrosecondArray::from_iter_values(values.map(|ts| ts.timestamp_micros()))
+            .with_timezone("UTC"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, RecordBatchReader};
+    use chrono::TimeZone;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn ts() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()
+    }
+
+    fn columns(dataset: Dataset) -> Columns {
+        match dataset {
+            Dataset::Transfers => transfer_columns(
+                1,
+                &[(
+                    7,
+                    100,
+                    vec![0xab; 32],
+                    vec![1; 32],
+                    0,
+                    vec![9; 20],
+                    vec![1; 20],
+                    vec![2; 20],
+                    BigDecimal::from(1_000_000),
+                    "USDC".to_string(),
+                    6,
+                    ts(),
+                )],
+            ),
+            Dataset::DefiEvents => defi_event_columns(
+                1,
+                &[(
+                    8,
+                    100,
+                    vec![1; 32],
+                    2,
+                    "uniswap_v3".to_string(),
+                    "swap".to_string(),
+                    vec![3; 20],
+                    Some(vec![1; 20]),
+                    Some(vec![9; 20]),
+                    None,
+                    Some(BigDecimal::from(5)),
+                    None,
+                    ts(),
+                    Some(serde_json::json!({ "fee": 500 })),
+                )],
+            ),
+            Dataset::Anomalies => anomaly_columns(
+                1,
+                &[(
+                    9,
+                    Some(7),
+                    ts(),
+                    "large_transfer".to_string(),
+                    0.5,
+                    vec!["sanctioned".to_string()],
+                    None,
+                    Some(vec![1; 20]),
+                    ts(),
+                    false,
+                )],
+            ),
+            Dataset::EntityFlags => entity_flag_columns(
+                1,
+                &[(
+                    10,
+                    7,
+                    ts(),
+                    4,
+                    "Binance".to_string(),
+                    "exchange".to_string(),
+                    "manual".to_string(),
+                    "to".to_string(),
+                )],
+            ),
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn test_columns_match_schema_and_round_trip_through_parquet() {
+        let dir = std::env::temp_dir().join(format!("chainwatch-parquet-{}", std::process::id()));
+        for dataset in Dataset::ALL {
+            let (ids, timestamps, columns) = columns(dataset);
+            assert_eq!((ids.len(), timestamps.len()), (1, 1));
+
+            // try_new checks every column's type and nullability against the schema
+            let batch = RecordBatch::try_new(dataset.schema(), columns).unwrap();
+            let path = dir.join(format!("{}.parquet", dataset.as_str()));
+            write_parquet(&path, &batch).unwrap();
+
+            let file = std::fs::File::open(&path).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!(reader.schema(), dataset.schema(), "{}", dataset.as_str());
+            let read: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, vec![batch]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_amounts_beyond_decimal128_export_as_null() {
+        let max = BigDecimal::from(10u128.pow(38) - 1);
+        let too_big = BigDecimal::from(10u128.pow(38));
+        let column = amount_column([Some(&max), Some(&too_big), None].into_iter()).unwrap();
+        let amounts = column.as_any().downcast_ref::<Decimal128Array>().unwrap();
+
+        assert_eq!(amounts.value(0), 10i128.pow(38) - 1);
+        assert!(amounts.is_null(1) && amounts.is_null(2));
+    }
+}
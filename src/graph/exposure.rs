@@ -0,0 +1,412 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::config::SanctionsExposureConfig;
+use crate::entity::label_store::EntityLabelStore;
+use crate::indexer::types::StablecoinTransfer;
+
+/// Stored exposure of one wallet on one chain.
+#[derive(Debug, Clone)]
+pub struct WalletExposure {
+    pub chain_id: i64,
+    pub inbound: f64,
+    pub inbound_hops: Option<i16>,
+    pub outbound: f64,
+    pub outbound_hops: Option<i16>,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Which way exposure flows along the edges.
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    /// Funds received from sanctioned addresses, followed downstream.
+    Inbound,
+    /// Funds sent towards sanctioned addresses, followed upstream.
+    Outbound,
+}
+
+/// Recompute sanctions exposure for every wallet within `max_hops` of a sanctioned
+/// address on the chain, replacing the stored results. Returns the number of wallets stored.
+///
+/// Exposure starts at 1 on each sanctioned address and moves one hop per step. A wallet's
+/// inbound exposure is the sum over its incoming edges of the edge's share of everything
+/// the wallet received, times the sender's exposure from the previous step. Outbound is
+/// the mirror image over outgoing edges. Each step beyond the first is multiplied by
+/// `decay`. The result is capped at 1. Wallets only take exposure on the hop that first
+/// reaches them, so cycles and longer paths back to a wallet add nothing.
+pub async fn recompute_chain(
+    pool: &PgPool,
+    chain_id: i64,
+    config: &SanctionsExposureConfig,
+) -> eyre::Result<u64> {
+    let sanctioned: Vec<Vec<u8>> = sqlx::query_scalar(
+        "SELECT DISTINCT address FROM entity_labels
+         WHERE (entity_type = 'sanctioned' OR label_source = 'ofac_sdn')
+           AND (chain_id IS NULL OR chain_id = $1)",
+    )
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await?;
+
+    let inbound = propagate(pool, chain_id, &sanctioned, Flow::Inbound, config).await?;
+    let outbound = propagate(pool, chain_id, &sanctioned, Flow::Outbound, config).await?;
+
+    let addresses: Vec<&Vec<u8>> = inbound
+        .keys()
+        .chain(outbound.keys().filter(|a| !inbound.contains_key(*a)))
+        .collect();
+    let inbound_values: Vec<f64> = addresses
+        .iter()
+        .map(|a| inbound.get(*a).map_or(0.0, |(v, _)| *v))
+        .collect();
+    let inbound_hops: Vec<Option<i16>> = addresses
+        .iter()
+        .map(|a| inbound.get(*a).map(|(_, h)| *h))
+        .collect();
+    let outbound_values: Vec<f64> = addresses
+        .iter()
+        .map(|a| outbound.get(*a).map_or(0.0, |(v, _)| *v))
+        .collect();
+    let outbound_hops: Vec<Option<i16>> = addresses
+        .iter()
+        .map(|a| outbound.get(*a).map(|(_, h)| *h))
+        .collect();
+
+    // Replace the chain's results in one transaction so readers never see a partial set
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM wallet_sanctions_exposure WHERE chain_id = $1")
+        .bind(chain_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO wallet_sanctions_exposure
+             (address, chain_id, inbound_exposure, inbound_hops, outbound_exposure, outbound_hops)
+         SELECT address, $1, inbound, inbound_hops, outbound, outbound_hops
+         FROM UNNEST($2::BYTEA[], $3::FLOAT8[], $4::SMALLINT[], $5::FLOAT8[], $6::SMALLINT[])
+              AS e(address, inbound, inbound_hops, outbound, outbound_hops)",
+    )
+    .bind(chain_id)
+    .bind(&addresses)
+    .bind(&inbound_values)
+    .bind(&inbound_hops)
+    .bind(&outbound_values)
+    .bind(&outbound_hops)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        chain_id,
+        sanctioned = sanctioned.len(),
+        wallets = addresses.len(),
+        "Sanctions exposure recomputed"
+    );
+
+    Ok(addresses.len() as u64)
+}
+
+/// Spread exposure from the sanctioned addresses one hop at a time, returning each
+/// reached wallet's (exposure, nearest hop). Sanctioned addresses are included at 1.0, hop 0.
+async fn propagate(
+    pool: &PgPool,
+    chain_id: i64,
+    sanctioned: &[Vec<u8>],
+    flow: Flow,
+    config: &SanctionsExposureConfig,
+) -> eyre::Result<HashMap<Vec<u8>, (f64, i16)>> {
+    let mut exposure: HashMap<Vec<u8>, (f64, i16)> =
+        sanctioned.iter().map(|a| (a.clone(), (1.0, 0))).collect();
+    let mut frontier: HashMap<Vec<u8>, f64> = sanctioned.iter().map(|a| (a.clone(), 1.0)).collect();
+
+    // `near` is the frontier side of the edge, `far` the wallet exposure moves to, and
+    // `share` the edge's fraction of everything `far` received (inbound) or sent (outbound).
+    let sql = match flow {
+        Flow::Inbound => {
+            "SELECT e.source_address, e.dest_address,
+                    (e.total_amount / NULLIF(t.total, 0))::FLOAT8
+             FROM wallet_graph_edges e
+             CROSS JOIN LATERAL (
+                 SELECT SUM(total_amount) AS total FROM wallet_graph_edges
+                 WHERE dest_address = e.dest_address AND chain_id = $1
+             ) t
+             WHERE e.chain_id = $1 AND e.source_address = ANY($2)
+               AND e.source_address <> e.dest_address"
+        }
+        Flow::Outbound => {
+            "SELECT e.dest_address, e.source_address,
+                    (e.total_amount / NULLIF(t.total, 0))::FLOAT8
+             FROM wallet_graph_edges e
+             CROSS JOIN LATERAL (
+                 SELECT SUM(total_amount) AS total FROM wallet_graph_edges
+                 WHERE source_address = e.source_address AND chain_id = $1
+             ) t
+             WHERE e.chain_id = $1 AND e.dest_address = ANY($2)
+               AND e.source_address <> e.dest_address"
+        }
+    };
+
+    for hop in 1..=config.max_hops {
+        if frontier.is_empty() {
+            break;
+        }
+
+        let near: Vec<&[u8]> = frontier.keys().map(Vec::as_slice).collect();
+        let mut edges = Vec::new();
+        for chunk in near.chunks(1000) {
+            let rows: Vec<(Vec<u8>, Vec<u8>, Option<f64>)> = sqlx::query_as(sql)
+                .bind(chain_id)
+                .bind(chunk)
+                .fetch_all(pool)
+                .await?;
+
+            edges.extend(rows);
+        }
+
+        frontier = spread(&mut exposure, &frontier, edges, hop, config);
+    }
+
+    Ok(exposure)
+}
+
+/// Move exposure one hop along `edges` (near, far, share) from the frontier, recording the
+/// wallets it reaches in `exposure` and returning them as the next frontier. Wallets already
+/// in `exposure`, sanctioned ones included, were reached on an earlier hop and are skipped.
+fn spread(
+    exposure: &mut HashMap<Vec<u8>, (f64, i16)>,
+    frontier: &HashMap<Vec<u8>, f64>,
+    edges: Vec<(Vec<u8>, Vec<u8>, Option<f64>)>,
+    hop: u32,
+    config: &SanctionsExposureConfig,
+) -> HashMap<Vec<u8>, f64> {
+    let mut next: HashMap<Vec<u8>, f64> = HashMap::new();
+    for (near, far, share) in edges {
+        if exposure.contains_key(&far) {
+            continue;
+        }
+        let carried = frontier[&near] * share.unwrap_or(0.0);
+        *next.entry(far).or_default() += carried;
+    }
+
+    next.retain(|_, carried| *carried >= config.min_weight);
+    let weight = config.decay.powi(hop as i32 - 1);
+    for (address, carried) in &next {
+        exposure.insert(address.clone(), ((weight * carried).min(1.0), hop as i16));
+    }
+    next
+}
+
+/// Address, chain, inbound and outbound exposure with their hops, and when computed.
+type ExposureRow = (Vec<u8>, i64, f64, Option<i16>, f64, Option<i16>, DateTime<Utc>);
+
+/// Stored exposure for a set of addresses on any chain, keyed by (chain_id, address).
+pub async fn load_exposures(
+    pool: &PgPool,
+    addresses: &[&[u8]],
+) -> eyre::Result<HashMap<(i64, Vec<u8>), WalletExposure>> {
+    let rows: Vec<ExposureRow> =
+        sqlx::query_as(
+            "SELECT address, chain_id, inbound_exposure, inbound_hops, outbound_exposure,
+                    outbound_hops, computed_at
+             FROM wallet_sanctions_exposure WHERE address = ANY($1)",
+        )
+        .bind(addresses)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(address, chain_id, inbound, inbound_hops, outbound, outbound_hops, computed_at)| {
+            (
+                (chain_id, address),
+                WalletExposure {
+                    chain_id,
+                    inbound,
+                    inbound_hops,
+                    outbound,
+                    outbound_hops,
+                    computed_at,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Keeps stored exposure current as the pipeline adds edges.
+///
+/// A batch marks its chain stale when it touches a sanctioned or already exposed wallet,
+/// since only those edges can change anyone's exposure. Each chain is also recomputed on
+/// its first batch after startup, to pick up label changes made while stopped.
+///
+/// Stale chains are recomputed on a background task, at most one per chain at a time and
+/// at most once per `min_interval_secs`, so batches do not wait on the walk. Changes held
+/// back meanwhile are picked up by the next batch on that chain.
+pub struct ExposureTracker {
+    config: SanctionsExposureConfig,
+    stale: HashSet<i64>,
+    last_run: HashMap<i64, Instant>,
+    running: HashMap<i64, JoinHandle<eyre::Result<u64>>>,
+}
+
+impl ExposureTracker {
+    pub fn new(config: SanctionsExposureConfig) -> Self {
+        Self {
+            config,
+            stale: HashSet::new(),
+            last_run: HashMap::new(),
+            running: HashMap::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &SanctionsExposureConfig {
+        &self.config
+    }
+
+    /// Record a batch whose edges were just written and start recomputing stale chains
+    /// that are due. Returns the number of wallet exposures written by recomputations
+    /// that finished since the previous batch.
+    pub async fn observe(
+        &mut self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+        label_store: &EntityLabelStore,
+    ) -> eyre::Result<u64> {
+        if !self.config.enabled || transfers.is_empty() {
+            return Ok(0);
+        }
+
+        let written = self.collect_finished().await;
+
+        let mut unmarked: Vec<&StablecoinTransfer> = Vec::new();
+        for t in transfers {
+            if self.stale.contains(&t.chain_id) {
+                continue;
+            }
+            if !self.last_run.contains_key(&t.chain_id)
+                || label_store.is_sanctioned(&t.from_address) || label_store.is_sanctioned(&t.to_address)
+            {
+                self.stale.insert(t.chain_id);
+            } else {
+                unmarked.push(t);
+            }
+        }
+
+        let unmarked: Vec<&StablecoinTransfer> = unmarked
+            .into_iter()
+            .filter(|t| !self.stale.contains(&t.chain_id))
+            .collect();
+        if !unmarked.is_empty() {
+            let chain_ids: Vec<i64> = unmarked.iter().map(|t| t.chain_id).collect();
+            let addresses: Vec<&[u8]> = unmarked
+                .iter()
+                .flat_map(|t| [t.from_address.as_slice(), t.to_address.as_slice()])
+                .collect();
+            let touched: Vec<i64> = sqlx::query_scalar(
+                "SELECT DISTINCT e.chain_id FROM wallet_sanctions_exposure e
+                 JOIN UNNEST($1::BIGINT[]) AS c(chain_id) ON c.chain_id = e.chain_id
+                 WHERE e.address = ANY($2)",
+            )
+            .bind(&chain_ids)
+            .bind(&addresses)
+            .fetch_all(pool)
+            .await?;
+            self.stale.extend(touched);
+        }
+
+        let interval = Duration::from_secs(self.config.min_interval_secs);
+        let due: Vec<i64> = self
+            .stale
+            .iter()
+            .copied()
+            .filter(|chain_id| !self.running.contains_key(chain_id))
+            .filter(|chain_id| {
+                self.last_run
+                    .get(chain_id)
+                    .is_none_or(|last| last.elapsed() >= interval)
+            })
+            .collect();
+
+        for chain_id in due {
+            let pool = pool.clone();
+            let config = self.config.clone();
+            let handle =
+                tokio::spawn(async move { recompute_chain(&pool, chain_id, &config).await });
+            self.running.insert(chain_id, handle);
+            self.stale.remove(&chain_id);
+            self.last_run.insert(chain_id, Instant::now());
+        }
+
+        Ok(written)
+    }
+
+    /// Reap finished recomputations, returning the wallets they wrote. A failed one marks
+    /// its chain stale again, so the next due batch retries it.
+    async fn collect_finished(&mut self) -> u64 {
+        let finished: Vec<i64> = self
+            .running
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(chain_id, _)| *chain_id)
+            .collect();
+
+        let mut written = 0;
+        for chain_id in finished {
+            let Some(handle) = self.running.remove(&chain_id) else {
+                continue;
+            };
+            match handle.await {
+                Ok(Ok(wallets)) => written += wallets,
+                Ok(Err(e)) => {
+                    tracing::error!(chain_id, error = %e, "Sanctions exposure recompute failed");
+                    self.stale.insert(chain_id);
+                }
+                Err(e) => {
+                    tracing::error!(chain_id, error = %e, "Sanctions exposure task panicked");
+                    self.stale.insert(chain_id);
+                }
+            }
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spread_ignores_cycles_back_to_reached_wallets() {
+        let config = SanctionsExposureConfig::default();
+        // S is sanctioned; A and B pass funds back and forth and B also pays C
+        let edges: [(&[u8], &[u8], f64); 5] = [
+            (b"S", b"A", 0.8),
+            (b"A", b"B", 1.0),
+            (b"A", b"S", 1.0),
+            (b"B", b"A", 0.2),
+            (b"B", b"C", 1.0),
+        ];
+
+        let mut exposure: HashMap<Vec<u8>, (f64, i16)> = HashMap::from([(b"S".to_vec(), (1.0, 0))]);
+        let mut frontier: HashMap<Vec<u8>, f64> = HashMap::from([(b"S".to_vec(), 1.0)]);
+        for hop in 1..=config.max_hops {
+            let step = edges
+                .iter()
+                .filter(|(near, _, _)| frontier.contains_key(*near))
+                .map(|(near, far, share)| (near.to_vec(), far.to_vec(), Some(*share)))
+                .collect();
+            frontier = spread(&mut exposure, &frontier, step, hop, &config);
+        }
+
+        assert_eq!(exposure[&b"S".to_vec()], (1.0, 0));
+        assert_eq!(exposure[&b"A".to_vec()], (0.8, 1));
+        assert_eq!(exposure[&b"B".to_vec()], (0.4, 2));
+        assert_eq!(exposure[&b"C".to_vec()], (0.2, 3));
+        assert_eq!(exposure.len(), 4);
+    }
+}
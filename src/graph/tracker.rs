@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 
+use crate::db::repository::{self, AdditiveStage};
 use crate::indexer::types::StablecoinTransfer;
 
 /// Pre-aggregated edge for a single (source, dest, chain_id) tuple.
@@ -20,6 +21,7 @@ struct AggregatedEdge {
 /// Update wallet graph edges for a batch of transfers.
 /// Pre-aggregates edges with the same (source, dest, chain_id) to avoid
 /// PostgreSQL's "ON CONFLICT DO UPDATE cannot affect row a second time" error.
+/// Transfers already added, e.g. by an earlier attempt at the same batch, are skipped.
 pub async fn update_edges(
     pool: &PgPool,
     transfers: &[StablecoinTransfer],
@@ -28,6 +30,9 @@ pub async fn update_edges(
         return Ok(0);
     }
 
+    let mut tx = pool.begin().await?;
+    let transfers = repository::claim_stage_batch(&mut tx, AdditiveStage::Edges, transfers).await?;
+
     // Pre-aggregate: group transfers by (source, dest, chain_id)
     let mut edge_map: HashMap<(Vec<u8>, Vec<u8>, i64), AggregatedEdge> = HashMap::new();
     for t in transfers {
@@ -76,13 +81,227 @@ pub async fn update_edges(
                   last_seen = GREATEST(wallet_graph_edges.last_seen, EXCLUDED.last_seen)",
         );
 
-        let result = query_builder.build().execute(pool).await?;
+        let result = query_builder.build().execute(&mut *tx).await?;
         count += result.rows_affected();
     }
 
+    tx.commit().await?;
     Ok(count)
 }
 
+/// A wallet and the moment it must have had an incoming edge active, for
+/// `recent_incoming_edges`.
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeProbe<'a> {
+    pub chain_id: i64,
+    pub address: &'a [u8],
+    pub at: DateTime<Utc>,
+    pub window_secs: u64,
+}
+
+/// Indexes of the probes whose wallet had any edge into it active within `window_secs`
+/// before `at`, in one query. A cheap necessary condition for the wallet having received
+/// a transfer in that window.
+pub async fn recent_incoming_edges(
+    pool: &PgPool,
+    probes: &[EdgeProbe<'_>],
+) -> eyre::Result<HashSet<usize>> {
+    if probes.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let chain_ids: Vec<i64> = probes.iter().map(|p| p.chain_id).collect();
+    let addresses: Vec<&[u8]> = probes.iter().map(|p| p.address).collect();
+    let timestamps: Vec<DateTime<Utc>> = probes.iter().map(|p| p.at).collect();
+    let windows: Vec<f64> = probes.iter().map(|p| p.window_secs as f64).collect();
+
+    let rows: Vec<(i64,)> = sqlx::query_as(
+        "SELECT p.n - 1
+         FROM UNNEST($1::BIGINT[], $2::BYTEA[], $3::TIMESTAMPTZ[], $4::FLOAT8[])
+              WITH ORDINALITY AS p(chain_id, address, at, window_secs, n)
+         WHERE EXISTS (
+             SELECT 1 FROM wallet_graph_edges
+             WHERE dest_address = p.address AND chain_id = p.chain_id
+               AND first_seen <= p.at AND last_seen >= p.at - make_interval(secs => p.window_secs)
+         )",
+    )
+    .bind(&chain_ids)
+    .bind(&addresses)
+    .bind(&timestamps)
+    .bind(&windows)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|(index,)| index as usize).collect())
+}
+
+/// Outcome of recomputing a set of graph edges from stored transfers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EdgeRebuild {
+    pub created: u64,
+    pub changed: u64,
+    pub unchanged: u64,
+}
+
+/// Edge aggregated from stored transfers, with the stored count and total if any.
+type EdgeRebuildRow = (
+    Vec<u8>,
+    Vec<u8>,
+    i64,
+    BigDecimal,
+    DateTime<Utc>,
+    DateTime<Utc>,
+    Option<i64>,
+    Option<BigDecimal>,
+);
+
+/// Recompute the given (source, dest) edges on a chain from the transfers the enrichment
+/// worker has already added to them, replacing their stored aggregates. Idempotent, unlike
+/// `update_edges` which adds deltas; transfers past the checkpoint are left for the worker
+/// to add. With `dry_run`, only reports how the stored edges differ.
+pub async fn rebuild_edges(
+    pool: &PgPool,
+    chain_id: i64,
+    pairs: &[(Vec<u8>, Vec<u8>)],
+    dry_run: bool,
+) -> eyre::Result<EdgeRebuild> {
+    let mut outcome = EdgeRebuild::default();
+
+    for chunk in pairs.chunks(500) {
+        // Hold the worker off the chain between reading and replacing the edges
+        let mut tx = pool.begin().await?;
+        repository::lock_enrichment_state(&mut tx, chain_id).await?;
+
+        let sources: Vec<&[u8]> = chunk.iter().map(|(s, _)| s.as_slice()).collect();
+        let dests: Vec<&[u8]> = chunk.iter().map(|(_, d)| d.as_slice()).collect();
+
+        let rows: Vec<EdgeRebuildRow> = sqlx::query_as(
+            "WITH pairs AS (
+                SELECT * FROM UNNEST($2::BYTEA[], $3::BYTEA[]) AS p(source_address, dest_address)
+            ),
+            agg AS (
+                SELECT t.from_address, t.to_address, COUNT(*) AS cnt, SUM(t.amount) AS total,
+                       MIN(t.block_timestamp) AS first_seen, MAX(t.block_timestamp) AS last_seen
+                FROM transfers t
+                JOIN pairs p ON t.from_address = p.source_address AND t.to_address = p.dest_address
+                JOIN enrichment_state s
+                  ON s.chain_id = t.chain_id AND t.id <= s.edges_transfer_id
+                WHERE t.chain_id = $1
+                GROUP BY t.from_address, t.to_address
+            )
+            SELECT agg.from_address, agg.to_address, agg.cnt, agg.total,
+                   agg.first_seen, agg.last_seen, e.transfer_count, e.total_amount
+            FROM agg
+            LEFT JOIN wallet_graph_edges e
+              ON e.source_address = agg.from_address
+             AND e.dest_address = agg.to_address
+             AND e.chain_id = $1",
+        )
+        .bind(chain_id)
+        .bind(&sources)
+        .bind(&dests)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut stale = Vec::new();
+        for (source, dest, count, total, first_seen, last_seen, stored_count, stored_total) in rows
+        {
+            match (stored_count, stored_total) {
+                (None, _) | (_, None) => outcome.created += 1,
+                (Some(c), Some(t)) if c != count || t != total => outcome.changed += 1,
+                _ => {
+                    outcome.unchanged += 1;
+                    continue;
+                }
+            }
+            stale.push(AggregatedEdge {
+                source_address: source,
+                dest_address: dest,
+                chain_id,
+                transfer_count: count,
+                total_amount: total,
+                first_seen,
+                last_seen,
+            });
+        }
+
+        if dry_run || stale.is_empty() {
+            tx.commit().await?;
+            continue;
+        }
+
+        let mut query_builder: sqlx::QueryBuilder<sqlx::Postgres> = sqlx::QueryBuilder::new(
+            "INSERT INTO wallet_graph_edges (source_address, dest_address, chain_id, transfer_count, total_amount, first_seen, last_seen) ",
+        );
+
+        query_builder.push_values(&stale, |mut b, e| {
+            b.push_bind(&e.source_address)
+                .push_bind(&e.dest_address)
+                .push_bind(e.chain_id)
+                .push_bind(e.transfer_count)
+                .push_bind(&e.total_amount)
+                .push_bind(e.first_seen)
+                .push_bind(e.last_seen);
+        });
+
+        query_builder.push(
+            " ON CONFLICT (source_address, dest_address, chain_id) DO UPDATE
+              SET transfer_count = EXCLUDED.transfer_count,
+                  total_amount = EXCLUDED.total_amount,
+                  first_seen = EXCLUDED.first_seen,
+                  last_seen = EXCLUDED.last_seen",
+        );
+
+        query_builder.build().execute(&mut *tx).await?;
+        tx.commit().await?;
+    }
+
+    Ok(outcome)
+}
+
+/// Reset the edges that transfers at or after `from_block` contribute to, counting only the
+/// transfers before that block the enrichment worker has already added. Edges left without
+/// transfers are removed. Used by a replay or reorg rollback in the same transaction, under
+/// the chain's enrichment lock, so the worker adds the range's transfers once when it
+/// reaches them again.
+pub async fn rewind_edges(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i64,
+    from_block: i64,
+) -> eyre::Result<u64> {
+    let removed = sqlx::query(
+        "DELETE FROM wallet_graph_edges e
+         USING (SELECT DISTINCT from_address, to_address FROM transfers
+                WHERE chain_id = $1 AND block_number >= $2) p
+         WHERE e.chain_id = $1 AND e.source_address = p.from_address
+           AND e.dest_address = p.to_address",
+    )
+    .bind(chain_id)
+    .bind(from_block)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    sqlx::query(
+        "INSERT INTO wallet_graph_edges
+                (source_address, dest_address, chain_id, transfer_count, total_amount, first_seen, last_seen)
+         SELECT t.from_address, t.to_address, t.chain_id, COUNT(*), SUM(t.amount),
+                MIN(t.block_timestamp), MAX(t.block_timestamp)
+         FROM transfers t
+         JOIN enrichment_state e ON e.chain_id = t.chain_id AND t.id <= e.edges_transfer_id
+         JOIN (SELECT DISTINCT from_address, to_address FROM transfers
+               WHERE chain_id = $1 AND block_number >= $2) p
+           ON t.from_address = p.from_address AND t.to_address = p.to_address
+         WHERE t.chain_id = $1 AND t.block_number < $2
+         GROUP BY t.from_address, t.to_address, t.chain_id",
+    )
+    .bind(chain_id)
+    .bind(from_block)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(removed)
+}
+
 /// Get all outgoing edges from an address (who did this wallet send money to).
 pub async fn get_outgoing_edges(
     pool: &PgPool,
@@ -3,29 +3,24 @@ use alloy::providers::{Provider, ProviderBuilder, WsConnect};
 use alloy::rpc::types::{BlockNumberOrTag, Filter};
 use chrono::{DateTime, Utc};
 use futures::StreamExt;
-use sqlx::PgPool;
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
 use crate::config::ChainConfig;
-use crate::db::repository;
+use crate::db::store::Store;
 use crate::indexer::decoder;
 use crate::indexer::defi_decoder;
 use crate::indexer::receipt_fetcher;
 use crate::indexer::types::{StablecoinTransfer, TokenMeta};
-use crate::pipeline::TransferPipeline;
 use crate::tokens::registry::build_watched_tokens;
 
 /// Main entry point for a single chain's indexer task.
 /// Runs backfill (if configured), then switches to live indexing.
-pub async fn run_chain_indexer(
+pub async fn run_chain_indexer<S: Store>(
     config: ChainConfig,
-    pool: PgPool,
+    store: S,
     shutdown: CancellationToken,
-    pipeline: Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     let chain_id = config.chain_id as i64;
     tracing::info!(chain = %config.name, chain_id, "Starting chain indexer");
@@ -44,7 +39,7 @@ pub async fn run_chain_indexer(
     );
 
     // Determine where to resume from
-    let last_indexed = repository::get_last_indexed_block(&pool, chain_id).await?;
+    let last_indexed = store.get_last_indexed_block(chain_id).await?;
     let start_block = last_indexed
         .map(|b| b + 1)
         .or(config.start_block);
@@ -53,14 +48,14 @@ pub async fn run_chain_indexer(
     if let Some(start) = start_block {
         if !shutdown.is_cancelled() {
             tracing::info!(chain = %config.name, start_block = start, "Starting backfill");
-            backfill(&config, &pool, &watched_tokens, start, &shutdown, &pipeline).await?;
+            backfill(&config, &store, &watched_tokens, start, &shutdown).await?;
         }
     }
 
     // Phase 2: Live indexing
     if !shutdown.is_cancelled() {
         tracing::info!(chain = %config.name, "Switching to live indexing");
-        live_index(&config, &pool, &watched_tokens, &shutdown, &pipeline).await?;
+        live_index(&config, &store, &watched_tokens, &shutdown).await?;
     }
 
     tracing::info!(chain = %config.name, "Chain indexer stopped");
@@ -68,13 +63,12 @@ pub async fn run_chain_indexer(
 }
 
 /// Backfill historical blocks from `start_block` up to the current chain tip.
-async fn backfill(
+async fn backfill<S: Store>(
     config: &ChainConfig,
-    pool: &PgPool,
+    store: &S,
     watched_tokens: &HashMap<Address, TokenMeta>,
     start_block: u64,
     shutdown: &CancellationToken,
-    pipeline: &Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     let provider = ProviderBuilder::new()
         .connect_http(config.rpc_http.parse().map_err(|e| eyre::eyre!("Invalid RPC URL: {}", e))?);
@@ -122,7 +116,9 @@ async fn backfill(
         let mut block_timestamps: HashMap<u64, DateTime<Utc>> = HashMap::new();
         for log in &logs {
             if let Some(block_num) = log.block_number {
-                if !block_timestamps.contains_key(&block_num) {
+                if let std::collections::hash_map::Entry::Vacant(entry) =
+                    block_timestamps.entry(block_num)
+                {
                     let block = retry_rpc(|| async {
                         provider.get_block_by_number(BlockNumberOrTag::Number(block_num)).await
                     })
@@ -131,7 +127,7 @@ async fn backfill(
                     if let Some(block) = block {
                         let ts = DateTime::from_timestamp(block.header.timestamp as i64, 0)
                             .unwrap_or_default();
-                        block_timestamps.insert(block_num, ts);
+                        entry.insert(ts);
                     }
                 }
             }
@@ -149,6 +145,7 @@ async fn backfill(
                     .unwrap_or_default();
 
                 transfers.push(StablecoinTransfer {
+                    id: None,
                     chain_id,
                     block_number: block_num as i64,
                     block_hash: block_hash.as_slice().to_vec(),
@@ -161,34 +158,11 @@ async fn backfill(
                     token_symbol: decoded.token_symbol,
                     token_decimals: decoded.token_decimals,
                     block_timestamp: timestamp,
+                    amount_usd: None,
                 });
             }
         }
 
-        // Batch insert
-        if !transfers.is_empty() {
-            tracing::info!(
-                chain = %config.name,
-                count = transfers.len(),
-                "Inserting transfers"
-            );
-            repository::insert_transfers_batch(pool, &transfers).await?;
-
-            // Run enrichment pipeline
-            let mut pl = pipeline.lock().await;
-            let result = pl.enrich(pool, &config.name, &transfers).await?;
-            if result.anomalies_detected > 0 || result.entities_attributed > 0 {
-                tracing::info!(
-                    chain = %config.name,
-                    entities = result.entities_attributed,
-                    new_wallets = result.new_wallets_found,
-                    anomalies = result.anomalies_detected,
-                    edges = result.graph_edges_updated,
-                    "Enrichment complete"
-                );
-            }
-        }
-
         // Fetch receipts and decode DeFi events
         if config.decode_defi && !transfers.is_empty() {
             let unique_tx_hashes: Vec<B256> = transfers
@@ -201,16 +175,21 @@ async fn backfill(
             if unique_tx_hashes.len() <= 100 {
                 match receipt_fetcher::fetch_receipts_for_txs(&provider, &unique_tx_hashes, 50).await {
                     Ok(receipt_logs) => {
-                        let all_receipt_logs: Vec<_> = receipt_logs
+                        // Each event takes its own block's timestamp, which the partitioned
+                        // joins against transfers rely on
+                        let batch_timestamp = block_timestamps.values().next().copied().unwrap_or_default();
+                        let defi_events: Vec<_> = receipt_logs
                             .iter()
                             .flat_map(|(_, logs)| logs.iter())
-                            .cloned()
+                            .flat_map(|log| {
+                                let timestamp = log
+                                    .block_number
+                                    .and_then(|n| block_timestamps.get(&n).copied())
+                                    .unwrap_or(batch_timestamp);
+                                defi_decoder::decode_defi_logs(std::slice::from_ref(log), timestamp, chain_id)
+                            })
                             .collect();
 
-                        // Use the most common timestamp from the batch
-                        let batch_timestamp = block_timestamps.values().next().copied().unwrap_or_default();
-                        let defi_events = defi_decoder::decode_defi_logs(&all_receipt_logs, batch_timestamp, chain_id);
-
                         if !defi_events.is_empty() {
                             tracing::info!(
                                 chain = %config.name,
@@ -218,7 +197,7 @@ async fn backfill(
                                 receipts = receipt_logs.len(),
                                 "Decoded DeFi events from receipts"
                             );
-                            repository::insert_defi_events_batch(pool, &defi_events).await?;
+                            store.insert_defi_events_batch(&defi_events).await?;
                         }
                     }
                     Err(e) => {
@@ -238,8 +217,19 @@ async fn backfill(
             }
         }
 
+        // Batch insert, after the DeFi events: enrichment picks transfers up as soon as
+        // they commit and reads the events of their transactions
+        if !transfers.is_empty() {
+            tracing::info!(
+                chain = %config.name,
+                count = transfers.len(),
+                "Inserting transfers"
+            );
+            store.insert_transfers_batch(&mut transfers).await?;
+        }
+
         // Update checkpoint
-        repository::upsert_indexer_state(pool, chain_id, to_block as i64, None).await?;
+        store.upsert_indexer_state(chain_id, to_block as i64, None).await?;
 
         current = to_block + 1;
     }
@@ -249,15 +239,14 @@ async fn backfill(
 }
 
 /// Live indexing: subscribe to new blocks via WebSocket, or poll via HTTP.
-async fn live_index(
+async fn live_index<S: Store>(
     config: &ChainConfig,
-    pool: &PgPool,
+    store: &S,
     watched_tokens: &HashMap<Address, TokenMeta>,
     shutdown: &CancellationToken,
-    pipeline: &Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     if let Some(ws_url) = &config.rpc_ws {
-        match live_index_ws(config, ws_url, pool, watched_tokens, shutdown, pipeline).await {
+        match live_index_ws(config, ws_url, store, watched_tokens, shutdown).await {
             Ok(()) => return Ok(()),
             Err(e) => {
                 tracing::warn!(
@@ -269,17 +258,16 @@ async fn live_index(
         }
     }
 
-    live_index_http(config, pool, watched_tokens, shutdown, pipeline).await
+    live_index_http(config, store, watched_tokens, shutdown).await
 }
 
 /// Live indexing via WebSocket block subscription.
-async fn live_index_ws(
+async fn live_index_ws<S: Store>(
     config: &ChainConfig,
     ws_url: &str,
-    pool: &PgPool,
+    store: &S,
     watched_tokens: &HashMap<Address, TokenMeta>,
     shutdown: &CancellationToken,
-    pipeline: &Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     let ws = WsConnect::new(ws_url);
     let provider = ProviderBuilder::new().connect_ws(ws).await?;
@@ -295,7 +283,7 @@ async fn live_index_ws(
                 match maybe_block {
                     Some(block_header) => {
                         if let Err(e) = process_new_block(
-                            &provider, pool, watched_tokens, config, &block_header, pipeline
+                            &provider, store, watched_tokens, config, &block_header
                         ).await {
                             tracing::error!(
                                 chain = %config.name,
@@ -322,12 +310,11 @@ async fn live_index_ws(
 }
 
 /// Live indexing via HTTP polling (fallback when WS is unavailable).
-async fn live_index_http(
+async fn live_index_http<S: Store>(
     config: &ChainConfig,
-    pool: &PgPool,
+    store: &S,
     watched_tokens: &HashMap<Address, TokenMeta>,
     shutdown: &CancellationToken,
-    pipeline: &Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     let provider = ProviderBuilder::new()
         .connect_http(config.rpc_http.parse().map_err(|e| eyre::eyre!("Invalid RPC URL: {}", e))?);
@@ -375,7 +362,7 @@ async fn live_index_http(
 
             if let Some(block) = block {
                 if let Err(e) = process_new_block(
-                    &provider, pool, watched_tokens, config, &block.header, pipeline
+                    &provider, store, watched_tokens, config, &block.header
                 ).await {
                     tracing::error!(
                         chain = %config.name,
@@ -393,14 +380,14 @@ async fn live_index_http(
     Ok(())
 }
 
-/// Process a single new block: detect reorgs, fetch logs, decode, insert, enrich.
-async fn process_new_block<P: Provider>(
+/// Process a single new block: detect reorgs, fetch logs, decode, insert.
+/// Enrichment picks the committed transfers up separately via the enrichment worker.
+async fn process_new_block<P: Provider, S: Store>(
     provider: &P,
-    pool: &PgPool,
+    store: &S,
     watched_tokens: &HashMap<Address, TokenMeta>,
     config: &ChainConfig,
     block_header: &alloy::consensus::Header,
-    pipeline: &Arc<Mutex<TransferPipeline>>,
 ) -> eyre::Result<()> {
     let chain_id = config.chain_id as i64;
     let block_number = block_header.number;
@@ -410,7 +397,7 @@ async fn process_new_block<P: Provider>(
     // --- Reorg Detection ---
     if block_number > 0 {
         let stored_hash =
-            repository::get_block_hash(pool, chain_id, block_number as i64 - 1).await?;
+            store.get_block_hash(chain_id, block_number as i64 - 1).await?;
 
         if let Some(stored) = stored_hash {
             if stored.as_slice() != parent_hash.as_slice() {
@@ -421,11 +408,11 @@ async fn process_new_block<P: Provider>(
                 );
 
                 // Walk backwards to find the fork point
-                let fork_block = find_fork_point(pool, chain_id, block_number, config.max_reorg_depth).await?;
+                let fork_block = find_fork_point(store, chain_id, block_number, config.max_reorg_depth).await?;
 
-                let deleted = repository::delete_transfers_from_block(pool, chain_id, fork_block as i64).await?;
-                let deleted_defi = repository::delete_defi_events_from_block(pool, chain_id, fork_block as i64).await?;
-                repository::delete_block_hashes_from(pool, chain_id, fork_block as i64).await?;
+                let deleted = store.delete_transfers_from_block(chain_id, fork_block as i64).await?;
+                let deleted_defi = store.delete_defi_events_from_block(chain_id, fork_block as i64).await?;
+                store.delete_block_hashes_from(chain_id, fork_block as i64).await?;
 
                 tracing::info!(
                     chain = %config.name,
@@ -457,6 +444,7 @@ async fn process_new_block<P: Provider>(
     for log in &logs {
         if let Some(decoded) = decoder::decode_transfer_log(log, watched_tokens) {
             transfers.push(StablecoinTransfer {
+                id: None,
                 chain_id,
                 block_number: block_number as i64,
                 block_hash: block_hash.as_slice().to_vec(),
@@ -469,30 +457,11 @@ async fn process_new_block<P: Provider>(
                 token_symbol: decoded.token_symbol,
                 token_decimals: decoded.token_decimals,
                 block_timestamp: timestamp,
+                amount_usd: None,
             });
         }
     }
 
-    // Insert transfers
-    if !transfers.is_empty() {
-        repository::insert_transfers_batch(pool, &transfers).await?;
-
-        // Run enrichment pipeline
-        let mut pl = pipeline.lock().await;
-        let result = pl.enrich(pool, &config.name, &transfers).await?;
-        if result.anomalies_detected > 0 || result.entities_attributed > 0 {
-            tracing::info!(
-                chain = %config.name,
-                block = block_number,
-                entities = result.entities_attributed,
-                new_wallets = result.new_wallets_found,
-                anomalies = result.anomalies_detected,
-                edges = result.graph_edges_updated,
-                "Enrichment complete"
-            );
-        }
-    }
-
     // Fetch receipts and decode DeFi events for live blocks
     if config.decode_defi && !transfers.is_empty() {
         let unique_tx_hashes: Vec<B256> = transfers
@@ -519,7 +488,7 @@ async fn process_new_block<P: Provider>(
                         defi_events = defi_events.len(),
                         "Decoded DeFi events from receipts"
                     );
-                    repository::insert_defi_events_batch(pool, &defi_events).await?;
+                    store.insert_defi_events_batch(&defi_events).await?;
                 }
             }
             Err(e) => {
@@ -533,34 +502,32 @@ async fn process_new_block<P: Provider>(
         }
     }
 
-    // Store block hash for future reorg detection
-    repository::upsert_block_hash(
-        pool,
-        chain_id,
-        block_number as i64,
-        block_hash.as_slice(),
-        parent_hash.as_slice(),
-    )
-    .await?;
+    // Insert transfers once their transactions' DeFi events are stored
+    if !transfers.is_empty() {
+        store.insert_transfers_batch(&mut transfers).await?;
+    }
 
-    // Prune old block hashes
-    if block_number > config.max_reorg_depth {
-        repository::prune_block_hashes(
-            pool,
+    // Store block hash for future reorg detection
+    store
+        .upsert_block_hash(
             chain_id,
-            (block_number - config.max_reorg_depth) as i64,
+            block_number as i64,
+            block_hash.as_slice(),
+            parent_hash.as_slice(),
         )
         .await?;
+
+    // Prune old block hashes
+    if block_number > config.max_reorg_depth {
+        store
+            .prune_block_hashes(chain_id, (block_number - config.max_reorg_depth) as i64)
+            .await?;
     }
 
     // Update checkpoint
-    repository::upsert_indexer_state(
-        pool,
-        chain_id,
-        block_number as i64,
-        Some(block_hash.as_slice()),
-    )
-    .await?;
+    store
+        .upsert_indexer_state(chain_id, block_number as i64, Some(block_hash.as_slice()))
+        .await?;
 
     tracing::info!(
         chain = %config.name,
@@ -573,21 +540,17 @@ async fn process_new_block<P: Provider>(
 }
 
 /// Walk backwards from a block to find where the chain forked.
-async fn find_fork_point(
-    pool: &PgPool,
+async fn find_fork_point<S: Store>(
+    store: &S,
     chain_id: i64,
     block_number: u64,
     max_depth: u64,
 ) -> eyre::Result<u64> {
-    let earliest = if block_number > max_depth {
-        block_number - max_depth
-    } else {
-        0
-    };
+    let earliest = block_number.saturating_sub(max_depth);
 
     // Walk backwards; the first block without a stored hash is the fork point
     for num in (earliest..block_number).rev() {
-        let stored = repository::get_block_hash(pool, chain_id, num as i64).await?;
+        let stored = store.get_block_hash(chain_id, num as i64).await?;
         if stored.is_some() {
             return Ok(num + 1);
         }
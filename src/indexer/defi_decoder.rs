@@ -1,6 +1,5 @@
-use alloy::primitives::U256;
+use alloy::primitives::{Address, U256};
 use alloy::rpc::types::Log;
-use alloy::sol;
 use alloy::sol_types::SolEvent;
 use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
@@ -10,99 +9,159 @@ use std::str::FromStr;
 // DeFi Event Signatures
 // ============================================================
 
-sol! {
-    // Uniswap V2 / SushiSwap / forks
-    event UniV2Swap(
-        address indexed sender,
-        uint256 amount0In,
-        uint256 amount1In,
-        uint256 amount0Out,
-        uint256 amount1Out,
-        address indexed to
-    );
-
-    // Uniswap V3
-    event UniV3Swap(
-        address indexed sender,
-        address indexed recipient,
-        int256 amount0,
-        int256 amount1,
-        uint160 sqrtPriceX96,
-        uint128 liquidity,
-        int24 tick
-    );
-
-    // Curve TokenExchange
-    event TokenExchange(
-        address indexed buyer,
-        int128 sold_id,
-        uint256 tokens_sold,
-        int128 bought_id,
-        uint256 tokens_bought
-    );
-
-    // Aave V3 Supply
-    event AaveSupply(
-        address indexed reserve,
-        address user,
-        address indexed onBehalfOf,
-        uint256 amount,
-        uint16 indexed referralCode
-    );
-
-    // Aave V3 Borrow
-    event AaveBorrow(
-        address indexed reserve,
-        address user,
-        address indexed onBehalfOf,
-        uint256 amount,
-        uint8 interestRateMode,
-        uint256 borrowRate,
-        uint16 indexed referralCode
-    );
-
-    // Aave V3 Repay
-    event AaveRepay(
-        address indexed reserve,
-        address indexed user,
-        address indexed repayer,
-        uint256 amount,
-        bool useATokens
-    );
-
-    // Aave V3 LiquidationCall
-    event LiquidationCall(
-        address indexed collateralAsset,
-        address indexed debtAsset,
-        address indexed user,
-        uint256 debtToCover,
-        uint256 liquidatedCollateralAmount,
-        address liquidator,
-        bool receiveAToken
-    );
-
-    // Compound V3 (Comet) Supply
-    event CometSupply(
-        address indexed from,
-        address indexed dst,
-        uint256 amount
-    );
-
-    // Compound V3 (Comet) Withdraw
-    event CometWithdraw(
-        address indexed src,
-        address indexed to,
-        uint256 amount
-    );
-
-    // Compound V3 AbsorbCollateral
-    event AbsorbCollateral(
-        address indexed absorber,
-        address indexed borrower,
-        address indexed asset,
-        uint256 collateralAbsorbed,
-        uint256 usdValue
-    );
+// Each protocol's events get their own module: `sol!` derives an event's signature from
+// its name, and several protocols share names such as Supply, Swap and FlashLoan.
+
+mod uniswap_v2 {
+    alloy::sol! {
+        // Uniswap V2 / SushiSwap / forks
+        event Swap(
+            address indexed sender,
+            uint256 amount0In,
+            uint256 amount1In,
+            uint256 amount0Out,
+            uint256 amount1Out,
+            address indexed to
+        );
+    }
+}
+
+mod uniswap_v3 {
+    alloy::sol! {
+        event Swap(
+            address indexed sender,
+            address indexed recipient,
+            int256 amount0,
+            int256 amount1,
+            uint160 sqrtPriceX96,
+            uint128 liquidity,
+            int24 tick
+        );
+    }
+}
+
+mod curve {
+    alloy::sol! {
+        event TokenExchange(
+            address indexed buyer,
+            int128 sold_id,
+            uint256 tokens_sold,
+            int128 bought_id,
+            uint256 tokens_bought
+        );
+    }
+}
+
+mod aave_v3 {
+    alloy::sol! {
+        event Supply(
+            address indexed reserve,
+            address user,
+            address indexed onBehalfOf,
+            uint256 amount,
+            uint16 indexed referralCode
+        );
+
+        event Borrow(
+            address indexed reserve,
+            address user,
+            address indexed onBehalfOf,
+            uint256 amount,
+            uint8 interestRateMode,
+            uint256 borrowRate,
+            uint16 indexed referralCode
+        );
+
+        event Repay(
+            address indexed reserve,
+            address indexed user,
+            address indexed repayer,
+            uint256 amount,
+            bool useATokens
+        );
+
+        event LiquidationCall(
+            address indexed collateralAsset,
+            address indexed debtAsset,
+            address indexed user,
+            uint256 debtToCover,
+            uint256 liquidatedCollateralAmount,
+            address liquidator,
+            bool receiveAToken
+        );
+
+        // A non-zero interestRateMode opens a borrow position instead of repaying
+        event FlashLoan(
+            address indexed target,
+            address initiator,
+            address indexed asset,
+            uint256 amount,
+            uint8 interestRateMode,
+            uint256 premium,
+            uint16 indexed referralCode
+        );
+    }
+}
+
+mod aave_v2 {
+    alloy::sol! {
+        event FlashLoan(
+            address indexed target,
+            address indexed initiator,
+            address indexed asset,
+            uint256 amount,
+            uint256 premium,
+            uint16 referralCode
+        );
+    }
+}
+
+mod balancer_v2 {
+    alloy::sol! {
+        // Emitted by the Vault
+        event FlashLoan(
+            address indexed recipient,
+            address indexed token,
+            uint256 amount,
+            uint256 feeAmount
+        );
+    }
+}
+
+mod maker {
+    alloy::sol! {
+        // DssFlash (ERC-3156). Same signature as Balancer's, with the token unindexed.
+        event FlashLoan(
+            address indexed receiver,
+            address token,
+            uint256 amount,
+            uint256 fee
+        );
+    }
+}
+
+mod compound_v3 {
+    alloy::sol! {
+        event Supply(
+            address indexed from,
+            address indexed dst,
+            uint256 amount
+        );
+
+        event Withdraw(
+            address indexed src,
+            address indexed to,
+            uint256 amount
+        );
+
+        event AbsorbCollateral(
+            address indexed absorber,
+            address indexed borrower,
+            address indexed asset,
+            uint256 collateralAbsorbed,
+            uint256 usdValue
+        );
+    }
 }
 
 // ============================================================
@@ -158,25 +217,33 @@ fn try_decode_log(log: &Log, block_timestamp: DateTime<Utc>, chain_id: i64) -> O
     let log_index = log.log_index.unwrap_or(0) as i32;
     let contract_address = log.inner.address.as_slice().to_vec();
 
-    if sig == UniV2Swap::SIGNATURE_HASH {
+    if sig == uniswap_v2::Swap::SIGNATURE_HASH {
         decode_univ2_swap(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == UniV3Swap::SIGNATURE_HASH {
+    } else if sig == uniswap_v3::Swap::SIGNATURE_HASH {
         decode_univ3_swap(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == TokenExchange::SIGNATURE_HASH {
+    } else if sig == curve::TokenExchange::SIGNATURE_HASH {
         decode_curve_exchange(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == AaveSupply::SIGNATURE_HASH {
+    } else if sig == aave_v3::Supply::SIGNATURE_HASH {
         decode_aave_supply(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == AaveBorrow::SIGNATURE_HASH {
+    } else if sig == aave_v3::Borrow::SIGNATURE_HASH {
         decode_aave_borrow(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == AaveRepay::SIGNATURE_HASH {
+    } else if sig == aave_v3::Repay::SIGNATURE_HASH {
         decode_aave_repay(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == LiquidationCall::SIGNATURE_HASH {
+    } else if sig == aave_v3::LiquidationCall::SIGNATURE_HASH {
         decode_aave_liquidation(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == CometSupply::SIGNATURE_HASH {
+    } else if sig == aave_v3::FlashLoan::SIGNATURE_HASH {
+        decode_aave_v3_flash_loan(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
+    } else if sig == aave_v2::FlashLoan::SIGNATURE_HASH {
+        decode_aave_v2_flash_loan(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
+    } else if sig == balancer_v2::FlashLoan::SIGNATURE_HASH {
+        // Maker's event shares the signature; only the number of topics tells them apart
+        decode_balancer_flash_loan(log, chain_id, block_number, tx_hash.clone(), log_index, contract_address.clone(), block_timestamp)
+            .or_else(|| decode_maker_flash_loan(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp))
+    } else if sig == compound_v3::Supply::SIGNATURE_HASH {
         decode_comet_supply(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == CometWithdraw::SIGNATURE_HASH {
+    } else if sig == compound_v3::Withdraw::SIGNATURE_HASH {
         decode_comet_withdraw(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
-    } else if sig == AbsorbCollateral::SIGNATURE_HASH {
+    } else if sig == compound_v3::AbsorbCollateral::SIGNATURE_HASH {
         decode_comet_absorb(log, chain_id, block_number, tx_hash, log_index, contract_address, block_timestamp)
     } else {
         None
@@ -204,7 +271,7 @@ fn decode_univ2_swap(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = UniV2Swap::decode_log(&log.inner).ok()?;
+    let decoded = uniswap_v2::Swap::decode_log(&log.inner).ok()?;
     let sender = decoded.sender;
     let to = decoded.to;
     let amount0_in = u256_to_bd(decoded.amount0In);
@@ -253,7 +320,7 @@ fn decode_univ3_swap(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = UniV3Swap::decode_log(&log.inner).ok()?;
+    let decoded = uniswap_v3::Swap::decode_log(&log.inner).ok()?;
     let sender = decoded.sender;
     let recipient = decoded.recipient;
     let amount0 = decoded.amount0;
@@ -301,7 +368,7 @@ fn decode_curve_exchange(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = TokenExchange::decode_log(&log.inner).ok()?;
+    let decoded = curve::TokenExchange::decode_log(&log.inner).ok()?;
     let buyer = decoded.buyer;
     let tokens_sold = u256_to_bd(decoded.tokens_sold);
     let tokens_bought = u256_to_bd(decoded.tokens_bought);
@@ -339,7 +406,7 @@ fn decode_aave_supply(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = AaveSupply::decode_log(&log.inner).ok()?;
+    let decoded = aave_v3::Supply::decode_log(&log.inner).ok()?;
     let reserve = decoded.reserve;
     let on_behalf_of = decoded.onBehalfOf;
     let amount = u256_to_bd(decoded.amount);
@@ -375,7 +442,7 @@ fn decode_aave_borrow(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = AaveBorrow::decode_log(&log.inner).ok()?;
+    let decoded = aave_v3::Borrow::decode_log(&log.inner).ok()?;
     let reserve = decoded.reserve;
     let on_behalf_of = decoded.onBehalfOf;
     let amount = u256_to_bd(decoded.amount);
@@ -413,7 +480,7 @@ fn decode_aave_repay(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = AaveRepay::decode_log(&log.inner).ok()?;
+    let decoded = aave_v3::Repay::decode_log(&log.inner).ok()?;
     let reserve = decoded.reserve;
     let user = decoded.user;
     let amount = u256_to_bd(decoded.amount);
@@ -450,7 +517,7 @@ fn decode_aave_liquidation(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = LiquidationCall::decode_log(&log.inner).ok()?;
+    let decoded = aave_v3::LiquidationCall::decode_log(&log.inner).ok()?;
     let collateral = decoded.collateralAsset;
     let debt = decoded.debtAsset;
     let user = decoded.user;
@@ -481,6 +548,195 @@ fn decode_aave_liquidation(
     })
 }
 
+/// The terms of one decoded flash loan, common to every lender's event.
+struct FlashLoanTerms {
+    protocol: &'static str,
+    receiver: Address,
+    asset: Address,
+    amount: U256,
+    fee: U256,
+    /// Paid back within the transaction rather than left open as debt.
+    repaid: bool,
+    raw_data: serde_json::Value,
+}
+
+/// A flash loan of `amount` of `asset` to `receiver`. The lender pays out on `token_out`
+/// and, when the loan was repaid within the transaction, is paid back `amount + fee` on
+/// `token_in`.
+fn flash_loan_event(
+    chain_id: i64,
+    block_number: i64,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+    contract_address: Vec<u8>,
+    block_timestamp: DateTime<Utc>,
+    terms: FlashLoanTerms,
+) -> DefiEvent {
+    let asset = terms.asset.as_slice().to_vec();
+    let repaid = terms.repaid;
+    DefiEvent {
+        chain_id,
+        block_number,
+        tx_hash,
+        log_index,
+        protocol: terms.protocol.to_string(),
+        event_type: "flash_loan".to_string(),
+        contract_address,
+        account: Some(terms.receiver.as_slice().to_vec()),
+        token_in: repaid.then(|| asset.clone()),
+        token_out: Some(asset),
+        amount_in: repaid.then(|| u256_to_bd(terms.amount.saturating_add(terms.fee))),
+        amount_out: Some(u256_to_bd(terms.amount)),
+        block_timestamp,
+        raw_data: Some(terms.raw_data),
+    }
+}
+
+fn decode_aave_v3_flash_loan(
+    log: &Log,
+    chain_id: i64,
+    block_number: i64,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+    contract_address: Vec<u8>,
+    block_timestamp: DateTime<Utc>,
+) -> Option<DefiEvent> {
+    let decoded = aave_v3::FlashLoan::decode_log(&log.inner).ok()?;
+
+    Some(flash_loan_event(
+        chain_id,
+        block_number,
+        tx_hash,
+        log_index,
+        contract_address,
+        block_timestamp,
+        FlashLoanTerms {
+            protocol: "aave_v3",
+            receiver: decoded.target,
+            asset: decoded.asset,
+            amount: decoded.amount,
+            fee: decoded.premium,
+            repaid: decoded.interestRateMode == 0,
+            raw_data: serde_json::json!({
+                "target": format!("0x{}", hex::encode(decoded.target.as_slice())),
+                "initiator": format!("0x{}", hex::encode(decoded.initiator.as_slice())),
+                "asset": format!("0x{}", hex::encode(decoded.asset.as_slice())),
+                "interestRateMode": decoded.interestRateMode,
+                "premium": decoded.premium.to_string(),
+            }),
+        },
+    ))
+}
+
+fn decode_aave_v2_flash_loan(
+    log: &Log,
+    chain_id: i64,
+    block_number: i64,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+    contract_address: Vec<u8>,
+    block_timestamp: DateTime<Utc>,
+) -> Option<DefiEvent> {
+    let decoded = aave_v2::FlashLoan::decode_log(&log.inner).ok()?;
+
+    Some(flash_loan_event(
+        chain_id,
+        block_number,
+        tx_hash,
+        log_index,
+        contract_address,
+        block_timestamp,
+        FlashLoanTerms {
+            protocol: "aave_v2",
+            receiver: decoded.target,
+            asset: decoded.asset,
+            amount: decoded.amount,
+            fee: decoded.premium,
+            repaid: true,
+            raw_data: serde_json::json!({
+                "target": format!("0x{}", hex::encode(decoded.target.as_slice())),
+                "initiator": format!("0x{}", hex::encode(decoded.initiator.as_slice())),
+                "asset": format!("0x{}", hex::encode(decoded.asset.as_slice())),
+                "premium": decoded.premium.to_string(),
+            }),
+        },
+    ))
+}
+
+fn decode_balancer_flash_loan(
+    log: &Log,
+    chain_id: i64,
+    block_number: i64,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+    contract_address: Vec<u8>,
+    block_timestamp: DateTime<Utc>,
+) -> Option<DefiEvent> {
+    if log.inner.data.topics().len() != 3 {
+        return None;
+    }
+    let decoded = balancer_v2::FlashLoan::decode_log(&log.inner).ok()?;
+
+    Some(flash_loan_event(
+        chain_id,
+        block_number,
+        tx_hash,
+        log_index,
+        contract_address,
+        block_timestamp,
+        FlashLoanTerms {
+            protocol: "balancer_v2",
+            receiver: decoded.recipient,
+            asset: decoded.token,
+            amount: decoded.amount,
+            fee: decoded.feeAmount,
+            repaid: true,
+            raw_data: serde_json::json!({
+                "recipient": format!("0x{}", hex::encode(decoded.recipient.as_slice())),
+                "token": format!("0x{}", hex::encode(decoded.token.as_slice())),
+                "feeAmount": decoded.feeAmount.to_string(),
+            }),
+        },
+    ))
+}
+
+fn decode_maker_flash_loan(
+    log: &Log,
+    chain_id: i64,
+    block_number: i64,
+    tx_hash: Vec<u8>,
+    log_index: i32,
+    contract_address: Vec<u8>,
+    block_timestamp: DateTime<Utc>,
+) -> Option<DefiEvent> {
+    if log.inner.data.topics().len() != 2 {
+        return None;
+    }
+    let decoded = maker::FlashLoan::decode_log(&log.inner).ok()?;
+
+    Some(flash_loan_event(
+        chain_id,
+        block_number,
+        tx_hash,
+        log_index,
+        contract_address,
+        block_timestamp,
+        FlashLoanTerms {
+            protocol: "maker",
+            receiver: decoded.receiver,
+            asset: decoded.token,
+            amount: decoded.amount,
+            fee: decoded.fee,
+            repaid: true,
+            raw_data: serde_json::json!({
+                "receiver": format!("0x{}", hex::encode(decoded.receiver.as_slice())),
+                "token": format!("0x{}", hex::encode(decoded.token.as_slice())),
+                "fee": decoded.fee.to_string(),
+            }),
+        },
+    ))
+}
+
 fn decode_comet_supply(
     log: &Log,
     chain_id: i64,
@@ -490,7 +746,7 @@ fn decode_comet_supply(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = CometSupply::decode_log(&log.inner).ok()?;
+    let decoded = compound_v3::Supply::decode_log(&log.inner).ok()?;
     let from = decoded.from;
     let dst = decoded.dst;
     let amount = u256_to_bd(decoded.amount);
@@ -525,7 +781,7 @@ fn decode_comet_withdraw(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = CometWithdraw::decode_log(&log.inner).ok()?;
+    let decoded = compound_v3::Withdraw::decode_log(&log.inner).ok()?;
     let src = decoded.src;
     let to = decoded.to;
     let amount = u256_to_bd(decoded.amount);
@@ -560,7 +816,7 @@ fn decode_comet_absorb(
     contract_address: Vec<u8>,
     block_timestamp: DateTime<Utc>,
 ) -> Option<DefiEvent> {
-    let decoded = AbsorbCollateral::decode_log(&log.inner).ok()?;
+    let decoded = compound_v3::AbsorbCollateral::decode_log(&log.inner).ok()?;
     let absorber = decoded.absorber;
     let borrower = decoded.borrower;
     let asset = decoded.asset;
@@ -589,3 +845,127 @@ fn decode_comet_absorb(
         })),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, b256};
+
+    fn log_of(event: &impl SolEvent, lender: Address) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: lender,
+                data: event.encode_log_data(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_signatures_match_mainnet_topics() {
+        assert_eq!(
+            uniswap_v2::Swap::SIGNATURE_HASH,
+            b256!("d78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822")
+        );
+        assert_eq!(
+            uniswap_v3::Swap::SIGNATURE_HASH,
+            b256!("c42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")
+        );
+        assert_eq!(
+            curve::TokenExchange::SIGNATURE_HASH,
+            b256!("8b3e96f2b889fa771c53c981b40daf005f63f637f1869f707052d15a3dd97140")
+        );
+        assert_eq!(
+            aave_v3::Supply::SIGNATURE_HASH,
+            b256!("2b627736bca15cd5381dcf80b0bf11fd197d01a037c52b927a881a10fb73ba61")
+        );
+        assert_eq!(
+            aave_v3::Borrow::SIGNATURE_HASH,
+            b256!("b3d084820fb1a9decffb176436bd02558d15fac9b0ddfed8c465bc7359d7dce0")
+        );
+        assert_eq!(
+            aave_v3::Repay::SIGNATURE_HASH,
+            b256!("a534c8dbe71f871f9f3530e97a74601fea17b426cae02e1c5aee42c96c784051")
+        );
+        assert_eq!(
+            aave_v3::LiquidationCall::SIGNATURE_HASH,
+            b256!("e413a321e8681d831f4dbccbca790d2952b56f977908e45be37335533e005286")
+        );
+        assert_eq!(
+            compound_v3::Supply::SIGNATURE_HASH,
+            b256!("d1cf3d156d5f8f0d50f6c122ed609cec09d35c9b9fb3fff6ea0959134dae424e")
+        );
+        assert_eq!(
+            compound_v3::Withdraw::SIGNATURE_HASH,
+            b256!("9b1bfa7fa9ee420a16e124f794c35ac9f90472acc99140eb2f6447c714cad8eb")
+        );
+        assert_eq!(
+            compound_v3::AbsorbCollateral::SIGNATURE_HASH,
+            b256!("9850ab1af75177e4a9201c65a2cf7976d5d28e40ef63494b44366f86b2f9412e")
+        );
+        assert_eq!(
+            aave_v3::FlashLoan::SIGNATURE_HASH,
+            b256!("efefaba5e921573100900a3ad9cf29f222d995fb3b6045797eaea7521bd8d6f0")
+        );
+        assert_eq!(
+            aave_v2::FlashLoan::SIGNATURE_HASH,
+            b256!("631042c832b07452973831137f2d73e395028b44b250dedc5abb0ee766e168ac")
+        );
+        assert_eq!(
+            balancer_v2::FlashLoan::SIGNATURE_HASH,
+            b256!("0d7d75e01ab95780d3cd1c8ec0dd6c2ce19e3a20427eec8bf53283b6fb8e95f0")
+        );
+        assert_eq!(balancer_v2::FlashLoan::SIGNATURE_HASH, maker::FlashLoan::SIGNATURE_HASH);
+    }
+
+    #[test]
+    fn test_decode_flash_loans() {
+        let receiver = address!("00000000000000000000000000000000000000aa");
+        let dai = address!("6b175474e89094c44da98b954eedeac495271d0f");
+        let amount = U256::from(1_000_000u64);
+        let ts = Utc::now();
+
+        let balancer = log_of(
+            &balancer_v2::FlashLoan {
+                recipient: receiver,
+                token: dai,
+                amount,
+                feeAmount: U256::ZERO,
+            },
+            address!("ba12222222228d8ba445958a75a0704d566bf2c8"),
+        );
+        let maker = log_of(
+            &maker::FlashLoan {
+                receiver,
+                token: dai,
+                amount,
+                fee: U256::from(5u64),
+            },
+            address!("60744434d6339a6b27d73d9eda62b6f66a0a04fa"),
+        );
+        let aave_debt = log_of(
+            &aave_v3::FlashLoan {
+                target: receiver,
+                initiator: receiver,
+                asset: dai,
+                amount,
+                interestRateMode: 2,
+                premium: U256::ZERO,
+                referralCode: 0,
+            },
+            address!("87870bca3f3fd6335c3f4ce8392d69350b4fa4e2"),
+        );
+
+        let events = decode_defi_logs(&[balancer, maker, aave_debt], ts, 1);
+        let protocols: Vec<&str> = events.iter().map(|e| e.protocol.as_str()).collect();
+        assert_eq!(protocols, ["balancer_v2", "maker", "aave_v3"]);
+        assert!(events.iter().all(|e| e.event_type == "flash_loan"));
+        assert_eq!(events[0].account.as_deref(), Some(receiver.as_slice()));
+        assert_eq!(events[0].token_out.as_deref(), Some(dai.as_slice()));
+
+        // Repaid with the fee, except the Aave loan left open as debt
+        assert_eq!(events[1].amount_in, Some(BigDecimal::from(1_000_005)));
+        assert_eq!(events[2].amount_in, None);
+        assert_eq!(events[2].token_in, None);
+    }
+}
@@ -5,6 +5,8 @@ use chrono::{DateTime, Utc};
 /// A decoded stablecoin Transfer event, ready for DB insertion.
 #[derive(Debug, Clone)]
 pub struct StablecoinTransfer {
+    /// Database id, known once the transfer has been stored.
+    pub id: Option<i64>,
     pub chain_id: i64,
     pub block_number: i64,
     pub block_hash: Vec<u8>,
@@ -17,6 +19,54 @@ pub struct StablecoinTransfer {
     pub token_symbol: String,
     pub token_decimals: i16,
     pub block_timestamp: DateTime<Utc>,
+    /// USD value, once the pricing stage has valued the transfer.
+    pub amount_usd: Option<BigDecimal>,
+}
+
+impl StablecoinTransfer {
+    /// Key referencing this transfer from partitioned child tables, once it has been stored.
+    pub fn key(&self) -> Option<TransferKey> {
+        self.id.map(|id| TransferKey {
+            id,
+            chain_id: self.chain_id,
+            block_timestamp: self.block_timestamp,
+        })
+    }
+}
+
+/// A USDC transfer on chain 1 for tests, with `amount` in base units. Hashes follow the
+/// block number and blocks are a minute apart from 2024-01-01; override the rest with
+/// struct update syntax.
+#[cfg(test)]
+pub fn test_transfer(block_number: i64, from: u8, to: u8, amount: i64) -> StablecoinTransfer {
+    use chrono::{Duration, TimeZone};
+
+    StablecoinTransfer {
+        id: None,
+        chain_id: 1,
+        block_number,
+        block_hash: vec![block_number as u8; 32],
+        tx_hash: vec![block_number as u8; 32],
+        log_index: 0,
+        token_address: vec![9; 20],
+        from_address: vec![from; 20],
+        to_address: vec![to; 20],
+        amount: BigDecimal::from(amount),
+        token_symbol: "USDC".to_string(),
+        token_decimals: 6,
+        block_timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+            + Duration::minutes(block_number),
+        amount_usd: None,
+    }
+}
+
+/// Full key of a stored transfer. `transfers` is partitioned by chain and month,
+/// so foreign keys from child tables carry all three columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransferKey {
+    pub id: i64,
+    pub chain_id: i64,
+    pub block_timestamp: DateTime<Utc>,
 }
 
 /// Minimal block info needed by the indexer.
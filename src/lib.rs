@@ -1,12 +1,16 @@
+pub mod alert;
 pub mod anomaly;
 pub mod api;
 pub mod config;
 pub mod db;
+pub mod enrichment;
 pub mod entity;
+pub mod export;
 pub mod graph;
 pub mod indexer;
 pub mod onramp;
 pub mod pipeline;
+pub mod pricing;
 pub mod seed;
 pub mod tokens;
 pub mod wallet;
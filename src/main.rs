@@ -1,16 +1,129 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
 use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::EnvFilter;
 
+use chainwatch_indexer::alert::dispatch::{run_alert_dispatcher, AlertDispatcher};
+use chainwatch_indexer::anomaly::backtest::{backtest, BacktestOptions, GroundTruth};
+use chainwatch_indexer::anomaly::engine::AnomalyEngine;
 use chainwatch_indexer::config::Config;
+use chainwatch_indexer::db::partitions::run_partition_maintenance;
+use chainwatch_indexer::entity::label_store::EntityLabelStore;
+use chainwatch_indexer::enrichment::reenrich::{reenrich, ReenrichOptions, Stage};
+use chainwatch_indexer::enrichment::worker::run_enrichment_worker;
+use chainwatch_indexer::export::job::{run_export, run_export_scheduler, ExportOptions};
+use chainwatch_indexer::export::parquet::Dataset;
 use chainwatch_indexer::indexer::chain::run_chain_indexer;
 use chainwatch_indexer::onramp::registry::{seed_fiat_currencies, seed_onramp_providers};
 use chainwatch_indexer::pipeline::TransferPipeline;
 use chainwatch_indexer::tokens::registry::seed_known_tokens;
 
+#[derive(Parser)]
+#[command(name = "chainwatch-indexer", args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Path to the TOML config file
+    #[arg(default_value = "config.toml")]
+    config: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay stored transfers through selected enrichment stages
+    Reenrich(ReenrichArgs),
+    /// Export transfers, DeFi events, anomalies and entity flags to Parquet
+    Export(ExportArgs),
+    /// Count the alerts candidate anomaly rules would raise over stored transfers
+    Backtest(BacktestArgs),
+}
+
+#[derive(Args)]
+struct ReenrichArgs {
+    /// Path to the TOML config file
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+    /// Chain name as configured in [[chains]]
+    #[arg(long)]
+    chain: String,
+    #[arg(long)]
+    from_block: Option<i64>,
+    #[arg(long)]
+    to_block: Option<i64>,
+    /// RFC 3339 timestamp, inclusive
+    #[arg(long)]
+    since: Option<DateTime<Utc>>,
+    /// RFC 3339 timestamp, inclusive
+    #[arg(long)]
+    until: Option<DateTime<Utc>>,
+    /// Comma-separated stages to rebuild
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Stage::Prices, Stage::Entities, Stage::Anomalies, Stage::Graph, Stage::Rollups, Stage::Risk])]
+    stages: Vec<Stage>,
+    /// Report differences without writing anything
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long, default_value_t = 50)]
+    batch_size: i64,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Path to the TOML config file
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+    /// Chain name as configured in [[chains]]; all chains when omitted
+    #[arg(long)]
+    chain: Option<String>,
+    /// Comma-separated datasets to export
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = Dataset::ALL)]
+    datasets: Vec<Dataset>,
+    /// Overrides [export].output_dir
+    #[arg(long)]
+    output_dir: Option<String>,
+    /// Overrides [export].batch_size
+    #[arg(long)]
+    batch_size: Option<i64>,
+}
+
+#[derive(Args)]
+struct BacktestArgs {
+    /// Path to the TOML config file
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+    /// Chain name as configured in [[chains]]; all chains when omitted
+    #[arg(long)]
+    chain: Option<String>,
+    /// Needs --chain
+    #[arg(long, requires = "chain")]
+    from_block: Option<i64>,
+    /// Needs --chain
+    #[arg(long, requires = "chain")]
+    to_block: Option<i64>,
+    /// RFC 3339 timestamp, inclusive
+    #[arg(long)]
+    since: Option<DateTime<Utc>>,
+    /// RFC 3339 timestamp, inclusive
+    #[arg(long)]
+    until: Option<DateTime<Utc>>,
+    /// TOML file whose [anomaly_detection] table replaces the config's
+    #[arg(long)]
+    anomaly_config: Option<String>,
+    /// Overrides anomaly_detection.custom_rules_path
+    #[arg(long)]
+    custom_rules: Option<String>,
+    /// CSV of labelled transfers (chain_id,tx_hash,log_index[,anomaly_type]) to score against
+    #[arg(long)]
+    ground_truth: Option<String>,
+    #[arg(long, default_value_t = 500)]
+    batch_size: i64,
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     color_eyre::install()?;
@@ -23,14 +136,18 @@ async fn main() -> eyre::Result<()> {
         .with_target(true)
         .init();
 
-    tracing::info!("ChainWatch Indexer starting");
-
-    // Load configuration
-    let config_path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "config.toml".to_string());
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Reenrich(args)) => run_reenrich(args).await,
+        Some(Command::Export(args)) => run_export_command(args).await,
+        Some(Command::Backtest(args)) => run_backtest(args).await,
+        None => run_indexer(&cli.config).await,
+    }
+}
 
-    let config = Config::load(&config_path)?;
+/// Load configuration, connect to PostgreSQL and run migrations.
+async fn connect(config_path: &str) -> eyre::Result<(Config, PgPool)> {
+    let config = Config::load(config_path)?;
     tracing::info!(
         chains = config.chains.len(),
         "Configuration loaded from {}",
@@ -53,6 +170,143 @@ async fn main() -> eyre::Result<()> {
         .map_err(|e| eyre::eyre!("Failed to run migrations: {}", e))?;
 
     tracing::info!("Database migrations complete");
+    Ok((config, pool))
+}
+
+/// Initialize the enrichment pipeline and seed exchange wallet labels into it.
+async fn init_pipeline(pool: &PgPool, config: &Config) -> eyre::Result<TransferPipeline> {
+    // Initialize the enrichment pipeline (entity labels, wallet tracker, anomaly engine)
+    let mut pipeline = TransferPipeline::init(pool, Some(pool), config).await?;
+    tracing::info!("Enrichment pipeline initialized");
+
+    // Seed exchange wallets from JSON file
+    if let Some(ref path) = config.api.exchange_wallets_path {
+        match chainwatch_indexer::seed::exchange_wallets::seed_exchange_wallets(
+            pool,
+            &mut pipeline.entity_store,
+            path,
+        )
+        .await
+        {
+            Ok(count) => tracing::info!(count, "Exchange wallets seeded"),
+            Err(e) => tracing::warn!(error = %e, "Failed to seed exchange wallets, continuing without"),
+        }
+    }
+
+    Ok(pipeline)
+}
+
+/// Rebuild enrichment for a historical range and print the resulting diff report.
+async fn run_reenrich(args: ReenrichArgs) -> eyre::Result<()> {
+    let (config, pool) = connect(&args.config).await?;
+
+    let chain = config
+        .chains
+        .iter()
+        .find(|c| c.name == args.chain)
+        .ok_or_else(|| eyre::eyre!("Chain '{}' is not configured", args.chain))?;
+
+    let pipeline = init_pipeline(&pool, &config).await?;
+
+    let options = ReenrichOptions {
+        chain_id: chain.chain_id as i64,
+        from_block: args.from_block,
+        to_block: args.to_block,
+        since: args.since,
+        until: args.until,
+        stages: args.stages,
+        dry_run: args.dry_run,
+        batch_size: args.batch_size,
+    };
+
+    let report = reenrich(&pool, &pipeline, &options).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Run candidate anomaly rules over a historical range and print alert counts, scored
+/// against the ground truth file when one is given. Nothing is written.
+async fn run_backtest(args: BacktestArgs) -> eyre::Result<()> {
+    let (config, pool) = connect(&args.config).await?;
+
+    let mut candidate = match &args.anomaly_config {
+        Some(path) => config.with_anomaly_detection(path)?,
+        None => config.clone(),
+    };
+    if let Some(path) = args.custom_rules {
+        candidate.anomaly_detection.custom_rules_path = Some(path);
+    }
+    let engine = AnomalyEngine::new(candidate.anomaly_detection.clone(), &candidate.chains)?;
+    let label_store = EntityLabelStore::load(&pool).await?;
+    let truth = args
+        .ground_truth
+        .as_deref()
+        .map(GroundTruth::load)
+        .transpose()?;
+
+    let chains = match &args.chain {
+        Some(name) => {
+            let chain = config
+                .chains
+                .iter()
+                .find(|c| &c.name == name)
+                .ok_or_else(|| eyre::eyre!("Chain '{}' is not configured", name))?;
+            vec![(chain.chain_id as i64, chain.name.clone())]
+        }
+        None => config
+            .chains
+            .iter()
+            .map(|c| (c.chain_id as i64, c.name.clone()))
+            .collect(),
+    };
+
+    let options = BacktestOptions {
+        chains,
+        from_block: args.from_block,
+        to_block: args.to_block,
+        since: args.since,
+        until: args.until,
+        batch_size: args.batch_size,
+    };
+
+    let report = backtest(&pool, &engine, &label_store, &options, truth.as_ref()).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Export new rows since the last run to Parquet and print a summary report.
+async fn run_export_command(args: ExportArgs) -> eyre::Result<()> {
+    let (config, pool) = connect(&args.config).await?;
+
+    let chain_ids = match &args.chain {
+        Some(name) => {
+            let chain = config
+                .chains
+                .iter()
+                .find(|c| &c.name == name)
+                .ok_or_else(|| eyre::eyre!("Chain '{}' is not configured", name))?;
+            vec![chain.chain_id as i64]
+        }
+        None => config.chains.iter().map(|c| c.chain_id as i64).collect(),
+    };
+
+    let options = ExportOptions {
+        output_dir: args.output_dir.unwrap_or(config.export.output_dir).into(),
+        chain_ids,
+        datasets: args.datasets,
+        batch_size: args.batch_size.unwrap_or(config.export.batch_size),
+    };
+
+    let report = run_export(&pool, &options).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Run ingestion, enrichment workers and the API until Ctrl+C.
+async fn run_indexer(config_path: &str) -> eyre::Result<()> {
+    tracing::info!("ChainWatch Indexer starting");
+
+    let (config, pool) = connect(config_path).await?;
 
     // Seed known tokens from config
     seed_known_tokens(&pool, &config.chains).await?;
@@ -75,24 +329,7 @@ async fn main() -> eyre::Result<()> {
         );
     }
 
-    // Initialize the enrichment pipeline (entity labels, wallet tracker, anomaly engine)
-    let mut pipeline = TransferPipeline::init(&pool, &config).await?;
-    tracing::info!("Enrichment pipeline initialized");
-
-    // Seed exchange wallets from JSON file
-    if let Some(ref path) = config.api.exchange_wallets_path {
-        match chainwatch_indexer::seed::exchange_wallets::seed_exchange_wallets(
-            &pool,
-            &mut pipeline.entity_store,
-            path,
-        )
-        .await
-        {
-            Ok(count) => tracing::info!(count, "Exchange wallets seeded"),
-            Err(e) => tracing::warn!(error = %e, "Failed to seed exchange wallets, continuing without"),
-        }
-    }
-
+    let pipeline = init_pipeline(&pool, &config).await?;
     let pipeline = Arc::new(Mutex::new(pipeline));
 
     // Spawn API server
@@ -101,7 +338,7 @@ async fn main() -> eyre::Result<()> {
         let host = config.api.host.clone();
         let port = config.api.port;
         tokio::spawn(async move {
-            if let Err(e) = chainwatch_indexer::api::serve(api_pool, &host, port).await {
+            if let Err(e) = chainwatch_indexer::api::serve(api_pool.clone(), Some(api_pool), &host, port).await {
                 tracing::error!(error = %e, "API server failed");
             }
         });
@@ -110,24 +347,87 @@ async fn main() -> eyre::Result<()> {
     // Create shutdown signal
     let shutdown = CancellationToken::new();
 
-    // Spawn one indexer task per chain
     let mut handles = Vec::new();
-    for chain_config in config.chains {
+
+    // Spawn partition maintenance (upcoming partitions and retention)
+    {
         let pool = pool.clone();
         let shutdown = shutdown.clone();
-        let pipeline = pipeline.clone();
+        let storage_config = config.storage.clone();
+        let chain_ids: Vec<i64> = config.chains.iter().map(|c| c.chain_id as i64).collect();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = run_partition_maintenance(chain_ids, storage_config, pool, shutdown).await {
+                tracing::error!(error = %e, "Partition maintenance failed");
+            }
+        }));
+    }
+
+    // Spawn scheduled Parquet export
+    if config.export.enabled {
+        let pool = pool.clone();
+        let shutdown = shutdown.clone();
+        let export_config = config.export.clone();
+        let chain_ids: Vec<i64> = config.chains.iter().map(|c| c.chain_id as i64).collect();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = run_export_scheduler(chain_ids, export_config, pool, shutdown).await {
+                tracing::error!(error = %e, "Parquet export scheduler failed");
+            }
+        }));
+    }
+
+    // Spawn outbound alert delivery
+    if config.alerting.enabled {
+        // Built here so a missing webhook secret stops startup
+        let dispatcher = AlertDispatcher::new(config.alerting.clone())?;
+        let pool = pool.clone();
+        let shutdown = shutdown.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = run_alert_dispatcher(dispatcher, pool, shutdown).await {
+                tracing::error!(error = %e, "Alert dispatcher failed");
+            }
+        }));
+    }
+
+    // Spawn one indexer task and one enrichment worker per chain
+    for chain_config in config.chains {
         let chain_name = chain_config.name.clone();
+        let chain_id = chain_config.chain_id as i64;
+
+        let enrichment_handle = {
+            let pool = pool.clone();
+            let shutdown = shutdown.clone();
+            let pipeline = pipeline.clone();
+            let enrichment_config = config.enrichment.clone();
+            let chain_name = chain_name.clone();
+            tokio::spawn(async move {
+                if let Err(e) = run_enrichment_worker(
+                    chain_name.clone(),
+                    chain_id,
+                    enrichment_config,
+                    pool,
+                    shutdown,
+                    pipeline,
+                )
+                .await
+                {
+                    tracing::error!(chain = %chain_name, error = %e, "Enrichment worker failed");
+                }
+            })
+        };
 
+        let pool = pool.clone();
+        let shutdown = shutdown.clone();
         let handle = tokio::spawn(async move {
-            if let Err(e) = run_chain_indexer(chain_config, pool, shutdown, pipeline).await {
+            if let Err(e) = run_chain_indexer(chain_config, pool, shutdown).await {
                 tracing::error!(chain = %chain_name, error = %e, "Chain indexer failed");
             }
         });
 
         handles.push(handle);
+        handles.push(enrichment_handle);
     }
 
-    tracing::info!("All chain indexers started. Press Ctrl+C to stop.");
+    tracing::info!("All chain indexers and enrichment workers started. Press Ctrl+C to stop.");
 
     // Wait for shutdown signal
     tokio::signal::ctrl_c().await?;
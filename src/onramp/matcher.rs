@@ -1,27 +1,30 @@
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
-use crate::indexer::types::StablecoinTransfer;
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
 use crate::onramp::registry::ProviderWalletInfo;
 
 /// Match a batch of transfers against known provider wallets and record attributions.
-/// For each transfer where from_address or to_address matches a known exchange wallet,
-/// insert a record into onramp_transfers.
+/// For each stored transfer where from_address or to_address matches a known exchange wallet,
+/// insert a record into onramp_transfers. All attributions are written in one statement.
 pub async fn attribute_onramp_transfers(
     pool: &PgPool,
     chain_name: &str,
     transfers: &[StablecoinTransfer],
     wallet_index: &HashMap<(String, Vec<u8>), ProviderWalletInfo>,
 ) -> eyre::Result<u64> {
-    let mut attributed = 0u64;
+    let mut attributions: Vec<(TransferKey, i32, &str)> = Vec::new();
 
     for transfer in transfers {
+        let Some(key) = transfer.key() else {
+            continue;
+        };
         let chain_key = chain_name.to_string();
 
         // Check if from_address is a known provider wallet (withdrawal: exchange -> user)
         if let Some(info) = wallet_index.get(&(chain_key.clone(), transfer.from_address.clone())) {
-            record_attribution(pool, transfer, info.provider_id, "withdrawal").await?;
-            attributed += 1;
+            attributions.push((key, info.provider_id, "withdrawal"));
             tracing::debug!(
                 provider = %info.provider_name,
                 direction = "withdrawal",
@@ -33,8 +36,7 @@ pub async fn attribute_onramp_transfers(
 
         // Check if to_address is a known provider wallet (deposit: user -> exchange)
         if let Some(info) = wallet_index.get(&(chain_key, transfer.to_address.clone())) {
-            record_attribution(pool, transfer, info.provider_id, "deposit").await?;
-            attributed += 1;
+            attributions.push((key, info.provider_id, "deposit"));
             tracing::debug!(
                 provider = %info.provider_name,
                 direction = "deposit",
@@ -44,40 +46,38 @@ pub async fn attribute_onramp_transfers(
         }
     }
 
-    Ok(attributed)
+    record_attributions(pool, &attributions).await?;
+    Ok(attributions.len() as u64)
 }
 
-/// Insert an attribution record linking a transfer to a provider.
-/// Uses the transfer's chain_id + tx_hash + log_index to find the transfer ID.
-async fn record_attribution(
+/// Insert attribution records as (transfer, provider_id, direction) in bulk.
+async fn record_attributions(
     pool: &PgPool,
-    transfer: &StablecoinTransfer,
-    provider_id: i32,
-    direction: &str,
+    attributions: &[(TransferKey, i32, &str)],
 ) -> eyre::Result<()> {
-    // Look up the transfer ID (it was just inserted in the same batch)
-    let row: Option<(i64,)> = sqlx::query_as(
-        "SELECT id FROM transfers
-         WHERE chain_id = $1 AND tx_hash = $2 AND log_index = $3",
+    if attributions.is_empty() {
+        return Ok(());
+    }
+
+    let transfer_ids: Vec<i64> = attributions.iter().map(|(key, _, _)| key.id).collect();
+    let chain_ids: Vec<i64> = attributions.iter().map(|(key, _, _)| key.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> =
+        attributions.iter().map(|(key, _, _)| key.block_timestamp).collect();
+    let provider_ids: Vec<i32> = attributions.iter().map(|(_, p, _)| *p).collect();
+    let directions: Vec<&str> = attributions.iter().map(|(_, _, d)| *d).collect();
+
+    sqlx::query(
+        "INSERT INTO onramp_transfers (transfer_id, chain_id, block_timestamp, provider_id, direction)
+         SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::INTEGER[], $5::TEXT[])
+         ON CONFLICT (transfer_id, chain_id, block_timestamp) DO NOTHING",
     )
-    .bind(transfer.chain_id)
-    .bind(&transfer.tx_hash)
-    .bind(transfer.log_index)
-    .fetch_optional(pool)
+    .bind(&transfer_ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&provider_ids)
+    .bind(&directions)
+    .execute(pool)
     .await?;
 
-    if let Some((transfer_id,)) = row {
-        sqlx::query(
-            "INSERT INTO onramp_transfers (transfer_id, provider_id, direction)
-             VALUES ($1, $2, $3)
-             ON CONFLICT (transfer_id) DO NOTHING",
-        )
-        .bind(transfer_id)
-        .bind(provider_id)
-        .bind(direction)
-        .execute(pool)
-        .await?;
-    }
-
     Ok(())
 }
@@ -106,12 +106,15 @@ pub async fn seed_fiat_currencies(
     Ok(())
 }
 
+/// Chain name, address, provider id and name, and wallet label.
+type ProviderWalletRow = (String, Vec<u8>, i32, String, Option<String>);
+
 /// Build an in-memory lookup of provider wallet addresses to provider IDs.
 /// Used at runtime to match incoming transfers against known exchange wallets.
 pub async fn load_provider_wallet_index(
     pool: &PgPool,
 ) -> eyre::Result<HashMap<(String, Vec<u8>), ProviderWalletInfo>> {
-    let rows: Vec<(String, Vec<u8>, i32, String, Option<String>)> = sqlx::query_as(
+    let rows: Vec<ProviderWalletRow> = sqlx::query_as(
         "SELECT pw.chain_name, pw.address, pw.provider_id, op.name, pw.label
          FROM provider_wallets pw
          JOIN onramp_providers op ON op.id = pw.provider_id",
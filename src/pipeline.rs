@@ -1,46 +1,75 @@
 use sqlx::PgPool;
 
+use crate::alert::dispatch;
+use crate::alert::routing::AlertRouter;
+use crate::anomaly::activity::ActivityWindows;
+use crate::anomaly::{custom, suppression};
 use crate::anomaly::engine::{self, AnomalyEngine};
-use crate::config::Config;
+use crate::config::{Config, RiskScoreConfig};
+use crate::db::store::Store;
 use crate::entity::label_store::EntityLabelStore;
 use crate::entity::matcher;
 use crate::entity::ofac;
-use crate::graph::tracker;
+use crate::enrichment::rollup;
+use crate::graph::exposure::ExposureTracker;
 use crate::indexer::types::StablecoinTransfer;
+use crate::pricing::{self, PriceOracle};
 use crate::wallet::first_seen::WalletTracker;
+use crate::wallet::risk;
 
 /// Result of running the enrichment pipeline on a batch of transfers.
 #[derive(Debug, Default)]
 pub struct EnrichmentResult {
+    pub amounts_priced: u64,
     pub entities_attributed: u64,
     pub new_wallets_found: u64,
     pub anomalies_detected: u64,
+    pub anomalies_suppressed: u64,
     pub graph_edges_updated: u64,
+    pub exposures_updated: u64,
+    pub baselines_updated: u64,
+    pub alerts_queued: u64,
+    pub risk_scores_updated: u64,
 }
 
 /// Orchestrates all post-insert enrichment steps:
+/// 0. USD valuation of each transfer and stablecoin price windows
 /// 1. Wallet first-seen detection
 /// 2. Entity attribution (label matching)
-/// 3. Graph edge updates
-/// 4. Anomaly detection
+/// 3. Graph edge updates and sanctions exposure
+/// 4. Anomaly detection and suppression, wallet baseline updates and alert routing
+/// 5. Rollup updates
+/// 6. Address risk scores
 pub struct TransferPipeline {
+    pub price_oracle: PriceOracle,
     pub entity_store: EntityLabelStore,
     pub wallet_tracker: WalletTracker,
     pub anomaly_engine: AnomalyEngine,
+    pub activity_windows: ActivityWindows,
+    pub exposure_tracker: ExposureTracker,
+    pub alert_router: AlertRouter,
+    pub risk_config: RiskScoreConfig,
+    /// Pool for the stages that only exist as SQL; they are skipped without one.
+    pub postgres: Option<PgPool>,
 }
 
 impl TransferPipeline {
     /// Initialize the pipeline: load entity labels, wallet tracker, and anomaly config.
-    pub async fn init(pool: &PgPool, config: &Config) -> eyre::Result<Self> {
-        // Load entity labels from DB
-        let mut entity_store = EntityLabelStore::load_from_db(pool).await?;
+    /// Without Postgres, prices start without FX rates and activity windows start empty.
+    pub async fn init<S: Store>(
+        store: &S,
+        postgres: Option<&PgPool>,
+        config: &Config,
+    ) -> eyre::Result<Self> {
+        // Load entity labels from the store
+        let mut entity_store = EntityLabelStore::load(store).await?;
 
         // Seed OFAC entries if configured
         if let Some(ofac_path) = &config.entity_attribution.ofac_sdn_path {
             match ofac::parse_ofac_csv(ofac_path) {
                 Ok(entries) => {
                     let count =
-                        ofac::seed_ofac_entries(pool, &mut entity_store, &entries).await?;
+                        ofac::seed_ofac_entries(store, postgres, &mut entity_store, &entries).await?;
                     tracing::info!(count, "OFAC SDN entries loaded");
                 }
                 Err(e) => {
@@ -51,71 +80,184 @@ impl TransferPipeline {
 
         // Seed manual labels from config
         if !config.entity_attribution.manual_labels.is_empty() {
-            ofac::seed_manual_labels(pool, &mut entity_store, &config.entity_attribution.manual_labels)
+            ofac::seed_manual_labels(store, &mut entity_store, &config.entity_attribution.manual_labels)
                 .await?;
         }
 
+        let price_oracle = match postgres {
+            Some(pool) => PriceOracle::init(pool, config).await?,
+            None => PriceOracle::new(config)?,
+        };
+
         // Load wallet tracker
-        let wallet_tracker = WalletTracker::load_from_db(pool).await?;
+        let wallet_tracker = WalletTracker::load(store).await?;
 
-        // Create anomaly engine
-        let anomaly_engine = AnomalyEngine::new(config.anomaly_detection.clone());
+        // Create anomaly engine and record the custom rule versions it runs
+        let anomaly_engine = AnomalyEngine::new(config.anomaly_detection.clone(), &config.chains)?;
+        let activity_windows = match postgres {
+            Some(pool) => {
+                if !anomaly_engine.custom_rules().is_empty() {
+                    custom::register_rules(pool, anomaly_engine.custom_rules()).await?;
+                    tracing::info!(
+                        rules = anomaly_engine.custom_rules().len(),
+                        "Custom anomaly rules loaded"
+                    );
+                }
+                suppression::sync_config_rules(pool, &config.anomaly_detection.suppressions, &config.chains)
+                    .await?;
+                ActivityWindows::load_from_db(pool, &config.anomaly_detection).await?
+            }
+            None => ActivityWindows::new(&config.anomaly_detection),
+        };
 
         Ok(Self {
+            price_oracle,
             entity_store,
             wallet_tracker,
             anomaly_engine,
+            activity_windows,
+            exposure_tracker: ExposureTracker::new(config.sanctions_exposure.clone()),
+            alert_router: AlertRouter::new(&config.alerting, &config.chains),
+            risk_config: config.risk_score.clone(),
+            postgres: postgres.cloned(),
         })
     }
 
     /// Run all enrichment steps on a batch of just-inserted transfers.
-    pub async fn enrich(
+    /// Without Postgres only steps 1 to 3 run, minus sanctions exposure.
+    pub async fn enrich<S: Store>(
         &mut self,
-        pool: &PgPool,
-        _chain_name: &str,
+        store: &S,
         transfers: &[StablecoinTransfer],
     ) -> eyre::Result<EnrichmentResult> {
         if transfers.is_empty() {
             return Ok(EnrichmentResult::default());
         }
 
+        let postgres = self.postgres.clone();
+
+        // Step 0: Value each transfer in USD, which the rules below compare against
+        let (transfers, amounts_priced) = match &postgres {
+            Some(pool) => self.value_transfers(pool, transfers).await?,
+            None => (transfers.to_vec(), 0),
+        };
+        let transfers = transfers.as_slice();
+
         // Step 1: Detect new wallets
         let new_wallets = self
             .wallet_tracker
-            .process_transfers(pool, transfers)
+            .process_transfers(store, transfers)
             .await?;
         let new_wallets_found = new_wallets.len() as u64;
 
         // Step 2: Entity attribution
         let entities_attributed =
-            matcher::attribute_entities(pool, transfers, &self.entity_store).await?;
+            matcher::attribute_entities(store, transfers, &self.entity_store).await?;
 
         // Step 3: Update graph edges
-        let graph_edges_updated = tracker::update_edges(pool, transfers).await?;
+        let graph_edges_updated = store.update_graph_edges(transfers).await?;
+
+        let Some(pool) = &postgres else {
+            return Ok(EnrichmentResult {
+                new_wallets_found,
+                entities_attributed,
+                graph_edges_updated,
+                ..Default::default()
+            });
+        };
+        let exposures_updated = self
+            .exposure_tracker
+            .observe(pool, transfers, &self.entity_store)
+            .await?;
 
         // Step 4: Anomaly detection
+        self.activity_windows.cover(pool, transfers).await?;
+        self.activity_windows.record(transfers);
         let anomalies = self
             .anomaly_engine
-            .analyze_batch(pool, transfers, &self.entity_store, &new_wallets)
+            .analyze_batch(
+                pool,
+                transfers,
+                &self.entity_store,
+                &new_wallets,
+                &self.activity_windows,
+            )
             .await?;
-        let anomalies_detected = engine::persist_anomalies(pool, &anomalies).await?;
-
-        if anomalies_detected > 0 {
-            for anomaly in &anomalies {
-                tracing::warn!(
-                    anomaly_type = anomaly.anomaly_type.as_str(),
-                    risk_score = anomaly.risk_score,
-                    flags = ?anomaly.flags,
-                    "ANOMALY DETECTED"
-                );
-            }
+        let (anomalies, suppressed) = self
+            .anomaly_engine
+            .suppress(pool, anomalies, transfers, &self.entity_store, true)
+            .await?;
+        let anomalies_suppressed = suppressed.len() as u64;
+        // Only newly stored anomalies are logged and alerted, so a retried batch stays quiet
+        let (anomaly_ids, anomalies) =
+            engine::persist_anomalies(pool, anomalies, self.anomaly_engine.aggregation()).await?;
+        let anomalies_detected = anomaly_ids.len() as u64;
+        let baselines_updated = self.anomaly_engine.record_baselines(pool, transfers).await?;
+        let alerts_queued = dispatch::enqueue(
+            pool,
+            &self.alert_router,
+            &anomalies,
+            transfers,
+            &self.entity_store,
+        )
+        .await?;
+
+        for anomaly in &anomalies {
+            tracing::warn!(
+                anomaly_type = anomaly.anomaly_type.as_str(),
+                risk_score = anomaly.risk_score,
+                flags = ?anomaly.flags,
+                "ANOMALY DETECTED"
+            );
         }
 
+        // Step 5: Rollups
+        rollup::update_rollups(pool, transfers, &self.entity_store, &anomaly_ids).await?;
+
+        // Step 6: Address risk scores, once anomalies and exposure are stored
+        let risk_scores_updated =
+            risk::update_scores(pool, transfers, &self.entity_store, &self.risk_config).await?;
+
         Ok(EnrichmentResult {
+            amounts_priced,
             entities_attributed,
             new_wallets_found,
             anomalies_detected,
+            anomalies_suppressed,
             graph_edges_updated,
+            exposures_updated,
+            baselines_updated,
+            alerts_queued,
+            risk_scores_updated,
         })
     }
+
+    /// Price a batch and store the USD amounts. Returns the priced transfers and how
+    /// many amounts were stored.
+    async fn value_transfers(
+        &mut self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<(Vec<StablecoinTransfer>, u64)> {
+        self.price_oracle.refresh_fx(pool).await?;
+        self.price_oracle.refresh_dex(pool, transfers).await?;
+        self.price_oracle.refresh_stable_prices(pool, transfers).await?;
+        let values = self.price_oracle.value(pool, transfers).await?;
+        let mut amounts = Vec::new();
+        let transfers: Vec<StablecoinTransfer> = transfers
+            .iter()
+            .zip(values)
+            .map(|(t, amount_usd)| {
+                if let (Some(key), Some(value)) = (t.key(), &amount_usd) {
+                    amounts.push((key, Some(value.clone())));
+                }
+                StablecoinTransfer {
+                    amount_usd,
+                    ..t.clone()
+                }
+            })
+            .collect();
+        let amounts_priced = pricing::store_amounts(pool, &amounts).await?;
+        Ok((transfers, amounts_priced))
+    }
 }
@@ -0,0 +1,117 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+/// Recompute the hourly DEX price buckets of `chain_id` covering `[since, until]`.
+///
+/// Swap events do not carry their tokens, so each side is read from the watched transfers
+/// into and out of the pool in the same transaction. A non-reference token swapped for a
+/// reference token adds to its bucket's volumes. Buckets are rewritten whole, so recomputing
+/// one that later swaps have joined is idempotent.
+pub async fn refresh_buckets(
+    pool: &PgPool,
+    chain_id: i64,
+    reference_tokens: &[Vec<u8>],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> eyre::Result<u64> {
+    if reference_tokens.is_empty() {
+        return Ok(0);
+    }
+    let hour = Duration::hours(1);
+    let since = since.duration_trunc(hour)?;
+    let until = until.duration_trunc(hour)? + hour;
+
+    let result = sqlx::query(
+        "INSERT INTO dex_token_prices (chain_id, token_address, bucket, token_volume, usd_volume, swap_count)
+         SELECT s.chain_id, p.token_address, date_trunc('hour', s.block_timestamp),
+                SUM(p.amount / power(10::NUMERIC, p.token_decimals)),
+                SUM(r.amount / power(10::NUMERIC, r.token_decimals)),
+                COUNT(DISTINCT s.id)
+         FROM defi_events s
+         JOIN transfers p ON p.chain_id = s.chain_id AND p.block_timestamp = s.block_timestamp
+            AND p.tx_hash = s.tx_hash
+            AND (p.from_address = s.contract_address OR p.to_address = s.contract_address)
+         JOIN transfers r ON r.chain_id = s.chain_id AND r.block_timestamp = s.block_timestamp
+            AND r.tx_hash = s.tx_hash
+            AND (r.from_address = s.contract_address AND p.to_address = s.contract_address
+                 OR r.to_address = s.contract_address AND p.from_address = s.contract_address)
+         WHERE s.chain_id = $1 AND s.event_type = 'swap'
+           AND s.block_timestamp >= $3 AND s.block_timestamp < $4
+           AND r.token_address = ANY($2) AND NOT p.token_address = ANY($2)
+           AND p.amount > 0
+         GROUP BY 1, 2, 3
+         ON CONFLICT (chain_id, token_address, bucket) DO UPDATE
+         SET token_volume = EXCLUDED.token_volume, usd_volume = EXCLUDED.usd_volume,
+             swap_count = EXCLUDED.swap_count",
+    )
+    .bind(chain_id)
+    .bind(reference_tokens)
+    .bind(since)
+    .bind(until)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// DEX prices of the tokens of one batch, by (chain, token) and hourly bucket.
+#[derive(Debug, Default)]
+pub struct DexPrices {
+    buckets: HashMap<(i64, Vec<u8>), BTreeMap<DateTime<Utc>, BigDecimal>>,
+}
+
+impl DexPrices {
+    /// Load buckets for `tokens` on `chain_id` that can price a transfer in `[since, until]`,
+    /// skipping those with less than `min_usd_volume` of reference volume.
+    pub async fn load(
+        pool: &PgPool,
+        chain_id: i64,
+        tokens: &[Vec<u8>],
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        min_usd_volume: f64,
+    ) -> eyre::Result<Self> {
+        let rows: Vec<(Vec<u8>, DateTime<Utc>, BigDecimal)> = sqlx::query_as(
+            "SELECT token_address, bucket, usd_volume / token_volume
+             FROM dex_token_prices
+             WHERE chain_id = $1 AND token_address = ANY($2)
+               AND bucket >= $3 AND bucket <= $4
+               AND token_volume > 0 AND usd_volume >= $5::FLOAT8::NUMERIC",
+        )
+        .bind(chain_id)
+        .bind(tokens)
+        .bind(since)
+        .bind(until)
+        .bind(min_usd_volume)
+        .fetch_all(pool)
+        .await?;
+
+        let mut prices = Self::default();
+        for (token, bucket, price) in rows {
+            prices
+                .buckets
+                .entry((chain_id, token))
+                .or_default()
+                .insert(bucket, price);
+        }
+        Ok(prices)
+    }
+
+    /// Price of the latest bucket started at or before `at`, if it is no older than `max_age`.
+    pub fn price_at(
+        &self,
+        chain_id: i64,
+        token: &[u8],
+        at: DateTime<Utc>,
+        max_age: Duration,
+    ) -> Option<&BigDecimal> {
+        self.buckets
+            .get(&(chain_id, token.to_vec()))?
+            .range((Bound::Included(at - max_age), Bound::Included(at)))
+            .next_back()
+            .map(|(_, price)| price)
+    }
+}
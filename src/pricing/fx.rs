@@ -0,0 +1,143 @@
+use bigdecimal::{BigDecimal, Signed};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::str::FromStr;
+
+/// One `currency,valid_from,usd_rate` row of the configured rates file.
+#[derive(Debug, Clone)]
+pub struct FxRate {
+    pub currency: String,
+    pub valid_from: DateTime<Utc>,
+    pub usd_rate: BigDecimal,
+}
+
+/// Parse an FX rates CSV with a header row. `valid_from` is RFC 3339.
+pub fn parse_fx_csv(path: &str) -> eyre::Result<Vec<FxRate>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| eyre::eyre!("Failed to open FX rates CSV '{}': {}", path, e))?;
+
+    let mut rates = Vec::new();
+    for (line, result) in reader.records().enumerate() {
+        let record = result?;
+        let field = |i: usize| record.get(i).unwrap_or("");
+        let valid_from = DateTime::parse_from_rfc3339(field(1))
+            .map_err(|e| eyre::eyre!("FX rates row {}: bad valid_from: {}", line + 2, e))?
+            .with_timezone(&Utc);
+        let usd_rate = BigDecimal::from_str(field(2))
+            .map_err(|e| eyre::eyre!("FX rates row {}: bad usd_rate: {}", line + 2, e))?;
+        if !usd_rate.is_positive() {
+            return Err(eyre::eyre!(
+                "FX rates row {}: usd_rate must be positive",
+                line + 2
+            ));
+        }
+        rates.push(FxRate {
+            currency: field(0).to_uppercase(),
+            valid_from,
+            usd_rate,
+        });
+    }
+    Ok(rates)
+}
+
+/// Upsert parsed rates into `fx_rates`. Currencies missing from the registry are skipped.
+pub async fn seed_fx_rates(pool: &PgPool, rates: &[FxRate]) -> eyre::Result<usize> {
+    let known: Vec<(String,)> = sqlx::query_as("SELECT code FROM fiat_currencies")
+        .fetch_all(pool)
+        .await?;
+    let known: HashSet<String> = known.into_iter().map(|(c,)| c).collect();
+
+    let (rates, unknown): (Vec<&FxRate>, Vec<&FxRate>) =
+        rates.iter().partition(|r| known.contains(&r.currency));
+    for rate in &unknown {
+        tracing::warn!(currency = %rate.currency, "FX rate for currency missing from registry, skipped");
+    }
+
+    let currencies: Vec<&str> = rates.iter().map(|r| r.currency.as_str()).collect();
+    let valid_from: Vec<DateTime<Utc>> = rates.iter().map(|r| r.valid_from).collect();
+    let usd_rates: Vec<BigDecimal> = rates.iter().map(|r| r.usd_rate.clone()).collect();
+    sqlx::query(
+        "INSERT INTO fx_rates (currency, valid_from, usd_rate, source)
+         SELECT *, 'file' FROM UNNEST($1::VARCHAR[], $2::TIMESTAMPTZ[], $3::NUMERIC[])
+         ON CONFLICT (currency, valid_from) DO UPDATE
+         SET usd_rate = EXCLUDED.usd_rate, source = EXCLUDED.source",
+    )
+    .bind(&currencies)
+    .bind(&valid_from)
+    .bind(&usd_rates)
+    .execute(pool)
+    .await?;
+
+    Ok(rates.len())
+}
+
+/// Every stored FX rate, by currency and start of validity.
+#[derive(Debug, Default)]
+pub struct FxTable {
+    rates: HashMap<String, BTreeMap<DateTime<Utc>, BigDecimal>>,
+}
+
+impl FxTable {
+    pub async fn load(pool: &PgPool) -> eyre::Result<Self> {
+        let rows: Vec<(String, DateTime<Utc>, BigDecimal)> =
+            sqlx::query_as("SELECT currency, valid_from, usd_rate FROM fx_rates")
+                .fetch_all(pool)
+                .await?;
+
+        let mut table = Self::default();
+        for (currency, valid_from, usd_rate) in rows {
+            table
+                .rates
+                .entry(currency)
+                .or_default()
+                .insert(valid_from, usd_rate);
+        }
+        Ok(table)
+    }
+
+    /// USD per unit of `currency` at `at`: the latest rate valid from then or earlier.
+    /// USD is always 1.
+    pub fn rate_at(&self, currency: &str, at: DateTime<Utc>) -> Option<BigDecimal> {
+        if currency == "USD" {
+            return Some(BigDecimal::from(1));
+        }
+        self.rates
+            .get(currency)?
+            .range((Bound::Unbounded, Bound::Included(at)))
+            .next_back()
+            .map(|(_, rate)| rate.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rate_at_uses_latest_valid_rate() {
+        let mut table = FxTable::default();
+        let jan = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let jun = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+        let eur = table.rates.entry("EUR".to_string()).or_default();
+        eur.insert(jan, BigDecimal::from_str("1.03").unwrap());
+        eur.insert(jun, BigDecimal::from_str("1.14").unwrap());
+
+        assert_eq!(table.rate_at("EUR", jan - chrono::Duration::days(1)), None);
+        assert_eq!(
+            table.rate_at("EUR", jun - chrono::Duration::seconds(1)),
+            Some(BigDecimal::from_str("1.03").unwrap())
+        );
+        assert_eq!(
+            table.rate_at("EUR", jun),
+            Some(BigDecimal::from_str("1.14").unwrap())
+        );
+        assert_eq!(table.rate_at("USD", jan), Some(BigDecimal::from(1)));
+        assert_eq!(table.rate_at("GBP", jun), None);
+    }
+}
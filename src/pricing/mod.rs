@@ -0,0 +1,407 @@
+pub mod dex;
+pub mod fx;
+pub mod stable;
+
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
+
+use crate::config::{Config, PricingConfig};
+use crate::indexer::types::{StablecoinTransfer, TransferKey};
+
+use dex::DexPrices;
+use fx::FxTable;
+
+/// Decimal places stored amounts are rounded to, in USD.
+const USD_SCALE: i64 = 8;
+
+/// How a configured token is valued.
+#[derive(Debug, Clone)]
+struct TokenPricing {
+    currency: String,
+    floating: bool,
+}
+
+/// First and last block timestamps of a chain's transfers, with their tokens.
+type TokenSpan<T> = (DateTime<Utc>, DateTime<Utc>, T);
+
+/// Values transfers in USD: pegged tokens at the FX rate of their currency, unless DEX
+/// swaps against a reference token show them trading away from it, and floating tokens at
+/// their DEX price alone.
+pub struct PriceOracle {
+    config: PricingConfig,
+    tokens: HashMap<(i64, Vec<u8>), TokenPricing>,
+    /// Reference token addresses per chain.
+    references: HashMap<i64, Vec<Vec<u8>>>,
+    fx: FxTable,
+    fx_loaded_at: Instant,
+    depeg_threshold: BigDecimal,
+    /// Blocks per stablecoin price window, per chain.
+    window_blocks: HashMap<i64, i64>,
+}
+
+impl PriceOracle {
+    /// Upsert the configured FX rates file into `fx_rates` and load every stored rate.
+    pub async fn init(pool: &PgPool, config: &Config) -> eyre::Result<Self> {
+        if let (true, Some(path)) = (config.pricing.enabled, &config.pricing.fx_rates_path) {
+            match fx::parse_fx_csv(path) {
+                Ok(rates) => {
+                    let count = fx::seed_fx_rates(pool, &rates).await?;
+                    tracing::info!(count, "FX rates loaded");
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to load FX rates file, continuing without");
+                }
+            }
+        }
+
+        let mut oracle = Self::new(config)?;
+        oracle.fx = FxTable::load(pool).await?;
+        Ok(oracle)
+    }
+
+    /// Oracle for the configured tokens with no FX rates loaded.
+    pub fn new(config: &Config) -> eyre::Result<Self> {
+        let pricing = config.pricing.clone();
+        let mut tokens = HashMap::new();
+        let mut references: HashMap<i64, Vec<Vec<u8>>> = HashMap::new();
+        let mut window_blocks = HashMap::new();
+        for chain in &config.chains {
+            let chain_id = chain.chain_id as i64;
+            let window = pricing
+                .chains
+                .get(&chain.name)
+                .and_then(|c| c.window_blocks)
+                .unwrap_or(pricing.window_blocks);
+            window_blocks.insert(chain_id, window as i64);
+            for token in &chain.tokens {
+                let Ok(address) = hex::decode(token.address.trim_start_matches("0x")) else {
+                    continue;
+                };
+                if pricing.reference_tokens.contains(&token.symbol) {
+                    references
+                        .entry(chain_id)
+                        .or_default()
+                        .push(address.clone());
+                }
+                tokens.insert(
+                    (chain_id, address),
+                    TokenPricing {
+                        currency: token.currency.clone(),
+                        floating: token.floating,
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            depeg_threshold: BigDecimal::try_from(pricing.depeg_threshold)?,
+            config: pricing,
+            tokens,
+            references,
+            fx: FxTable::default(),
+            fx_loaded_at: Instant::now(),
+            window_blocks,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Reload `fx_rates` once `fx_refresh_secs` have passed since the last load.
+    pub async fn refresh_fx(&mut self, pool: &PgPool) -> eyre::Result<()> {
+        if self.config.enabled
+            && self.fx_loaded_at.elapsed().as_secs() >= self.config.fx_refresh_secs
+        {
+            self.fx = FxTable::load(pool).await?;
+            self.fx_loaded_at = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Recompute the DEX price buckets of the hours `transfers` fall in, so swaps stored
+    /// with them are counted.
+    pub async fn refresh_dex(
+        &self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<u64> {
+        if !self.config.enabled {
+            return Ok(0);
+        }
+        let mut buckets = 0;
+        for (chain_id, (since, until, _)) in self.dex_tokens(transfers) {
+            let references = self
+                .references
+                .get(&chain_id)
+                .map_or(&[][..], Vec::as_slice);
+            buckets += dex::refresh_buckets(pool, chain_id, references, since, until).await?;
+        }
+        Ok(buckets)
+    }
+
+    /// Recompute the stablecoin price windows the blocks of `transfers` fall in, so swaps
+    /// stored with them are counted.
+    pub async fn refresh_stable_prices(
+        &self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<u64> {
+        if !self.config.enabled {
+            return Ok(0);
+        }
+        let mut spans: HashMap<i64, (i64, i64)> = HashMap::new();
+        for t in transfers {
+            let span = spans
+                .entry(t.chain_id)
+                .or_insert((t.block_number, t.block_number));
+            span.0 = span.0.min(t.block_number);
+            span.1 = span.1.max(t.block_number);
+        }
+
+        let mut windows = 0;
+        for (chain_id, (first, last)) in spans {
+            let Some(&window) = self.window_blocks.get(&chain_id) else {
+                continue;
+            };
+            let pairs = stable::load_pair_volumes(
+                pool,
+                chain_id,
+                window,
+                first / window * window,
+                (last / window + 1) * window,
+            )
+            .await?;
+            let currency_of = |token: &[u8]| {
+                self.tokens
+                    .get(&(chain_id, token.to_vec()))
+                    .filter(|p| !p.floating)
+                    .map(|p| p.currency.as_str())
+            };
+            let prices = stable::window_prices(&pairs, currency_of, &self.fx);
+            windows += stable::store_prices(pool, chain_id, window, &prices).await?;
+        }
+        Ok(windows)
+    }
+
+    /// USD value of each transfer, in order. `None` where no price is known. With pricing
+    /// disabled, see `face_values`.
+    pub async fn value(
+        &self,
+        pool: &PgPool,
+        transfers: &[StablecoinTransfer],
+    ) -> eyre::Result<Vec<Option<BigDecimal>>> {
+        if !self.config.enabled {
+            return Ok(self.face_values(transfers));
+        }
+
+        let max_age = Duration::seconds(self.config.dex_max_age_secs as i64);
+        let mut dex: HashMap<i64, DexPrices> = HashMap::new();
+        for (chain_id, (since, until, tokens)) in self.dex_tokens(transfers) {
+            let prices = DexPrices::load(
+                pool,
+                chain_id,
+                &tokens,
+                since - max_age,
+                until,
+                self.config.dex_min_usd_volume,
+            )
+            .await?;
+            dex.insert(chain_id, prices);
+        }
+
+        Ok(transfers
+            .iter()
+            .map(|t| {
+                let (currency, floating) = self.token_pricing(t);
+                let peg = (!floating)
+                    .then(|| self.fx.rate_at(currency, t.block_timestamp))
+                    .flatten();
+                let dex_price = dex.get(&t.chain_id).and_then(|p| {
+                    p.price_at(t.chain_id, &t.token_address, t.block_timestamp, max_age)
+                });
+                let price = usd_price(peg, dex_price, &self.depeg_threshold)?;
+                Some(amount_usd(&t.amount, t.token_decimals, &price))
+            })
+            .collect())
+    }
+
+    /// Value without FX rates or DEX prices: tokens pegged to USD at face value, anything
+    /// else unpriced, as its face value is not in USD.
+    fn face_values(&self, transfers: &[StablecoinTransfer]) -> Vec<Option<BigDecimal>> {
+        let one = BigDecimal::from(1);
+        transfers
+            .iter()
+            .map(|t| {
+                (self.token_pricing(t) == ("USD", false))
+                    .then(|| amount_usd(&t.amount, t.token_decimals, &one))
+            })
+            .collect()
+    }
+
+    /// A transfer's (currency, floating). Tokens missing from the config are USD stablecoins.
+    fn token_pricing(&self, transfer: &StablecoinTransfer) -> (&str, bool) {
+        self.tokens
+            .get(&(transfer.chain_id, transfer.token_address.clone()))
+            .map_or(("USD", false), |p| (p.currency.as_str(), p.floating))
+    }
+
+    /// Time span and non-reference tokens per chain, the ones a DEX price can apply to.
+    fn dex_tokens(
+        &self,
+        transfers: &[StablecoinTransfer],
+    ) -> HashMap<i64, TokenSpan<Vec<Vec<u8>>>> {
+        let mut spans: HashMap<i64, TokenSpan<BTreeSet<Vec<u8>>>> = HashMap::new();
+        for t in transfers {
+            let is_reference = self
+                .references
+                .get(&t.chain_id)
+                .is_some_and(|refs| refs.contains(&t.token_address));
+            if is_reference {
+                continue;
+            }
+            let span = spans
+                .entry(t.chain_id)
+                .or_insert_with(|| (t.block_timestamp, t.block_timestamp, BTreeSet::new()));
+            span.0 = span.0.min(t.block_timestamp);
+            span.1 = span.1.max(t.block_timestamp);
+            span.2.insert(t.token_address.clone());
+        }
+        spans
+            .into_iter()
+            .map(|(chain_id, (since, until, tokens))| {
+                (chain_id, (since, until, tokens.into_iter().collect()))
+            })
+            .collect()
+    }
+}
+
+/// The peg unless the DEX price strays more than `threshold` (a fraction of the peg) from
+/// it; the DEX price alone when there is no peg.
+fn usd_price(
+    peg: Option<BigDecimal>,
+    dex: Option<&BigDecimal>,
+    threshold: &BigDecimal,
+) -> Option<BigDecimal> {
+    match (peg, dex) {
+        (Some(peg), Some(dex)) if (dex - &peg).abs() > &peg * threshold => Some(dex.clone()),
+        (Some(peg), _) => Some(peg),
+        (None, dex) => dex.cloned(),
+    }
+}
+
+/// `amount` raw token units at `price` USD per whole token, rounded to `USD_SCALE` places.
+fn amount_usd(amount: &BigDecimal, decimals: i16, price: &BigDecimal) -> BigDecimal {
+    let (units, scale) = amount.as_bigint_and_exponent();
+    let tokens = BigDecimal::new(units, scale + decimals as i64);
+    (tokens * price).with_scale_round(USD_SCALE, RoundingMode::HalfEven)
+}
+
+/// Store the USD value of each transfer.
+pub async fn store_amounts(
+    pool: &PgPool,
+    amounts: &[(TransferKey, Option<BigDecimal>)],
+) -> eyre::Result<u64> {
+    if amounts.is_empty() {
+        return Ok(0);
+    }
+    let ids: Vec<i64> = amounts.iter().map(|(k, _)| k.id).collect();
+    let chain_ids: Vec<i64> = amounts.iter().map(|(k, _)| k.chain_id).collect();
+    let timestamps: Vec<DateTime<Utc>> = amounts.iter().map(|(k, _)| k.block_timestamp).collect();
+    let values: Vec<Option<&BigDecimal>> = amounts.iter().map(|(_, v)| v.as_ref()).collect();
+
+    let result = sqlx::query(
+        "UPDATE transfers t SET amount_usd = u.amount_usd
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::NUMERIC[])
+              AS u(id, chain_id, block_timestamp, amount_usd)
+         WHERE t.id = u.id AND t.chain_id = u.chain_id AND t.block_timestamp = u.block_timestamp",
+    )
+    .bind(&ids)
+    .bind(&chain_ids)
+    .bind(&timestamps)
+    .bind(&values)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_amount_usd_is_exact() {
+        // 1,234.567891 EURC at 1.0834 USD
+        assert_eq!(
+            amount_usd(&dec("1234567891"), 6, &dec("1.0834")),
+            dec("1337.53085311")
+        );
+        // 18 decimals rounds to 8 places
+        assert_eq!(
+            amount_usd(&dec("1000000000000000001"), 18, &dec("1")),
+            dec("1.00000000")
+        );
+    }
+
+    #[test]
+    fn test_without_pricing_only_usd_tokens_have_face_value() {
+        let config: Config = toml::from_str(
+            r#"
+            [database]
+            url = "postgres://unused"
+
+            [[chains]]
+            name = "ethereum"
+            chain_id = 1
+            rpc_http = "http://unused"
+            tokens = [
+                { symbol = "USDC", address = "0x0909090909090909090909090909090909090909", decimals = 6 },
+                { symbol = "EURC", address = "0x0808080808080808080808080808080808080808", decimals = 6, currency = "EUR" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let oracle = PriceOracle::new(&config).unwrap();
+
+        let transfer = |token: u8| StablecoinTransfer {
+            token_address: vec![token; 20],
+            ..test_transfer(1, 1, 2, 2_500_000)
+        };
+        assert_eq!(
+            oracle.face_values(&[transfer(9), transfer(8)]),
+            vec![Some(dec("2.5")), None]
+        );
+    }
+
+    #[test]
+    fn test_dex_price_overrides_peg_only_when_depegged() {
+        let threshold = dec("0.02");
+        assert_eq!(
+            usd_price(Some(dec("1")), Some(&dec("0.99")), &threshold),
+            Some(dec("1"))
+        );
+        assert_eq!(
+            usd_price(Some(dec("1")), Some(&dec("0.95")), &threshold),
+            Some(dec("0.95"))
+        );
+        assert_eq!(
+            usd_price(Some(dec("1.1")), None, &threshold),
+            Some(dec("1.1"))
+        );
+        assert_eq!(
+            usd_price(None, Some(&dec("3.02")), &threshold),
+            Some(dec("3.02"))
+        );
+        assert_eq!(usd_price(None, None, &threshold), None);
+    }
+}
@@ -0,0 +1,261 @@
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+use super::fx::FxTable;
+use super::USD_SCALE;
+
+/// Swaps that trade one token for another at an executed price.
+const SWAP_PROTOCOLS: [&str; 3] = ["uniswap_v2", "uniswap_v3", "curve"];
+
+/// Volumes swapped from one watched token into another within one block window.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PairVolume {
+    pub window_start: i64,
+    pub token_in: Vec<u8>,
+    pub symbol_in: String,
+    pub token_out: Vec<u8>,
+    pub symbol_out: String,
+    /// Whole tokens paid into the pool.
+    pub amount_in: BigDecimal,
+    /// Whole tokens paid out of the pool.
+    pub amount_out: BigDecimal,
+    pub swap_count: i64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+}
+
+/// Implied USD price of one token over one block window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StablePrice {
+    pub token_address: Vec<u8>,
+    pub token_symbol: String,
+    pub window_start: i64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub price_usd: BigDecimal,
+    pub peg_usd: Option<BigDecimal>,
+    pub token_volume: BigDecimal,
+    pub usd_volume: BigDecimal,
+    pub swap_count: i64,
+}
+
+/// Swap volumes between watched tokens on `chain_id` in blocks `[from_block, to_block)`,
+/// grouped by window of `window_blocks` and token pair.
+///
+/// Swap events do not carry their tokens, so each side is read from the watched transfers
+/// into and out of the pool in the same transaction, while the amounts are the ones the
+/// swap itself decoded. Each swap counts once per pair however many transfers match it.
+pub async fn load_pair_volumes(
+    pool: &PgPool,
+    chain_id: i64,
+    window_blocks: i64,
+    from_block: i64,
+    to_block: i64,
+) -> eyre::Result<Vec<PairVolume>> {
+    let pairs: Vec<PairVolume> = sqlx::query_as(
+        "SELECT (block_number / $2) * $2 AS window_start, token_in, symbol_in, token_out,
+                symbol_out,
+                SUM(amount_in / power(10::NUMERIC, decimals_in)) AS amount_in,
+                SUM(amount_out / power(10::NUMERIC, decimals_out)) AS amount_out,
+                COUNT(*) AS swap_count, MIN(block_timestamp) AS started_at,
+                MAX(block_timestamp) AS ended_at
+         FROM (
+             SELECT DISTINCT s.id, s.block_number, s.block_timestamp, s.amount_in, s.amount_out,
+                    a.token_address AS token_in, a.token_symbol AS symbol_in,
+                    a.token_decimals AS decimals_in,
+                    b.token_address AS token_out, b.token_symbol AS symbol_out,
+                    b.token_decimals AS decimals_out
+             FROM defi_events s
+             JOIN transfers a ON a.chain_id = s.chain_id AND a.block_timestamp = s.block_timestamp
+                AND a.tx_hash = s.tx_hash AND a.to_address = s.contract_address
+             JOIN transfers b ON b.chain_id = s.chain_id AND b.block_timestamp = s.block_timestamp
+                AND b.tx_hash = s.tx_hash AND b.from_address = s.contract_address
+             WHERE s.chain_id = $1 AND s.event_type = 'swap' AND s.protocol = ANY($5)
+               AND s.block_number >= $3 AND s.block_number < $4
+               AND a.token_address <> b.token_address
+               AND s.amount_in > 0 AND s.amount_out > 0
+         ) swaps
+         GROUP BY 1, 2, 3, 4, 5",
+    )
+    .bind(chain_id)
+    .bind(window_blocks)
+    .bind(from_block)
+    .bind(to_block)
+    .bind(&SWAP_PROTOCOLS[..])
+    .fetch_all(pool)
+    .await?;
+
+    Ok(pairs)
+}
+
+/// Volume-weighted price of each pegged token per window, from both sides of its swaps.
+///
+/// `currency_of` names the currency a token is pegged to, or `None` for floating and
+/// unknown tokens. Each swap values the pegged token at what was paid for it, with the
+/// counter token at the FX rate of its own currency. Counter tokens without a peg or rate
+/// add nothing, so a window priced only against them is left out.
+pub fn window_prices<'a>(
+    pairs: &[PairVolume],
+    currency_of: impl Fn(&[u8]) -> Option<&'a str>,
+    fx: &FxTable,
+) -> Vec<StablePrice> {
+    let mut windows: BTreeMap<(Vec<u8>, i64), StablePrice> = BTreeMap::new();
+    let sides = pairs.iter().flat_map(|p| {
+        [
+            (&p.token_in, &p.symbol_in, &p.amount_in, &p.token_out, &p.amount_out, p),
+            (&p.token_out, &p.symbol_out, &p.amount_out, &p.token_in, &p.amount_in, p),
+        ]
+    });
+    for (token, symbol, volume, counter, counter_volume, pair) in sides {
+        if currency_of(token).is_none() {
+            continue;
+        }
+        let Some(counter_usd) = currency_of(counter).and_then(|c| fx.rate_at(c, pair.ended_at))
+        else {
+            continue;
+        };
+
+        let price = windows
+            .entry((token.clone(), pair.window_start))
+            .or_insert_with(|| StablePrice {
+                token_address: token.clone(),
+                token_symbol: symbol.clone(),
+                window_start: pair.window_start,
+                started_at: pair.started_at,
+                ended_at: pair.ended_at,
+                price_usd: BigDecimal::zero(),
+                peg_usd: None,
+                token_volume: BigDecimal::zero(),
+                usd_volume: BigDecimal::zero(),
+                swap_count: 0,
+            });
+        price.started_at = price.started_at.min(pair.started_at);
+        price.ended_at = price.ended_at.max(pair.ended_at);
+        price.token_volume += volume;
+        price.usd_volume += counter_volume * &counter_usd;
+        price.swap_count += pair.swap_count;
+    }
+
+    windows
+        .into_values()
+        .map(|mut price| {
+            price.price_usd = (&price.usd_volume / &price.token_volume)
+                .with_scale_round(USD_SCALE, RoundingMode::HalfEven);
+            price.usd_volume = price
+                .usd_volume
+                .with_scale_round(USD_SCALE, RoundingMode::HalfEven);
+            price.peg_usd =
+                currency_of(&price.token_address).and_then(|c| fx.rate_at(c, price.ended_at));
+            price
+        })
+        .collect()
+}
+
+/// Upsert window prices of `chain_id`, replacing earlier computations of the same windows.
+pub async fn store_prices(
+    pool: &PgPool,
+    chain_id: i64,
+    window_blocks: i64,
+    prices: &[StablePrice],
+) -> eyre::Result<u64> {
+    if prices.is_empty() {
+        return Ok(0);
+    }
+    let tokens: Vec<&[u8]> = prices.iter().map(|p| p.token_address.as_slice()).collect();
+    let symbols: Vec<&str> = prices.iter().map(|p| p.token_symbol.as_str()).collect();
+    let window_starts: Vec<i64> = prices.iter().map(|p| p.window_start).collect();
+    let started_at: Vec<DateTime<Utc>> = prices.iter().map(|p| p.started_at).collect();
+    let ended_at: Vec<DateTime<Utc>> = prices.iter().map(|p| p.ended_at).collect();
+    let price_usd: Vec<&BigDecimal> = prices.iter().map(|p| &p.price_usd).collect();
+    let peg_usd: Vec<Option<&BigDecimal>> = prices.iter().map(|p| p.peg_usd.as_ref()).collect();
+    let token_volume: Vec<&BigDecimal> = prices.iter().map(|p| &p.token_volume).collect();
+    let usd_volume: Vec<&BigDecimal> = prices.iter().map(|p| &p.usd_volume).collect();
+    let swap_count: Vec<i32> = prices.iter().map(|p| p.swap_count as i32).collect();
+
+    let result = sqlx::query(
+        "INSERT INTO stablecoin_prices (chain_id, token_address, token_symbol, window_start,
+             window_blocks, started_at, ended_at, price_usd, peg_usd, token_volume, usd_volume,
+             swap_count)
+         SELECT $1, u.token_address, u.token_symbol, u.window_start, $2, u.started_at,
+                u.ended_at, u.price_usd, u.peg_usd, u.token_volume, u.usd_volume, u.swap_count
+         FROM UNNEST($3::BYTEA[], $4::VARCHAR[], $5::BIGINT[], $6::TIMESTAMPTZ[],
+                     $7::TIMESTAMPTZ[], $8::NUMERIC[], $9::NUMERIC[], $10::NUMERIC[],
+                     $11::NUMERIC[], $12::INT[])
+              AS u(token_address, token_symbol, window_start, started_at, ended_at, price_usd,
+                   peg_usd, token_volume, usd_volume, swap_count)
+         ON CONFLICT (chain_id, token_address, window_start) DO UPDATE
+         SET token_symbol = EXCLUDED.token_symbol, window_blocks = EXCLUDED.window_blocks,
+             started_at = EXCLUDED.started_at, ended_at = EXCLUDED.ended_at,
+             price_usd = EXCLUDED.price_usd, peg_usd = EXCLUDED.peg_usd,
+             token_volume = EXCLUDED.token_volume, usd_volume = EXCLUDED.usd_volume,
+             swap_count = EXCLUDED.swap_count",
+    )
+    .bind(chain_id)
+    .bind(window_blocks as i32)
+    .bind(&tokens)
+    .bind(&symbols)
+    .bind(&window_starts)
+    .bind(&started_at)
+    .bind(&ended_at)
+    .bind(&price_usd)
+    .bind(&peg_usd)
+    .bind(&token_volume)
+    .bind(&usd_volume)
+    .bind(&swap_count)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn pair(token_in: u8, token_out: u8, amount_in: &str, amount_out: &str) -> PairVolume {
+        let at = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        PairVolume {
+            window_start: 100,
+            token_in: vec![token_in],
+            symbol_in: format!("T{}", token_in),
+            token_out: vec![token_out],
+            symbol_out: format!("T{}", token_out),
+            amount_in: dec(amount_in),
+            amount_out: dec(amount_out),
+            swap_count: 1,
+            started_at: at,
+            ended_at: at,
+        }
+    }
+
+    #[test]
+    fn test_window_prices_are_volume_weighted() {
+        // Token 1 is sold for 0.97 then 0.99 of token 2, token 3 floats
+        let pairs = [
+            pair(1, 2, "1000", "970"),
+            pair(1, 2, "3000", "2970"),
+            pair(3, 1, "1", "3000"),
+        ];
+        let currency_of = |token: &[u8]| (token != [3]).then_some("USD");
+        let prices = window_prices(&pairs, currency_of, &FxTable::default());
+
+        let one = prices.iter().find(|p| p.token_address == [1]).unwrap();
+        // 3940 USD paid for 4000 tokens; the floating counter adds nothing
+        assert_eq!(one.price_usd, dec("0.985"));
+        assert_eq!(one.token_volume, dec("4000"));
+        assert_eq!(one.swap_count, 2);
+        assert_eq!(one.peg_usd, Some(dec("1")));
+
+        let two = prices.iter().find(|p| p.token_address == [2]).unwrap();
+        assert_eq!(two.price_usd, dec("1.01522843"));
+        assert!(prices.iter().all(|p| p.token_address != [3]));
+    }
+}
@@ -3,7 +3,7 @@ use serde::Deserialize;
 use sqlx::PgPool;
 use std::str::FromStr;
 
-use crate::entity::label_store::EntityLabelStore;
+use crate::entity::label_store::{EntityLabelStore, LabelSeed};
 
 #[derive(Debug, Deserialize)]
 struct ExchangeProvider {
@@ -78,13 +78,15 @@ pub async fn seed_exchange_wallets(
             entity_store
                 .seed_label(
                     pool,
-                    address.as_slice(),
-                    Some(provider.chain_id),
-                    &wallet.label,
-                    "exchange",
-                    "seed_data",
-                    1.0,
-                    None,
+                    LabelSeed {
+                        address: address.as_slice(),
+                        chain_id: Some(provider.chain_id),
+                        entity_name: &wallet.label,
+                        entity_type: "exchange",
+                        label_source: "seed_data",
+                        confidence: 1.0,
+                        metadata: None,
+                    },
                 )
                 .await?;
 
@@ -1,10 +1,9 @@
 use alloy::primitives::Address;
-use sqlx::PgPool;
 use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::config::ChainConfig;
-use crate::db::repository;
+use crate::db::store::Store;
 use crate::indexer::types::TokenMeta;
 
 /// Build an in-memory lookup map of watched token addresses for a chain.
@@ -36,20 +35,20 @@ pub fn build_watched_tokens(config: &ChainConfig) -> HashMap<Address, TokenMeta>
 }
 
 /// Seed the known_tokens table from config at startup (idempotent).
-pub async fn seed_known_tokens(pool: &PgPool, chains: &[ChainConfig]) -> eyre::Result<()> {
+pub async fn seed_known_tokens<S: Store>(store: &S, chains: &[ChainConfig]) -> eyre::Result<()> {
     for chain in chains {
         for token in &chain.tokens {
             let address = Address::from_str(&token.address)
                 .map_err(|e| eyre::eyre!("Invalid address '{}': {}", token.address, e))?;
 
-            repository::upsert_known_token(
-                pool,
-                chain.chain_id as i64,
-                address.as_slice(),
-                &token.symbol,
-                token.decimals as i16,
-            )
-            .await?;
+            store
+                .upsert_known_token(
+                    chain.chain_id as i64,
+                    address.as_slice(),
+                    &token.symbol,
+                    token.decimals as i16,
+                )
+                .await?;
 
             tracing::debug!(
                 chain = %chain.name,
@@ -0,0 +1,364 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::anomaly::rules::usd_amount;
+use crate::config::BaselineConfig;
+use crate::indexer::types::StablecoinTransfer;
+
+/// Amounts below this are treated as this for the log-amount statistics.
+const MIN_AMOUNT: f64 = 0.01;
+
+/// Floor for the log-amount standard deviation, so a wallet that always moves the same
+/// amount does not flag a slightly different one (0.1 in log10 is about ±26%).
+const MIN_LOG_STDDEV: f64 = 0.1;
+
+/// Rolling behaviour of one wallet on one chain, over transfers in either direction.
+#[derive(Debug, Clone)]
+pub struct WalletBaseline {
+    pub transfer_count: i64,
+    pub log_amount_mean: f64,
+    pub log_amount_var: f64,
+    /// Exponentially weighted transfers per day, over completed days including idle ones.
+    pub daily_count_mean: f64,
+    pub current_day: NaiveDate,
+    pub current_day_count: i32,
+    /// Hex counterparty address -> transfers with it, capped at `max_counterparties`.
+    pub counterparties: HashMap<String, i64>,
+    pub last_transfer_id: i64,
+}
+
+/// How a transfer compares to a wallet's baseline.
+#[derive(Debug, Clone)]
+pub struct Deviation {
+    /// Standard deviations above the wallet's typical log amount.
+    pub amount_z: f64,
+    pub typical_amount: f64,
+    /// Today's transfer count including this one, over the daily mean.
+    pub daily_ratio: f64,
+    pub daily_count_mean: f64,
+    pub new_counterparty: bool,
+    pub history: i64,
+}
+
+impl WalletBaseline {
+    fn new(day: NaiveDate) -> Self {
+        Self {
+            transfer_count: 0,
+            log_amount_mean: 0.0,
+            log_amount_var: 0.0,
+            daily_count_mean: 0.0,
+            current_day: day,
+            current_day_count: 0,
+            counterparties: HashMap::new(),
+            last_transfer_id: 0,
+        }
+    }
+
+    /// Compare a transfer against the baseline. None until the wallet has
+    /// `min_history` transfers.
+    pub fn deviation(
+        &self,
+        amount: f64,
+        counterparty: &str,
+        at: DateTime<Utc>,
+        config: &BaselineConfig,
+    ) -> Option<Deviation> {
+        if self.transfer_count < config.min_history as i64 {
+            return None;
+        }
+
+        let stddev = self.log_amount_var.sqrt().max(MIN_LOG_STDDEV);
+        let amount_z = (amount.max(MIN_AMOUNT).log10() - self.log_amount_mean) / stddev;
+
+        let today = if at.date_naive() > self.current_day {
+            1
+        } else {
+            self.current_day_count + 1
+        };
+        let daily_ratio = today as f64 / self.daily_count_mean.max(1.0);
+
+        Some(Deviation {
+            amount_z,
+            typical_amount: 10f64.powf(self.log_amount_mean),
+            daily_ratio,
+            daily_count_mean: self.daily_count_mean,
+            new_counterparty: !self.counterparties.contains_key(counterparty),
+            history: self.transfer_count,
+        })
+    }
+
+    /// Fold one transfer into the baseline.
+    pub fn fold(
+        &mut self,
+        transfer_id: i64,
+        amount: f64,
+        counterparty: &str,
+        at: DateTime<Utc>,
+        config: &BaselineConfig,
+    ) {
+        let day = at.date_naive();
+        if day > self.current_day {
+            // Close the current day, then decay through the idle days in between
+            let idle_days = (day - self.current_day).num_days() - 1;
+            let keep = 1.0 - config.daily_alpha;
+            self.daily_count_mean = keep * self.daily_count_mean
+                + config.daily_alpha * self.current_day_count as f64;
+            self.daily_count_mean *= keep.powi(idle_days as i32);
+            self.current_day = day;
+            self.current_day_count = 0;
+        }
+        self.current_day_count += 1;
+
+        let x = amount.max(MIN_AMOUNT).log10();
+        if self.transfer_count == 0 {
+            self.log_amount_mean = x;
+            self.log_amount_var = 0.0;
+        } else {
+            let diff = x - self.log_amount_mean;
+            let increment = config.alpha * diff;
+            self.log_amount_mean += increment;
+            self.log_amount_var = (1.0 - config.alpha) * (self.log_amount_var + diff * increment);
+        }
+
+        *self.counterparties.entry(counterparty.to_string()).or_default() += 1;
+        if self.counterparties.len() > config.max_counterparties {
+            // Evict the least used counterparty, ties broken by address for determinism
+            if let Some(evict) = self
+                .counterparties
+                .iter()
+                .filter(|(address, _)| address.as_str() != counterparty)
+                .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+                .map(|(address, _)| address.clone())
+            {
+                self.counterparties.remove(&evict);
+            }
+        }
+
+        self.transfer_count += 1;
+        self.last_transfer_id = self.last_transfer_id.max(transfer_id);
+    }
+}
+
+/// Both sides of a transfer as (wallet, counterparty hex).
+pub fn sides(transfer: &StablecoinTransfer) -> [(&[u8], String); 2] {
+    [
+        (&transfer.from_address, hex::encode(&transfer.to_address)),
+        (&transfer.to_address, hex::encode(&transfer.from_address)),
+    ]
+}
+
+/// Fold a transfer into both sides' baselines in the map, creating missing ones.
+/// Transfers already folded into a side (id at or below its `last_transfer_id`) are skipped,
+/// as are unpriced ones, since the amounts are in USD.
+pub fn fold_transfer(
+    baselines: &mut HashMap<(i64, Vec<u8>), WalletBaseline>,
+    transfer: &StablecoinTransfer,
+    config: &BaselineConfig,
+) {
+    let Some(transfer_id) = transfer.id else {
+        return;
+    };
+    if transfer.from_address == transfer.to_address {
+        return;
+    }
+    let Some(amount) = usd_amount(transfer) else {
+        return;
+    };
+
+    for (wallet, counterparty) in sides(transfer) {
+        let baseline = baselines
+            .entry((transfer.chain_id, wallet.to_vec()))
+            .or_insert_with(|| WalletBaseline::new(transfer.block_timestamp.date_naive()));
+        if transfer_id > baseline.last_transfer_id {
+            baseline.fold(transfer_id, amount, &counterparty, transfer.block_timestamp, config);
+        }
+    }
+}
+
+/// Row shape of a stored `WalletBaseline`, keyed by address and chain.
+type BaselineRow = (Vec<u8>, i64, i64, f64, f64, f64, NaiveDate, i32, serde_json::Value, i64);
+
+/// Stored baselines for the wallets on both sides of the transfers.
+pub async fn load_baselines(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+) -> eyre::Result<HashMap<(i64, Vec<u8>), WalletBaseline>> {
+    let chain_ids: Vec<i64> = transfers
+        .iter()
+        .flat_map(|t| [t.chain_id, t.chain_id])
+        .collect();
+    let addresses: Vec<&[u8]> = transfers
+        .iter()
+        .flat_map(|t| [t.from_address.as_slice(), t.to_address.as_slice()])
+        .collect();
+
+    let rows: Vec<BaselineRow> =
+        sqlx::query_as(
+            "SELECT b.address, b.chain_id, b.transfer_count, b.log_amount_mean, b.log_amount_var,
+                    b.daily_count_mean, b.current_day, b.current_day_count, b.counterparties,
+                    b.last_transfer_id
+             FROM wallet_baselines b
+             JOIN (SELECT DISTINCT * FROM UNNEST($1::BIGINT[], $2::BYTEA[])) AS w(chain_id, address)
+               ON b.chain_id = w.chain_id AND b.address = w.address",
+        )
+        .bind(&chain_ids)
+        .bind(&addresses)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(
+                address,
+                chain_id,
+                transfer_count,
+                log_amount_mean,
+                log_amount_var,
+                daily_count_mean,
+                current_day,
+                current_day_count,
+                counterparties,
+                last_transfer_id,
+            )| {
+                let counterparties = serde_json::from_value(counterparties).unwrap_or_default();
+                (
+                    (chain_id, address),
+                    WalletBaseline {
+                        transfer_count,
+                        log_amount_mean,
+                        log_amount_var,
+                        daily_count_mean,
+                        current_day,
+                        current_day_count,
+                        counterparties,
+                        last_transfer_id,
+                    },
+                )
+            },
+        )
+        .collect())
+}
+
+/// Fold a processed batch into the stored baselines. Safe to repeat: each wallet records
+/// the newest transfer folded in, and the write only moves that forward.
+/// Returns the number of baselines written.
+pub async fn update_baselines(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    config: &BaselineConfig,
+) -> eyre::Result<u64> {
+    if transfers.is_empty() {
+        return Ok(0);
+    }
+
+    let mut baselines = load_baselines(pool, transfers).await?;
+    let before: HashMap<(i64, Vec<u8>), i64> = baselines
+        .iter()
+        .map(|(key, b)| (key.clone(), b.last_transfer_id))
+        .collect();
+
+    for transfer in transfers {
+        fold_transfer(&mut baselines, transfer, config);
+    }
+    baselines.retain(|key, b| before.get(key) != Some(&b.last_transfer_id));
+
+    let mut written = 0;
+    let entries: Vec<_> = baselines.into_iter().collect();
+    for chunk in entries.chunks(5000) {
+        let addresses: Vec<&[u8]> = chunk.iter().map(|((_, a), _)| a.as_slice()).collect();
+        let chain_ids: Vec<i64> = chunk.iter().map(|((c, _), _)| *c).collect();
+        let counts: Vec<i64> = chunk.iter().map(|(_, b)| b.transfer_count).collect();
+        let means: Vec<f64> = chunk.iter().map(|(_, b)| b.log_amount_mean).collect();
+        let vars: Vec<f64> = chunk.iter().map(|(_, b)| b.log_amount_var).collect();
+        let daily: Vec<f64> = chunk.iter().map(|(_, b)| b.daily_count_mean).collect();
+        let days: Vec<NaiveDate> = chunk.iter().map(|(_, b)| b.current_day).collect();
+        let day_counts: Vec<i32> = chunk.iter().map(|(_, b)| b.current_day_count).collect();
+        let counterparties: Vec<serde_json::Value> = chunk
+            .iter()
+            .map(|(_, b)| serde_json::json!(b.counterparties))
+            .collect();
+        let last_ids: Vec<i64> = chunk.iter().map(|(_, b)| b.last_transfer_id).collect();
+
+        let result = sqlx::query(
+            "INSERT INTO wallet_baselines (address, chain_id, transfer_count, log_amount_mean,
+                                           log_amount_var, daily_count_mean, current_day,
+                                           current_day_count, counterparties, last_transfer_id,
+                                           updated_at)
+             SELECT *, NOW() FROM UNNEST($1::BYTEA[], $2::BIGINT[], $3::BIGINT[], $4::FLOAT8[],
+                                         $5::FLOAT8[], $6::FLOAT8[], $7::DATE[], $8::INT[],
+                                         $9::JSONB[], $10::BIGINT[])
+             ON CONFLICT (address, chain_id) DO UPDATE SET
+                 transfer_count = EXCLUDED.transfer_count,
+                 log_amount_mean = EXCLUDED.log_amount_mean,
+                 log_amount_var = EXCLUDED.log_amount_var,
+                 daily_count_mean = EXCLUDED.daily_count_mean,
+                 current_day = EXCLUDED.current_day,
+                 current_day_count = EXCLUDED.current_day_count,
+                 counterparties = EXCLUDED.counterparties,
+                 last_transfer_id = EXCLUDED.last_transfer_id,
+                 updated_at = EXCLUDED.updated_at
+             WHERE wallet_baselines.last_transfer_id < EXCLUDED.last_transfer_id",
+        )
+        .bind(&addresses)
+        .bind(&chain_ids)
+        .bind(&counts)
+        .bind(&means)
+        .bind(&vars)
+        .bind(&daily)
+        .bind(&days)
+        .bind(&day_counts)
+        .bind(&counterparties)
+        .bind(&last_ids)
+        .execute(pool)
+        .await?;
+        written += result.rows_affected();
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::types::test_transfer;
+    use bigdecimal::BigDecimal;
+    use chrono::{Duration, TimeZone};
+
+    fn transfer(id: i64, amount: i64, to: u8, hours: i64) -> StablecoinTransfer {
+        StablecoinTransfer {
+            id: Some(id),
+            block_timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+                + Duration::hours(hours),
+            amount_usd: Some(BigDecimal::from(amount)),
+            ..test_transfer(id, 1, to, amount * 1_000_000)
+        }
+    }
+
+    #[test]
+    fn test_spike_scores_against_history_and_replays_are_skipped() {
+        let config = BaselineConfig::default();
+        let mut baselines = HashMap::new();
+        for i in 0..40 {
+            fold_transfer(&mut baselines, &transfer(i + 1, 100 + i, 2, i * 12), &config);
+        }
+        let sender = &baselines[&(1, vec![1; 20])];
+        assert_eq!(sender.transfer_count, 40);
+        assert!((sender.daily_count_mean - 2.0).abs() < 0.5);
+
+        let at = Utc.with_ymd_and_hms(2024, 1, 21, 12, 0, 0).unwrap();
+        let usual = sender.deviation(120.0, &hex::encode([2; 20]), at, &config).unwrap();
+        assert!(usual.amount_z < config.amount_z_threshold);
+        assert!(!usual.new_counterparty);
+
+        let spike = sender.deviation(50_000.0, &hex::encode([3; 20]), at, &config).unwrap();
+        assert!(spike.amount_z >= config.amount_z_threshold);
+        assert!(spike.new_counterparty);
+
+        // Folding an already folded transfer changes nothing
+        let before = sender.log_amount_mean;
+        fold_transfer(&mut baselines, &transfer(40, 50_000, 2, 480), &config);
+        assert_eq!(baselines[&(1, vec![1; 20])].log_amount_mean, before);
+    }
+}
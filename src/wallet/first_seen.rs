@@ -2,6 +2,7 @@ use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use std::collections::HashSet;
 
+use crate::db::store::Store;
 use crate::indexer::types::StablecoinTransfer;
 
 /// Event emitted when a wallet address is seen for the first time on a chain.
@@ -22,17 +23,9 @@ pub struct WalletTracker {
 }
 
 impl WalletTracker {
-    /// Load all known addresses from the database.
-    pub async fn load_from_db(pool: &PgPool) -> eyre::Result<Self> {
-        let rows: Vec<(Vec<u8>, i64)> =
-            sqlx::query_as("SELECT address, chain_id FROM wallet_first_seen")
-                .fetch_all(pool)
-                .await?;
-
-        let mut known = HashSet::with_capacity(rows.len());
-        for (address, chain_id) in rows {
-            known.insert((address, chain_id));
-        }
+    /// Load all known addresses from the store.
+    pub async fn load<S: Store>(store: &S) -> eyre::Result<Self> {
+        let known: HashSet<(Vec<u8>, i64)> = store.load_known_wallets().await?.into_iter().collect();
 
         tracing::info!(wallets = known.len(), "Loaded wallet tracker");
         Ok(Self { known })
@@ -40,9 +33,9 @@ impl WalletTracker {
 
     /// Process a batch of transfers, detecting new wallet addresses.
     /// Returns a list of NewWalletEvent for addresses seen for the first time.
-    pub async fn process_transfers(
+    pub async fn process_transfers<S: Store>(
         &mut self,
-        pool: &PgPool,
+        store: &S,
         transfers: &[StablecoinTransfer],
     ) -> eyre::Result<Vec<NewWalletEvent>> {
         let mut new_wallets = Vec::new();
@@ -62,7 +55,6 @@ impl WalletTracker {
                     direction: "from".to_string(),
                 };
 
-                upsert_first_seen(pool, &event).await?;
                 new_wallets.push(event);
             }
 
@@ -80,12 +72,12 @@ impl WalletTracker {
                     direction: "to".to_string(),
                 };
 
-                upsert_first_seen(pool, &event).await?;
                 new_wallets.push(event);
             }
         }
 
         if !new_wallets.is_empty() {
+            store.insert_first_seen(&new_wallets).await?;
             tracing::debug!(count = new_wallets.len(), "New wallets detected");
         }
 
@@ -93,21 +85,97 @@ impl WalletTracker {
     }
 }
 
-/// Insert a first-seen record. Uses ON CONFLICT to keep the earliest sighting.
-async fn upsert_first_seen(pool: &PgPool, event: &NewWalletEvent) -> eyre::Result<()> {
-    sqlx::query(
-        "INSERT INTO wallet_first_seen (address, chain_id, first_seen_at, first_block, first_tx_hash, first_direction)
-         VALUES ($1, $2, $3, $4, $5, $6)
-         ON CONFLICT (address, chain_id) DO NOTHING",
+/// Every (address, chain_id) with a first-seen record.
+pub async fn load_known(pool: &PgPool) -> eyre::Result<Vec<(Vec<u8>, i64)>> {
+    let rows: Vec<(Vec<u8>, i64)> = sqlx::query_as("SELECT address, chain_id FROM wallet_first_seen")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Insert first-seen records in bulk. Uses ON CONFLICT to keep the earliest sighting.
+pub async fn insert_first_seen(pool: &PgPool, events: &[NewWalletEvent]) -> eyre::Result<()> {
+    for chunk in events.chunks(5000) {
+        let addresses: Vec<&[u8]> = chunk.iter().map(|e| e.address.as_slice()).collect();
+        let chain_ids: Vec<i64> = chunk.iter().map(|e| e.chain_id).collect();
+        let seen_at: Vec<DateTime<Utc>> = chunk.iter().map(|e| e.first_seen_at).collect();
+        let blocks: Vec<i64> = chunk.iter().map(|e| e.first_block).collect();
+        let tx_hashes: Vec<&[u8]> = chunk.iter().map(|e| e.first_tx_hash.as_slice()).collect();
+        let directions: Vec<&str> = chunk.iter().map(|e| e.direction.as_str()).collect();
+
+        sqlx::query(
+            "INSERT INTO wallet_first_seen (address, chain_id, first_seen_at, first_block, first_tx_hash, first_direction)
+             SELECT * FROM UNNEST($1::BYTEA[], $2::BIGINT[], $3::TIMESTAMPTZ[], $4::BIGINT[],
+                                  $5::BYTEA[], $6::TEXT[])
+             ON CONFLICT (address, chain_id) DO NOTHING",
+        )
+        .bind(&addresses)
+        .bind(&chain_ids)
+        .bind(&seen_at)
+        .bind(&blocks)
+        .bind(&tx_hashes)
+        .bind(&directions)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Chain, first seen time, block, transaction and direction.
+type ChainFirstSeenRow = (i64, DateTime<Utc>, i64, Option<Vec<u8>>, String);
+
+/// Earliest sighting of an address on any chain.
+pub async fn earliest_first_seen(pool: &PgPool, address: &[u8]) -> eyre::Result<Option<NewWalletEvent>> {
+    let row: Option<ChainFirstSeenRow> = sqlx::query_as(
+        "SELECT chain_id, first_seen_at, first_block, first_tx_hash, first_direction
+         FROM wallet_first_seen WHERE address = $1
+         ORDER BY first_seen_at ASC LIMIT 1",
     )
-    .bind(&event.address)
-    .bind(event.chain_id)
-    .bind(event.first_seen_at)
-    .bind(event.first_block)
-    .bind(&event.first_tx_hash)
-    .bind(&event.direction)
-    .execute(pool)
+    .bind(address)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(())
+    Ok(row.map(|(chain_id, first_seen_at, first_block, tx_hash, direction)| NewWalletEvent {
+        address: address.to_vec(),
+        chain_id,
+        first_seen_at,
+        first_block,
+        first_tx_hash: tx_hash.unwrap_or_default(),
+        direction,
+    }))
+}
+
+/// Address, first seen time, block, transaction and direction.
+type FirstSeenRow = (Vec<u8>, DateTime<Utc>, i64, Option<Vec<u8>>, String);
+
+/// Rebuild the first-seen events for a set of stored transactions on a chain.
+/// Used when replaying history, where the in-memory tracker already knows every wallet.
+pub async fn load_first_seen_events(
+    pool: &PgPool,
+    chain_id: i64,
+    tx_hashes: &[Vec<u8>],
+) -> eyre::Result<Vec<NewWalletEvent>> {
+    let rows: Vec<FirstSeenRow> = sqlx::query_as(
+        "SELECT address, first_seen_at, first_block, first_tx_hash, first_direction
+         FROM wallet_first_seen
+         WHERE chain_id = $1 AND first_tx_hash = ANY($2)",
+    )
+    .bind(chain_id)
+    .bind(tx_hashes)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(address, first_seen_at, first_block, tx_hash, direction)| NewWalletEvent {
+            address,
+            chain_id,
+            first_seen_at,
+            first_block,
+            first_tx_hash: tx_hash.unwrap_or_default(),
+            direction,
+        })
+        .collect())
 }
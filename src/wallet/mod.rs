@@ -1 +1,3 @@
+pub mod baseline;
 pub mod first_seen;
+pub mod risk;
@@ -0,0 +1,362 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::{RiskScoreConfig, RiskWeights};
+use crate::entity::label_store::EntityLabelStore;
+use crate::graph::exposure;
+use crate::indexer::types::StablecoinTransfer;
+
+/// What a wallet's score is computed from.
+#[derive(Debug, Clone, Default)]
+pub struct RiskInputs {
+    /// Anomalies flagged on the address, excluding false positives.
+    pub anomaly_count: i64,
+    /// Sum of their risk scores, each halved per elapsed half-life.
+    pub decayed_anomaly_risk: f64,
+    pub inbound_exposure: f64,
+    pub outbound_exposure: f64,
+    /// Riskiest own label as (entity name, entity type, risk after confidence).
+    pub label: Option<(String, String, f64)>,
+    /// Share of the wallet's volume exchanged with risky entities, weighted by their risk.
+    pub counterparty_risk: f64,
+    pub risky_counterparties: i64,
+    pub age_days: Option<f64>,
+}
+
+/// Each input scaled to 0..1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RiskFactors {
+    pub anomalies: f64,
+    pub sanctions_exposure: f64,
+    pub entity_labels: f64,
+    pub counterparties: f64,
+    pub wallet_age: f64,
+}
+
+impl RiskFactors {
+    pub fn from_inputs(inputs: &RiskInputs, config: &RiskScoreConfig) -> Self {
+        let wallet_age = match inputs.age_days {
+            Some(days) if config.new_wallet_days > 0.0 => {
+                (1.0 - days / config.new_wallet_days).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        };
+        Self {
+            anomalies: 1.0 - (-inputs.decayed_anomaly_risk / config.anomaly_scale).exp(),
+            sanctions_exposure: inputs.inbound_exposure.max(inputs.outbound_exposure).min(1.0),
+            entity_labels: inputs.label.as_ref().map_or(0.0, |(_, _, risk)| *risk),
+            counterparties: inputs.counterparty_risk.min(1.0),
+            wallet_age,
+        }
+    }
+
+    /// Factors as (name, factor, weight).
+    fn weighted(&self, weights: &RiskWeights) -> [(&'static str, f64, f64); 5] {
+        [
+            ("anomalies", self.anomalies, weights.anomalies),
+            ("sanctions_exposure", self.sanctions_exposure, weights.sanctions_exposure),
+            ("entity_labels", self.entity_labels, weights.entity_labels),
+            ("counterparties", self.counterparties, weights.counterparties),
+            ("wallet_age", self.wallet_age, weights.wallet_age),
+        ]
+    }
+
+    /// Score between 0 and 100. Each factor removes its weighted share of the risk that
+    /// is left, so one strong factor dominates and weaker ones add up without passing 100.
+    pub fn score(&self, weights: &RiskWeights) -> f64 {
+        let clean: f64 = self
+            .weighted(weights)
+            .iter()
+            .map(|(_, factor, weight)| 1.0 - weight * factor)
+            .product();
+        100.0 * (1.0 - clean)
+    }
+
+    /// Per-factor breakdown with the inputs behind each one. `points` is what the factor
+    /// would score on its own.
+    pub fn breakdown(&self, inputs: &RiskInputs, weights: &RiskWeights) -> serde_json::Value {
+        let mut breakdown = serde_json::Map::new();
+        for (name, factor, weight) in self.weighted(weights) {
+            breakdown.insert(
+                name.to_string(),
+                serde_json::json!({ "factor": factor, "weight": weight, "points": 100.0 * weight * factor }),
+            );
+        }
+        breakdown["anomalies"]["count"] = inputs.anomaly_count.into();
+        breakdown["anomalies"]["decayed_risk"] = inputs.decayed_anomaly_risk.into();
+        breakdown["sanctions_exposure"]["inbound"] = inputs.inbound_exposure.into();
+        breakdown["sanctions_exposure"]["outbound"] = inputs.outbound_exposure.into();
+        if let Some((name, entity_type, _)) = &inputs.label {
+            breakdown["entity_labels"]["entity_name"] = name.as_str().into();
+            breakdown["entity_labels"]["entity_type"] = entity_type.as_str().into();
+        }
+        breakdown["counterparties"]["risky"] = inputs.risky_counterparties.into();
+        breakdown["wallet_age"]["days"] = inputs.age_days.into();
+        breakdown.into()
+    }
+}
+
+/// Rescore every party to the batch, plus up to `refresh_batch` of the stalest stored
+/// scores so decay and exposure changes reach wallets that stopped transacting.
+/// Returns the number of scores written.
+pub async fn update_scores(
+    pool: &PgPool,
+    transfers: &[StablecoinTransfer],
+    label_store: &EntityLabelStore,
+    config: &RiskScoreConfig,
+) -> eyre::Result<u64> {
+    if !config.enabled {
+        return Ok(0);
+    }
+
+    let mut by_chain: HashMap<i64, BTreeSet<Vec<u8>>> = HashMap::new();
+    for t in transfers {
+        let parties = by_chain.entry(t.chain_id).or_default();
+        parties.insert(t.from_address.clone());
+        parties.insert(t.to_address.clone());
+    }
+
+    let stale: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+        "SELECT chain_id, address FROM wallet_risk_scores
+         WHERE computed_at < NOW() - make_interval(secs => $1)
+         ORDER BY computed_at
+         LIMIT $2",
+    )
+    .bind(config.refresh_interval_secs as f64)
+    .bind(config.refresh_batch)
+    .fetch_all(pool)
+    .await?;
+    for (chain_id, address) in stale {
+        by_chain.entry(chain_id).or_default().insert(address);
+    }
+
+    let mut written = 0;
+    for (chain_id, addresses) in by_chain {
+        let addresses: Vec<Vec<u8>> = addresses.into_iter().collect();
+        written += score_addresses(pool, chain_id, &addresses, label_store, config).await?;
+    }
+    Ok(written)
+}
+
+/// Compute and store scores for addresses on one chain. Returns the number written.
+pub async fn score_addresses(
+    pool: &PgPool,
+    chain_id: i64,
+    addresses: &[Vec<u8>],
+    label_store: &EntityLabelStore,
+    config: &RiskScoreConfig,
+) -> eyre::Result<u64> {
+    if addresses.is_empty() {
+        return Ok(0);
+    }
+
+    let inputs = load_inputs(pool, chain_id, addresses, label_store, config).await?;
+
+    let mut scores = Vec::with_capacity(addresses.len());
+    let mut anomaly_factors = Vec::with_capacity(addresses.len());
+    let mut exposure_factors = Vec::with_capacity(addresses.len());
+    let mut label_factors = Vec::with_capacity(addresses.len());
+    let mut counterparty_factors = Vec::with_capacity(addresses.len());
+    let mut age_factors = Vec::with_capacity(addresses.len());
+    let mut breakdowns = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let inputs = inputs.get(address).cloned().unwrap_or_default();
+        let factors = RiskFactors::from_inputs(&inputs, config);
+        scores.push(factors.score(&config.weights) as f32);
+        anomaly_factors.push(factors.anomalies as f32);
+        exposure_factors.push(factors.sanctions_exposure as f32);
+        label_factors.push(factors.entity_labels as f32);
+        counterparty_factors.push(factors.counterparties as f32);
+        age_factors.push(factors.wallet_age as f32);
+        breakdowns.push(factors.breakdown(&inputs, &config.weights));
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO wallet_risk_scores (address, chain_id, score, anomaly_factor, exposure_factor,
+                                         label_factor, counterparty_factor, age_factor, breakdown)
+         SELECT s.address, $1, s.score, s.anomaly, s.exposure, s.label, s.counterparty, s.age,
+                s.breakdown
+         FROM UNNEST($2::BYTEA[], $3::REAL[], $4::REAL[], $5::REAL[], $6::REAL[], $7::REAL[],
+                     $8::REAL[], $9::JSONB[])
+              AS s(address, score, anomaly, exposure, label, counterparty, age, breakdown)
+         ON CONFLICT (address, chain_id) DO UPDATE SET
+            score = EXCLUDED.score, anomaly_factor = EXCLUDED.anomaly_factor,
+            exposure_factor = EXCLUDED.exposure_factor, label_factor = EXCLUDED.label_factor,
+            counterparty_factor = EXCLUDED.counterparty_factor, age_factor = EXCLUDED.age_factor,
+            breakdown = EXCLUDED.breakdown, computed_at = NOW()",
+    )
+    .bind(chain_id)
+    .bind(addresses)
+    .bind(&scores)
+    .bind(&anomaly_factors)
+    .bind(&exposure_factors)
+    .bind(&label_factors)
+    .bind(&counterparty_factors)
+    .bind(&age_factors)
+    .bind(&breakdowns)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Gather every address's inputs on one chain. Addresses with nothing on record are absent.
+async fn load_inputs(
+    pool: &PgPool,
+    chain_id: i64,
+    addresses: &[Vec<u8>],
+    label_store: &EntityLabelStore,
+    config: &RiskScoreConfig,
+) -> eyre::Result<HashMap<Vec<u8>, RiskInputs>> {
+    let mut inputs: HashMap<Vec<u8>, RiskInputs> = HashMap::new();
+
+    let anomalies: Vec<(Vec<u8>, i64, f64)> = sqlx::query_as(
+        "SELECT address, COUNT(*),
+                SUM(risk_score * power(0.5, GREATEST(EXTRACT(EPOCH FROM NOW() - block_timestamp), 0)::FLOAT8
+                                            / $3))::FLOAT8
+         FROM anomalies
+         WHERE chain_id = $1 AND address = ANY($2) AND status <> 'false_positive'
+         GROUP BY address",
+    )
+    .bind(chain_id)
+    .bind(addresses)
+    .bind(config.anomaly_half_life_secs as f64)
+    .fetch_all(pool)
+    .await?;
+    for (address, count, decayed) in anomalies {
+        let entry = inputs.entry(address).or_default();
+        entry.anomaly_count = count;
+        entry.decayed_anomaly_risk = decayed;
+    }
+
+    let refs: Vec<&[u8]> = addresses.iter().map(Vec::as_slice).collect();
+    for ((exposure_chain, address), exposure) in exposure::load_exposures(pool, &refs).await? {
+        if exposure_chain == chain_id {
+            let entry = inputs.entry(address).or_default();
+            entry.inbound_exposure = exposure.inbound;
+            entry.outbound_exposure = exposure.outbound;
+        }
+    }
+
+    for address in addresses {
+        let label = label_store
+            .lookup(address)
+            .unwrap_or_default()
+            .iter()
+            .filter(|l| l.chain_id.is_none_or(|c| c == chain_id))
+            .filter_map(|l| {
+                let risk = config.entity_types.get(&l.entity_type)? * l.confidence as f64;
+                Some((l.entity_name.clone(), l.entity_type.clone(), risk))
+            })
+            .filter(|(_, _, risk)| *risk > 0.0)
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        if label.is_some() {
+            inputs.entry(address.clone()).or_default().label = label;
+        }
+    }
+
+    let (entity_types, entity_risks): (Vec<&str>, Vec<f64>) = config
+        .entity_types
+        .iter()
+        .map(|(entity_type, risk)| (entity_type.as_str(), *risk))
+        .unzip();
+    let counterparties: Vec<(Vec<u8>, Option<f64>, i64)> = sqlx::query_as(
+        "WITH flows AS (
+             SELECT source_address AS address, dest_address AS counterparty,
+                    total_amount::FLOAT8 AS amount
+             FROM wallet_graph_edges WHERE chain_id = $1 AND source_address = ANY($2)
+             UNION ALL
+             SELECT dest_address, source_address, total_amount::FLOAT8
+             FROM wallet_graph_edges WHERE chain_id = $1 AND dest_address = ANY($2)
+         )
+         SELECT f.address,
+                SUM(f.amount * COALESCE(c.risk, 0)) / NULLIF(SUM(f.amount), 0),
+                COUNT(*) FILTER (WHERE c.risk > 0)
+         FROM flows f
+         LEFT JOIN LATERAL (
+             SELECT MAX(r.risk * l.confidence) AS risk
+             FROM entity_labels l
+             JOIN UNNEST($3::TEXT[], $4::FLOAT8[]) AS r(entity_type, risk)
+               ON r.entity_type = l.entity_type
+             WHERE l.address = f.counterparty AND (l.chain_id IS NULL OR l.chain_id = $1)
+         ) c ON TRUE
+         WHERE f.address <> f.counterparty
+         GROUP BY f.address",
+    )
+    .bind(chain_id)
+    .bind(addresses)
+    .bind(&entity_types)
+    .bind(&entity_risks)
+    .fetch_all(pool)
+    .await?;
+    for (address, risk, risky) in counterparties {
+        if risky > 0 {
+            let entry = inputs.entry(address).or_default();
+            entry.counterparty_risk = risk.unwrap_or(0.0);
+            entry.risky_counterparties = risky;
+        }
+    }
+
+    let first_seen: Vec<(Vec<u8>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT address, first_seen_at FROM wallet_first_seen
+         WHERE chain_id = $1 AND address = ANY($2)",
+    )
+    .bind(chain_id)
+    .bind(addresses)
+    .fetch_all(pool)
+    .await?;
+    let now = Utc::now();
+    for (address, at) in first_seen {
+        let days = (now - at).num_seconds().max(0) as f64 / 86_400.0;
+        inputs.entry(address).or_default().age_days = Some(days);
+    }
+
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_factors_compound_without_passing_100() {
+        let config = RiskScoreConfig::default();
+
+        let quiet = RiskInputs {
+            age_days: Some(400.0),
+            ..Default::default()
+        };
+        let factors = RiskFactors::from_inputs(&quiet, &config);
+        assert_eq!(factors, RiskFactors::default());
+        assert_eq!(factors.score(&config.weights), 0.0);
+
+        // One fresh risk-100 anomaly on a week-old wallet
+        let flagged = RiskInputs {
+            anomaly_count: 1,
+            decayed_anomaly_risk: 100.0,
+            age_days: Some(7.5),
+            ..Default::default()
+        };
+        let factors = RiskFactors::from_inputs(&flagged, &config);
+        assert!((factors.anomalies - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+        assert!((factors.wallet_age - 0.75).abs() < 1e-9);
+        let alone = 100.0 * config.weights.anomalies * factors.anomalies;
+        let score = factors.score(&config.weights);
+        assert!(score > alone && score < alone + 100.0 * config.weights.wallet_age * 0.75);
+
+        // A sanctioned label at full confidence saturates the score
+        let sanctioned = RiskInputs {
+            label: Some(("OFAC".into(), "sanctioned".into(), 1.0)),
+            inbound_exposure: 1.0,
+            ..flagged.clone()
+        };
+        let factors = RiskFactors::from_inputs(&sanctioned, &config);
+        assert_eq!(factors.score(&config.weights), 100.0);
+
+        let breakdown = factors.breakdown(&sanctioned, &config.weights);
+        assert_eq!(breakdown["entity_labels"]["entity_type"], "sanctioned");
+        assert_eq!(breakdown["anomalies"]["count"], 1);
+        assert_eq!(breakdown["entity_labels"]["points"], 100.0);
+    }
+}